@@ -0,0 +1,78 @@
+use std::ops::Range;
+
+use crate::film::{ Film, Filter };
+use crate::framebuffer::Framebuffer;
+
+// The film every sample rendered since the last reset went into, and its average for display. Each frame adds
+// the next samples of every pixel until there are target_samples of them.
+pub struct Accumulator
+{
+	film : Film,
+	average : Framebuffer,
+	sample_count : u32,
+	target_samples : u32,
+}
+
+impl Accumulator
+{
+	pub fn new(width : u32, height : u32, filter : Filter, target_samples : u32) -> Self
+	{
+		Self
+		{
+			film : Film::new(width, height, filter),
+			average : Framebuffer::new(width, height),
+			sample_count : 0,
+			target_samples : target_samples.max(1),
+		}
+	}
+
+	// Starts over, keeping the last average on display until the next frame replaces it.
+	pub fn reset(&mut self)
+	{
+		self.sample_count = 0;
+	}
+
+	pub fn set_filter(&mut self, filter : Filter)
+	{
+		self.film.set_filter(filter);
+		self.reset();
+	}
+
+	pub fn is_complete(&self) -> bool
+	{
+		self.sample_count >= self.target_samples
+	}
+
+	// The sample indices the next frame should render, up to samples_per_frame of them.
+	pub fn next_samples(&self, samples_per_frame : u32) -> Range<u32>
+	{
+		self.sample_count..(self.sample_count + samples_per_frame.max(1)).min(self.target_samples)
+	}
+
+	// The film to add the samples in range to, emptied first when they start over.
+	pub fn film(&mut self, samples : &Range<u32>) -> &mut Film
+	{
+		if samples.start == 0
+		{
+			self.film.clear();
+		}
+		&mut self.film
+	}
+
+	// Takes note of the samples in range having been added to the film and resolves it for display.
+	pub fn add(&mut self, samples : Range<u32>)
+	{
+		self.sample_count = samples.end;
+		self.film.resolve(&mut self.average, 1.0 / self.sample_count as f32);
+	}
+
+	pub fn average(&self) -> &Framebuffer
+	{
+		&self.average
+	}
+
+	pub fn aspect_ratio(&self) -> f32
+	{
+		self.average.aspect_ratio()
+	}
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use cgmath::InnerSpace;
+use cgmath::Vector3;
+
+use crate::mesh::Mesh;
+use crate::scene_description::MaterialDescription;
+
+pub fn luminance(color : Vector3<f32>) -> f32
+{
+	0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+// A triangle of a mesh whose material emits. Emits from its front side, or both when the material is double sided.
+#[derive(Copy, Clone, Debug)]
+struct EmissiveTriangle
+{
+	p0 : Vector3<f32>,
+	edge1 : Vector3<f32>,
+	edge2 : Vector3<f32>,
+	normal : Vector3<f32>, // unit length, front side
+	emission : Vector3<f32>,
+	double_sided : bool,
+}
+
+// An emissive triangle reduced to a point at its centroid, for lighting that has to be deterministic.
+#[derive(Copy, Clone, Debug)]
+pub struct PointApproximation
+{
+	pub position : Vector3<f32>,
+	pub normal : Vector3<f32>,
+	pub power : Vector3<f32>, // emission times area
+	pub double_sided : bool,
+}
+
+// A point picked on a light, as seen from the point being shaded.
+#[derive(Copy, Clone, Debug)]
+pub struct LightSample
+{
+	pub direction : Vector3<f32>, // unit length, from the shaded point toward the light
+	pub distance : f32,
+	pub radiance : Vector3<f32>,
+	pub pdf : f32,                // per unit solid angle at the shaded point, or a probability for delta lights
+	pub delta : bool,             // from a light nothing but sampling finds, like a point light
+}
+
+// Every emissive triangle in the scene, picked with probability proportional to its power and then sampled
+// uniformly by area. That makes the area density of any point on any light luminance(emission) / total power.
+pub struct AreaLights
+{
+	triangles : Vec<EmissiveTriangle>,
+	cumulative_power : Vec<f32>,
+	total_power : f32,
+}
+
+impl AreaLights
+{
+	pub fn new() -> Self
+	{
+		Self { triangles : Vec::new(), cumulative_power : Vec::new(), total_power : 0.0 }
+	}
+
+	pub fn from_meshes(meshes : &[Arc<Mesh>], materials : &[MaterialDescription]) -> Self
+	{
+		let mut lights = Self::new();
+		for mesh in meshes
+		{
+			for first_index in (0..mesh.indices.len() / 3 * 3).step_by(3)
+			{
+				let material = match mesh.material_at(first_index).and_then(|material| materials.get(material))
+				{
+					Some(material) if luminance(material.emission) > 0.0 => material,
+					_ => continue,
+				};
+
+				let corner = |offset : usize| mesh.vertices[mesh.indices[first_index + offset] as usize].position;
+				let (p0, p1, p2) = (corner(0), corner(1), corner(2));
+				let cross = (p1 - p0).cross(p2 - p0);
+				let area = 0.5 * cross.magnitude();
+				if area <= 0.0
+				{
+					continue;
+				}
+
+				lights.total_power += luminance(material.emission) * area;
+				lights.cumulative_power.push(lights.total_power);
+				lights.triangles.push(EmissiveTriangle
+				{
+					p0 : p0,
+					edge1 : p1 - p0,
+					edge2 : p2 - p0,
+					normal : cross / (2.0 * area),
+					emission : material.emission,
+					double_sided : material.double_sided,
+				});
+			}
+		}
+		lights
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.triangles.is_empty()
+	}
+
+	pub fn point_approximations(&self) -> impl Iterator<Item = PointApproximation> + '_
+	{
+		self.triangles.iter().map(|triangle|
+		{
+			let area = 0.5 * triangle.edge1.cross(triangle.edge2).magnitude();
+			PointApproximation
+			{
+				position : triangle.p0 + (triangle.edge1 + triangle.edge2) / 3.0,
+				normal : triangle.normal,
+				power : triangle.emission * area,
+				double_sided : triangle.double_sided,
+			}
+		})
+	}
+
+	// Picks a point on a light with three uniform numbers in [0, 1). Returns None for points that face away.
+	pub fn sample(&self, point : Vector3<f32>, u_select : f32, u1 : f32, u2 : f32) -> Option<LightSample>
+	{
+		if self.is_empty()
+		{
+			return None;
+		}
+
+		let target = u_select * self.total_power;
+		let index = self.cumulative_power.partition_point(|&power| power <= target).min(self.triangles.len() - 1);
+		let triangle = &self.triangles[index];
+
+		// uniform on the triangle by folding the unit square in half.
+		let (b1, b2) = if u1 + u2 > 1.0 { (1.0 - u1, 1.0 - u2) } else { (u1, u2) };
+		let light_point = triangle.p0 + triangle.edge1 * b1 + triangle.edge2 * b2;
+
+		let offset = light_point - point;
+		let distance_squared = offset.magnitude2();
+		let distance = distance_squared.sqrt();
+		if distance <= 0.0
+		{
+			return None;
+		}
+		let direction = offset / distance;
+
+		let cosine = -direction.dot(triangle.normal);
+		if cosine <= 0.0 && !triangle.double_sided
+		{
+			return None;
+		}
+
+		let area_pdf = self.area_pdf(triangle.emission);
+		Some(LightSample
+		{
+			direction : direction,
+			distance : distance,
+			radiance : triangle.emission,
+			pdf : area_pdf * distance_squared / cosine.abs().max(1.0e-8),
+			delta : false,
+		})
+	}
+
+	// The density, per unit solid angle, with which sample would have picked a point that a ray found instead.
+	pub fn pdf(&self, emission : Vector3<f32>, distance : f32, cosine : f32) -> f32
+	{
+		self.area_pdf(emission) * distance * distance / cosine.abs().max(1.0e-8)
+	}
+
+	fn area_pdf(&self, emission : Vector3<f32>) -> f32
+	{
+		if self.total_power > 0.0 { luminance(emission) / self.total_power } else { 0.0 }
+	}
+}
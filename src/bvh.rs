@@ -0,0 +1,531 @@
+use std::fmt;
+use std::time::{ Duration, Instant };
+
+use cgmath::{ Vector3, Vector4 };
+
+use crate::geometry::{ Aabb, Bounded, ColoredTriangle, ColoredVertex };
+use crate::ray::{ Ray, HitRecord, Hittable };
+use crate::rng::Rng;
+
+const BIN_COUNT : usize = 16;
+const MAX_LEAF_PRIMITIVES : usize = 8;  // larger leaves are split even when SAH says otherwise
+const MAX_DEPTH : usize = 64;           // size of the traversal stack
+const TRAVERSAL_COST : f32 = 0.125;     // cost of visiting a node, relative to one primitive intersection
+
+// Nodes are stored depth first: an interior node's first child directly follows it and offset is the
+// index of its second child. For a leaf offset is the index of its first primitive.
+#[derive(Copy, Clone, Debug)]
+struct BvhNode
+{
+	bounds : Aabb,
+	offset : u32,
+	primitive_count : u32, // zero for interior nodes
+	axis : u8,             // the axis interior nodes were split along, decides which child is visited first
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BuildStats
+{
+	pub build_time : Duration,
+	pub primitive_count : usize,
+	pub node_count : usize,
+	pub leaf_count : usize,
+	pub max_depth : usize,
+	pub max_leaf_primitives : usize,
+	pub sah_cost : f32, // expected cost of a ray through the root, in primitive intersections
+}
+
+impl fmt::Display for BuildStats
+{
+	fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{} primitives, {} nodes, {} leaves (at most {} primitives), depth {}, SAH cost {:.2}, built in {:.2?}",
+			self.primitive_count, self.node_count, self.leaf_count, self.max_leaf_primitives,
+			self.max_depth, self.sah_cost, self.build_time)
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TraversalStats
+{
+	pub rays : u64,
+	pub nodes_visited : u64,
+	pub primitives_tested : u64,
+}
+
+impl fmt::Display for TraversalStats
+{
+	fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+	{
+		let rays = self.rays.max(1) as f64;
+		write!(f, "{} rays, {:.1} nodes and {:.1} primitives per ray",
+			self.rays, self.nodes_visited as f64 / rays, self.primitives_tested as f64 / rays)
+	}
+}
+
+// Bounding volume hierarchy over any list of bounded primitives, built with the binned surface area heuristic.
+pub struct Bvh<P>
+{
+	primitives : Vec<P>, // reordered so every leaf covers a contiguous range
+	nodes : Vec<BvhNode>,
+	stats : BuildStats,
+}
+
+struct BuildPrimitive
+{
+	index : usize,
+	bounds : Aabb,
+	centroid : Vector3<f32>,
+}
+
+#[derive(Copy, Clone)]
+struct Bin
+{
+	bounds : Aabb,
+	count : usize,
+}
+
+struct Split
+{
+	axis : usize,
+	last_left_bin : usize,
+	cost : f32,
+}
+
+impl<P : Bounded + Hittable> Bvh<P>
+{
+	pub fn build(primitives : Vec<P>) -> Self
+	{
+		let start = Instant::now();
+
+		let mut build_primitives : Vec<BuildPrimitive> = primitives.iter()
+			.enumerate()
+			.map(|(index, primitive)|
+			{
+				let bounds = primitive.bounds();
+				BuildPrimitive { index : index, bounds : bounds, centroid : bounds.centroid() }
+			})
+			.collect();
+
+		let mut nodes = Vec::with_capacity(2 * primitives.len());
+		let mut stats = BuildStats { primitive_count : primitives.len(), ..BuildStats::default() };
+		if !build_primitives.is_empty()
+		{
+			build_node(&mut build_primitives, 0, 1, &mut nodes, &mut stats);
+		}
+
+		let mut slots : Vec<Option<P>> = primitives.into_iter().map(Some).collect();
+		let primitives = build_primitives.iter()
+			.map(|build_primitive| slots[build_primitive.index].take().expect("every primitive is placed once"))
+			.collect();
+
+		stats.node_count = nodes.len();
+		stats.sah_cost = sah_cost(&nodes);
+		stats.build_time = start.elapsed();
+
+		Self { primitives : primitives, nodes : nodes, stats : stats }
+	}
+
+	pub fn stats(&self) -> &BuildStats
+	{
+		&self.stats
+	}
+
+	pub fn closest_hit(&self, ray : &Ray, t_min : f32, t_max : f32, stats : &mut TraversalStats) -> Option<HitRecord>
+	{
+		let mut closest = None;
+		let mut closest_t = t_max;
+
+		self.traverse(ray, t_min, t_max, stats, |primitive, stats|
+		{
+			stats.primitives_tested += 1;
+			if let Some(hit) = primitive.hit(ray, t_min, closest_t)
+			{
+				closest_t = hit.t;
+				closest = Some(hit);
+			}
+			(false, closest_t)
+		});
+
+		closest
+	}
+
+	pub fn any_hit(&self, ray : &Ray, t_min : f32, t_max : f32, stats : &mut TraversalStats) -> bool
+	{
+		let mut found = false;
+
+		self.traverse(ray, t_min, t_max, stats, |primitive, stats|
+		{
+			stats.primitives_tested += 1;
+			found = primitive.occluded(ray, t_min, t_max);
+			(found, t_max)
+		});
+
+		found
+	}
+
+	// Walks the nodes the ray overlaps, nearest child first. visit is called for every primitive in the
+	// leaves reached and returns whether to stop, along with the t_max to cull the remaining nodes with.
+	fn traverse<F>(&self, ray : &Ray, t_min : f32, t_max : f32, stats : &mut TraversalStats, mut visit : F)
+		where F : FnMut(&P, &mut TraversalStats) -> (bool, f32)
+	{
+		stats.rays += 1;
+		if self.nodes.is_empty()
+		{
+			return;
+		}
+
+		let inverse_direction = Vector3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+		let direction_is_negative = [ray.direction.x < 0.0, ray.direction.y < 0.0, ray.direction.z < 0.0];
+
+		let mut t_max = t_max;
+		let mut stack = [0u32; MAX_DEPTH];
+		let mut stack_size = 0;
+		let mut node_index = 0;
+
+		loop
+		{
+			let node = &self.nodes[node_index];
+			stats.nodes_visited += 1;
+
+			if node.bounds.overlaps_ray(ray, inverse_direction, t_min, t_max)
+			{
+				if node.primitive_count > 0
+				{
+					let start = node.offset as usize;
+					for primitive in &self.primitives[start..start + node.primitive_count as usize]
+					{
+						let (stop, new_t_max) = visit(primitive, stats);
+						if stop
+						{
+							return;
+						}
+						t_max = new_t_max;
+					}
+				}
+				else
+				{
+					let (near, far) = if direction_is_negative[node.axis as usize]
+					{
+						(node.offset as usize, node_index + 1)
+					}
+					else
+					{
+						(node_index + 1, node.offset as usize)
+					};
+					stack[stack_size] = far as u32;
+					stack_size += 1;
+					node_index = near;
+					continue;
+				}
+			}
+
+			if stack_size == 0
+			{
+				return;
+			}
+			stack_size -= 1;
+			node_index = stack[stack_size] as usize;
+		}
+	}
+}
+
+// So a BVH can itself be a primitive of another BVH, e.g. one per mesh with a top level BVH over the meshes.
+impl<P> Bounded for Bvh<P>
+{
+	fn bounds(&self) -> Aabb
+	{
+		self.nodes.first().map_or(Aabb::empty(), |root| root.bounds)
+	}
+}
+
+impl<P : Bounded + Hittable> Hittable for Bvh<P>
+{
+	fn hit(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<HitRecord>
+	{
+		self.closest_hit(ray, t_min, t_max, &mut TraversalStats::default())
+	}
+
+	fn occluded(&self, ray : &Ray, t_min : f32, t_max : f32) -> bool
+	{
+		self.any_hit(ray, t_min, t_max, &mut TraversalStats::default())
+	}
+}
+
+// Appends the subtree over primitives, its root first. first is where primitives start in the final order.
+fn build_node(primitives : &mut [BuildPrimitive], first : usize, depth : usize, nodes : &mut Vec<BvhNode>, stats : &mut BuildStats)
+{
+	let bounds = primitives.iter().fold(Aabb::empty(), |bounds, primitive| bounds.union(&primitive.bounds));
+	let node_index = nodes.len();
+	nodes.push(BvhNode { bounds : bounds, offset : first as u32, primitive_count : primitives.len() as u32, axis : 0 });
+	stats.max_depth = stats.max_depth.max(depth);
+
+	let count = primitives.len();
+	if count == 1 || depth == MAX_DEPTH
+	{
+		make_leaf(count, stats);
+		return;
+	}
+
+	let centroid_bounds = primitives.iter().fold(Aabb::empty(), |bounds, primitive| bounds.grow(primitive.centroid));
+	let (axis, middle) = match find_split(primitives, &bounds, &centroid_bounds)
+	{
+		Some(ref split) if count <= MAX_LEAF_PRIMITIVES && split.cost >= count as f32 =>
+		{
+			make_leaf(count, stats);
+			return;
+		}
+		Some(split) =>
+		{
+			let mut middle = 0;
+			for index in 0..count
+			{
+				if bin_index(primitives[index].centroid, &centroid_bounds, split.axis) <= split.last_left_bin
+				{
+					primitives.swap(index, middle);
+					middle += 1;
+				}
+			}
+			(split.axis, middle)
+		}
+		None if count <= MAX_LEAF_PRIMITIVES =>
+		{
+			make_leaf(count, stats);
+			return;
+		}
+		None => (centroid_bounds.longest_axis(), count / 2), // every centroid is in the same place, any split will do
+	};
+
+	let (left, right) = primitives.split_at_mut(middle);
+	build_node(left, first, depth + 1, nodes, stats);
+	let second_child = nodes.len();
+	build_node(right, first + middle, depth + 1, nodes, stats);
+
+	nodes[node_index] = BvhNode { bounds : bounds, offset : second_child as u32, primitive_count : 0, axis : axis as u8 };
+}
+
+fn make_leaf(count : usize, stats : &mut BuildStats)
+{
+	stats.leaf_count += 1;
+	stats.max_leaf_primitives = stats.max_leaf_primitives.max(count);
+}
+
+fn bin_index(centroid : Vector3<f32>, centroid_bounds : &Aabb, axis : usize) -> usize
+{
+	let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+	let bin = ((centroid[axis] - centroid_bounds.min[axis]) / extent * BIN_COUNT as f32) as usize;
+	bin.min(BIN_COUNT - 1)
+}
+
+// The cheapest split between bins on any axis, None when the centroids can't be separated.
+fn find_split(primitives : &[BuildPrimitive], bounds : &Aabb, centroid_bounds : &Aabb) -> Option<Split>
+{
+	let parent_area = bounds.surface_area().max(f32::MIN_POSITIVE);
+	let mut best : Option<Split> = None;
+
+	for axis in 0..3
+	{
+		if centroid_bounds.max[axis] <= centroid_bounds.min[axis]
+		{
+			continue;
+		}
+
+		let mut bins = [Bin { bounds : Aabb::empty(), count : 0 }; BIN_COUNT];
+		for primitive in primitives
+		{
+			let bin = &mut bins[bin_index(primitive.centroid, centroid_bounds, axis)];
+			bin.bounds = bin.bounds.union(&primitive.bounds);
+			bin.count += 1;
+		}
+
+		// sweep from the right to get the area and count right of every split, then from the left to price them.
+		let mut right_area = [0.0; BIN_COUNT];
+		let mut right_count = [0; BIN_COUNT];
+		let mut right = Bin { bounds : Aabb::empty(), count : 0 };
+		for bin in (1..BIN_COUNT).rev()
+		{
+			right.bounds = right.bounds.union(&bins[bin].bounds);
+			right.count += bins[bin].count;
+			right_area[bin - 1] = right.bounds.surface_area();
+			right_count[bin - 1] = right.count;
+		}
+
+		let mut left = Bin { bounds : Aabb::empty(), count : 0 };
+		for bin in 0..BIN_COUNT - 1
+		{
+			left.bounds = left.bounds.union(&bins[bin].bounds);
+			left.count += bins[bin].count;
+			if left.count == 0 || right_count[bin] == 0
+			{
+				continue;
+			}
+
+			let cost = TRAVERSAL_COST
+				+ (left.bounds.surface_area() * left.count as f32 + right_area[bin] * right_count[bin] as f32) / parent_area;
+			if best.as_ref().is_none_or(|best| cost < best.cost)
+			{
+				best = Some(Split { axis : axis, last_left_bin : bin, cost : cost });
+			}
+		}
+	}
+
+	best
+}
+
+// Expected cost of a random ray through the root: each node costs its price weighted by the chance
+// a ray that hits the root also hits the node, which is the ratio of their surface areas.
+fn sah_cost(nodes : &[BvhNode]) -> f32
+{
+	let root_area = match nodes.first()
+	{
+		Some(root) => root.bounds.surface_area().max(f32::MIN_POSITIVE),
+		None => return 0.0,
+	};
+
+	nodes.iter()
+		.map(|node|
+		{
+			let price = if node.primitive_count > 0 { node.primitive_count as f32 } else { TRAVERSAL_COST };
+			price * node.bounds.surface_area() / root_area
+		})
+		.sum()
+}
+
+// A soup of random triangles in a box around the origin, with rays from further out aimed at its middle.
+fn random_soup(triangle_count : usize, ray_count : usize) -> (Vec<ColoredTriangle>, Vec<Ray>)
+{
+	let mut rng = Rng::new(0x5eed, 0);
+	let mut random_point = |scale : f32| Vector3::new(rng.next_f32() - 0.5, rng.next_f32() - 0.5, rng.next_f32() - 0.5) * scale;
+
+	let triangle_size = 4.0 / (triangle_count as f32).sqrt();
+	let triangles : Vec<ColoredTriangle> = (0..triangle_count)
+		.map(|_|
+		{
+			let center = random_point(2.0);
+			let mut vertex = || ColoredVertex { position : center + random_point(triangle_size), color : Vector4::new(1.0, 1.0, 1.0, 1.0) };
+			ColoredTriangle::new([vertex(), vertex(), vertex()])
+		})
+		.collect();
+
+	let rays = (0..ray_count)
+		.map(|_|
+		{
+			let origin = random_point(8.0);
+			Ray::new(origin, random_point(1.0) - origin)
+		})
+		.collect();
+
+	(triangles, rays)
+}
+
+// The closest hit found by testing every triangle.
+fn brute_force_closest(triangles : &[ColoredTriangle], ray : &Ray, t_max : f32) -> Option<f32>
+{
+	let mut closest_t = t_max;
+	let mut closest = None;
+	for triangle in triangles
+	{
+		if let Some(hit) = triangle.hit(ray, 0.0, closest_t)
+		{
+			closest_t = hit.t;
+			closest = Some(hit.t);
+		}
+	}
+	closest
+}
+
+// Times the BVH against testing every triangle on a soup of random triangles, and checks both find the same hits.
+pub fn run_benchmark(triangle_count : usize)
+{
+	const RAY_COUNT : usize = 4096;
+
+	let (triangles, rays) = random_soup(triangle_count, RAY_COUNT);
+	println!("BVH benchmark: {} triangles, {} rays", triangle_count, RAY_COUNT);
+
+	let brute_force_start = Instant::now();
+	let brute_force_hits : Vec<Option<f32>> = rays.iter()
+		.map(|ray| brute_force_closest(&triangles, ray, f32::INFINITY))
+		.collect();
+	let brute_force_time = brute_force_start.elapsed();
+
+	let bvh = Bvh::build(triangles);
+	println!("  build: {}", bvh.stats());
+
+	let mut closest_stats = TraversalStats::default();
+	let closest_start = Instant::now();
+	let bvh_hits : Vec<Option<f32>> = rays.iter()
+		.map(|ray| bvh.closest_hit(ray, 0.0, f32::INFINITY, &mut closest_stats).map(|hit| hit.t))
+		.collect();
+	let closest_time = closest_start.elapsed();
+
+	let mut any_stats = TraversalStats::default();
+	let any_start = Instant::now();
+	let any_hits : Vec<bool> = rays.iter()
+		.map(|ray| bvh.any_hit(ray, 0.0, f32::INFINITY, &mut any_stats))
+		.collect();
+	let any_time = any_start.elapsed();
+
+	let mismatches = (0..RAY_COUNT)
+		.filter(|&index| brute_force_hits[index] != bvh_hits[index] || brute_force_hits[index].is_some() != any_hits[index])
+		.count();
+
+	let speedup = brute_force_time.as_secs_f64() / closest_time.as_secs_f64().max(1e-9);
+	println!("  brute force: {:.2?}", brute_force_time);
+	println!("  closest hit: {:.2?} ({:.1}x), {}", closest_time, speedup, closest_stats);
+	println!("  any hit:     {:.2?}, {}", any_time, any_stats);
+	println!("  {} of {} rays hit, {} mismatches", bvh_hits.iter().filter(|hit| hit.is_some()).count(), RAY_COUNT, mismatches);
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn closest_and_any_hit_match_brute_force()
+	{
+		let (triangles, rays) = random_soup(2000, 1024);
+		let brute_force_hits : Vec<Option<f32>> = rays.iter().map(|ray| brute_force_closest(&triangles, ray, f32::INFINITY)).collect();
+		assert!(brute_force_hits.iter().any(Option::is_some) && brute_force_hits.iter().any(Option::is_none));
+
+		let bvh = Bvh::build(triangles);
+		let mut stats = TraversalStats::default();
+		for (index, (ray, expected)) in rays.iter().zip(&brute_force_hits).enumerate()
+		{
+			assert_eq!(bvh.closest_hit(ray, 0.0, f32::INFINITY, &mut stats).map(|hit| hit.t), *expected, "ray {}", index);
+			assert_eq!(bvh.any_hit(ray, 0.0, f32::INFINITY, &mut stats), expected.is_some(), "ray {}", index);
+		}
+	}
+
+	#[test]
+	fn hits_beyond_t_max_are_ignored()
+	{
+		// shadow rays only look as far as the light, so hits past t_max must not count. The rays end in the
+		// middle of the soup at t = 1.
+		let (triangles, rays) = random_soup(2000, 1024);
+		let t_max = 1.0;
+		let brute_force_hits : Vec<Option<f32>> = rays.iter().map(|ray| brute_force_closest(&triangles, ray, t_max)).collect();
+		let cut_off = rays.iter().zip(&brute_force_hits)
+			.filter(|(ray, hit)| hit.is_none() && brute_force_closest(&triangles, ray, f32::INFINITY).is_some())
+			.count();
+		assert!(cut_off > 0, "t_max should hide some hits");
+
+		let bvh = Bvh::build(triangles);
+		let mut stats = TraversalStats::default();
+		for (index, (ray, expected)) in rays.iter().zip(&brute_force_hits).enumerate()
+		{
+			assert_eq!(bvh.closest_hit(ray, 0.0, t_max, &mut stats).map(|hit| hit.t), *expected, "ray {}", index);
+			assert_eq!(bvh.any_hit(ray, 0.0, t_max, &mut stats), expected.is_some(), "ray {}", index);
+		}
+	}
+
+	#[test]
+	fn empty_bvh_hits_nothing()
+	{
+		let bvh : Bvh<ColoredTriangle> = Bvh::build(Vec::new());
+		let ray = Ray::new(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+		let mut stats = TraversalStats::default();
+		assert!(bvh.closest_hit(&ray, 0.0, f32::INFINITY, &mut stats).is_none());
+		assert!(!bvh.any_hit(&ray, 0.0, f32::INFINITY, &mut stats));
+	}
+}
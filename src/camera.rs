@@ -0,0 +1,482 @@
+use cgmath::InnerSpace;
+use cgmath::{ Matrix4, Vector3 };
+use cgmath::{ Deg, Rad };
+
+use crate::ray::Ray;
+
+// Looking straight up or down makes yaw meaningless, so pitch stops just short of it.
+const MAX_PITCH : Rad<f32> = Rad(1.553343); // 89 degrees
+
+// How a camera maps image coordinates to rays.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProjectionModel
+{
+	Pinhole,
+	ThinLens(ThinLens),                   // depth of field, a pinhole with an aperture
+	Orthographic { half_height : f32 },   // world units, the width follows the aspect ratio
+	Equirectangular,                      // 360 by 180 degrees around the camera, kept level
+}
+
+// A lens focused on the plane focus_distance in front of the camera. With 3 or more blades the aperture, and
+// so the bokeh, is a regular polygon with a corner at blade_rotation from the right, otherwise it's round.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ThinLens
+{
+	pub aperture_radius : f32,
+	pub focus_distance : f32,
+	pub blade_count : u32,
+	pub blade_rotation : Rad<f32>,
+}
+
+impl ThinLens
+{
+	// A point on the aperture, relative to its center, from two uniform numbers.
+	fn sample_aperture(&self, u1 : f32, u2 : f32) -> (f32, f32)
+	{
+		if self.blade_count < 3
+		{
+			let radius = self.aperture_radius * u1.sqrt();
+			let angle = 2.0 * std::f32::consts::PI * u2;
+			return (radius * angle.cos(), radius * angle.sin());
+		}
+
+		// u1 picks the triangle between the center and one side, then is reused within it.
+		let blades = self.blade_count as f32;
+		let blade = (u1 * blades).floor().min(blades - 1.0);
+		let u1 = u1 * blades - blade;
+		let corner = |index : f32|
+		{
+			let angle = self.blade_rotation.0 + 2.0 * std::f32::consts::PI * index / blades;
+			(angle.cos(), angle.sin())
+		};
+		let ((x0, y0), (x1, y1)) = (corner(blade), corner(blade + 1.0));
+		let radius = self.aperture_radius * u1.sqrt();
+		(radius * (x0 + (x1 - x0) * u2), radius * (y0 + (y1 - y0) * u2))
+	}
+}
+
+// Camera shared by the rasterizer and the ray tracer, a pinhole unless given another projection model.
+// World space is right handed with +y up, a yaw and pitch of zero looks down -z and a positive yaw turns
+// to the right.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera
+{
+	position : Vector3<f32>,
+	yaw : Rad<f32>,
+	pitch : Rad<f32>,
+	vertical_fov : Deg<f32>,
+	aspect_ratio : f32,
+	near_plane : f32,
+	far_plane : f32,
+	model : ProjectionModel,
+
+	// derived from the above by update_basis()
+	forward : Vector3<f32>,
+	right : Vector3<f32>,
+	up : Vector3<f32>,
+	half_width : f32,
+	half_height : f32,
+}
+
+impl Camera
+{
+	pub fn new(
+		position : Vector3<f32>,
+		yaw : Rad<f32>,
+		pitch : Rad<f32>,
+		vertical_fov : Deg<f32>,
+		aspect_ratio : f32) -> Self
+	{
+		let mut camera = Self
+		{
+			position : position,
+			yaw : yaw,
+			pitch : Rad(pitch.0.clamp(-MAX_PITCH.0, MAX_PITCH.0)),
+			vertical_fov : vertical_fov,
+			aspect_ratio : aspect_ratio,
+			near_plane : 0.01,
+			far_plane : 1000.0,
+			model : ProjectionModel::Pinhole,
+			forward : Vector3::new(0.0, 0.0, -1.0),
+			right : Vector3::new(1.0, 0.0, 0.0),
+			up : Vector3::new(0.0, 1.0, 0.0),
+			half_width : 0.0,
+			half_height : 0.0,
+		};
+		camera.update_basis();
+		camera
+	}
+
+	pub fn look_at(
+		position : Vector3<f32>,
+		target : Vector3<f32>,
+		vertical_fov : Deg<f32>,
+		aspect_ratio : f32) -> Self
+	{
+		let direction = (target - position).normalize();
+		let yaw = Rad(direction.x.atan2(-direction.z));
+		let pitch = Rad(direction.y.asin());
+		Self::new(position, yaw, pitch, vertical_fov, aspect_ratio)
+	}
+
+	pub fn position(&self) -> Vector3<f32>
+	{
+		self.position
+	}
+
+	pub fn set_position(&mut self, position : Vector3<f32>)
+	{
+		self.position = position;
+	}
+
+	pub fn yaw(&self) -> Rad<f32>
+	{
+		self.yaw
+	}
+
+	pub fn pitch(&self) -> Rad<f32>
+	{
+		self.pitch
+	}
+
+	pub fn set_orientation(&mut self, yaw : Rad<f32>, pitch : Rad<f32>)
+	{
+		self.yaw = Rad(yaw.0 % (2.0 * std::f32::consts::PI));
+		self.pitch = Rad(pitch.0.clamp(-MAX_PITCH.0, MAX_PITCH.0));
+		self.update_basis();
+	}
+
+	pub fn vertical_fov(&self) -> Deg<f32>
+	{
+		self.vertical_fov
+	}
+
+	pub fn set_vertical_fov(&mut self, vertical_fov : Deg<f32>)
+	{
+		self.vertical_fov = vertical_fov;
+		self.update_basis();
+	}
+
+	pub fn set_aspect_ratio(&mut self, aspect_ratio : f32)
+	{
+		self.aspect_ratio = aspect_ratio;
+		self.update_basis();
+	}
+
+	pub fn near_plane(&self) -> f32
+	{
+		self.near_plane
+	}
+
+	pub fn far_plane(&self) -> f32
+	{
+		self.far_plane
+	}
+
+	// The depth range of projection_matrix, the ray tracers don't clip.
+	pub fn set_clip_planes(&mut self, near : f32, far : f32)
+	{
+		self.near_plane = near;
+		self.far_plane = far;
+	}
+
+	pub fn model(&self) -> ProjectionModel
+	{
+		self.model
+	}
+
+	pub fn set_model(&mut self, model : ProjectionModel)
+	{
+		self.model = model;
+	}
+
+	// Whether rays should be spread over a lens with sample_ray.
+	pub fn has_aperture(&self) -> bool
+	{
+		match self.model
+		{
+			ProjectionModel::ThinLens(lens) => lens.aperture_radius > 0.0,
+			_ => false,
+		}
+	}
+
+	pub fn forward(&self) -> Vector3<f32>
+	{
+		self.forward
+	}
+
+	pub fn right(&self) -> Vector3<f32>
+	{
+		self.right
+	}
+
+	#[cfg_attr(not(windows), allow(dead_code))]
+	pub fn view_matrix(&self) -> Matrix4<f32>
+	{
+		Matrix4::look_at_dir(cgmath::Point3::new(self.position.x, self.position.y, self.position.z), self.forward, self.up)
+	}
+
+	// Projection with the D3D clip space depth range of [0, 1]. A rasterizer has no depth of field, so a thin lens
+	// projects like its pinhole, and no matrix wraps around the camera, so equirectangular falls back to it too.
+	#[cfg_attr(not(windows), allow(dead_code))]
+	pub fn projection_matrix(&self) -> Matrix4<f32>
+	{
+		// cgmath targets OpenGL's [-1, 1] depth, remap z to 0.5 * z + 0.5 * w.
+		let opengl_to_d3d = Matrix4::new(
+			1.0, 0.0, 0.0, 0.0,
+			0.0, 1.0, 0.0, 0.0,
+			0.0, 0.0, 0.5, 0.0,
+			0.0, 0.0, 0.5, 1.0);
+		match self.model
+		{
+			ProjectionModel::Orthographic { half_height } =>
+			{
+				let half_width = self.aspect_ratio * half_height;
+				opengl_to_d3d * cgmath::ortho(-half_width, half_width, -half_height, half_height, self.near_plane, self.far_plane)
+			}
+			_ => opengl_to_d3d * cgmath::perspective(self.vertical_fov, self.aspect_ratio, self.near_plane, self.far_plane),
+		}
+	}
+
+	#[cfg_attr(not(windows), allow(dead_code))]
+	pub fn view_projection_matrix(&self) -> Matrix4<f32>
+	{
+		self.projection_matrix() * self.view_matrix()
+	}
+
+	// s and t are normalized image coordinates: (0, 0) is the bottom left corner, (1, 1) the top right.
+	// A thin lens is sampled at its center, which sees what the pinhole does.
+	pub fn get_ray(&self, s : f32, t : f32) -> Ray
+	{
+		let x = 2.0 * s - 1.0;
+		let y = 2.0 * t - 1.0;
+		match self.model
+		{
+			ProjectionModel::Pinhole | ProjectionModel::ThinLens(_) =>
+			{
+				let direction = self.forward + self.right * (x * self.half_width) + self.up * (y * self.half_height);
+				Ray::new(self.position, direction.normalize())
+			}
+			ProjectionModel::Orthographic { half_height } =>
+			{
+				let origin = self.position + self.right * (x * self.aspect_ratio * half_height) + self.up * (y * half_height);
+				Ray::new(origin, self.forward)
+			}
+			ProjectionModel::Equirectangular =>
+			{
+				// longitude around world +y from the view direction, latitude from the horizon.
+				let longitude = x * std::f32::consts::PI;
+				let latitude = y * std::f32::consts::FRAC_PI_2;
+				let level_forward = Vector3::new(self.yaw.0.sin(), 0.0, -self.yaw.0.cos());
+				let direction = (level_forward * longitude.cos() + self.right * longitude.sin()) * latitude.cos() + Vector3::unit_y() * latitude.sin();
+				Ray::new(self.position, direction.normalize())
+			}
+		}
+	}
+
+	// Like get_ray, with the ray leaving a thin lens at the point u1 and u2 pick, toward where the pinhole ray
+	// crosses the plane of focus.
+	pub fn sample_ray(&self, s : f32, t : f32, u1 : f32, u2 : f32) -> Ray
+	{
+		let pinhole = self.get_ray(s, t);
+		let lens = match self.model
+		{
+			ProjectionModel::ThinLens(lens) if lens.aperture_radius > 0.0 => lens,
+			_ => return pinhole,
+		};
+		let focus = pinhole.origin + pinhole.direction * (lens.focus_distance / pinhole.direction.dot(self.forward));
+		let (x, y) = lens.sample_aperture(u1, u2);
+		let origin = self.position + self.right * x + self.up * y;
+		Ray::new(origin, (focus - origin).normalize())
+	}
+
+	fn update_basis(&mut self)
+	{
+		let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
+		let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
+
+		self.forward = Vector3::new(cos_pitch * sin_yaw, sin_pitch, -cos_pitch * cos_yaw);
+		self.right = self.forward.cross(Vector3::new(0.0, 1.0, 0.0)).normalize();
+		self.up = self.right.cross(self.forward);
+
+		let theta : Rad<f32> = self.vertical_fov.into();
+		self.half_height = (theta.0 * 0.5).tan();
+		self.half_width = self.aspect_ratio * self.half_height;
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use cgmath::Vector4;
+
+	use super::*;
+
+	fn assert_near(actual : Vector3<f32>, expected : Vector3<f32>, tolerance : f32)
+	{
+		assert!((actual - expected).magnitude() < tolerance, "{:?} is not {:?}", actual, expected);
+	}
+
+	// Looking somewhere off the axes, so mixing up right, up and forward shows.
+	fn camera(model : ProjectionModel) -> Camera
+	{
+		let mut camera = Camera::new(Vector3::new(1.0, -2.0, 3.0), Rad(0.7), Rad(0.3), Deg(50.0), 1.5);
+		camera.set_model(model);
+		camera
+	}
+
+	fn lens(blade_count : u32) -> ThinLens
+	{
+		ThinLens { aperture_radius : 0.25, focus_distance : 4.0, blade_count : blade_count, blade_rotation : Rad(0.4) }
+	}
+
+	// Uniform numbers on a grid over [0, 1), including the edges of the blades.
+	fn unit_grid() -> impl Iterator<Item = (f32, f32)>
+	{
+		(0..=24).flat_map(|i| (0..=24).map(move |j| ((i as f32 / 24.0).min(0.9999), (j as f32 / 24.0).min(0.9999))))
+	}
+
+	fn check_rays_meet_on_the_focus_plane(blade_count : u32)
+	{
+		let lens = lens(blade_count);
+		let camera = camera(ProjectionModel::ThinLens(lens));
+		for &(s, t) in &[(0.5, 0.5), (0.1, 0.8), (0.95, 0.05)]
+		{
+			let pinhole = camera.get_ray(s, t);
+			let on_focus_plane = |ray : &Ray|
+			{
+				let distance = (lens.focus_distance - (ray.origin - camera.position()).dot(camera.forward())) / ray.direction.dot(camera.forward());
+				ray.origin + ray.direction * distance
+			};
+			let focus = on_focus_plane(&pinhole);
+			for (u1, u2) in unit_grid()
+			{
+				let ray = camera.sample_ray(s, t, u1, u2);
+				assert!((ray.direction.magnitude() - 1.0).abs() < 1.0e-5);
+				assert!((ray.origin - camera.position()).dot(camera.forward()).abs() < 1.0e-5, "the ray doesn't leave the lens");
+				assert_near(on_focus_plane(&ray), focus, 1.0e-4);
+			}
+		}
+	}
+
+	#[test]
+	fn round_lens_rays_meet_on_the_focus_plane()
+	{
+		check_rays_meet_on_the_focus_plane(0);
+	}
+
+	#[test]
+	fn bladed_lens_rays_meet_on_the_focus_plane()
+	{
+		check_rays_meet_on_the_focus_plane(6);
+	}
+
+	fn check_aperture_inside_polygon(blade_count : u32)
+	{
+		let lens = lens(blade_count);
+		let blades = blade_count as f32;
+		// inside when no farther along the middle of any side than the side is from the center.
+		let apothem = lens.aperture_radius * (std::f32::consts::PI / blades).cos();
+		let mut farthest : f32 = 0.0;
+		for (u1, u2) in unit_grid()
+		{
+			let (x, y) = lens.sample_aperture(u1, u2);
+			farthest = farthest.max((x * x + y * y).sqrt());
+			for side in 0..blade_count
+			{
+				let angle = lens.blade_rotation.0 + 2.0 * std::f32::consts::PI * (side as f32 + 0.5) / blades;
+				assert!(x * angle.cos() + y * angle.sin() <= apothem + 1.0e-6, "({}, {}) from {} {} is outside side {}", x, y, u1, u2, side);
+			}
+		}
+		// and the grid reaches out to the corners.
+		assert!(farthest > 0.99 * lens.aperture_radius, "{}", farthest);
+	}
+
+	#[test]
+	fn triangular_aperture_samples_stay_inside()
+	{
+		check_aperture_inside_polygon(3);
+	}
+
+	#[test]
+	fn pentagonal_aperture_samples_stay_inside()
+	{
+		check_aperture_inside_polygon(5);
+	}
+
+	#[test]
+	fn octagonal_aperture_samples_stay_inside()
+	{
+		check_aperture_inside_polygon(8);
+	}
+
+	#[test]
+	fn orthographic_rays_are_parallel_and_spread_over_the_view()
+	{
+		let half_height = 2.0;
+		let camera = camera(ProjectionModel::Orthographic { half_height : half_height });
+		let up = camera.right().cross(camera.forward());
+		let half_width = half_height * 1.5;
+
+		for &(s, t) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0), (0.5, 0.5), (0.25, 0.75)]
+		{
+			let ray = camera.get_ray(s, t);
+			assert_near(ray.direction, camera.forward(), 1.0e-6);
+			let expected = camera.position() + camera.right() * ((2.0 * s - 1.0) * half_width) + up * ((2.0 * t - 1.0) * half_height);
+			assert_near(ray.origin, expected, 1.0e-5);
+		}
+	}
+
+	#[test]
+	fn equirectangular_center_is_forward_edges_are_backward_and_top_is_up()
+	{
+		// the panorama stays level, so pitch doesn't tilt it.
+		let camera = camera(ProjectionModel::Equirectangular);
+		let level_forward = Vector3::new(0.7f32.sin(), 0.0, -0.7f32.cos());
+		let direction = |s : f32, t : f32| camera.get_ray(s, t).direction;
+
+		assert_near(direction(0.5, 0.5), level_forward, 1.0e-5);
+		assert_near(direction(0.0, 0.5), -level_forward, 1.0e-5);
+		assert_near(direction(1.0, 0.5), -level_forward, 1.0e-5);
+		assert_near(direction(0.75, 0.5), camera.right(), 1.0e-5);
+		assert_near(direction(0.25, 0.5), -camera.right(), 1.0e-5);
+		assert_near(direction(0.5, 1.0), Vector3::unit_y(), 1.0e-5);
+		assert_near(direction(0.2, 0.0), -Vector3::unit_y(), 1.0e-5);
+	}
+
+	fn check_depth_range(model : ProjectionModel, near : f32, far : f32)
+	{
+		let mut camera = camera(model);
+		camera.set_clip_planes(near, far);
+		let view_projection = camera.view_projection_matrix();
+		let up = camera.right().cross(camera.forward());
+		let depth = |distance : f32, x : f32, y : f32|
+		{
+			// depth only depends on the distance along forward, not on how far off to the side the point is.
+			let point = camera.position() + camera.right() * x + up * y + camera.forward() * distance;
+			let clip : Vector4<f32> = view_projection * point.extend(1.0);
+			clip.z / clip.w
+		};
+
+		for &(x, y) in &[(0.0, 0.0), (0.003, -0.002)]
+		{
+			assert!(depth(near, x, y).abs() < 1.0e-4, "{:?} near depth {}", model, depth(near, x, y));
+			assert!((depth(far, x, y) - 1.0).abs() < 1.0e-4, "{:?} far depth {}", model, depth(far, x, y));
+			assert!(depth(0.5 * (near + far), x, y) > 0.0 && depth(0.5 * (near + far), x, y) < 1.0);
+		}
+	}
+
+	#[test]
+	fn pinhole_projection_maps_near_and_far_to_zero_and_one()
+	{
+		check_depth_range(ProjectionModel::Pinhole, 0.1, 100.0);
+		let defaults = camera(ProjectionModel::Pinhole);
+		check_depth_range(ProjectionModel::Pinhole, defaults.near_plane(), defaults.far_plane());
+	}
+
+	#[test]
+	fn orthographic_projection_maps_near_and_far_to_zero_and_one()
+	{
+		check_depth_range(ProjectionModel::Orthographic { half_height : 2.0 }, 0.5, 20.0);
+	}
+}
@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+
+use cgmath::{ Deg, Rad, Vector3 };
+use cgmath::{ InnerSpace, Zero };
+
+use crate::camera::{ Camera, ProjectionModel };
+use crate::input::{ InputEvent, Key, MouseButton };
+
+// Fly-through controls:
+//     W / S         forward / back along the view direction
+//     A / D         strafe left / right
+//     E / Q         up / down along world +y
+//     Shift         move faster
+//     right mouse   hold and drag to look around
+//     mouse wheel   zoom by narrowing or widening the field of view
+pub struct FlyCameraController
+{
+	pub move_speed : f32,          // world units per second
+	pub fast_multiplier : f32,     // applied to move_speed while shift is held
+	pub look_sensitivity : f32,    // radians per pixel of mouse movement
+	pub zoom_step : Deg<f32>,      // field of view change per wheel notch
+
+	keys_held : HashSet<Key>,
+	looking : bool,
+	last_mouse_position : Option<(i32, i32)>,
+	pending_yaw : f32,
+	pending_pitch : f32,
+	pending_zoom : f32,
+}
+
+const MIN_FOV : Deg<f32> = Deg(10.0);
+const MAX_FOV : Deg<f32> = Deg(120.0);
+
+impl FlyCameraController
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			move_speed : 1.0,
+			fast_multiplier : 4.0,
+			look_sensitivity : 0.003,
+			zoom_step : Deg(2.0),
+			keys_held : HashSet::new(),
+			looking : false,
+			last_mouse_position : None,
+			pending_yaw : 0.0,
+			pending_pitch : 0.0,
+			pending_zoom : 0.0,
+		}
+	}
+
+	pub fn handle_event(&mut self, event : &InputEvent)
+	{
+		match *event
+		{
+			InputEvent::KeyDown(key) => { self.keys_held.insert(key); }
+			InputEvent::KeyUp(key) => { self.keys_held.remove(&key); }
+			InputEvent::MouseButtonDown(MouseButton::Right) =>
+			{
+				self.looking = true;
+				self.last_mouse_position = None; // the first move after pressing only sets the anchor
+			}
+			InputEvent::MouseButtonUp(MouseButton::Right) => self.looking = false,
+			InputEvent::MouseMove { x, y } =>
+			{
+				if self.looking
+				{
+					if let Some((last_x, last_y)) = self.last_mouse_position
+					{
+						self.pending_yaw += (x - last_x) as f32 * self.look_sensitivity;
+						self.pending_pitch -= (y - last_y) as f32 * self.look_sensitivity; // window y grows downwards
+					}
+				}
+				self.last_mouse_position = Some((x, y));
+			}
+			InputEvent::MouseWheel { delta } => self.pending_zoom += delta,
+			InputEvent::Focus(false) =>
+			{
+				// key up events go to whichever window has focus now, so forget what was held.
+				self.keys_held.clear();
+				self.looking = false;
+			}
+			_ => (),
+		}
+	}
+
+	pub fn is_key_held(&self, key : Key) -> bool
+	{
+		self.keys_held.contains(&key)
+	}
+
+	// Applies the input gathered since the last update. Returns true when the camera moved.
+	pub fn update(&mut self, camera : &mut Camera, delta_seconds : f32) -> bool
+	{
+		let before = *camera;
+
+		if self.pending_yaw != 0.0 || self.pending_pitch != 0.0
+		{
+			camera.set_orientation(camera.yaw() + Rad(self.pending_yaw), camera.pitch() + Rad(self.pending_pitch));
+			self.pending_yaw = 0.0;
+			self.pending_pitch = 0.0;
+		}
+
+		if self.pending_zoom != 0.0
+		{
+			let before_fov = camera.vertical_fov();
+			let fov = before_fov - self.zoom_step * self.pending_zoom; // wheel away from the user zooms in
+			camera.set_vertical_fov(Deg(fov.0.clamp(MIN_FOV.0, MAX_FOV.0)));
+			self.pending_zoom = 0.0;
+
+			// orthographic cameras zoom by what they see shrinking the way it would in perspective.
+			if let ProjectionModel::Orthographic { half_height } = camera.model()
+			{
+				let tangent = |fov : Deg<f32>| (Rad::from(fov).0 * 0.5).tan();
+				let half_height = half_height * tangent(camera.vertical_fov()) / tangent(before_fov);
+				camera.set_model(ProjectionModel::Orthographic { half_height : half_height });
+			}
+		}
+
+		let axis = |positive : Key, negative : Key| -> f32
+		{
+			(self.is_key_held(positive) as i32 - self.is_key_held(negative) as i32) as f32
+		};
+
+		let direction = camera.forward() * axis(Key::W, Key::S)
+			+ camera.right() * axis(Key::D, Key::A)
+			+ Vector3::new(0.0, 1.0, 0.0) * axis(Key::E, Key::Q);
+
+		if !direction.is_zero()
+		{
+			let speed = if self.is_key_held(Key::Shift) { self.move_speed * self.fast_multiplier } else { self.move_speed };
+			camera.set_position(camera.position() + direction.normalize() * (speed * delta_seconds));
+		}
+
+		*camera != before
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use cgmath::{ Deg, Rad, Vector3 };
+	use cgmath::InnerSpace;
+
+	use super::*;
+
+	// At the origin looking down -z, so forward is -z and right is +x.
+	fn camera() -> Camera
+	{
+		Camera::new(Vector3::new(0.0, 0.0, 0.0), Rad(0.0), Rad(0.0), Deg(45.0), 1.0)
+	}
+
+	fn feed(controller : &mut FlyCameraController, events : &[InputEvent])
+	{
+		for event in events
+		{
+			controller.handle_event(event);
+		}
+	}
+
+	fn assert_near(actual : Vector3<f32>, expected : Vector3<f32>)
+	{
+		assert!((actual - expected).magnitude() < 1.0e-5, "{:?}, expected {:?}", actual, expected);
+	}
+
+	#[test]
+	fn held_keys_move_along_the_view_and_stop_on_release()
+	{
+		let (mut controller, mut camera) = (FlyCameraController::new(), camera());
+		feed(&mut controller, &[InputEvent::KeyDown(Key::W)]);
+		assert!(controller.update(&mut camera, 0.5));
+		assert_near(camera.position(), Vector3::new(0.0, 0.0, -0.5));
+
+		feed(&mut controller, &[InputEvent::KeyUp(Key::W), InputEvent::KeyDown(Key::D)]);
+		assert!(controller.update(&mut camera, 0.25));
+		assert_near(camera.position(), Vector3::new(0.25, 0.0, -0.5));
+
+		feed(&mut controller, &[InputEvent::KeyUp(Key::D)]);
+		assert!(!controller.update(&mut camera, 1.0));
+		assert_near(camera.position(), Vector3::new(0.25, 0.0, -0.5));
+	}
+
+	#[test]
+	fn diagonal_moves_are_as_fast_and_shift_multiplies_speed()
+	{
+		let (mut controller, mut camera) = (FlyCameraController::new(), camera());
+		feed(&mut controller, &[InputEvent::KeyDown(Key::S), InputEvent::KeyDown(Key::A)]);
+		controller.update(&mut camera, 1.0);
+		assert_near(camera.position(), Vector3::new(-1.0, 0.0, 1.0).normalize());
+
+		let (mut controller, mut camera) = (FlyCameraController::new(), self::camera());
+		feed(&mut controller, &[InputEvent::KeyDown(Key::Shift), InputEvent::KeyDown(Key::E)]);
+		controller.update(&mut camera, 0.5);
+		assert_near(camera.position(), Vector3::new(0.0, 0.5 * controller.fast_multiplier, 0.0));
+	}
+
+	#[test]
+	fn up_and_down_follow_world_y_whatever_the_pitch()
+	{
+		let mut camera = camera();
+		camera.set_orientation(Rad(0.3), Rad(-0.7));
+		let mut controller = FlyCameraController::new();
+		feed(&mut controller, &[InputEvent::KeyDown(Key::Q)]);
+		controller.update(&mut camera, 2.0);
+		assert_near(camera.position(), Vector3::new(0.0, -2.0, 0.0));
+	}
+
+	#[test]
+	fn dragging_with_the_right_button_turns_the_camera()
+	{
+		let (mut controller, mut camera) = (FlyCameraController::new(), camera());
+		let sensitivity = controller.look_sensitivity;
+
+		// moving without the button, and the first move after pressing it, don't turn.
+		feed(&mut controller, &[InputEvent::MouseMove { x : 0, y : 0 }, InputEvent::MouseMove { x : 50, y : 50 }]);
+		assert!(!controller.update(&mut camera, 0.0));
+		feed(&mut controller, &[InputEvent::MouseButtonDown(MouseButton::Right), InputEvent::MouseMove { x : 100, y : 100 }]);
+		assert!(!controller.update(&mut camera, 0.0));
+
+		// right turns toward +x, up (window y shrinking) looks up.
+		feed(&mut controller, &[InputEvent::MouseMove { x : 110, y : 90 }, InputEvent::MouseMove { x : 120, y : 80 }]);
+		assert!(controller.update(&mut camera, 0.0));
+		assert!((camera.yaw().0 - 20.0 * sensitivity).abs() < 1.0e-6, "yaw {:?}", camera.yaw());
+		assert!((camera.pitch().0 - 20.0 * sensitivity).abs() < 1.0e-6, "pitch {:?}", camera.pitch());
+		assert!(camera.forward().x > 0.0 && camera.forward().y > 0.0);
+
+		feed(&mut controller, &[InputEvent::MouseButtonUp(MouseButton::Right), InputEvent::MouseMove { x : 500, y : 500 }]);
+		assert!(!controller.update(&mut camera, 0.0));
+	}
+
+	#[test]
+	fn pitch_stops_short_of_straight_up()
+	{
+		let (mut controller, mut camera) = (FlyCameraController::new(), camera());
+		feed(&mut controller, &[InputEvent::MouseButtonDown(MouseButton::Right), InputEvent::MouseMove { x : 0, y : 0 }, InputEvent::MouseMove { x : 0, y : -100_000 }]);
+		controller.update(&mut camera, 0.0);
+		assert!((Deg::from(camera.pitch()).0 - 89.0).abs() < 1.0e-3, "pitch {:?}", Deg::from(camera.pitch()));
+	}
+
+	#[test]
+	fn wheel_zooms_within_limits()
+	{
+		let (mut controller, mut camera) = (FlyCameraController::new(), camera());
+		feed(&mut controller, &[InputEvent::MouseWheel { delta : 1.0 }, InputEvent::MouseWheel { delta : 0.5 }]);
+		assert!(controller.update(&mut camera, 0.0));
+		assert!((camera.vertical_fov().0 - 42.0).abs() < 1.0e-4, "{:?}", camera.vertical_fov());
+
+		feed(&mut controller, &[InputEvent::MouseWheel { delta : 1000.0 }]);
+		controller.update(&mut camera, 0.0);
+		assert_eq!(camera.vertical_fov(), MIN_FOV);
+		feed(&mut controller, &[InputEvent::MouseWheel { delta : -1000.0 }]);
+		controller.update(&mut camera, 0.0);
+		assert_eq!(camera.vertical_fov(), MAX_FOV);
+	}
+
+	#[test]
+	fn losing_focus_releases_keys_and_the_mouse()
+	{
+		let (mut controller, mut camera) = (FlyCameraController::new(), camera());
+		feed(&mut controller, &[InputEvent::KeyDown(Key::W), InputEvent::MouseButtonDown(MouseButton::Right), InputEvent::Focus(false)]);
+		assert!(!controller.is_key_held(Key::W));
+		feed(&mut controller, &[InputEvent::MouseMove { x : 0, y : 0 }, InputEvent::MouseMove { x : 40, y : 0 }]);
+		assert!(!controller.update(&mut camera, 1.0));
+	}
+}
@@ -0,0 +1,336 @@
+use std::path::PathBuf;
+
+use cgmath::{ InnerSpace, Rad, Vector3 };
+
+use crate::camera::{ Camera, ProjectionModel, ThinLens };
+use crate::cpu_raytracer::Integrator;
+use crate::exr_image::ExrSettings;
+use crate::film::{ Filter, FilterKind };
+use crate::path_tracer::PathTracerSettings;
+use crate::reference_scenes::ReferenceScene;
+use crate::sampler::SamplerKind;
+use crate::tone_mapping::{ DisplayTransform, Encoding, ToneCurve };
+use crate::whitted::WhittedSettings;
+use crate::render_backend::BackendKind;
+
+pub const USAGE : &str = "\
+usage: rust_raytracer [options]
+
+options:
+    --backend <name>    renderer to use, dx12 or software (default dx12 when compiled in,
+                        headless renders only support software)
+    --headless          render without a window and write the image to disk
+    --frames <count>    exit after rendering this many frames
+    --input-script <path>
+                        play back input events from a file on the null platform (software backend only,
+                        the default with a script)
+    --scene <path>      OBJ, glTF or GLB file to render instead of the sample triangle
+    --reference-scene <name>
+                        render a built in scene instead: cornell, cornell-empty, cornell-specular, furnace,
+                        floor or lights
+    --integrator <name> software shading, headlight, whitted or path (default headlight)
+    --max-depth <count> bounces, for whitted (default 5) and path (default 8)
+    --projection <model>
+                        pinhole, thin-lens, orthographic or equirectangular (default the scene camera's)
+    --aperture <radius> lens radius in world units, picks thin-lens without --projection
+    --focus-distance <distance>
+                        distance to the plane in focus (default the center of the scene)
+    --blades <count>    aperture blades, 3 or more for polygonal bokeh (default round)
+    --ortho-height <height>
+                        half the height an orthographic camera sees (default what the pinhole
+                        sees at the center of the scene)
+    --light-sampling <strategy>
+                        bsdf, light or mis for the path integrator (default mis)
+    --environment <path>
+                        equirectangular Radiance .hdr image that lights the scene from every direction
+                        and shows behind it, instead of the background color (software backend only)
+    --environment-rotation <degrees>
+                        turn the environment around the vertical axis (default 0)
+    --environment-intensity <scale>
+                        multiply the environment's radiance (default 1)
+    --samples <count>   samples per pixel for headless renders (default 1)
+    --sampler <name>    random, stratified, halton, sobol or blue-noise (default sobol)
+    --filter <name>     pixel reconstruction filter, box, tent, gaussian, mitchell or
+                        blackman-harris (default box)
+    --filter-radius <pixels>
+                        how far from a pixel center the filter reaches (default 0.5 for box,
+                        1 for tent, 1.5 for gaussian and blackman-harris, 2 for mitchell)
+    --threads <count>   render threads for headless renders (default one per core)
+    --target-samples <count>
+                        samples per pixel the software backend accumulates in a window before
+                        it idles, starting over when the camera moves (default 256)
+    --exposure <stops>  brighten or darken before tone mapping (default 0)
+    --tone-map <curve>  none, reinhard, hable, aces or agx (default none, which clips)
+    --white-balance <kelvin>
+                        color temperature of the light that should come out white (default none)
+    --gamma <value>     encode for a pure power law display instead of sRGB
+    --output <path>     headless output image, .png, .ppm, .hdr or .exr (default render.ppm),
+                        EXR files also get albedo, normal, depth and object_id layers
+    --exr-type <type>   EXR sample type for color layers, half or float (default half)
+    --exr-compression <method>
+                        none, rle, zips, zip, piz or pxr24 (default zip)
+    --exr-tile-size <pixels>
+                        write square tiles instead of scanlines
+    --bvh-benchmark <triangles>
+                        time BVH traversal against brute force on random triangles and exit
+    --exr-info <path>   print the layers and channel statistics of an EXR file and exit
+    --help              print this message";
+
+#[derive(Clone, Debug)]
+pub struct Options
+{
+	pub backend : Option<BackendKind>, // None picks the default for the mode
+	pub headless : bool,
+	pub frames : Option<u32>,
+	pub input_script : Option<PathBuf>,
+	pub scene : Option<PathBuf>,
+	pub reference_scene : Option<ReferenceScene>,
+	pub integrator : IntegratorKind,
+	pub max_depth : Option<u32>,
+	pub projection : Option<ProjectionKind>,
+	pub aperture : Option<f32>,
+	pub focus_distance : Option<f32>,
+	pub blades : u32,
+	pub ortho_height : Option<f32>,
+	pub path_tracer : PathTracerSettings,
+	pub environment : Option<PathBuf>,
+	pub environment_rotation : f32, // degrees
+	pub environment_intensity : f32,
+	pub samples : u32,
+	pub sampler : SamplerKind,
+	pub filter : FilterKind,
+	pub filter_radius : Option<f32>,
+	pub threads : Option<u32>,
+	pub target_samples : u32,
+	pub exposure : f32,
+	pub tone_curve : ToneCurve,
+	pub white_balance : Option<f32>,
+	pub gamma : Option<f32>,
+	pub output : PathBuf,
+	pub exr : ExrSettings,
+	pub bvh_benchmark : Option<u32>, // triangle count
+	pub exr_info : Option<PathBuf>,
+	pub help : bool,
+}
+
+impl Default for Options
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			backend : None,
+			headless : false,
+			frames : None,
+			input_script : None,
+			scene : None,
+			reference_scene : None,
+			integrator : IntegratorKind::Headlight,
+			max_depth : None,
+			projection : None,
+			aperture : None,
+			focus_distance : None,
+			blades : 0,
+			ortho_height : None,
+			path_tracer : PathTracerSettings::default(),
+			environment : None,
+			environment_rotation : 0.0,
+			environment_intensity : 1.0,
+			samples : 1,
+			sampler : SamplerKind::Sobol,
+			filter : FilterKind::Box,
+			filter_radius : None,
+			threads : None,
+			target_samples : 256,
+			exposure : 0.0,
+			tone_curve : ToneCurve::Clamp,
+			white_balance : None,
+			gamma : None,
+			output : PathBuf::from("render.ppm"),
+			exr : ExrSettings::default(),
+			bvh_benchmark : None,
+			exr_info : None,
+			help : false,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IntegratorKind
+{
+	Headlight,
+	Whitted,
+	PathTracer,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProjectionKind
+{
+	Pinhole,
+	ThinLens,
+	Orthographic,
+	Equirectangular,
+}
+
+impl Options
+{
+	// The integrator picked on the command line with its settings.
+	pub fn integrator(&self) -> Integrator
+	{
+		match self.integrator
+		{
+			IntegratorKind::Headlight => Integrator::Headlight,
+			IntegratorKind::Whitted =>
+			{
+				let defaults = WhittedSettings::default();
+				Integrator::Whitted(WhittedSettings { max_depth : self.max_depth.unwrap_or(defaults.max_depth) })
+			}
+			IntegratorKind::PathTracer =>
+			{
+				let max_depth = self.max_depth.unwrap_or(self.path_tracer.max_depth);
+				Integrator::PathTracer(PathTracerSettings { max_depth : max_depth, ..self.path_tracer })
+			}
+		}
+	}
+
+	// The reconstruction filter picked on the command line, at its usual radius unless given one.
+	pub fn filter(&self) -> Filter
+	{
+		Filter { kind : self.filter, radius : self.filter_radius.unwrap_or_else(|| self.filter.default_radius()) }
+	}
+
+	// How images are brought to the display when they are shown or saved as PNG or PPM.
+	pub fn display_transform(&self) -> DisplayTransform
+	{
+		let encoding = self.gamma.map_or(Encoding::Srgb, Encoding::Gamma);
+		DisplayTransform::new(self.exposure, self.tone_curve, self.white_balance, encoding)
+	}
+
+	// The projection model picked on the command line for camera, or None to keep its own. Distances left out
+	// default to the plane through target.
+	pub fn projection_model(&self, camera : &Camera, target : Vector3<f32>) -> Option<ProjectionModel>
+	{
+		let kind = self.projection.or_else(|| self.aperture.map(|_| ProjectionKind::ThinLens))?;
+		let target_distance = (target - camera.position()).dot(camera.forward()).max(camera.near_plane());
+		Some(match kind
+		{
+			ProjectionKind::Pinhole => ProjectionModel::Pinhole,
+			ProjectionKind::ThinLens => ProjectionModel::ThinLens(ThinLens
+			{
+				aperture_radius : self.aperture.unwrap_or(0.0),
+				focus_distance : self.focus_distance.unwrap_or(target_distance),
+				blade_count : self.blades,
+				blade_rotation : Rad(0.0),
+			}),
+			ProjectionKind::Orthographic =>
+			{
+				let half_fov : Rad<f32> = (camera.vertical_fov() * 0.5).into();
+				ProjectionModel::Orthographic { half_height : self.ortho_height.unwrap_or(target_distance * half_fov.0.tan()) }
+			}
+			ProjectionKind::Equirectangular => ProjectionModel::Equirectangular,
+		})
+	}
+}
+
+// Parses the arguments that follow the program name.
+pub fn parse<I>(args : I) -> Result<Options, String>
+	where I : IntoIterator<Item = String>
+{
+	let mut options = Options::default();
+	let mut args = args.into_iter();
+
+	while let Some(arg) = args.next()
+	{
+		match arg.as_str()
+		{
+			"--backend" => options.backend = Some(next_value(&mut args, &arg)?.parse::<BackendKind>()?),
+			"--headless" => options.headless = true,
+			"--frames" => options.frames = Some(positive_integer(&next_value(&mut args, &arg)?, &arg)?),
+			"--input-script" => options.input_script = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+			"--scene" => options.scene = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+			"--reference-scene" => options.reference_scene = Some(next_value(&mut args, &arg)?.parse()?),
+			"--integrator" => options.integrator = match next_value(&mut args, &arg)?.as_str()
+			{
+				"headlight" => IntegratorKind::Headlight,
+				"whitted" => IntegratorKind::Whitted,
+				"path" => IntegratorKind::PathTracer,
+				other => return Err(format!("unknown integrator '{}', expected headlight, whitted or path", other)),
+			},
+			"--max-depth" => options.max_depth = Some(positive_integer(&next_value(&mut args, &arg)?, &arg)?),
+			"--projection" => options.projection = Some(match next_value(&mut args, &arg)?.as_str()
+			{
+				"pinhole" => ProjectionKind::Pinhole,
+				"thin-lens" => ProjectionKind::ThinLens,
+				"orthographic" => ProjectionKind::Orthographic,
+				"equirectangular" => ProjectionKind::Equirectangular,
+				other => return Err(format!("unknown projection '{}', expected pinhole, thin-lens, orthographic or equirectangular", other)),
+			}),
+			"--aperture" => options.aperture = Some(non_negative_number(&next_value(&mut args, &arg)?, &arg)?),
+			"--focus-distance" => options.focus_distance = Some(positive_number(&next_value(&mut args, &arg)?, &arg)?),
+			"--blades" => options.blades = positive_integer(&next_value(&mut args, &arg)?, &arg)?,
+			"--ortho-height" => options.ortho_height = Some(positive_number(&next_value(&mut args, &arg)?, &arg)?),
+			"--light-sampling" => options.path_tracer.light_sampling = next_value(&mut args, &arg)?.parse()?,
+			"--environment" => options.environment = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+			"--environment-rotation" => options.environment_rotation = number(&next_value(&mut args, &arg)?, &arg)?,
+			"--environment-intensity" => options.environment_intensity = non_negative_number(&next_value(&mut args, &arg)?, &arg)?,
+			"--samples" => options.samples = positive_integer(&next_value(&mut args, &arg)?, &arg)?,
+			"--sampler" => options.sampler = next_value(&mut args, &arg)?.parse()?,
+			"--filter" => options.filter = next_value(&mut args, &arg)?.parse()?,
+			"--filter-radius" => options.filter_radius = Some(positive_number(&next_value(&mut args, &arg)?, &arg)?),
+			"--threads" => options.threads = Some(positive_integer(&next_value(&mut args, &arg)?, &arg)?),
+			"--target-samples" => options.target_samples = positive_integer(&next_value(&mut args, &arg)?, &arg)?,
+			"--exposure" => options.exposure = number(&next_value(&mut args, &arg)?, &arg)?,
+			"--tone-map" => options.tone_curve = next_value(&mut args, &arg)?.parse()?,
+			"--white-balance" => options.white_balance = Some(positive_number(&next_value(&mut args, &arg)?, &arg)?),
+			"--gamma" => options.gamma = Some(positive_number(&next_value(&mut args, &arg)?, &arg)?),
+			"--output" => options.output = PathBuf::from(next_value(&mut args, &arg)?),
+			"--exr-type" => options.exr.sample_type = next_value(&mut args, &arg)?.parse()?,
+			"--exr-compression" => options.exr.compression = next_value(&mut args, &arg)?.parse()?,
+			"--exr-tile-size" => options.exr.tile_size = Some(positive_integer(&next_value(&mut args, &arg)?, &arg)?),
+			"--bvh-benchmark" => options.bvh_benchmark = Some(positive_integer(&next_value(&mut args, &arg)?, &arg)?),
+			"--exr-info" => options.exr_info = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+			"--help" | "-h" => options.help = true,
+			_ => return Err(format!("unknown argument '{}'", arg)),
+		}
+	}
+
+	Ok(options)
+}
+
+fn next_value<I>(args : &mut I, flag : &str) -> Result<String, String>
+	where I : Iterator<Item = String>
+{
+	args.next().ok_or_else(|| format!("{} expects a value", flag))
+}
+
+fn positive_integer(value : &str, flag : &str) -> Result<u32, String>
+{
+	value.parse::<u32>()
+		.ok()
+		.filter(|&count| count > 0)
+		.ok_or_else(|| format!("{} expects a positive integer, got '{}'", flag, value))
+}
+
+fn number(value : &str, flag : &str) -> Result<f32, String>
+{
+	value.parse::<f32>()
+		.ok()
+		.filter(|number| number.is_finite())
+		.ok_or_else(|| format!("{} expects a number, got '{}'", flag, value))
+}
+
+fn positive_number(value : &str, flag : &str) -> Result<f32, String>
+{
+	value.parse::<f32>()
+		.ok()
+		.filter(|&number| number > 0.0 && number.is_finite())
+		.ok_or_else(|| format!("{} expects a positive number, got '{}'", flag, value))
+}
+
+fn non_negative_number(value : &str, flag : &str) -> Result<f32, String>
+{
+	value.parse::<f32>()
+		.ok()
+		.filter(|&number| number >= 0.0 && number.is_finite())
+		.ok_or_else(|| format!("{} expects a number of at least zero, got '{}'", flag, value))
+}
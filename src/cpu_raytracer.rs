@@ -0,0 +1,312 @@
+use std::ops::Range;
+
+use cgmath::InnerSpace;
+use cgmath::Vector4;
+
+use crate::camera::Camera;
+use crate::film::{ Film, FilmTile, Filter };
+use crate::framebuffer::AovBuffers;
+use crate::path_tracer::{ self, PathTracerSettings };
+use crate::ray::{ Ray, HitRecord, Hittable };
+use crate::sampler::SamplerKind;
+use crate::scene::Scene;
+use crate::tile_scheduler::{ self, Tile, TILE_SIZE };
+use crate::whitted::{ self, WhittedSettings };
+
+// offset applied to t_min so secondary rays do not re-hit the surface they start on.
+pub const RAY_EPSILON : f32 = 1.0e-4;
+
+// How the color of a camera ray is computed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Integrator
+{
+	Headlight,                      // quick and noise free, see shade
+	Whitted(WhittedSettings),       // deterministic preview with shadows, mirrors and glass
+	PathTracer(PathTracerSettings), // physically based, needs lights, emissive materials or a bright background to show anything
+}
+
+// Shades what a ray hit. Surfaces are lit by a headlight at the camera, so unlit scenes are still readable.
+fn shade(ray : &Ray, scene : &Scene, hit : Option<&HitRecord>) -> Vector4<f32>
+{
+	match hit
+	{
+		None => scene.background_radiance(ray.direction).extend(1.0),
+		Some(hit) =>
+		{
+			let color = scene.surface(hit).color;
+			let facing = hit.normal.dot(-ray.direction).max(0.0);
+			let shade = 0.2 + 0.8 * facing;
+			Vector4::new(color.x * shade, color.y * shade, color.z * shade, color.w)
+		}
+	}
+}
+
+fn write_aovs(aovs : &mut AovBuffers, x : u32, y : u32, scene : &Scene, hit : Option<&HitRecord>)
+{
+	match hit
+	{
+		None =>
+		{
+			aovs.albedo.set(x, y, Vector4::new(0.0, 0.0, 0.0, 0.0));
+			aovs.normal.set(x, y, Vector4::new(0.0, 0.0, 0.0, 0.0));
+			aovs.depth.set(x, y, Vector4::new(f32::INFINITY, 0.0, 0.0, 0.0));
+			aovs.object_id.set(x, y, Vector4::new(0.0, 0.0, 0.0, 0.0));
+		}
+		Some(hit) =>
+		{
+			aovs.albedo.set(x, y, scene.surface(hit).color);
+			aovs.normal.set(x, y, hit.normal.extend(0.0));
+			aovs.depth.set(x, y, Vector4::new(hit.t, 0.0, 0.0, 0.0));
+			aovs.object_id.set(x, y, Vector4::new(hit.object_id as f32, 0.0, 0.0, 0.0));
+		}
+	}
+}
+
+// How the software backend renders a frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TracerSettings
+{
+	pub integrator : Integrator,
+	pub sampler : SamplerKind,
+	pub samples_per_pixel : u32, // samples the sampler lays its pattern out for
+	pub thread_count : usize,
+}
+
+// Adds the rays with the given sample indices per pixel to the film, rendering tiles of the image on
+// settings.thread_count threads. Every sample takes its position in the pixel, on the lens and along the path from
+// the sampler, which only depends on the pixel and the sample index, and finished tiles are merged into the film
+// in order, so the result is the same on every run, for any thread count and however the samples are split
+// between frames. The AOVs come from a ray through the pixel center and are recorded by the frame with sample 0,
+// later frames leave them alone.
+pub fn render(scene : &Scene, camera : &Camera, film : &mut Film, mut aovs : Option<&mut AovBuffers>, settings : &TracerSettings, samples : Range<u32>)
+{
+	let samples = samples.start..samples.end.max(samples.start + 1);
+	let frame = Frame
+	{
+		scene : scene,
+		camera : camera,
+		settings : settings,
+		filter : film.filter(),
+		record_aovs : aovs.is_some() && samples.start == 0,
+		samples : samples,
+		width : film.width(),
+		height : film.height(),
+	};
+	let tiles = tile_scheduler::tiles(frame.width, frame.height, TILE_SIZE);
+	let columns = frame.width.div_ceil(TILE_SIZE);
+	let mut finished : Vec<Option<FilmTile>> = tiles.iter().map(|_| None).collect();
+	let mut next_merge = 0;
+	tile_scheduler::run(tiles, settings.thread_count, |tile| frame.render_tile(tile), |tile, rendered|
+	{
+		if let (Some(aovs), Some(tile_aovs)) = (aovs.as_deref_mut(), rendered.aovs.as_ref())
+		{
+			aovs.write_tile(tile.x, tile.y, tile_aovs);
+		}
+		finished[((tile.y / TILE_SIZE) * columns + tile.x / TILE_SIZE) as usize] = Some(rendered.film);
+		while let Some(film_tile) = finished.get_mut(next_merge).and_then(Option::take)
+		{
+			film.merge_tile(&film_tile);
+			next_merge += 1;
+		}
+	});
+}
+
+// What every tile of a frame is rendered with.
+struct Frame<'a>
+{
+	scene : &'a Scene,
+	camera : &'a Camera,
+	settings : &'a TracerSettings,
+	filter : Filter,
+	samples : Range<u32>,
+	width : u32,
+	height : u32,
+	record_aovs : bool,
+}
+
+struct RenderedTile
+{
+	film : FilmTile,
+	aovs : Option<AovBuffers>,
+}
+
+impl<'a> Frame<'a>
+{
+	fn render_tile(&self, tile : Tile) -> RenderedTile
+	{
+		let (scene, camera, integrator) = (self.scene, self.camera, &self.settings.integrator);
+		let (width, height) = (self.width, self.height);
+		let mut film = FilmTile::new(tile, width, height, self.filter);
+		let mut aovs = if self.record_aovs { Some(AovBuffers::new(tile.width, tile.height)) } else { None };
+		let mut sampler = self.settings.sampler.create(self.settings.samples_per_pixel);
+		let film_position = |x : u32, y : u32, (jitter_x, jitter_y) : (f32, f32)| -> (f32, f32)
+		{
+			(x as f32 + jitter_x, y as f32 + jitter_y)
+		};
+		let screen_position = |(film_x, film_y) : (f32, f32)| -> (f32, f32)
+		{
+			(film_x / width as f32, (height as f32 - film_y) / height as f32)
+		};
+
+		for y in tile.y..tile.y + tile.height
+		{
+			for x in tile.x..tile.x + tile.width
+			{
+				if let Some(aovs) = aovs.as_mut()
+				{
+					let (s, t) = screen_position(film_position(x, y, (0.5, 0.5)));
+					let hit = scene.hit(&camera.get_ray(s, t), RAY_EPSILON, f32::INFINITY);
+					write_aovs(aovs, x - tile.x, y - tile.y, scene, hit.as_ref());
+				}
+
+				for sample_index in self.samples.clone()
+				{
+					sampler.start_pixel_sample(x, y, sample_index);
+					let (film_x, film_y) = film_position(x, y, sampler.next_2d());
+					let (s, t) = screen_position((film_x, film_y));
+					let ray = if camera.has_aperture()
+					{
+						let (u1, u2) = sampler.next_2d();
+						camera.sample_ray(s, t, u1, u2)
+					}
+					else
+					{
+						camera.get_ray(s, t)
+					};
+
+					let color = match integrator
+					{
+						Integrator::Headlight => shade(&ray, scene, scene.hit(&ray, RAY_EPSILON, f32::INFINITY).as_ref()),
+						Integrator::Whitted(settings) => whitted::radiance(&ray, scene, settings).extend(1.0),
+						Integrator::PathTracer(settings) => path_tracer::radiance(&ray, scene, settings, sampler.as_mut()).extend(1.0),
+					};
+					film.add_sample(film_x, film_y, color);
+				}
+			}
+		}
+
+		RenderedTile { film : film, aovs : aovs }
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::sync::Arc;
+
+	use cgmath::{ InnerSpace, Vector4 };
+
+	use super::*;
+	use crate::film::{ Film, Filter, FilterKind };
+	use crate::framebuffer::Framebuffer;
+	use crate::mesh::Mesh;
+	use crate::reference_scenes;
+	use crate::scene;
+
+	fn sample_triangle_scene() -> Scene
+	{
+		Scene::from_meshes(&[Arc::new(Mesh::sample_triangle())], &[], &[], &[])
+	}
+
+	fn render_headlight(scene : &Scene, width : u32, height : u32) -> Framebuffer
+	{
+		let camera = scene::sample_triangle_camera(width as f32 / height as f32);
+		let settings = TracerSettings { integrator : Integrator::Headlight, sampler : SamplerKind::Sobol, samples_per_pixel : 1, thread_count : 1 };
+		let mut film = Film::new(width, height, Filter::default());
+		render(scene, &camera, &mut film, None, &settings, 0..1);
+		let mut framebuffer = Framebuffer::new(width, height);
+		film.resolve(&mut framebuffer, 1.0);
+		framebuffer
+	}
+
+	#[test]
+	fn center_ray_hits_the_sample_triangle_facing_the_camera()
+	{
+		let scene = sample_triangle_scene();
+		let camera = scene::sample_triangle_camera(1.0);
+		let ray = camera.get_ray(0.5, 0.5);
+		let hit = scene.hit(&ray, RAY_EPSILON, f32::INFINITY).expect("the camera looks at the triangle");
+		assert!((hit.t - 2.0).abs() < 1.0e-4, "t = {}", hit.t);
+		// the vertices wind clockwise seen from the camera, front facing for DirectX, so this is the outward side's back.
+		assert!(!hit.front_face);
+		assert!(hit.normal.dot(ray.direction) < 0.0);
+		assert!(hit.point.z.abs() < 1.0e-5);
+		assert_eq!(hit.object_id, 1);
+	}
+
+	#[test]
+	fn headlight_render_shows_the_triangle_over_the_background()
+	{
+		let (width, height) = (32, 32);
+		let framebuffer = render_headlight(&sample_triangle_scene(), width, height);
+		let pixel = |x : u32, y : u32| framebuffer.pixels()[(y * width + x) as usize];
+
+		let background = Vector4::from(crate::render_settings::G_BACKGROUND);
+		for &(x, y) in &[(0, 0), (width - 1, 0), (0, height - 1), (width - 1, height - 1)]
+		{
+			assert_eq!(pixel(x, y), background, "corner {} {}", x, y);
+		}
+
+		// the triangle faces the camera, so the headlight shows its vertex colors at full brightness.
+		let center = pixel(width / 2, height / 2);
+		assert_ne!(center, background);
+		assert!((center.x + center.y + center.z - 1.0).abs() < 1.0e-3, "{:?}", center);
+		assert_eq!(center.w, 1.0);
+	}
+
+	// Renders the Cornell box with the path tracer and a filter wide enough for tiles to overlap, on thread_count
+	// threads.
+	fn render_cornell_box(width : u32, height : u32, thread_count : usize) -> (Framebuffer, AovBuffers)
+	{
+		let description = reference_scenes::cornell_box(true);
+		let meshes : Vec<Arc<Mesh>> = description.instanced_meshes().into_iter().map(Arc::new).collect();
+		let scene = Scene::from_meshes(&meshes, &description.materials, &description.textures, &description.instanced_lights());
+		let camera = description.camera(width as f32 / height as f32).expect("the Cornell box has a camera");
+
+		let integrator = Integrator::PathTracer(PathTracerSettings { max_depth : 4, ..PathTracerSettings::default() });
+		let settings = TracerSettings { integrator : integrator, sampler : SamplerKind::Sobol, samples_per_pixel : 4, thread_count : thread_count };
+		let mut film = Film::new(width, height, Filter { kind : FilterKind::Mitchell, radius : FilterKind::Mitchell.default_radius() });
+		let mut aovs = AovBuffers::new(width, height);
+		render(&scene, &camera, &mut film, Some(&mut aovs), &settings, 0..4);
+
+		let mut framebuffer = Framebuffer::new(width, height);
+		film.resolve(&mut framebuffer, 0.25);
+		(framebuffer, aovs)
+	}
+
+	fn assert_bit_identical(name : &str, a : &Framebuffer, b : &Framebuffer)
+	{
+		for (index, (a, b)) in a.pixels().iter().zip(b.pixels()).enumerate()
+		{
+			let bits = |pixel : &Vector4<f32>| [pixel.x.to_bits(), pixel.y.to_bits(), pixel.z.to_bits(), pixel.w.to_bits()];
+			assert_eq!(bits(a), bits(b), "{} pixel {}: {:?} against {:?}", name, index, a, b);
+		}
+	}
+
+	#[test]
+	fn renders_are_bit_identical_for_any_thread_count()
+	{
+		// several tiles across and down, the last ones partial.
+		let (width, height) = (TILE_SIZE * 2 + 7, TILE_SIZE + 9);
+		let (framebuffer, aovs) = render_cornell_box(width, height, 1);
+		assert!(framebuffer.pixels().iter().any(|pixel| pixel.x > 0.0), "the box is lit");
+
+		for &thread_count in &[2, 3, 8]
+		{
+			let (threaded, threaded_aovs) = render_cornell_box(width, height, thread_count);
+			assert_bit_identical(&format!("{} threads", thread_count), &framebuffer, &threaded);
+			assert_bit_identical("albedo", &aovs.albedo, &threaded_aovs.albedo);
+			assert_bit_identical("normal", &aovs.normal, &threaded_aovs.normal);
+			assert_bit_identical("depth", &aovs.depth, &threaded_aovs.depth);
+			assert_bit_identical("object id", &aovs.object_id, &threaded_aovs.object_id);
+		}
+	}
+
+	#[test]
+	fn empty_scene_renders_the_background()
+	{
+		let framebuffer = render_headlight(&Scene::new(), 8, 8);
+		let background = Vector4::from(crate::render_settings::G_BACKGROUND);
+		assert!(framebuffer.pixels().iter().all(|&pixel| pixel == background));
+	}
+}
@@ -1,8 +1,14 @@
 extern crate d3d12_rs;
 extern crate winapi;
-use crate::win_window;
-use crate::geometry;
+use crate::camera::Camera;
 use crate::geometry::*;
+use crate::lights::Light;
+use crate::mesh::Mesh;
+use crate::render_settings::{ G_WIDTH, G_HEIGHT, G_BACKGROUND };
+use crate::render_backend::RenderBackend;
+use crate::scene_description::{ MaterialDescription, Texture };
+use crate::tone_mapping::{ DisplayTransform, Encoding, ToneCurve };
+use crate::window_handle::RawWindowHandle;
 
 use winapi::{
 	shared::{
@@ -10,6 +16,7 @@ use winapi::{
 		minwindef::{FALSE, TRUE, UINT}, 
 		basetsd::{SIZE_T},
 		ntdef::HANDLE,
+		windef::HWND,
 	},
 	//dxgi1_6, minwindef::TRUE, winerror},
 	um::{
@@ -34,15 +41,19 @@ use std::{
 	os::windows::ffi::OsStrExt,
 	ptr,
 	string::String,
+	sync::Arc,
 	convert::TryFrom,
 };
 
 use d3d12_rs::WeakPtr;
 
+use cgmath::{ Matrix4, SquareMatrix };
+
 const G_MAX_FRAME_COUNT : usize = 3;
 const G_SINGLE_NODEMASK : u32 = 0;
-const G_WIDTH : u32 = 1280;
-const G_HEIGHT : u32 = 720;
+const G_CAMERA_CONSTANT_COUNT : u32 = 16; // one float4x4
+const G_DISPLAY_CONSTANT_COUNT : u32 = (mem::size_of::<DisplayConstants>() / 4) as u32;
+const G_DEPTH_FORMAT : dxgiformat::DXGI_FORMAT = dxgiformat::DXGI_FORMAT_D32_FLOAT;
 
 #[allow(dead_code)]
 pub struct Renderer 
@@ -65,9 +76,58 @@ pub struct Renderer
 	frame_index : usize,
 	vertex_buffer : WeakPtr<d3d12::ID3D12Resource>,
 	vertex_buffer_view : d3d12::D3D12_VERTEX_BUFFER_VIEW,
+	index_buffer : WeakPtr<d3d12::ID3D12Resource>,
+	index_buffer_view : d3d12::D3D12_INDEX_BUFFER_VIEW,
+	index_count : u32,
+	dsv_descriptor_heap : WeakPtr<d3d12::ID3D12DescriptorHeap>,
+	depth_buffer : WeakPtr<d3d12::ID3D12Resource>,
 	fence : WeakPtr<d3d12::ID3D12Fence>,
 	fence_values : [u64 ; G_MAX_FRAME_COUNT],
 	fence_event : HANDLE,
+	view_projection : Matrix4<f32>,
+	display_constants : DisplayConstants,
+	clear_color : [f32 ; 4], // the background through the display transform, as the render target is UNORM
+}
+
+// The pixel shader's DisplayConstants at b1, see shaders.hlsl.
+#[repr(C)]
+#[allow(dead_code)] // only read on the GPU
+struct DisplayConstants
+{
+	color_rows : [[f32; 4]; 3],
+	tone_curve : u32,
+	encoding : u32,
+	gamma : f32,
+	padding : f32,
+}
+
+impl DisplayConstants
+{
+	fn new(display : &DisplayTransform) -> Self
+	{
+		let (color_matrix, curve, encoding) = display.shader_parameters();
+		let row = |row : [f32; 3]| [row[0], row[1], row[2], 0.0];
+		let (encoding, gamma) = match encoding
+		{
+			Encoding::Srgb => (0, 1.0),
+			Encoding::Gamma(gamma) => (1, gamma),
+		};
+		Self
+		{
+			color_rows : [row(color_matrix[0]), row(color_matrix[1]), row(color_matrix[2])],
+			tone_curve : match curve
+			{
+				ToneCurve::Clamp => 0,
+				ToneCurve::Reinhard => 1,
+				ToneCurve::Hable => 2,
+				ToneCurve::Aces => 3,
+				ToneCurve::AgX => 4,
+			},
+			encoding : encoding,
+			gamma : gamma,
+			padding : 0.0,
+		}
+	}
 }
 
 fn to_wchar(str : &str) -> Vec<u16> 
@@ -181,13 +241,21 @@ pub fn new() -> Self
 		frame_index : 0,
 		vertex_buffer : WeakPtr::<d3d12::ID3D12Resource>::null(),
 		vertex_buffer_view : unsafe { mem::zeroed() },
+		index_buffer : WeakPtr::<d3d12::ID3D12Resource>::null(),
+		index_buffer_view : unsafe { mem::zeroed() },
+		index_count : 0,
+		dsv_descriptor_heap : WeakPtr::<d3d12::ID3D12DescriptorHeap>::null(),
+		depth_buffer : WeakPtr::<d3d12::ID3D12Resource>::null(),
 		fence : WeakPtr::<d3d12::ID3D12Fence>::null(),
 		fence_values : [0; G_MAX_FRAME_COUNT],
 		fence_event : ptr::null_mut(),
+		view_projection : Matrix4::identity(),
+		display_constants : DisplayConstants::new(&DisplayTransform::default()),
+		clear_color : DisplayTransform::default().apply(G_BACKGROUND.into()).into(),
 	}
 }
 
-pub fn load_pipeline(&mut self, window : win_window::Window)
+pub fn load_pipeline(&mut self, hwnd : HWND)
 {
 	if cfg!(debug_assertions)
 	{
@@ -313,7 +381,7 @@ pub fn load_pipeline(&mut self, window : win_window::Window)
 
 		let hr = self.factory.CreateSwapChainForHwnd(
 			command_queue.as_mut_ptr() as *mut _,
-			window.handle,
+			hwnd,
 			&desc,
 			ptr::null(),
 			ptr::null_mut(),
@@ -369,6 +437,77 @@ pub fn load_pipeline(&mut self, window : win_window::Window)
 		}
 	}
 
+	// Create the Depth Buffer and its Descriptor Heap. One is enough, frames never draw at the same time.
+	let dsv_heap_type = d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_DSV;
+	let mut dsv_descriptor_heap = WeakPtr::<d3d12::ID3D12DescriptorHeap>::null();
+	let dsv_descriptor_heap_desc = d3d12::D3D12_DESCRIPTOR_HEAP_DESC 
+	{
+		Type: dsv_heap_type as _,
+		NumDescriptors: 1,
+		Flags: d3d12::D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+		NodeMask: G_SINGLE_NODEMASK,
+	};
+	let dsv_descriptor_heap_hr = unsafe {
+		self.device.CreateDescriptorHeap(
+			&dsv_descriptor_heap_desc,
+			&d3d12::ID3D12DescriptorHeap::uuidof(),
+			dsv_descriptor_heap.mut_void())
+		};
+	assert!(winerror::SUCCEEDED(dsv_descriptor_heap_hr), "error on dsv descriptor_heap creation 0x{:x}", dsv_descriptor_heap_hr);
+	self.dsv_descriptor_heap = dsv_descriptor_heap;
+
+	let default_heap_properties = d3d12::D3D12_HEAP_PROPERTIES {
+		Type: d3d12::D3D12_HEAP_TYPE_DEFAULT,
+		CPUPageProperty: d3d12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+		MemoryPoolPreference: d3d12::D3D12_MEMORY_POOL_UNKNOWN,
+		CreationNodeMask: G_SINGLE_NODEMASK,
+		VisibleNodeMask: G_SINGLE_NODEMASK,
+	};
+
+	let depth_buffer_desc = d3d12::D3D12_RESOURCE_DESC {
+		Dimension: d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+		Alignment: 0,
+		Width: G_WIDTH as u64,
+		Height: G_HEIGHT,
+		DepthOrArraySize: 1,
+		MipLevels: 1,
+		Format: G_DEPTH_FORMAT,
+		SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+			Count: 1,
+			Quality: 0,
+		},
+		Layout: d3d12::D3D12_TEXTURE_LAYOUT_UNKNOWN,
+		Flags: d3d12::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL,
+	};
+
+	let mut depth_clear_value = d3d12::D3D12_CLEAR_VALUE {
+		Format: G_DEPTH_FORMAT,
+		.. unsafe { mem::zeroed() }
+	};
+	unsafe {
+		*depth_clear_value.u.DepthStencil_mut() = d3d12::D3D12_DEPTH_STENCIL_VALUE {
+			Depth: 1.0,
+			Stencil: 0,
+		};
+	}
+
+	let mut depth_buffer = WeakPtr::<d3d12::ID3D12Resource>::null();
+	unsafe
+	{
+		let hr_create_depth_buffer = self.device.CreateCommittedResource(
+			&default_heap_properties,
+			d3d12::D3D12_HEAP_FLAG_NONE,
+			&depth_buffer_desc,
+			d3d12::D3D12_RESOURCE_STATE_DEPTH_WRITE,
+			&depth_clear_value,
+			&d3d12::ID3D12Resource::uuidof(),
+			depth_buffer.mut_void());
+		assert!(winerror::SUCCEEDED(hr_create_depth_buffer), "Failed to create depth buffer. 0x{:x}", hr_create_depth_buffer);
+
+		self.device.CreateDepthStencilView(depth_buffer.as_mut_ptr(), ptr::null(), dsv_descriptor_heap.start_cpu_descriptor());
+	}
+	self.depth_buffer = depth_buffer;
+
 	// Create Command Allocators
 	for n in 0..self.frame_count as usize
 	{
@@ -430,12 +569,37 @@ pub fn _get_additional_device_data(device: WeakPtr<d3d12::ID3D12Device>)
 	assert!(winerror::SUCCEEDED(hr_check_feature_support_d3d12_options), "Failed to check feature support. 0x{:x}", hr_check_feature_support_d3d12_options);
 }
 
-pub fn load_assets(&mut self)
+pub fn load_assets(&mut self, meshes : &[Arc<Mesh>])
 {
-	// Create an empty Root Signature
+	// Create a Root Signature with the camera's view projection matrix as root constants at b0, and the display
+	// transform at b1.
 	let mut signature_raw = WeakPtr::<d3dcommon::ID3DBlob>::null();
 	let mut signature_error = WeakPtr::<d3dcommon::ID3DBlob>::null();
-	let parameters: &[d3d12::D3D12_ROOT_PARAMETER] = &[];
+	let mut camera_parameter = d3d12::D3D12_ROOT_PARAMETER {
+		ParameterType: d3d12::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+		ShaderVisibility: d3d12::D3D12_SHADER_VISIBILITY_VERTEX,
+		.. unsafe { mem::zeroed() }
+	};
+	unsafe {
+		*camera_parameter.u.Constants_mut() = d3d12::D3D12_ROOT_CONSTANTS {
+			ShaderRegister: 0,
+			RegisterSpace: 0,
+			Num32BitValues: G_CAMERA_CONSTANT_COUNT,
+		};
+	}
+	let mut display_parameter = d3d12::D3D12_ROOT_PARAMETER {
+		ParameterType: d3d12::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+		ShaderVisibility: d3d12::D3D12_SHADER_VISIBILITY_PIXEL,
+		.. unsafe { mem::zeroed() }
+	};
+	unsafe {
+		*display_parameter.u.Constants_mut() = d3d12::D3D12_ROOT_CONSTANTS {
+			ShaderRegister: 1,
+			RegisterSpace: 0,
+			Num32BitValues: G_DISPLAY_CONSTANT_COUNT,
+		};
+	}
+	let parameters: &[d3d12::D3D12_ROOT_PARAMETER] = &[camera_parameter, display_parameter];
 	let static_samplers: &[d3d12::D3D12_STATIC_SAMPLER_DESC] = &[];
 	let flags = d3d12::D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT;
 	
@@ -609,7 +773,7 @@ pub fn load_assets(&mut self)
 		d3d12::D3D12_RASTERIZER_DESC
 		{
 			FillMode : d3d12::D3D12_FILL_MODE_SOLID,
-			CullMode : d3d12::D3D12_CULL_MODE_BACK,
+			CullMode : d3d12::D3D12_CULL_MODE_NONE, // loaded meshes wind either way and the ray tracer is double sided too
 			FrontCounterClockwise : FALSE,
 			DepthBias : d3d12::D3D12_DEFAULT_DEPTH_BIAS as i32,
 			DepthBiasClamp : d3d12::D3D12_DEFAULT_DEPTH_BIAS_CLAMP,
@@ -632,9 +796,9 @@ pub fn load_assets(&mut self)
 
 	let depth_stencil_state_desc = d3d12::D3D12_DEPTH_STENCIL_DESC
 	{
-		DepthEnable: FALSE,
-		DepthWriteMask: d3d12::D3D12_DEPTH_WRITE_MASK_ZERO,
-		DepthFunc: d3d12::D3D12_COMPARISON_FUNC_NEVER,
+		DepthEnable: TRUE,
+		DepthWriteMask: d3d12::D3D12_DEPTH_WRITE_MASK_ALL,
+		DepthFunc: d3d12::D3D12_COMPARISON_FUNC_LESS,
 		StencilEnable: FALSE,
 		StencilReadMask: 0,
 		StencilWriteMask: 0,
@@ -674,7 +838,7 @@ pub fn load_assets(&mut self)
 		PrimitiveTopologyType: d3d12::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
 		NumRenderTargets: self.frame_count,
 		RTVFormats: default_rtv_formats,
-		DSVFormat: dxgiformat::DXGI_FORMAT_UNKNOWN,
+		DSVFormat: G_DEPTH_FORMAT,
 		SampleDesc: dxgitype::DXGI_SAMPLE_DESC { Count: 1, Quality: 0},
 		NodeMask: 0,
 		CachedPSO: d3d12::D3D12_CACHED_PIPELINE_STATE {
@@ -717,86 +881,36 @@ pub fn load_assets(&mut self)
 	}
 	self.command_list = command_list;
 
-	// Create Triangle Assets
-	// Upload to Vertex Buffer.
+	// Create Mesh Assets
+	// Every mesh goes into one vertex and one index buffer, indices are offset to where each mesh's vertices start.
 	{
-		let mut triangle_vertices : [geometry::ColoredVertex ; 3] = sample_colored_triangle_vertices();
-		let triangle_vertices_size = std::mem::size_of_val(&triangle_vertices);
-		let triangle_vertices_size_u32 = u32::try_from(triangle_vertices_size).expect("Failed Type Conversion: usize -> u32");
-		let vertex_size = std::mem::size_of_val(&triangle_vertices[0]);
-		let vertex_size_u32 = u32::try_from(vertex_size).expect("Failed Type Conversion: usize -> u32");
-
-		assert!(triangle_vertices_size == 84);
-
-		let default_heap_properties = d3d12::D3D12_HEAP_PROPERTIES {
-			Type: d3d12::D3D12_HEAP_TYPE_UPLOAD,
-			CPUPageProperty: d3d12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
-			MemoryPoolPreference: d3d12::D3D12_MEMORY_POOL_UNKNOWN,
-			CreationNodeMask: G_SINGLE_NODEMASK,
-			VisibleNodeMask: G_SINGLE_NODEMASK,
-		};
-
-		let vertex_buffer_resource_desc = d3d12::D3D12_RESOURCE_DESC {
-			Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
-			Alignment: 0,
-			Width: triangle_vertices_size as u64,
-			Height: 1,
-			DepthOrArraySize: 1,
-			MipLevels: 1,
-			Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
-			SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
-				Count: 1,
-				Quality: 0,
-			},
-			Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-			Flags: d3d12::D3D12_RESOURCE_FLAG_NONE,
-		};
-
-		let mut vertex_buffer = WeakPtr::<d3d12::ID3D12Resource>::null();
-
-		unsafe 
-		{		
-			let hr_create_committed_resource = self.device.CreateCommittedResource(
-				&default_heap_properties,
-				d3d12::D3D12_HEAP_FLAG_NONE,
-				&vertex_buffer_resource_desc,
-				d3d12::D3D12_RESOURCE_STATE_GENERIC_READ,
-				ptr::null() as _,
-				&d3d12::ID3D12Resource::uuidof(),
-				vertex_buffer.mut_void());
-
-			assert!(winerror::SUCCEEDED(hr_create_committed_resource), "Failed to create vertex buffer. 0x{:x}", hr_create_committed_resource);
-
-			let buffer_name : String = String::from("triangle vertex buffer");
-			let buffer_size = u32::try_from(buffer_name.len()).unwrap();
-			vertex_buffer.SetPrivateData(&d3dcommon::WKPDID_D3DDebugObjectName, buffer_size, buffer_name.as_ptr() as * mut _);
-		}
-		
-		let mut p_vertex_data_begin = ptr::null_mut::<winapi::ctypes::c_void>();
-
-		let read_range = d3d12::D3D12_RANGE { Begin: 0 , End: 0};
-		unsafe 
+		let mut vertices : Vec<ColoredVertex> = Vec::new();
+		let mut indices : Vec<u32> = Vec::new();
+		for mesh in meshes
 		{
-			let hr_map = vertex_buffer.Map(0, &read_range, &mut p_vertex_data_begin);
-			assert!(winerror::SUCCEEDED(hr_map), "Failed to map vertex buffer. 0x{:x}", hr_map);
-			assert!(!p_vertex_data_begin.is_null(), "Failed to map vertex buffer. 0x{:x}", hr_map);
+			let first_vertex = u32::try_from(vertices.len()).expect("Failed Type Conversion: usize -> u32");
+			vertices.extend(mesh.colored_vertices());
+			indices.extend(mesh.indices.iter().map(|index| first_vertex + index));
+		}
 
-			std::ptr::copy_nonoverlapping(
-				triangle_vertices.as_mut_ptr(),
-				p_vertex_data_begin as * mut ColoredVertex,
-				triangle_vertices_size);
+		let vertex_size = std::mem::size_of::<ColoredVertex>();
+		let vertex_size_u32 = u32::try_from(vertex_size).expect("Failed Type Conversion: usize -> u32");
+		assert!(vertex_size == 28);
 
-			vertex_buffer.Unmap(0, ptr::null());
-		}
-		
-		self.vertex_buffer = vertex_buffer;
+		self.vertex_buffer = self.create_upload_buffer(&vertices, "mesh vertex buffer");
 		self.vertex_buffer_view = d3d12::D3D12_VERTEX_BUFFER_VIEW {
 			BufferLocation: unsafe { self.vertex_buffer.GetGPUVirtualAddress() },
-			SizeInBytes: triangle_vertices_size_u32,
+			SizeInBytes: u32::try_from(vertices.len() * vertex_size).expect("Failed Type Conversion: usize -> u32"),
 			StrideInBytes: vertex_size_u32,
 		};
-		assert!(self.vertex_buffer_view.StrideInBytes == 28);
-		assert!(self.vertex_buffer_view.SizeInBytes == 84);
+
+		self.index_buffer = self.create_upload_buffer(&indices, "mesh index buffer");
+		self.index_buffer_view = d3d12::D3D12_INDEX_BUFFER_VIEW {
+			BufferLocation: unsafe { self.index_buffer.GetGPUVirtualAddress() },
+			SizeInBytes: u32::try_from(indices.len() * std::mem::size_of::<u32>()).expect("Failed Type Conversion: usize -> u32"),
+			Format: dxgiformat::DXGI_FORMAT_R32_UINT,
+		};
+		self.index_count = u32::try_from(indices.len()).expect("Failed Type Conversion: usize -> u32");
 	}
 
 	// Create synchronization objects and wait until assets have been uploaded to the GPU.
@@ -822,9 +936,82 @@ pub fn load_assets(&mut self)
 	}
 }
 
-pub fn update(&mut self)
+// Creates a buffer in the upload heap holding a copy of data. Upload heap buffers are read by the GPU directly,
+// which is slower than a default heap buffer but good enough for static meshes of the sizes we load.
+fn create_upload_buffer<T : Copy>(&self, data : &[T], name : &str) -> WeakPtr<d3d12::ID3D12Resource>
+{
+	// zero sized resources are invalid, keep at least one element's worth.
+	let data_size = std::mem::size_of_val(data).max(std::mem::size_of::<T>());
+
+	let upload_heap_properties = d3d12::D3D12_HEAP_PROPERTIES {
+		Type: d3d12::D3D12_HEAP_TYPE_UPLOAD,
+		CPUPageProperty: d3d12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+		MemoryPoolPreference: d3d12::D3D12_MEMORY_POOL_UNKNOWN,
+		CreationNodeMask: G_SINGLE_NODEMASK,
+		VisibleNodeMask: G_SINGLE_NODEMASK,
+	};
+
+	let buffer_resource_desc = d3d12::D3D12_RESOURCE_DESC {
+		Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
+		Alignment: 0,
+		Width: data_size as u64,
+		Height: 1,
+		DepthOrArraySize: 1,
+		MipLevels: 1,
+		Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
+		SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+			Count: 1,
+			Quality: 0,
+		},
+		Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+		Flags: d3d12::D3D12_RESOURCE_FLAG_NONE,
+	};
+
+	let mut buffer = WeakPtr::<d3d12::ID3D12Resource>::null();
+
+	unsafe 
+	{		
+		let hr_create_committed_resource = self.device.CreateCommittedResource(
+			&upload_heap_properties,
+			d3d12::D3D12_HEAP_FLAG_NONE,
+			&buffer_resource_desc,
+			d3d12::D3D12_RESOURCE_STATE_GENERIC_READ,
+			ptr::null() as _,
+			&d3d12::ID3D12Resource::uuidof(),
+			buffer.mut_void());
+
+		assert!(winerror::SUCCEEDED(hr_create_committed_resource), "Failed to create {}. 0x{:x}", name, hr_create_committed_resource);
+
+		let buffer_name_size = u32::try_from(name.len()).unwrap();
+		buffer.SetPrivateData(&d3dcommon::WKPDID_D3DDebugObjectName, buffer_name_size, name.as_ptr() as * mut _);
+	}
+
+	let mut p_data_begin = ptr::null_mut::<winapi::ctypes::c_void>();
+
+	let read_range = d3d12::D3D12_RANGE { Begin: 0 , End: 0}; // the CPU does not read the buffer
+	unsafe 
+	{
+		let hr_map = buffer.Map(0, &read_range, &mut p_data_begin);
+		assert!(winerror::SUCCEEDED(hr_map), "Failed to map {}. 0x{:x}", name, hr_map);
+		assert!(!p_data_begin.is_null(), "Failed to map {}. 0x{:x}", name, hr_map);
+
+		std::ptr::copy_nonoverlapping(
+			data.as_ptr(),
+			p_data_begin as * mut T,
+			data.len());
+
+		buffer.Unmap(0, ptr::null());
+	}
+
+	return buffer;
+}
+
+pub fn update(&mut self, camera : &Camera)
 {
-	// Nothing to do here. This sample has no simulation work.
+	// the swap chain is always G_WIDTH x G_HEIGHT and stretched to the window, so keep its aspect ratio.
+	let mut camera = *camera;
+	camera.set_aspect_ratio(G_WIDTH as f32 / G_HEIGHT as f32);
+	self.view_projection = camera.view_projection_matrix();
 }
 
 pub fn render(&mut self) -> i32
@@ -871,6 +1058,9 @@ pub fn populate_command_list(&mut self)
 		assert!(winerror::SUCCEEDED(hr_command_reset), "Failed to reset command list. 0x{:x}", hr_command_reset);
 
 		self.command_list.SetGraphicsRootSignature(self.root_signature.as_mut_ptr());
+		let view_projection : &[f32; 16] = self.view_projection.as_ref(); // column major, HLSL's default packing
+		self.command_list.SetGraphicsRoot32BitConstants(0, G_CAMERA_CONSTANT_COUNT, view_projection.as_ptr() as *const _, 0);
+		self.command_list.SetGraphicsRoot32BitConstants(1, G_DISPLAY_CONSTANT_COUNT, &self.display_constants as *const DisplayConstants as *const _, 0);
 		self.command_list.RSSetViewports(1, &self.viewport);
 		self.command_list.RSSetScissorRects(1, &self.scissor_rect);
 
@@ -889,18 +1079,21 @@ pub fn populate_command_list(&mut self)
 		self.command_list.ResourceBarrier(1, resource_barrier_start_d3d);
 
 		let rtv_handle = CD3D12_CPU_DESCRIPTOR_HANDLE::from_offset(&self.rtv_descriptor_heap.GetCPUDescriptorHandleForHeapStart(), self.frame_index as i32, self.rtv_descriptor_size);
-		self.command_list.OMSetRenderTargets(1, &rtv_handle.0, FALSE, ptr::null());
+		let dsv_handle = self.dsv_descriptor_heap.GetCPUDescriptorHandleForHeapStart();
+		self.command_list.OMSetRenderTargets(1, &rtv_handle.0, FALSE, &dsv_handle);
 
-		let clear_color : [f32 ; 4] = [0.0, 0.2, 0.4, 1.0];
-		self.command_list.ClearRenderTargetView(rtv_handle.0, &clear_color, 0, ptr::null());
+		self.command_list.ClearRenderTargetView(rtv_handle.0, &self.clear_color, 0, ptr::null());
+		self.command_list.ClearDepthStencilView(dsv_handle, d3d12::D3D12_CLEAR_FLAG_DEPTH, 1.0, 0, 0, ptr::null());
 
 		self.command_list.IASetPrimitiveTopology(d3dcommon::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
 		self.command_list.IASetVertexBuffers(0, 1, &self.vertex_buffer_view);
-		let vertex_count = 3;
+		self.command_list.IASetIndexBuffer(&self.index_buffer_view);
+		let index_count = self.index_count;
 		let instance_count= 1;
-		let start_vertex_location = 0;
+		let start_index_location = 0;
+		let base_vertex_location = 0;
 		let start_instance_location = 0;
-		self.command_list.DrawInstanced(vertex_count, instance_count, start_vertex_location, start_instance_location);
+		self.command_list.DrawIndexedInstanced(index_count, instance_count, start_index_location, base_vertex_location, start_instance_location);
 
 		let mut resource_barrier_end = d3d12::D3D12_RESOURCE_BARRIER {
 			Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
@@ -996,4 +1189,39 @@ pub fn move_to_next_frame(&mut self)
 	self.fence_values[next_fence_index] = current_fence_value + 1;
 }
 
+}
+
+impl RenderBackend for Renderer
+{
+	fn load_pipeline(&mut self, window : RawWindowHandle)
+	{
+		match window
+		{
+			RawWindowHandle::Win32 { hwnd } => Renderer::load_pipeline(self, hwnd as HWND),
+			RawWindowHandle::Headless => panic!("The DX12 backend needs a Win32 window to create its swap chain."),
+		}
+	}
+
+	fn load_assets(&mut self, meshes : &[Arc<Mesh>], _materials : &[MaterialDescription], _textures : &[Texture], _lights : &[Light])
+	{
+		// the rasterizer draws vertex colors, which already carry the base color of each material.
+		Renderer::load_assets(self, meshes);
+	}
+
+	fn update(&mut self, camera : &Camera)
+	{
+		Renderer::update(self, camera);
+	}
+
+	fn render(&mut self) -> i32
+	{
+		Renderer::render(self)
+	}
+
+	fn set_display_transform(&mut self, display : &DisplayTransform)
+	{
+		// the pixel shader applies it to the vertex colors, the clear color is worked out here.
+		self.display_constants = DisplayConstants::new(display);
+		self.clear_color = display.apply(G_BACKGROUND.into()).into();
+	}
 }
\ No newline at end of file
@@ -0,0 +1,352 @@
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+
+use cgmath::{ InnerSpace, Rad, Vector3 };
+
+use crate::area_lights::{ luminance, LightSample };
+use crate::framebuffer::Framebuffer;
+use crate::image_writer;
+use crate::sampler::ONE_MINUS_EPSILON;
+
+// A piecewise constant density on [0, 1), one piece per function value, sampled by inverting its cumulative
+// distribution.
+struct Distribution1d
+{
+	function : Vec<f32>,
+	cdf : Vec<f32>,  // function.len() + 1 entries rising from 0 to 1
+	integral : f32,  // of the function over [0, 1)
+}
+
+impl Distribution1d
+{
+	// Negative values count as zero. A function that is zero everywhere still picks points, uniformly, but with
+	// zero density.
+	fn new(function : Vec<f32>) -> Self
+	{
+		let function : Vec<f32> = function.into_iter().map(|value| value.max(0.0)).collect();
+		let count = function.len();
+
+		let mut sums = Vec::with_capacity(count + 1);
+		let mut sum = 0.0_f64;
+		sums.push(sum);
+		for &value in &function
+		{
+			sum += value as f64;
+			sums.push(sum);
+		}
+
+		let cdf = if sum > 0.0
+		{
+			sums.iter().map(|&partial| (partial / sum) as f32).collect()
+		}
+		else
+		{
+			(0..=count).map(|index| index as f32 / count as f32).collect()
+		};
+		Self { function : function, cdf : cdf, integral : (sum / count as f64) as f32 }
+	}
+
+	// The point u maps to and the piece it is in.
+	fn sample(&self, u : f32) -> (f32, usize)
+	{
+		let count = self.function.len();
+		let index = (self.cdf.partition_point(|&cdf| cdf <= u) - 1).min(count - 1);
+		let width = self.cdf[index + 1] - self.cdf[index];
+		let offset = if width > 0.0 { ((u - self.cdf[index]) / width).max(0.0) } else { 0.0 };
+		(((index as f32 + offset) / count as f32).min(ONE_MINUS_EPSILON), index)
+	}
+}
+
+// A piecewise constant density on the unit square: a row picked by the marginal distribution of the row integrals,
+// then a point along it by the row's own.
+struct Distribution2d
+{
+	rows : Vec<Distribution1d>,
+	marginal : Distribution1d,
+}
+
+impl Distribution2d
+{
+	fn new(function : &[f32], width : usize) -> Self
+	{
+		let rows : Vec<Distribution1d> = function.chunks(width).map(|row| Distribution1d::new(row.to_vec())).collect();
+		let marginal = Distribution1d::new(rows.iter().map(|row| row.integral).collect());
+		Self { rows : rows, marginal : marginal }
+	}
+
+	fn integral(&self) -> f32
+	{
+		self.marginal.integral
+	}
+
+	// The point u1 and u2 map to, as u and v, and the column and row it is in.
+	fn sample(&self, u1 : f32, u2 : f32) -> (f32, f32, usize, usize)
+	{
+		let (v, row) = self.marginal.sample(u2);
+		let (u, column) = self.rows[row].sample(u1);
+		(u, v, column, row)
+	}
+
+	// Density over the unit square anywhere in the piece at column and row.
+	fn pdf(&self, column : usize, row : usize) -> f32
+	{
+		if self.integral() > 0.0 { self.rows[row].function[column] / self.integral() } else { 0.0 }
+	}
+}
+
+// Radiance arriving from infinitely far away, looked up by direction in an equirectangular image and importance
+// sampled by the luminance of its texels for direct lighting. Each texel is constant over the patch of sphere it
+// covers, which is what the sampling assumes.
+// Longitude runs around +y like the equirectangular camera's, with -z in the middle of the image and +x a quarter
+// of the way right of it, and latitude from +y at the top to -y at the bottom.
+pub struct EnvironmentMap
+{
+	width : usize,
+	height : usize,
+	texels : Vec<Vector3<f32>>, // rows from the top, already times the intensity
+	rotation : f32,             // radians the map is turned around +y, positive toward +x
+	distribution : Distribution2d,
+	average : Vector3<f32>,
+}
+
+impl EnvironmentMap
+{
+	pub fn load(path : &Path, rotation : Rad<f32>, intensity : f32) -> io::Result<Self>
+	{
+		Ok(Self::new(&image_writer::read_hdr(path)?, rotation, intensity))
+	}
+
+	pub fn new(image : &Framebuffer, rotation : Rad<f32>, intensity : f32) -> Self
+	{
+		let (width, height) = (image.width() as usize, image.height() as usize);
+		let clean = |value : f32| if value > 0.0 && value.is_finite() { value * intensity } else { 0.0 };
+		let texels : Vec<Vector3<f32>> = image.pixels().iter().map(|pixel| Vector3::new(clean(pixel.x), clean(pixel.y), clean(pixel.z))).collect();
+
+		// rows near the poles cover less of the sphere, sin(theta) in their middle is close enough to sample by.
+		let weights : Vec<f32> = texels.iter().enumerate().map(|(index, &texel)|
+		{
+			let theta = ((index / width) as f32 + 0.5) / height as f32 * PI;
+			luminance(texel) * theta.sin()
+		}).collect();
+
+		// the average needs the exact solid angle, which is 2 pi / width times the difference of cos(theta) across the row.
+		let mut sum = Vector3::new(0.0_f64, 0.0, 0.0);
+		for (row, texels) in texels.chunks(width).enumerate()
+		{
+			let band = ((row as f64 / height as f64 * std::f64::consts::PI).cos() - ((row + 1) as f64 / height as f64 * std::f64::consts::PI).cos()) / (2.0 * width as f64);
+			for texel in texels
+			{
+				sum += texel.cast::<f64>().unwrap_or_else(|| Vector3::new(0.0, 0.0, 0.0)) * band;
+			}
+		}
+
+		Self
+		{
+			width : width,
+			height : height,
+			texels : texels,
+			rotation : rotation.0,
+			distribution : Distribution2d::new(&weights, width),
+			average : sum.cast::<f32>().unwrap_or_else(|| Vector3::new(0.0, 0.0, 0.0)),
+		}
+	}
+
+	pub fn width(&self) -> usize
+	{
+		self.width
+	}
+
+	pub fn height(&self) -> usize
+	{
+		self.height
+	}
+
+	// Radiance arriving from direction, which doesn't have to be unit length.
+	pub fn radiance(&self, direction : Vector3<f32>) -> Vector3<f32>
+	{
+		let (column, row) = self.texel(direction);
+		self.texels[row * self.width + column]
+	}
+
+	// Mean radiance over the sphere.
+	pub fn average(&self) -> Vector3<f32>
+	{
+		self.average
+	}
+
+	// True when there is no light to sample, so sample never returns anything.
+	pub fn is_black(&self) -> bool
+	{
+		self.distribution.integral() <= 0.0
+	}
+
+	// Picks a direction with two uniform numbers in [0, 1), with a density proportional to the luminance arriving
+	// from it. The light is infinitely far away.
+	pub fn sample(&self, u1 : f32, u2 : f32) -> Option<LightSample>
+	{
+		let (u, v, column, row) = self.distribution.sample(u1, u2);
+		let (sin_theta, cos_theta) = (v * PI).sin_cos();
+		let uv_pdf = self.distribution.pdf(column, row);
+		if uv_pdf <= 0.0 || sin_theta <= 0.0
+		{
+			return None;
+		}
+
+		let (sin_longitude, cos_longitude) = ((u - 0.5) * 2.0 * PI + self.rotation).sin_cos();
+		Some(LightSample
+		{
+			direction : Vector3::new(sin_theta * sin_longitude, cos_theta, -sin_theta * cos_longitude),
+			distance : f32::INFINITY,
+			radiance : self.texels[row * self.width + column],
+			pdf : uv_pdf / (2.0 * PI * PI * sin_theta),
+			delta : false,
+		})
+	}
+
+	// The density, per unit solid angle, with which sample would have picked direction.
+	pub fn pdf(&self, direction : Vector3<f32>) -> f32
+	{
+		let length = direction.magnitude();
+		let sin_theta = (direction.x * direction.x + direction.z * direction.z).sqrt() / length;
+		if sin_theta.is_nan() || sin_theta <= 0.0
+		{
+			return 0.0;
+		}
+		let (column, row) = self.texel(direction);
+		self.distribution.pdf(column, row) / (2.0 * PI * PI * sin_theta)
+	}
+
+	fn texel(&self, direction : Vector3<f32>) -> (usize, usize)
+	{
+		let longitude = direction.x.atan2(-direction.z) - self.rotation;
+		let u = (longitude / (2.0 * PI) + 0.5).rem_euclid(1.0);
+		let theta = (direction.x * direction.x + direction.z * direction.z).sqrt().atan2(direction.y);
+		let v = theta / PI;
+		(((u * self.width as f32) as usize).min(self.width - 1), ((v * self.height as f32) as usize).min(self.height - 1))
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::material::tests::uniform_sphere;
+	use crate::reference_scenes::sky_environment;
+	use crate::rng::Rng;
+
+	const SAMPLES : u32 = 200_000;
+
+	// Writes and reads back an image with runs of equal pixels next to spans that change every pixel, from black to
+	// far above 1. Mantissas keep 8 bits relative to the brightest component.
+	fn check_rgbe_round_trip(width : u32, height : u32)
+	{
+		let mut image = Framebuffer::new(width, height);
+		for y in 0..height
+		{
+			for x in 0..width
+			{
+				let step = if x < width / 2 { 0 } else { x };
+				let value = |scale : f32| if (x + y) % 7 == 0 { 0.0 } else { scale * 2.0_f32.powi((step + y) as i32 % 24 - 12) };
+				image.set(x, y, Vector3::new(value(1.0), value(0.3), value(2.5)).extend(1.0));
+			}
+		}
+
+		let path = std::env::temp_dir().join(format!("rust_raytracer_environment_test_{}x{}_{}.hdr", width, height, std::process::id()));
+		let result = image_writer::write_hdr(&image, &path).and_then(|()| image_writer::read_hdr(&path));
+		let _ = std::fs::remove_file(&path);
+		let read = result.expect("the image reads back");
+		assert_eq!((read.width(), read.height()), (width, height));
+		for (written, read) in image.pixels().iter().zip(read.pixels())
+		{
+			let brightest = written.x.max(written.y).max(written.z);
+			let error = (written - read).truncate().map(f32::abs);
+			let error = error.x.max(error.y).max(error.z) / if brightest > 0.0 { brightest } else { 1.0 };
+			assert!(error <= 1.0 / 256.0, "wrote {:?}, read {:?}", written, read);
+		}
+	}
+
+	fn sky(rotation : f32) -> EnvironmentMap
+	{
+		EnvironmentMap::new(&sky_environment(), Rad(rotation), 2.0)
+	}
+
+	#[test]
+	fn rgbe_round_trips_run_length_encoded_scanlines()
+	{
+		check_rgbe_round_trip(37, 5);
+	}
+
+	#[test]
+	fn rgbe_round_trips_scanlines_too_narrow_to_encode()
+	{
+		check_rgbe_round_trip(5, 3);
+	}
+
+	// A direction landing in a neighbouring texel on the way back through texel is float rounding, not a mistake.
+	#[test]
+	fn samples_agree_with_pdf_and_radiance()
+	{
+		let environment = sky(0.5);
+		let mut rng = Rng::new(7, 0);
+		let mismatches = (0..SAMPLES).filter(|_|
+		{
+			let sample = environment.sample(rng.next_f32(), rng.next_f32()).expect("the sky isn't black");
+			let pdf = environment.pdf(sample.direction);
+			(pdf - sample.pdf).abs() > 1.0e-3 * sample.pdf || environment.radiance(sample.direction) != sample.radiance
+		}).count() as u32;
+		assert!(mismatches <= SAMPLES / 1000, "{} of {} differ", mismatches, SAMPLES);
+	}
+
+	#[test]
+	fn importance_sampled_integral_converges()
+	{
+		let environment = sky(0.5);
+		let mut rng = Rng::new(7, 0);
+		let mut estimate = Vector3::new(0.0_f64, 0.0, 0.0);
+		for _ in 0..SAMPLES
+		{
+			let sample = environment.sample(rng.next_f32(), rng.next_f32()).expect("the sky isn't black");
+			estimate += (sample.radiance / sample.pdf).cast::<f64>().unwrap();
+		}
+		let estimate = estimate / SAMPLES as f64;
+		let expected = environment.average().cast::<f64>().unwrap() * (4.0 * std::f64::consts::PI);
+		let error = (estimate - expected).map(f64::abs);
+		let relative = error.x.max(error.y).max(error.z) / expected.x.max(expected.y).max(expected.z);
+		assert!(relative <= 0.01, "{:?}, expected {:?}", estimate, expected);
+	}
+
+	// The integral of the density over the sphere, by sampling the sphere uniformly.
+	#[test]
+	fn pdf_integrates_to_one()
+	{
+		let environment = sky(0.5);
+		let mut rng = Rng::new(11, 0);
+		let mut sum = 0.0_f64;
+		let mut sum_squared = 0.0_f64;
+		for _ in 0..SAMPLES
+		{
+			let value = (4.0 * PI * environment.pdf(uniform_sphere(rng.next_f32(), rng.next_f32()))) as f64;
+			sum += value;
+			sum_squared += value * value;
+		}
+		let mean = sum / SAMPLES as f64;
+		let standard_error = ((sum_squared / SAMPLES as f64 - mean * mean).max(0.0) / SAMPLES as f64).sqrt();
+		assert!((mean - 1.0).abs() <= 4.0 * standard_error + 1.0e-3, "{} (+- {})", mean, standard_error);
+	}
+
+	// Turning the map a quarter turn toward +x is the same as looking a quarter turn back toward -x.
+	#[test]
+	fn rotation_turns_around_the_vertical_axis()
+	{
+		let (unturned, turned) = (sky(0.0), sky(0.5 * PI));
+		let mut rng = Rng::new(13, 0);
+		let mismatches = (0..SAMPLES).filter(|_|
+		{
+			let direction = uniform_sphere(rng.next_f32(), rng.next_f32());
+			let turned_back = Vector3::new(direction.z, direction.y, -direction.x);
+			turned.radiance(direction) != unturned.radiance(turned_back)
+		}).count() as u32;
+		assert!(mismatches <= SAMPLES / 1000, "{} of {} differ", mismatches, SAMPLES);
+	}
+}
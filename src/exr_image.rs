@@ -0,0 +1,524 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{ Path, PathBuf };
+
+use cgmath::Vector4;
+use exr::prelude::{ AnyChannel, AnyChannels, FlatSamples, SmallVec, f16 };
+
+use crate::framebuffer::{ AovBuffers, Framebuffer };
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleType
+{
+	Half,
+	Float,
+	Uint, // for ids, values are rounded to the nearest integer
+}
+
+// The lossless compression methods the writer supports, named like the OpenEXR tools name them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression
+{
+	None,
+	Rle,
+	Zips, // zip, one scanline per block
+	Zip,  // zip, 16 scanlines per block
+	Piz,
+	Pxr24, // lossy for float channels, which are rounded to 24 bits
+}
+
+impl std::str::FromStr for SampleType
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		match value
+		{
+			"half" => Ok(SampleType::Half),
+			"float" => Ok(SampleType::Float),
+			_ => Err(format!("unknown EXR sample type '{}', expected half or float", value)),
+		}
+	}
+}
+
+impl std::str::FromStr for Compression
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		match value
+		{
+			"none" => Ok(Compression::None),
+			"rle" => Ok(Compression::Rle),
+			"zips" => Ok(Compression::Zips),
+			"zip" => Ok(Compression::Zip),
+			"piz" => Ok(Compression::Piz),
+			"pxr24" => Ok(Compression::Pxr24),
+			_ => Err(format!("unknown EXR compression '{}', expected none, rle, zips, zip, piz or pxr24", value)),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExrSettings
+{
+	pub sample_type : SampleType,   // for layers that don't ask for a type of their own
+	pub compression : Compression,
+	pub tile_size : Option<u32>,    // square tiles, scanlines when None
+}
+
+impl Default for ExrSettings
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			sample_type : SampleType::Half,
+			compression : Compression::Zip,
+			tile_size : None,
+		}
+	}
+}
+
+// A named group of up to four channels, stored in x, y, z and w of the framebuffer in the order they are listed.
+// Layers are written the way compositing packages expect them, as channels named "layer.channel" in a single part.
+// The layer with an empty name holds the plain R, G, B and A channels.
+#[derive(Clone, Debug)]
+pub struct ExrLayer
+{
+	pub name : String,
+	pub channels : Vec<String>,
+	pub sample_type : Option<SampleType>, // None uses ExrSettings::sample_type
+	pub framebuffer : Framebuffer,
+}
+
+impl ExrLayer
+{
+	pub fn new(name : &str, channels : &[&str], sample_type : Option<SampleType>, framebuffer : Framebuffer) -> Self
+	{
+		Self
+		{
+			name : name.to_string(),
+			channels : channels.iter().map(|channel| channel.to_string()).collect(),
+			sample_type : sample_type,
+			framebuffer : framebuffer,
+		}
+	}
+
+	fn full_channel_name(&self, channel : &str) -> String
+	{
+		if self.name.is_empty() { channel.to_string() } else { format!("{}.{}", self.name, channel) }
+	}
+}
+
+// The beauty pass as the main RGBA layer, followed by one layer per AOV. Depth is always written as float
+// and object ids as integers, so neither loses precision in a half float file.
+pub fn render_layers(beauty : &Framebuffer, aovs : Option<&AovBuffers>) -> Vec<ExrLayer>
+{
+	let mut layers = vec![ExrLayer::new("", &["R", "G", "B", "A"], None, beauty.clone())];
+	if let Some(aovs) = aovs
+	{
+		layers.push(ExrLayer::new("albedo", &["R", "G", "B"], None, aovs.albedo.clone()));
+		layers.push(ExrLayer::new("normal", &["X", "Y", "Z"], None, aovs.normal.clone()));
+		layers.push(ExrLayer::new("depth", &["Z"], Some(SampleType::Float), aovs.depth.clone()));
+		layers.push(ExrLayer::new("object_id", &["id"], Some(SampleType::Uint), aovs.object_id.clone()));
+	}
+	layers
+}
+
+#[derive(Debug)]
+pub struct ExrError
+{
+	pub path : PathBuf,
+	pub message : String,
+}
+
+impl fmt::Display for ExrError
+{
+	fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}: {}", self.path.display(), self.message)
+	}
+}
+
+impl Error for ExrError {}
+
+pub fn write_exr(path : &Path, layers : &[ExrLayer], settings : &ExrSettings) -> Result<(), ExrError>
+{
+	use exr::prelude::{ Encoding, Image, Layer, LayerAttributes, LineOrder, WritableImage };
+
+	let error = |message : String| ExrError { path : path.to_path_buf(), message : message };
+
+	let first_layer = layers.first().ok_or_else(|| error("there are no layers to write".to_string()))?;
+	let (width, height) = (first_layer.framebuffer.width(), first_layer.framebuffer.height());
+
+	let mut channels : SmallVec<[AnyChannel<FlatSamples>; 4]> = SmallVec::new();
+	for layer in layers
+	{
+		if layer.framebuffer.width() != width || layer.framebuffer.height() != height
+		{
+			return Err(error(format!("layer '{}' is {}x{} but the image is {}x{}",
+				layer.name, layer.framebuffer.width(), layer.framebuffer.height(), width, height)));
+		}
+		if layer.channels.len() > 4
+		{
+			return Err(error(format!("layer '{}' has {} channels, at most 4 fit in a framebuffer", layer.name, layer.channels.len())));
+		}
+
+		let sample_type = layer.sample_type.unwrap_or(settings.sample_type);
+		for (component, channel) in layer.channels.iter().enumerate()
+		{
+			let values = layer.framebuffer.pixels().iter().map(|pixel| pixel[component]);
+			let samples = match sample_type
+			{
+				SampleType::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+				SampleType::Float => FlatSamples::F32(values.collect()),
+				SampleType::Uint => FlatSamples::U32(values.map(|value| value.max(0.0).round() as u32).collect()),
+			};
+			channels.push(AnyChannel::new(layer.full_channel_name(channel).as_str(), samples));
+		}
+	}
+
+	let mut names : Vec<String> = channels.iter().map(|channel| channel.name.to_string()).collect();
+	names.sort_unstable();
+	if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1])
+	{
+		return Err(error(format!("channel '{}' is written twice", pair[0])));
+	}
+
+	let encoding = Encoding
+	{
+		compression : match settings.compression
+		{
+			Compression::None => exr::compression::Compression::Uncompressed,
+			Compression::Rle => exr::compression::Compression::RLE,
+			Compression::Zips => exr::compression::Compression::ZIP1,
+			Compression::Zip => exr::compression::Compression::ZIP16,
+			Compression::Piz => exr::compression::Compression::PIZ,
+			Compression::Pxr24 => exr::compression::Compression::PXR24,
+		},
+		blocks : match settings.tile_size
+		{
+			None => exr::image::Blocks::ScanLines,
+			Some(tile_size) => exr::image::Blocks::Tiles(exr::math::Vec2(tile_size as usize, tile_size as usize)),
+		},
+		line_order : LineOrder::Increasing,
+	};
+
+	let layer = Layer::new((width as usize, height as usize), LayerAttributes::default(), encoding, AnyChannels::sort(channels));
+	Image::from_layer(layer).write().to_file(path).map_err(|exr_error| error(exr_error.to_string()))
+}
+
+// Reads every channel of every part, grouping channels into layers by the prefix before their last dot.
+// Within a layer the channels are put in R, G, B, A, then X, Y, Z order, any others follow alphabetically.
+pub fn read_exr(path : &Path) -> Result<Vec<ExrLayer>, ExrError>
+{
+	use exr::prelude::ReadChannels;
+	use exr::prelude::ReadLayers;
+
+	let error = |message : String| ExrError { path : path.to_path_buf(), message : message };
+
+	let image = exr::prelude::read()
+		.no_deep_data()
+		.largest_resolution_level()
+		.all_channels()
+		.all_layers()
+		.all_attributes()
+		.from_file(path)
+		.map_err(|exr_error| error(exr_error.to_string()))?;
+
+	let (width, height) = (image.attributes.display_window.size.0, image.attributes.display_window.size.1);
+
+	let mut layers : Vec<ExrLayer> = Vec::new();
+	for part in &image.layer_data
+	{
+		if part.size.0 != width || part.size.1 != height
+		{
+			return Err(error(format!("a part is {}x{} but the image is {}x{}, cropped parts are not supported", part.size.0, part.size.1, width, height)));
+		}
+
+		for channel in &part.channel_data.list
+		{
+			let mut full_name = channel.name.to_string();
+			if let Some(part_name) = &part.attributes.layer_name
+			{
+				full_name = format!("{}.{}", part_name, full_name);
+			}
+			let (layer_name, channel_name) = match full_name.rfind('.')
+			{
+				Some(dot) => (full_name[..dot].to_string(), full_name[dot + 1..].to_string()),
+				None => (String::new(), full_name.clone()),
+			};
+
+			let sample_type = match channel.sample_data
+			{
+				FlatSamples::F16(_) => SampleType::Half,
+				FlatSamples::F32(_) => SampleType::Float,
+				FlatSamples::U32(_) => SampleType::Uint,
+			};
+
+			let layer_index = match layers.iter().position(|layer| layer.name == layer_name)
+			{
+				Some(index) => index,
+				None =>
+				{
+					layers.push(ExrLayer
+					{
+						name : layer_name.clone(),
+						channels : Vec::new(),
+						sample_type : Some(sample_type),
+						framebuffer : Framebuffer::new(width as u32, height as u32),
+					});
+					layers.len() - 1
+				}
+			};
+
+			let layer = &mut layers[layer_index];
+			if layer.channels.len() == 4
+			{
+				return Err(error(format!("layer '{}' has more than 4 channels", layer_name)));
+			}
+			if layer.sample_type != Some(sample_type)
+			{
+				layer.sample_type = None; // mixed
+			}
+			let component = layer.channels.len();
+			layer.channels.push(channel_name);
+			layer.framebuffer.pixels_mut().iter_mut()
+				.zip(channel.sample_data.values_as_f32())
+				.for_each(|(pixel, value)| pixel[component] = value);
+		}
+	}
+
+	for layer in &mut layers
+	{
+		sort_channels(layer);
+	}
+	Ok(layers)
+}
+
+// Prints every layer of an EXR file with the range and mean of each channel.
+pub fn print_info(path : &Path) -> Result<(), ExrError>
+{
+	let layers = read_exr(path)?;
+	for layer in &layers
+	{
+		let name = if layer.name.is_empty() { "(main)" } else { layer.name.as_str() };
+		let sample_type = layer.sample_type.map_or("mixed".to_string(), |sample_type| format!("{:?}", sample_type).to_lowercase());
+		println!("{} {}x{} {}", name, layer.framebuffer.width(), layer.framebuffer.height(), sample_type);
+
+		for (component, channel) in layer.channels.iter().enumerate()
+		{
+			let values = layer.framebuffer.pixels().iter().map(|pixel| pixel[component]);
+			let (min, max, sum, finite_count) = values.fold((f32::INFINITY, f32::NEG_INFINITY, 0.0_f64, 0), |(min, max, sum, count), value|
+			{
+				if value.is_finite() { (min.min(value), max.max(value), sum + value as f64, count + 1) } else { (min, max, sum, count) }
+			});
+			let mean = if finite_count > 0 { sum / finite_count as f64 } else { 0.0 };
+			println!("  {}: min {} max {} mean {:.6} ({} non finite)", channel, min, max, mean, layer.framebuffer.pixels().len() - finite_count);
+		}
+	}
+	Ok(())
+}
+
+fn sort_channels(layer : &mut ExrLayer)
+{
+	const ORDER : [&str; 7] = ["R", "G", "B", "A", "X", "Y", "Z"];
+	let rank = |name : &str| ORDER.iter().position(|&ordered| ordered == name).unwrap_or(ORDER.len());
+
+	let mut order : Vec<usize> = (0..layer.channels.len()).collect();
+	order.sort_by(|&a, &b| (rank(&layer.channels[a]), &layer.channels[a]).cmp(&(rank(&layer.channels[b]), &layer.channels[b])));
+
+	layer.channels = order.iter().map(|&index| layer.channels[index].clone()).collect();
+	for pixel in layer.framebuffer.pixels_mut()
+	{
+		// channels a layer doesn't have read as 0, except alpha which stays at the framebuffer's default of 1.
+		let mut sorted = Vector4::new(0.0, 0.0, 0.0, 1.0);
+		for (component, &index) in order.iter().enumerate()
+		{
+			sorted[component] = pixel[index];
+		}
+		*pixel = sorted;
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// A file in the temp directory that is deleted again when the test is done with it.
+	struct TempFile(PathBuf);
+
+	impl TempFile
+	{
+		fn new(name : &str) -> Self
+		{
+			TempFile(std::env::temp_dir().join(format!("rust_raytracer_{}_{}.exr", name, std::process::id())))
+		}
+	}
+
+	impl Drop for TempFile
+	{
+		fn drop(&mut self)
+		{
+			let _ = std::fs::remove_file(&self.0);
+		}
+	}
+
+	// Odd sizes, so tiles don't divide the image. Values are multiples of 1/8 in [-8, 8), which half floats hold
+	// exactly, and when full precision is wanted they are nudged off the half grid.
+	const WIDTH : u32 = 37;
+	const HEIGHT : u32 = 21;
+
+	fn test_framebuffer(seed : u32, full_precision : bool) -> Framebuffer
+	{
+		let mut framebuffer = Framebuffer::new(WIDTH, HEIGHT);
+		for y in 0..HEIGHT
+		{
+			for x in 0..WIDTH
+			{
+				let value = |component : u32| -> f32
+				{
+					let grid = ((x * 7 + y * 3 + component * 5 + seed * 11) % 128) as f32 / 8.0 - 8.0;
+					if full_precision { grid + 1.0e-4 * (x + 1) as f32 } else { grid }
+				};
+				framebuffer.set(x, y, Vector4::new(value(0), value(1), value(2), value(3)));
+			}
+		}
+		framebuffer
+	}
+
+	fn test_aovs(full_precision : bool) -> AovBuffers
+	{
+		let mut aovs = AovBuffers::new(WIDTH, HEIGHT);
+		aovs.albedo = test_framebuffer(1, full_precision);
+		aovs.normal = test_framebuffer(2, full_precision);
+		for y in 0..HEIGHT
+		{
+			for x in 0..WIDTH
+			{
+				// the left column missed everything, the rest is at depths and with ids no half float holds.
+				let missed = x == 0;
+				let depth = if missed { f32::INFINITY } else { 1000.0 + x as f32 * 0.123_456_7 + y as f32 };
+				let id = if missed { 0.0 } else { (x * 70_000 + y) as f32 };
+				aovs.depth.set(x, y, Vector4::new(depth, 0.0, 0.0, 0.0));
+				aovs.object_id.set(x, y, Vector4::new(id, 0.0, 0.0, 0.0));
+			}
+		}
+		aovs
+	}
+
+	fn find<'a>(layers : &'a [ExrLayer], name : &str) -> &'a ExrLayer
+	{
+		layers.iter().find(|layer| layer.name == name).unwrap_or_else(|| panic!("layer '{}' is missing", name))
+	}
+
+	fn assert_same_channels(written : &ExrLayer, read : &ExrLayer)
+	{
+		assert_eq!(read.channels, written.channels, "layer '{}'", written.name);
+		for (index, (read_pixel, written_pixel)) in read.framebuffer.pixels().iter().zip(written.framebuffer.pixels()).enumerate()
+		{
+			for component in 0..written.channels.len()
+			{
+				assert_eq!(read_pixel[component], written_pixel[component], "layer '{}' pixel {} component {}", written.name, index, component);
+			}
+		}
+	}
+
+	fn round_trip(name : &str, sample_type : SampleType, tile_size : Option<u32>)
+	{
+		let full_precision = sample_type == SampleType::Float;
+		let beauty = test_framebuffer(0, full_precision);
+		let aovs = test_aovs(full_precision);
+		let written = render_layers(&beauty, Some(&aovs));
+
+		let file = TempFile::new(name);
+		let settings = ExrSettings { sample_type : sample_type, compression : Compression::Zip, tile_size : tile_size };
+		write_exr(&file.0, &written, &settings).expect("writing succeeds");
+		let read = read_exr(&file.0).expect("reading succeeds");
+
+		assert_eq!(read.len(), written.len());
+		for layer in &written
+		{
+			let read_layer = find(&read, &layer.name);
+			assert_eq!((read_layer.framebuffer.width(), read_layer.framebuffer.height()), (WIDTH, HEIGHT));
+			assert_eq!(read_layer.sample_type, Some(layer.sample_type.unwrap_or(sample_type)), "layer '{}'", layer.name);
+			assert_same_channels(layer, read_layer);
+		}
+	}
+
+	#[test]
+	fn half_scanlines_round_trip()
+	{
+		round_trip("half_scanlines", SampleType::Half, None);
+	}
+
+	#[test]
+	fn half_tiles_round_trip()
+	{
+		round_trip("half_tiles", SampleType::Half, Some(16));
+	}
+
+	#[test]
+	fn float_scanlines_round_trip()
+	{
+		round_trip("float_scanlines", SampleType::Float, None);
+	}
+
+	#[test]
+	fn float_tiles_round_trip()
+	{
+		round_trip("float_tiles", SampleType::Float, Some(16));
+	}
+
+	#[test]
+	fn depth_keeps_infinity_and_ids_stay_integers()
+	{
+		let aovs = test_aovs(false);
+		let file = TempFile::new("aovs");
+		write_exr(&file.0, &render_layers(&test_framebuffer(0, false), Some(&aovs)), &ExrSettings::default()).expect("writing succeeds");
+		let read = read_exr(&file.0).expect("reading succeeds");
+
+		let depth = find(&read, "depth");
+		assert_eq!(depth.channels, vec!["Z".to_string()]);
+		assert_eq!(depth.framebuffer.pixels()[0].x, f32::INFINITY);
+		assert_eq!(depth.framebuffer.pixels()[1].x, aovs.depth.pixels()[1].x);
+
+		let object_id = find(&read, "object_id");
+		assert_eq!(object_id.sample_type, Some(SampleType::Uint));
+		assert_eq!(object_id.framebuffer.pixels()[0].x, 0.0);
+		let last = (WIDTH * HEIGHT - 1) as usize;
+		assert_eq!(object_id.framebuffer.pixels()[last].x, ((WIDTH - 1) * 70_000 + HEIGHT - 1) as f32);
+	}
+
+	#[test]
+	fn every_compression_round_trips_half_samples()
+	{
+		let layers = vec![ExrLayer::new("", &["R", "G", "B", "A"], None, test_framebuffer(0, false))];
+		for &compression in &[Compression::None, Compression::Rle, Compression::Zips, Compression::Zip, Compression::Piz, Compression::Pxr24]
+		{
+			// pxr24 only rounds float channels, half ones are kept as they are.
+			let file = TempFile::new(&format!("{:?}", compression).to_lowercase());
+			let settings = ExrSettings { compression : compression, ..ExrSettings::default() };
+			write_exr(&file.0, &layers, &settings).expect("writing succeeds");
+			let read = read_exr(&file.0).expect("reading succeeds");
+			assert_same_channels(&layers[0], find(&read, ""));
+		}
+	}
+
+	#[test]
+	fn mismatched_layers_are_rejected()
+	{
+		let file = TempFile::new("rejected");
+		let small = ExrLayer::new("small", &["Y"], None, Framebuffer::new(WIDTH - 1, HEIGHT));
+		let error = write_exr(&file.0, &[ExrLayer::new("", &["R"], None, test_framebuffer(0, false)), small], &ExrSettings::default()).unwrap_err();
+		assert!(error.message.contains("layer 'small'"), "{}", error);
+
+		let twice = ExrLayer::new("", &["R", "R"], None, test_framebuffer(0, false));
+		let error = write_exr(&file.0, &[twice], &ExrSettings::default()).unwrap_err();
+		assert!(error.message.contains("written twice"), "{}", error);
+	}
+}
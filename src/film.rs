@@ -0,0 +1,529 @@
+use cgmath::Vector4;
+
+use crate::framebuffer::Framebuffer;
+use crate::tile_scheduler::Tile;
+
+// Reconstruction filters, weighting each sample by how far it lands from a pixel center.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterKind
+{
+	Box,            // the plain average of the samples in the pixel, sharp but aliased
+	Tent,           // linear falloff
+	Gaussian,       // soft, a standard deviation of a third of the radius
+	Mitchell,       // Mitchell-Netravali with B = C = 1/3, sharp with slight ringing
+	BlackmanHarris, // close to a Gaussian with less blur, Cycles' default
+}
+
+impl FilterKind
+{
+	pub const ALL : [FilterKind; 5] = [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::BlackmanHarris];
+
+	// Radius in pixels the filter is usually used with.
+	pub fn default_radius(self) -> f32
+	{
+		match self
+		{
+			FilterKind::Box => 0.5,
+			FilterKind::Tent => 1.0,
+			FilterKind::Gaussian => 1.5,
+			FilterKind::Mitchell => 2.0,
+			FilterKind::BlackmanHarris => 1.5,
+		}
+	}
+
+	pub fn name(self) -> &'static str
+	{
+		match self
+		{
+			FilterKind::Box => "box",
+			FilterKind::Tent => "tent",
+			FilterKind::Gaussian => "gaussian",
+			FilterKind::Mitchell => "mitchell",
+			FilterKind::BlackmanHarris => "blackman-harris",
+		}
+	}
+}
+
+impl std::str::FromStr for FilterKind
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		FilterKind::ALL.iter()
+			.copied()
+			.find(|kind| kind.name() == value)
+			.ok_or_else(|| format!("unknown filter '{}', expected box, tent, gaussian, mitchell or blackman-harris", value))
+	}
+}
+
+// A separable filter reaching radius pixels from the pixel center on each axis.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Filter
+{
+	pub kind : FilterKind,
+	pub radius : f32,
+}
+
+impl Default for Filter
+{
+	fn default() -> Self
+	{
+		Self { kind : FilterKind::Box, radius : FilterKind::Box.default_radius() }
+	}
+}
+
+impl Filter
+{
+	// Weight of a sample dx, dy pixels from the center. Samples on the far edge, at dx or dy = radius, are out,
+	// so with a radius of 0.5 the box filter counts every sample in exactly one pixel.
+	pub fn evaluate(&self, dx : f32, dy : f32) -> f32
+	{
+		self.evaluate_1d(dx) * self.evaluate_1d(dy)
+	}
+
+	fn evaluate_1d(&self, offset : f32) -> f32
+	{
+		let radius = self.radius;
+		if offset < -radius || offset >= radius
+		{
+			return 0.0;
+		}
+		let x = offset.abs();
+		match self.kind
+		{
+			FilterKind::Box => 1.0,
+			FilterKind::Tent => radius - x,
+			FilterKind::Gaussian =>
+			{
+				// less the value at the radius, so the filter goes to zero there instead of stopping short.
+				let sigma = radius / 3.0;
+				let gaussian = |x : f32| (-x * x / (2.0 * sigma * sigma)).exp();
+				gaussian(x) - gaussian(radius)
+			}
+			FilterKind::Mitchell =>
+			{
+				let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+				let x = 2.0 * x / radius;
+				let value = if x < 1.0
+				{
+					(12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+				}
+				else
+				{
+					(-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+				};
+				value / 6.0
+			}
+			FilterKind::BlackmanHarris =>
+			{
+				let phase = std::f32::consts::PI * (offset / radius + 1.0);
+				0.35875 - 0.48829 * phase.cos() + 0.14128 * (2.0 * phase).cos() - 0.01168 * (3.0 * phase).cos()
+			}
+		}
+	}
+
+	// How many pixels past its own a sample can reach.
+	fn reach(&self) -> u32
+	{
+		(self.radius - 0.5).ceil().max(0.0) as u32
+	}
+}
+
+// Film positions are in pixels from the top left corner of the image, so pixel x, y covers [x, x + 1) by
+// [y, y + 1) and has its center at x + 0.5, y + 0.5.
+// Accumulates samples weighted by the filter, resolving to their weighted average per pixel. Splats are added to
+// the pixel they land in unfiltered and scaled at resolve time, for paths that reach the camera from the lights.
+pub struct Film
+{
+	width : u32,
+	height : u32,
+	filter : Filter,
+	sums : Vec<Vector4<f32>>,
+	weights : Vec<f32>,
+	splats : Vec<Vector4<f32>>,
+}
+
+impl Film
+{
+	pub fn new(width : u32, height : u32, filter : Filter) -> Self
+	{
+		let count = (width * height) as usize;
+		Self
+		{
+			width : width,
+			height : height,
+			filter : filter,
+			sums : vec![Vector4::new(0.0, 0.0, 0.0, 0.0); count],
+			weights : vec![0.0; count],
+			splats : vec![Vector4::new(0.0, 0.0, 0.0, 0.0); count],
+		}
+	}
+
+	pub fn width(&self) -> u32
+	{
+		self.width
+	}
+
+	pub fn height(&self) -> u32
+	{
+		self.height
+	}
+
+	pub fn filter(&self) -> Filter
+	{
+		self.filter
+	}
+
+	pub fn set_filter(&mut self, filter : Filter)
+	{
+		self.filter = filter;
+		self.clear();
+	}
+
+	pub fn clear(&mut self)
+	{
+		self.sums.iter_mut().for_each(|sum| *sum = Vector4::new(0.0, 0.0, 0.0, 0.0));
+		self.weights.iter_mut().for_each(|weight| *weight = 0.0);
+		self.splats.iter_mut().for_each(|splat| *splat = Vector4::new(0.0, 0.0, 0.0, 0.0));
+	}
+
+	// Padded tiles overlap, so for the same result on every run they have to be merged in the same order.
+	pub fn merge_tile(&mut self, tile : &FilmTile)
+	{
+		for row in 0..tile.height
+		{
+			for column in 0..tile.width
+			{
+				let index = ((tile.y + row) * self.width + tile.x + column) as usize;
+				let tile_index = (row * tile.width + column) as usize;
+				self.sums[index] += tile.sums[tile_index];
+				self.weights[index] += tile.weights[tile_index];
+			}
+		}
+		for &(x, y, color) in &tile.splats
+		{
+			self.splat(x, y, color);
+		}
+	}
+
+	// Adds color to the pixel at film position x, y, if it's on the film.
+	pub fn splat(&mut self, x : f32, y : f32, color : Vector4<f32>)
+	{
+		if x >= 0.0 && y >= 0.0 && x < self.width as f32 && y < self.height as f32
+		{
+			let index = (y as u32 * self.width + x as u32) as usize;
+			self.splats[index] += color;
+		}
+	}
+
+	// The weighted average of the samples in every pixel plus its splats times splat_scale, usually one over the
+	// samples per pixel. The negative lobes of the Mitchell filter can ring below zero, which is clamped.
+	pub fn resolve(&self, framebuffer : &mut Framebuffer, splat_scale : f32)
+	{
+		assert!(framebuffer.width() == self.width && framebuffer.height() == self.height, "the framebuffer has to match the film");
+		for (((pixel, sum), &weight), splat) in framebuffer.pixels_mut().iter_mut().zip(&self.sums).zip(&self.weights).zip(&self.splats)
+		{
+			let average = if weight != 0.0 { *sum / weight } else { Vector4::new(0.0, 0.0, 0.0, 0.0) };
+			let color = average + *splat * splat_scale;
+			*pixel = Vector4::new(color.x.max(0.0), color.y.max(0.0), color.z.max(0.0), color.w.max(0.0));
+		}
+	}
+}
+
+// Part of a film a worker thread adds samples to on its own, merged into the film when the tile is done.
+pub struct FilmTile
+{
+	x : u32,
+	y : u32,
+	width : u32,
+	height : u32,
+	filter : Filter,
+	sums : Vec<Vector4<f32>>,
+	weights : Vec<f32>,
+	splats : Vec<(f32, f32, Vector4<f32>)>,
+}
+
+impl FilmTile
+{
+	// A tile of a film_width by film_height film to add the samples inside tile to, padded with the pixels around
+	// it they reach.
+	pub fn new(tile : Tile, film_width : u32, film_height : u32, filter : Filter) -> Self
+	{
+		let reach = filter.reach();
+		let (x, y) = (tile.x.saturating_sub(reach), tile.y.saturating_sub(reach));
+		let width = (tile.x + tile.width + reach).min(film_width) - x;
+		let height = (tile.y + tile.height + reach).min(film_height) - y;
+		let count = (width * height) as usize;
+		Self
+		{
+			x : x,
+			y : y,
+			width : width,
+			height : height,
+			filter : filter,
+			sums : vec![Vector4::new(0.0, 0.0, 0.0, 0.0); count],
+			weights : vec![0.0; count],
+			splats : Vec::new(),
+		}
+	}
+
+	// Adds a sample at film position x, y to every pixel of the tile whose center is within the filter's radius.
+	pub fn add_sample(&mut self, x : f32, y : f32, color : Vector4<f32>)
+	{
+		let radius = self.filter.radius;
+		let first = |position : f32, start : u32| ((position - 0.5 - radius).floor() + 1.0).max(start as f32) as u32;
+		let last = |position : f32, end : u32| ((position - 0.5 + radius).floor() + 1.0).min(end as f32).max(0.0) as u32;
+		for pixel_y in first(y, self.y)..last(y, self.y + self.height)
+		{
+			for pixel_x in first(x, self.x)..last(x, self.x + self.width)
+			{
+				let weight = self.filter.evaluate(x - (pixel_x as f32 + 0.5), y - (pixel_y as f32 + 0.5));
+				let index = ((pixel_y - self.y) * self.width + pixel_x - self.x) as usize;
+				self.sums[index] += color * weight;
+				self.weights[index] += weight;
+			}
+		}
+	}
+
+	// Kept until the tile is merged, splats can land anywhere on the film.
+	// No integrator traces paths from the lights yet, so only the tests splat for now.
+	#[cfg_attr(not(test), allow(dead_code))]
+	pub fn splat(&mut self, x : f32, y : f32, color : Vector4<f32>)
+	{
+		self.splats.push((x, y, color));
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::rng::Rng;
+	use crate::tile_scheduler;
+
+	// Area under the filter along one axis.
+	fn integral_1d(filter : &Filter) -> f32
+	{
+		let steps = 10_000;
+		let step = 2.0 * filter.radius / steps as f32;
+		(0..steps).map(|index| filter.evaluate_1d(-filter.radius + (index as f32 + 0.5) * step) * step).sum()
+	}
+
+	// Renders the pixel centers' x coordinate, a ramp the filters should leave alone, with stratified samples into a
+	// film in tiles, and into one the size of the image.
+	fn render_ramp(filter : Filter, width : u32, height : u32, tile_size : u32, samples_per_pixel : u32) -> Framebuffer
+	{
+		let mut film = Film::new(width, height, filter);
+		let strata = (samples_per_pixel as f32).sqrt() as u32;
+		for tile in tile_scheduler::tiles(width, height, tile_size)
+		{
+			let mut film_tile = FilmTile::new(tile, width, height, filter);
+			for y in tile.y..tile.y + tile.height
+			{
+				for x in tile.x..tile.x + tile.width
+				{
+					let mut rng = Rng::new((y * width + x) as u64, 0x7e57);
+					for index in 0..strata * strata
+					{
+						let sample_x = x as f32 + ((index % strata) as f32 + rng.next_f32()) / strata as f32;
+						let sample_y = y as f32 + ((index / strata) as f32 + rng.next_f32()) / strata as f32;
+						film_tile.add_sample(sample_x, sample_y, Vector4::new(1.0, 0.5, sample_x, 1.0));
+					}
+				}
+			}
+			film.merge_tile(&film_tile);
+		}
+		let mut framebuffer = Framebuffer::new(width, height);
+		film.resolve(&mut framebuffer, 1.0 / samples_per_pixel as f32);
+		framebuffer
+	}
+
+	// The filter is zero from its radius on, peaks at the center and integrates to something positive.
+	fn check_filter(kind : FilterKind)
+	{
+		for &radius in &[kind.default_radius(), 0.75, 2.5]
+		{
+			let filter = Filter { kind : kind, radius : radius };
+			assert!(integral_1d(&filter) > 0.0, "radius {}: integral {}", radius, integral_1d(&filter));
+			for &(dx, dy) in &[(radius, 0.0), (0.0, -radius - 1.0e-4), (radius + 0.3, radius + 0.3)]
+			{
+				assert_eq!(filter.evaluate(dx, dy), 0.0, "radius {} at {} {}", radius, dx, dy);
+			}
+			for index in 0..=100
+			{
+				let offset = radius * index as f32 / 100.0 - radius / 2.0;
+				assert!(filter.evaluate_1d(offset) <= filter.evaluate_1d(0.0) + 1.0e-6, "radius {} at {}", radius, offset);
+			}
+		}
+	}
+
+	// Constants come out of the film unchanged and ramps nearly so, the same whether it's filled in tiles or in one
+	// go. Pixels near the edges only get samples from one side, so ramps are only checked away from them.
+	fn check_resolve(kind : FilterKind)
+	{
+		let (width, height) = (37, 23);
+		let filter = Filter { kind : kind, radius : kind.default_radius() };
+		let (tiled, whole) = (render_ramp(filter, width, height, 8, 64), render_ramp(filter, width, height, width.max(height), 64));
+		for y in 0..height
+		{
+			for x in 0..width
+			{
+				let (pixel, other) = (tiled.pixels()[(y * width + x) as usize], whole.pixels()[(y * width + x) as usize]);
+				assert!((pixel.x - 1.0).abs() <= 1.0e-4 && (pixel.y - 0.5).abs() <= 1.0e-4 && (pixel.w - 1.0).abs() <= 1.0e-4,
+					"pixel {} {}: {:?}", x, y, pixel);
+				if x >= 3 && x + 3 < width
+				{
+					assert!((pixel.z - (x as f32 + 0.5)).abs() <= 0.02, "pixel {} {}: ramp at {}", x, y, pixel.z);
+				}
+				assert!((pixel - other).x.abs() <= 1.0e-5 && (pixel - other).z.abs() / width as f32 <= 1.0e-5,
+					"pixel {} {}: {:?} in tiles, {:?} in one go", x, y, pixel, other);
+			}
+		}
+	}
+
+	#[test]
+	fn box_filter_shape()
+	{
+		check_filter(FilterKind::Box);
+	}
+
+	#[test]
+	fn tent_filter_shape()
+	{
+		check_filter(FilterKind::Tent);
+	}
+
+	#[test]
+	fn gaussian_filter_shape()
+	{
+		check_filter(FilterKind::Gaussian);
+	}
+
+	#[test]
+	fn mitchell_filter_shape()
+	{
+		check_filter(FilterKind::Mitchell);
+	}
+
+	#[test]
+	fn blackman_harris_filter_shape()
+	{
+		check_filter(FilterKind::BlackmanHarris);
+	}
+
+	#[test]
+	fn box_filter_resolves_constants_and_ramps()
+	{
+		check_resolve(FilterKind::Box);
+	}
+
+	#[test]
+	fn tent_filter_resolves_constants_and_ramps()
+	{
+		check_resolve(FilterKind::Tent);
+	}
+
+	#[test]
+	fn gaussian_filter_resolves_constants_and_ramps()
+	{
+		check_resolve(FilterKind::Gaussian);
+	}
+
+	#[test]
+	fn mitchell_filter_resolves_constants_and_ramps()
+	{
+		check_resolve(FilterKind::Mitchell);
+	}
+
+	#[test]
+	fn blackman_harris_filter_resolves_constants_and_ramps()
+	{
+		check_resolve(FilterKind::BlackmanHarris);
+	}
+
+	#[test]
+	fn pixels_without_samples_resolve_to_zero()
+	{
+		let film = Film::new(4, 3, Filter::default());
+		let mut framebuffer = Framebuffer::new(4, 3);
+		film.resolve(&mut framebuffer, 1.0);
+		assert!(framebuffer.pixels().iter().all(|&pixel| pixel == Vector4::new(0.0, 0.0, 0.0, 0.0)));
+	}
+
+	// Every tile splats energy anywhere on the film and a little past it, once per sample, on top of a constant
+	// it samples its own pixels with. Resolved at one over the sample count, each pixel is the constant plus the
+	// mean energy per sample that landed in it, whichever tiles it came from.
+	fn check_splat_energy(sample_count : u32)
+	{
+		let (width, height) = (19, 13);
+		let background = Vector4::new(0.25, 0.25, 0.25, 1.0);
+		let mut film = Film::new(width, height, Filter { kind : FilterKind::Gaussian, radius : FilterKind::Gaussian.default_radius() });
+		let mut splatted = vec![Vector4::new(0.0, 0.0, 0.0, 0.0); (width * height) as usize];
+		let mut crossed_tiles = 0;
+
+		for sample in 0..sample_count
+		{
+			for (index, tile) in tile_scheduler::tiles(width, height, 8).into_iter().enumerate()
+			{
+				let mut film_tile = FilmTile::new(tile, width, height, film.filter());
+				let mut rng = Rng::new(index as u64, sample as u64);
+				for y in tile.y..tile.y + tile.height
+				{
+					for x in tile.x..tile.x + tile.width
+					{
+						film_tile.add_sample(x as f32 + rng.next_f32(), y as f32 + rng.next_f32(), background);
+					}
+				}
+				for _ in 0..40
+				{
+					let x = rng.next_f32() * (width + 2) as f32 - 1.0;
+					let y = rng.next_f32() * (height + 2) as f32 - 1.0;
+					let color = Vector4::new(1.0, 0.5, 2.0, 0.0) * rng.next_f32();
+					film_tile.splat(x, y, color);
+					if x >= 0.0 && y >= 0.0 && x < width as f32 && y < height as f32
+					{
+						splatted[(y as u32 * width + x as u32) as usize] += color;
+						let in_tile = x >= tile.x as f32 && y >= tile.y as f32 && x < (tile.x + tile.width) as f32 && y < (tile.y + tile.height) as f32;
+						crossed_tiles += !in_tile as u32;
+					}
+				}
+				film.merge_tile(&film_tile);
+			}
+		}
+		assert!(crossed_tiles > 0);
+
+		let mut framebuffer = Framebuffer::new(width, height);
+		film.resolve(&mut framebuffer, 1.0 / sample_count as f32);
+		let mut total = Vector4::new(0.0, 0.0, 0.0, 0.0);
+		for (index, (pixel, splat)) in framebuffer.pixels().iter().zip(&splatted).enumerate()
+		{
+			let expected = background + *splat / sample_count as f32;
+			for component in 0..4
+			{
+				assert!((pixel[component] - expected[component]).abs() <= 1.0e-5 * expected[component].max(1.0),
+					"{} samples, pixel {}: {:?} is not {:?}", sample_count, index, pixel, expected);
+			}
+			total += *pixel - background;
+		}
+
+		let energy = splatted.iter().fold(Vector4::new(0.0, 0.0, 0.0, 0.0), |sum, splat| sum + *splat) / sample_count as f32;
+		for component in 0..4
+		{
+			assert!((total[component] - energy[component]).abs() <= 1.0e-4 * energy[component].max(1.0),
+				"{} samples: {:?} in the image, {:?} splatted", sample_count, total, energy);
+		}
+	}
+
+	#[test]
+	fn splats_from_one_sample_keep_their_energy()
+	{
+		check_splat_energy(1);
+	}
+
+	#[test]
+	fn splats_from_many_samples_keep_their_mean_energy()
+	{
+		check_splat_energy(7);
+	}
+}
@@ -0,0 +1,110 @@
+use cgmath::Vector4;
+
+// Linear floating point RGBA image. Row 0 is the top of the image, matching the swap chain layout.
+#[derive(Clone, Debug)]
+pub struct Framebuffer
+{
+	width : u32,
+	height : u32,
+	pixels : Vec<Vector4<f32>>,
+}
+
+impl Framebuffer
+{
+	pub fn new(width : u32, height : u32) -> Self
+	{
+		Self
+		{
+			width : width,
+			height : height,
+			pixels : vec![Vector4::new(0.0, 0.0, 0.0, 1.0); (width * height) as usize],
+		}
+	}
+
+	pub fn width(&self) -> u32
+	{
+		self.width
+	}
+
+	pub fn height(&self) -> u32
+	{
+		self.height
+	}
+
+	pub fn aspect_ratio(&self) -> f32
+	{
+		self.width as f32 / self.height as f32
+	}
+
+	pub fn set(&mut self, x : u32, y : u32, color : Vector4<f32>)
+	{
+		let index = self.index(x, y);
+		self.pixels[index] = color;
+	}
+
+	// Copies tile in with its top left corner at x, y.
+	pub fn write_tile(&mut self, x : u32, y : u32, tile : &Framebuffer)
+	{
+		for row in 0..tile.height
+		{
+			let start = self.index(x, y + row);
+			let end = self.index(x + tile.width - 1, y + row) + 1;
+			let tile_start = (row * tile.width) as usize;
+			self.pixels[start..end].copy_from_slice(&tile.pixels[tile_start..tile_start + tile.width as usize]);
+		}
+	}
+
+	pub fn pixels(&self) -> &[Vector4<f32>]
+	{
+		&self.pixels
+	}
+
+	pub fn pixels_mut(&mut self) -> &mut [Vector4<f32>]
+	{
+		&mut self.pixels
+	}
+
+	fn index(&self, x : u32, y : u32) -> usize
+	{
+		assert!(x < self.width && y < self.height, "pixel ({}, {}) is outside of the {}x{} framebuffer", x, y, self.width, self.height);
+		(y * self.width + x) as usize
+	}
+}
+
+// Per pixel data about what the pixel center sees, besides the shaded color, for compositing and denoising.
+// Pixels that see the background have zero albedo and normal, infinite depth and object id 0.
+#[derive(Clone, Debug)]
+pub struct AovBuffers
+{
+	pub albedo : Framebuffer,    // surface color before shading
+	pub normal : Framebuffer,    // world space shading normal, facing the camera
+	pub depth : Framebuffer,     // distance from the camera along the ray, in x
+	pub object_id : Framebuffer, // in x, 1 + the index of the mesh that was hit
+}
+
+impl AovBuffers
+{
+	pub fn new(width : u32, height : u32) -> Self
+	{
+		Self
+		{
+			albedo : Framebuffer::new(width, height),
+			normal : Framebuffer::new(width, height),
+			depth : Framebuffer::new(width, height),
+			object_id : Framebuffer::new(width, height),
+		}
+	}
+
+	pub fn write_tile(&mut self, x : u32, y : u32, tile : &AovBuffers)
+	{
+		self.albedo.write_tile(x, y, &tile.albedo);
+		self.normal.write_tile(x, y, &tile.normal);
+		self.depth.write_tile(x, y, &tile.depth);
+		self.object_id.write_tile(x, y, &tile.object_id);
+	}
+}
+
+pub fn to_unorm8(value : f32) -> u8
+{
+	(value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
@@ -1,35 +1,755 @@
+use cgmath::InnerSpace;
+use cgmath::Vector2;
 use cgmath::Vector3;
 use cgmath::Vector4;
 
+use crate::ray::{ Ray, HitRecord, Hittable };
+
+#[derive(Copy, Clone, Debug)]
 pub struct ColoredVertex
 {
-	#[allow(dead_code)]
-	position : Vector3<f32>,
+	pub position : Vector3<f32>,
 
-	#[allow(dead_code)]
-	color : Vector4<f32>
+	pub color : Vector4<f32>
 }
 
-static ASPECT_RATIO : f32 = 1280.0/720.0;
-
+// World space triangle in the z = 0 plane, framed by scene::sample_triangle_camera.
 pub fn sample_colored_triangle_vertices() -> [ColoredVertex; 3]
 {
-	return 
 	[
 		ColoredVertex 
 		{ 
-			position : Vector3::new(0.0, 0.25 * ASPECT_RATIO, 0.0), 
+			position : Vector3::new(0.0, 0.25, 0.0), 
 			color : Vector4::new(1.0, 0.0, 0.0, 1.0)
 		},
 		ColoredVertex 
 		{ 
-			position : Vector3::new(0.25, -0.25 * ASPECT_RATIO, 0.0), 
+			position : Vector3::new(0.25, -0.25, 0.0), 
 			color : Vector4::new(0.0, 1.0, 0.0, 1.0)
 		},
 		ColoredVertex 
 		{ 
-			position : Vector3::new(-0.25, -0.25 * ASPECT_RATIO, 0.0), 
+			position : Vector3::new(-0.25, -0.25, 0.0), 
 			color : Vector4::new(0.0, 0.0, 1.0, 1.0)
 		},
 	]
-}
\ No newline at end of file
+}
+
+// Where a ray met a primitive. The normal is the primitive's outward facing normal, front_face tells
+// whether the ray arrived from that side.
+#[derive(Copy, Clone, Debug)]
+pub struct SurfaceHit
+{
+	pub t : f32,
+	pub normal : Vector3<f32>,
+	pub uv : Vector2<f32>,
+	pub front_face : bool,
+}
+
+impl SurfaceHit
+{
+	fn new(ray : &Ray, t : f32, outward_normal : Vector3<f32>, uv : Vector2<f32>) -> Self
+	{
+		Self
+		{
+			t : t,
+			normal : outward_normal,
+			uv : uv,
+			front_face : ray.direction.dot(outward_normal) < 0.0,
+		}
+	}
+}
+
+pub trait Intersect
+{
+	// Closest intersection with t in [t_min, t_max].
+	fn intersect(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<SurfaceHit>;
+}
+
+// Primitives with finite extent, which is what acceleration structures need.
+pub trait Bounded
+{
+	fn bounds(&self) -> Aabb;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Sphere
+{
+	pub center : Vector3<f32>,
+	pub radius : f32,
+}
+
+impl Sphere
+{
+	pub fn new(center : Vector3<f32>, radius : f32) -> Self
+	{
+		Self { center : center, radius : radius }
+	}
+}
+
+impl Bounded for Sphere
+{
+	fn bounds(&self) -> Aabb
+	{
+		let radius = Vector3::new(self.radius, self.radius, self.radius);
+		Aabb::new(self.center - radius, self.center + radius)
+	}
+}
+
+impl Intersect for Sphere
+{
+	// uv is longitude and latitude: u wraps around +y starting at -x, v goes from the bottom pole to the top.
+	fn intersect(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<SurfaceHit>
+	{
+		let to_origin = ray.origin - self.center;
+		let a = ray.direction.magnitude2();
+		let half_b = to_origin.dot(ray.direction);
+		let c = to_origin.magnitude2() - self.radius * self.radius;
+
+		let discriminant = half_b * half_b - a * c;
+		if discriminant < 0.0 || a == 0.0
+		{
+			return None;
+		}
+
+		// the numerically stable form of the quadratic roots, avoids cancellation when half_b ~ sqrt(discriminant).
+		let q = -(half_b + discriminant.sqrt().copysign(half_b));
+		let (mut t0, mut t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+		if t0 > t1
+		{
+			std::mem::swap(&mut t0, &mut t1);
+		}
+
+		// the near root is behind t_min when the origin is inside the sphere, then the far root is the exit.
+		let t = if t0 >= t_min && t0 <= t_max { t0 } else if t1 >= t_min && t1 <= t_max { t1 } else { return None };
+
+		let outward_normal = (ray.at(t) - self.center) / self.radius;
+		let phi = (-outward_normal.z).atan2(outward_normal.x) + std::f32::consts::PI;
+		let theta = (-outward_normal.y).clamp(-1.0, 1.0).acos();
+		let uv = Vector2::new(phi / (2.0 * std::f32::consts::PI), theta / std::f32::consts::PI);
+
+		Some(SurfaceHit::new(ray, t, outward_normal, uv))
+	}
+}
+
+// Infinite, two sided plane through point.
+#[derive(Copy, Clone, Debug)]
+pub struct Plane
+{
+	pub point : Vector3<f32>,
+	pub normal : Vector3<f32>,
+}
+
+impl Plane
+{
+	pub fn new(point : Vector3<f32>, normal : Vector3<f32>) -> Self
+	{
+		Self { point : point, normal : normal.normalize() }
+	}
+}
+
+impl Intersect for Plane
+{
+	// uv is the distance from point along two tangents of the plane, so textures tile in world units.
+	fn intersect(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<SurfaceHit>
+	{
+		let denominator = self.normal.dot(ray.direction);
+		if denominator.abs() <= f32::EPSILON * ray.direction.magnitude()
+		{
+			return None; // parallel, including rays that lie in the plane
+		}
+
+		let t = self.normal.dot(self.point - ray.origin) / denominator;
+		if t < t_min || t > t_max
+		{
+			return None;
+		}
+
+		let (tangent, bitangent) = orthonormal_basis(self.normal);
+		let offset = ray.at(t) - self.point;
+		let uv = Vector2::new(offset.dot(tangent), offset.dot(bitangent));
+
+		Some(SurfaceHit::new(ray, t, self.normal, uv))
+	}
+}
+
+// Axis aligned bounding box. Also a solid primitive in its own right.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb
+{
+	pub min : Vector3<f32>,
+	pub max : Vector3<f32>,
+}
+
+impl Aabb
+{
+	pub fn new(min : Vector3<f32>, max : Vector3<f32>) -> Self
+	{
+		Self { min : min, max : max }
+	}
+
+	// Contains nothing, the identity for union.
+	pub fn empty() -> Self
+	{
+		Self
+		{
+			min : Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+			max : Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+		}
+	}
+
+	pub fn from_points(points : &[Vector3<f32>]) -> Self
+	{
+		points.iter().fold(Self::empty(), |bounds, &point| bounds.grow(point))
+	}
+
+	pub fn grow(&self, point : Vector3<f32>) -> Self
+	{
+		Self
+		{
+			min : Vector3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+			max : Vector3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z)),
+		}
+	}
+
+	pub fn union(&self, other : &Aabb) -> Self
+	{
+		self.grow(other.min).grow(other.max)
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+	}
+
+	pub fn centroid(&self) -> Vector3<f32>
+	{
+		(self.min + self.max) * 0.5
+	}
+
+	pub fn extent(&self) -> Vector3<f32>
+	{
+		self.max - self.min
+	}
+
+	pub fn surface_area(&self) -> f32
+	{
+		if self.is_empty()
+		{
+			return 0.0;
+		}
+		let extent = self.extent();
+		2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+	}
+
+	// 0, 1 or 2 for the x, y or z axis, whichever the box is longest along.
+	pub fn longest_axis(&self) -> usize
+	{
+		let extent = self.extent();
+		if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 }
+	}
+
+	// Cheap overlap test for traversal, inverse_direction is 1 / ray.direction computed once per ray.
+	// A zero direction component gives 0 * infinity = NaN on a slab the origin lies on, every comparison
+	// with NaN is false so that slab leaves the range alone instead of culling the ray.
+	pub fn overlaps_ray(&self, ray : &Ray, inverse_direction : Vector3<f32>, t_min : f32, t_max : f32) -> bool
+	{
+		let mut t_near = t_min;
+		let mut t_far = t_max;
+
+		for axis in 0..3
+		{
+			let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction[axis];
+			let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction[axis];
+			if t0 > t1
+			{
+				std::mem::swap(&mut t0, &mut t1);
+			}
+			if t0 > t_near
+			{
+				t_near = t0;
+			}
+			if t1 < t_far
+			{
+				t_far = t1;
+			}
+		}
+
+		t_near <= t_far
+	}
+
+	// Slab test. Returns the parametric range the ray spends inside the box, clipped to [t_min, t_max],
+	// and the axes of the slabs the ray enters and exits through.
+	fn slab_range(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<(f32, usize, f32, usize)>
+	{
+		let mut t_near = f32::NEG_INFINITY;
+		let mut t_far = f32::INFINITY;
+		let mut near_axis = 0;
+		let mut far_axis = 0;
+
+		for axis in 0..3
+		{
+			if ray.direction[axis] == 0.0
+			{
+				// parallel to this slab: inside it for all t, or never. Avoids 0 * infinity = NaN below.
+				if ray.origin[axis] < self.min[axis] || ray.origin[axis] > self.max[axis]
+				{
+					return None;
+				}
+				continue;
+			}
+
+			let inverse_direction = 1.0 / ray.direction[axis];
+			let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+			let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+			if t0 > t1
+			{
+				std::mem::swap(&mut t0, &mut t1);
+			}
+
+			if t0 > t_near
+			{
+				t_near = t0;
+				near_axis = axis;
+			}
+			if t1 < t_far
+			{
+				t_far = t1;
+				far_axis = axis;
+			}
+		}
+
+		if t_near > t_far || t_far < t_min || t_near > t_max
+		{
+			return None;
+		}
+		Some((t_near, near_axis, t_far, far_axis))
+	}
+
+	fn face_uv(&self, point : Vector3<f32>, axis : usize) -> Vector2<f32>
+	{
+		let coordinate = |axis : usize|
+		{
+			let extent = self.max[axis] - self.min[axis];
+			if extent > 0.0 { (point[axis] - self.min[axis]) / extent } else { 0.0 }
+		};
+		Vector2::new(coordinate((axis + 1) % 3), coordinate((axis + 2) % 3))
+	}
+}
+
+impl Bounded for Aabb
+{
+	fn bounds(&self) -> Aabb
+	{
+		*self
+	}
+}
+
+impl Intersect for Aabb
+{
+	// uv spans [0, 1] across each face, over the two axes that follow the face's axis (yz, zx or xy).
+	fn intersect(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<SurfaceHit>
+	{
+		let (t_near, near_axis, t_far, far_axis) = self.slab_range(ray, t_min, t_max)?;
+
+		// entering through the near slab, or leaving through the far one when the origin is inside.
+		let (t, axis, sign) = if t_near >= t_min { (t_near, near_axis, -1.0) } else if t_far <= t_max { (t_far, far_axis, 1.0) } else { return None };
+
+		let mut outward_normal = Vector3::new(0.0, 0.0, 0.0);
+		outward_normal[axis] = sign * ray.direction[axis].signum();
+
+		Some(SurfaceHit::new(ray, t, outward_normal, self.face_uv(ray.at(t), axis)))
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Triangle
+{
+	pub p0 : Vector3<f32>,
+	pub p1 : Vector3<f32>,
+	pub p2 : Vector3<f32>,
+}
+
+impl Triangle
+{
+	pub fn new(p0 : Vector3<f32>, p1 : Vector3<f32>, p2 : Vector3<f32>) -> Self
+	{
+		Self { p0 : p0, p1 : p1, p2 : p2 }
+	}
+}
+
+impl Bounded for Triangle
+{
+	fn bounds(&self) -> Aabb
+	{
+		Aabb::from_points(&[self.p0, self.p1, self.p2])
+	}
+}
+
+impl Intersect for Triangle
+{
+	// Möller–Trumbore. uv are the barycentric weights of p1 and p2, p0's weight is 1 - u - v.
+	// The outward normal follows the counter clockwise winding p0 -> p1 -> p2.
+	fn intersect(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<SurfaceHit>
+	{
+		let edge1 = self.p1 - self.p0;
+		let edge2 = self.p2 - self.p0;
+
+		let normal = edge1.cross(edge2);
+		let double_area = normal.magnitude();
+		if double_area == 0.0
+		{
+			return None; // degenerate, the points are collinear
+		}
+
+		let p = ray.direction.cross(edge2);
+		let determinant = edge1.dot(p);
+		if determinant.abs() <= f32::EPSILON * double_area * ray.direction.magnitude()
+		{
+			return None; // ray is parallel to the triangle
+		}
+
+		let inverse_determinant = 1.0 / determinant;
+		let to_origin = ray.origin - self.p0;
+		let u = to_origin.dot(p) * inverse_determinant;
+		if !(0.0..=1.0).contains(&u)
+		{
+			return None;
+		}
+
+		let q = to_origin.cross(edge1);
+		let v = ray.direction.dot(q) * inverse_determinant;
+		if v < 0.0 || u + v > 1.0
+		{
+			return None;
+		}
+
+		let t = edge2.dot(q) * inverse_determinant;
+		if t < t_min || t > t_max
+		{
+			return None;
+		}
+
+		Some(SurfaceHit::new(ray, t, normal / double_area, Vector2::new(u, v)))
+	}
+}
+
+// Two unit vectors perpendicular to the unit vector n and each other (Duff et al. 2017).
+pub fn orthonormal_basis(n : Vector3<f32>) -> (Vector3<f32>, Vector3<f32>)
+{
+	let sign = 1.0f32.copysign(n.z);
+	let a = -1.0 / (sign + n.z);
+	let b = n.x * n.y * a;
+	(
+		Vector3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+		Vector3::new(b, sign + n.y * n.y * a, -n.y),
+	)
+}
+
+// A triangle made of three ColoredVertex, as uploaded to the vertex buffer by the dx_renderer.
+// The hit color is the barycentric blend of the vertex colors, matching the rasterizer's interpolation.
+#[derive(Copy, Clone, Debug)]
+pub struct ColoredTriangle
+{
+	pub vertices : [ColoredVertex; 3],
+}
+
+impl ColoredTriangle
+{
+	pub fn new(vertices : [ColoredVertex; 3]) -> Self
+	{
+		Self { vertices : vertices }
+	}
+}
+
+impl Bounded for ColoredTriangle
+{
+	fn bounds(&self) -> Aabb
+	{
+		let [v0, v1, v2] = self.vertices;
+		Aabb::from_points(&[v0.position, v1.position, v2.position])
+	}
+}
+
+impl Hittable for ColoredTriangle
+{
+	fn hit(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<HitRecord>
+	{
+		let [v0, v1, v2] = self.vertices;
+		let surface_hit = Triangle::new(v0.position, v1.position, v2.position).intersect(ray, t_min, t_max)?;
+
+		let (u, v) = (surface_hit.uv.x, surface_hit.uv.y);
+		let color = v0.color * (1.0 - u - v) + v1.color * u + v2.color * v;
+
+		Some(HitRecord::new(ray, &surface_hit, color))
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use cgmath::{ InnerSpace, Vector2, Vector3, Vector4 };
+
+	use super::*;
+
+	fn ray(origin : (f32, f32, f32), direction : (f32, f32, f32)) -> Ray
+	{
+		Ray::new(Vector3::new(origin.0, origin.1, origin.2), Vector3::new(direction.0, direction.1, direction.2))
+	}
+
+	fn assert_near(actual : f32, expected : f32)
+	{
+		assert!((actual - expected).abs() < 1.0e-5, "{}, expected {}", actual, expected);
+	}
+
+	fn assert_near_vector(actual : Vector3<f32>, expected : Vector3<f32>)
+	{
+		assert!((actual - expected).magnitude() < 1.0e-5, "{:?}, expected {:?}", actual, expected);
+	}
+
+	fn unit_sphere() -> Sphere
+	{
+		Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0)
+	}
+
+	#[test]
+	fn sphere_hit_from_outside_enters_the_near_side()
+	{
+		let hit = unit_sphere().intersect(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).expect("aimed at the center");
+		assert_near(hit.t, 4.0);
+		assert_near_vector(hit.normal, Vector3::new(0.0, 0.0, 1.0));
+		assert!(hit.front_face);
+
+		// an unnormalized direction scales t, not the point.
+		let hit = unit_sphere().intersect(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -4.0)), 0.0, f32::INFINITY).expect("aimed at the center");
+		assert_near(hit.t, 1.0);
+	}
+
+	#[test]
+	fn sphere_hit_from_inside_leaves_through_the_far_side()
+	{
+		let hit = unit_sphere().intersect(&ray((0.0, 0.0, 0.5), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).expect("the origin is inside");
+		assert_near(hit.t, 1.5);
+		assert_near_vector(hit.normal, Vector3::new(0.0, 0.0, -1.0));
+		assert!(!hit.front_face);
+	}
+
+	#[test]
+	fn sphere_grazing_rays_touch_or_miss()
+	{
+		let tangent = unit_sphere().intersect(&ray((1.0, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).expect("the tangent touches");
+		assert_near(tangent.t, 5.0);
+		assert_near_vector(tangent.normal, Vector3::new(1.0, 0.0, 0.0));
+		assert!(unit_sphere().intersect(&ray((1.0001, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).is_none());
+	}
+
+	#[test]
+	fn sphere_respects_the_t_range()
+	{
+		let through = ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0));
+		assert!(unit_sphere().intersect(&through, 0.0, 3.9).is_none());
+		assert!(unit_sphere().intersect(&through, 6.1, f32::INFINITY).is_none());
+		assert_near(unit_sphere().intersect(&through, 4.5, f32::INFINITY).expect("the far side is in range").t, 6.0);
+		assert!(unit_sphere().intersect(&ray((0.0, 0.0, 5.0), (0.0, 0.0, 1.0)), 0.0, f32::INFINITY).is_none(), "behind the ray");
+	}
+
+	#[test]
+	fn sphere_degenerate_cases()
+	{
+		assert!(unit_sphere().intersect(&ray((0.0, 0.0, 5.0), (0.0, 0.0, 0.0)), 0.0, f32::INFINITY).is_none(), "zero direction");
+
+		// small and far away, where the textbook quadratic loses the near root to cancellation.
+		let far = Sphere::new(Vector3::new(0.0, 0.0, -1.0e3), 1.0);
+		let hit = far.intersect(&ray((0.0, 0.0, 0.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).expect("straight ahead");
+		assert!((hit.t - 999.0).abs() < 1.0e-3, "t = {}", hit.t);
+
+		let poles = [(Vector3::new(0.0, -1.0, 0.0), 0.0), (Vector3::new(0.0, 1.0, 0.0), 1.0)];
+		for &(pole, v) in &poles
+		{
+			let hit = unit_sphere().intersect(&Ray::new(pole * 2.0, -pole), 0.0, f32::INFINITY).expect("aimed at a pole");
+			assert_near(hit.uv.y, v);
+			assert!(hit.uv.x.is_finite());
+		}
+		assert_eq!(unit_sphere().bounds(), Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0)));
+	}
+
+	#[test]
+	fn plane_hits_from_either_side()
+	{
+		let plane = Plane::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 2.0, 0.0));
+		assert_near_vector(plane.normal, Vector3::new(0.0, 1.0, 0.0));
+
+		let above = plane.intersect(&ray((3.0, 4.0, -2.0), (0.0, -1.0, 0.0)), 0.0, f32::INFINITY).expect("straight down");
+		assert_near(above.t, 3.0);
+		assert!(above.front_face);
+
+		let below = plane.intersect(&ray((0.0, -1.0, 0.0), (0.0, 1.0, 0.0)), 0.0, f32::INFINITY).expect("straight up");
+		assert_near(below.t, 2.0);
+		assert!(!below.front_face);
+		assert_near_vector(below.normal, Vector3::new(0.0, 1.0, 0.0));
+
+		// uv are world units from the plane's point.
+		let offset = plane.intersect(&ray((3.0, 4.0, 0.0), (0.0, -1.0, 0.0)), 0.0, f32::INFINITY).expect("straight down");
+		assert_near(offset.uv.magnitude(), 3.0);
+	}
+
+	#[test]
+	fn plane_parallel_and_grazing_rays()
+	{
+		let plane = Plane::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+		assert!(plane.intersect(&ray((0.0, 1.0, 0.0), (1.0, 0.0, 0.0)), 0.0, f32::INFINITY).is_none(), "parallel above");
+		assert!(plane.intersect(&ray((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)), 0.0, f32::INFINITY).is_none(), "lying in the plane");
+
+		let grazing = plane.intersect(&ray((0.0, 1.0, 0.0), (1.0, -1.0e-3, 0.0)), 0.0, f32::INFINITY).expect("shallow but not parallel");
+		assert!((grazing.t - 1000.0).abs() < 0.1, "t = {}", grazing.t);
+		assert!(plane.intersect(&ray((0.0, 1.0, 0.0), (1.0, -1.0e-3, 0.0)), 0.0, 999.0).is_none());
+	}
+
+	fn unit_box() -> Aabb
+	{
+		Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0))
+	}
+
+	#[test]
+	fn box_hit_from_outside_and_inside()
+	{
+		let outside = unit_box().intersect(&ray((-5.0, 0.5, 0.0), (1.0, 0.0, 0.0)), 0.0, f32::INFINITY).expect("aimed at the -x face");
+		assert_near(outside.t, 4.0);
+		assert_near_vector(outside.normal, Vector3::new(-1.0, 0.0, 0.0));
+		assert!(outside.front_face);
+		assert_near(outside.uv.x, 0.75);
+		assert_near(outside.uv.y, 0.5);
+
+		let inside = unit_box().intersect(&ray((0.0, 0.0, 0.0), (0.0, 0.0, -2.0)), 0.0, f32::INFINITY).expect("the origin is inside");
+		assert_near(inside.t, 0.5);
+		assert_near_vector(inside.normal, Vector3::new(0.0, 0.0, -1.0));
+		assert!(!inside.front_face);
+	}
+
+	#[test]
+	fn box_grazing_rays()
+	{
+		// along a face and along an edge, parallel to the slabs they lie on.
+		let face = unit_box().intersect(&ray((-5.0, 1.0, 0.0), (1.0, 0.0, 0.0)), 0.0, f32::INFINITY).expect("sliding along the top face");
+		assert_near(face.t, 4.0);
+		let edge = unit_box().intersect(&ray((-5.0, 1.0, 1.0), (1.0, 0.0, 0.0)), 0.0, f32::INFINITY).expect("sliding along an edge");
+		assert_near(edge.t, 4.0);
+		assert!(unit_box().intersect(&ray((-5.0, 1.0001, 0.0), (1.0, 0.0, 0.0)), 0.0, f32::INFINITY).is_none());
+
+		// through a corner.
+		let corner = unit_box().intersect(&ray((-2.0, -2.0, -2.0), (1.0, 1.0, 1.0)), 0.0, f32::INFINITY).expect("aimed at the corner");
+		assert_near(corner.t, 1.0);
+		assert!(unit_box().intersect(&ray((-2.0, -2.0, -2.0), (-1.0, -1.0, -1.0)), 0.0, f32::INFINITY).is_none(), "behind the ray");
+	}
+
+	#[test]
+	fn box_t_range_and_degenerate_boxes()
+	{
+		let through = ray((-5.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+		assert!(unit_box().intersect(&through, 0.0, 3.9).is_none());
+		assert_near(unit_box().intersect(&through, 4.5, f32::INFINITY).expect("the exit is in range").t, 6.0);
+		assert!(unit_box().intersect(&through, 6.5, f32::INFINITY).is_none());
+
+		// a flat box still stops rays crossing it.
+		let flat = Aabb::new(Vector3::new(-1.0, -1.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+		let hit = flat.intersect(&ray((0.0, 0.0, 3.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).expect("crossing the flat box");
+		assert_near(hit.t, 3.0);
+		assert_near_vector(hit.normal, Vector3::new(0.0, 0.0, 1.0));
+
+		assert!(Aabb::empty().is_empty());
+		assert_eq!(Aabb::empty().surface_area(), 0.0);
+		assert!(Aabb::empty().intersect(&through, 0.0, f32::INFINITY).is_none());
+		assert_eq!(Aabb::empty().union(&unit_box()), unit_box());
+	}
+
+	#[test]
+	fn box_overlap_tolerates_zero_direction_components()
+	{
+		let along_x = ray((-5.0, 0.0, 1.0), (1.0, 0.0, 0.0));
+		let inverse = Vector3::new(1.0 / along_x.direction.x, 1.0 / along_x.direction.y, 1.0 / along_x.direction.z);
+		assert!(unit_box().overlaps_ray(&along_x, inverse, 0.0, f32::INFINITY), "on the z = max slab");
+		assert!(!unit_box().overlaps_ray(&along_x, inverse, 0.0, 3.0));
+
+		let outside = ray((-5.0, 0.0, 2.0), (1.0, 0.0, 0.0));
+		assert!(!unit_box().overlaps_ray(&outside, inverse, 0.0, f32::INFINITY));
+	}
+
+	fn triangle() -> Triangle
+	{
+		Triangle::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0))
+	}
+
+	#[test]
+	fn triangle_hit_has_barycentric_uv_and_winding_normal()
+	{
+		let hit = triangle().intersect(&ray((0.25, 0.5, 2.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).expect("inside the triangle");
+		assert_near(hit.t, 2.0);
+		assert_near(hit.uv.x, 0.25);
+		assert_near(hit.uv.y, 0.5);
+		assert_near_vector(hit.normal, Vector3::new(0.0, 0.0, 1.0));
+		assert!(hit.front_face);
+
+		let back = triangle().intersect(&ray((0.25, 0.25, -1.0), (0.0, 0.0, 1.0)), 0.0, f32::INFINITY).expect("from behind");
+		assert!(!back.front_face);
+	}
+
+	#[test]
+	fn triangle_edges_vertices_and_grazing_rays()
+	{
+		let down = |x : f32, y : f32| ray((x, y, 1.0), (0.0, 0.0, -1.0));
+		assert!(triangle().intersect(&down(0.5, 0.0), 0.0, f32::INFINITY).is_some(), "on an edge");
+		assert!(triangle().intersect(&down(0.5, 0.5), 0.0, f32::INFINITY).is_some(), "on the hypotenuse");
+		assert!(triangle().intersect(&down(0.0, 0.0), 0.0, f32::INFINITY).is_some(), "on a vertex");
+		assert!(triangle().intersect(&down(0.5, -0.001), 0.0, f32::INFINITY).is_none());
+		assert!(triangle().intersect(&down(0.501, 0.5), 0.0, f32::INFINITY).is_none());
+
+		assert!(triangle().intersect(&ray((-1.0, 0.25, 0.0), (1.0, 0.0, 0.0)), 0.0, f32::INFINITY).is_none(), "in the triangle's plane");
+		assert!(triangle().intersect(&ray((-1.0, 0.25, 1.0), (1.0, 0.0, 0.0)), 0.0, f32::INFINITY).is_none(), "parallel above");
+		let grazing = triangle().intersect(&ray((-1.0, 0.25, 1.0e-3), (1.0, 0.0, -1.0e-3)), 0.0, f32::INFINITY).expect("shallow");
+		assert!((grazing.t - 1.0).abs() < 1.0e-3, "t = {}", grazing.t);
+	}
+
+	#[test]
+	fn triangle_t_range_and_degenerate_triangles()
+	{
+		let through = ray((0.25, 0.25, 2.0), (0.0, 0.0, -1.0));
+		assert!(triangle().intersect(&through, 0.0, 1.9).is_none());
+		assert!(triangle().intersect(&through, 2.1, f32::INFINITY).is_none());
+
+		let collinear = Triangle::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 0.0), Vector3::new(2.0, 2.0, 0.0));
+		assert!(collinear.intersect(&ray((1.0, 1.0, 1.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).is_none());
+		let point = Triangle::new(Vector3::new(1.0, 1.0, 0.0), Vector3::new(1.0, 1.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+		assert!(point.intersect(&ray((1.0, 1.0, 1.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).is_none());
+		assert!(triangle().intersect(&ray((0.25, 0.25, 2.0), (0.0, 0.0, 0.0)), 0.0, f32::INFINITY).is_none(), "zero direction");
+	}
+
+	#[test]
+	fn colored_triangle_blends_vertex_colors()
+	{
+		let vertex = |x : f32, y : f32, color : Vector4<f32>| ColoredVertex { position : Vector3::new(x, y, 0.0), color : color };
+		let triangle = ColoredTriangle::new([
+			vertex(0.0, 0.0, Vector4::new(1.0, 0.0, 0.0, 1.0)),
+			vertex(1.0, 0.0, Vector4::new(0.0, 1.0, 0.0, 1.0)),
+			vertex(0.0, 1.0, Vector4::new(0.0, 0.0, 1.0, 1.0)),
+		]);
+		let hit = triangle.hit(&ray((0.25, 0.5, 1.0), (0.0, 0.0, -1.0)), 0.0, f32::INFINITY).expect("inside the triangle");
+		assert!((hit.color - Vector4::new(0.25, 0.25, 0.5, 1.0)).magnitude() < 1.0e-5, "{:?}", hit.color);
+		assert_eq!(hit.uv, Vector2::new(0.25, 0.5));
+	}
+
+	#[test]
+	fn orthonormal_basis_is_orthonormal_for_every_normal()
+	{
+		let normals = [(0.0, 0.0, 1.0), (0.0, 0.0, -1.0), (1.0, 0.0, 0.0), (0.0, -1.0, 0.0), (0.3, -0.4, 0.866), (1.0e-4, 0.0, -1.0)];
+		for &(x, y, z) in &normals
+		{
+			let n = Vector3::new(x, y, z).normalize();
+			let (tangent, bitangent) = orthonormal_basis(n);
+			assert_near(tangent.magnitude(), 1.0);
+			assert_near(bitangent.magnitude(), 1.0);
+			assert_near(tangent.dot(n), 0.0);
+			assert_near(bitangent.dot(n), 0.0);
+			assert_near(tangent.dot(bitangent), 0.0);
+		}
+	}
+}
@@ -0,0 +1,490 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{ Path, PathBuf };
+
+use cgmath::InnerSpace;
+use cgmath::{ Matrix4, Rad, Vector2, Vector3, Vector4 };
+
+use crate::mesh::{ MaterialGroup, Mesh, MeshVertex };
+use crate::scene_description::{ CameraDescription, LightDescription, LightKind, MaterialDescription };
+use crate::scene_description::{ Node, Projection, SceneDescription, Texture, WrapMode };
+
+#[derive(Debug)]
+pub struct GltfError
+{
+	pub path : PathBuf,
+	pub message : String,
+}
+
+impl fmt::Display for GltfError
+{
+	fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}: {}", self.path.display(), self.message)
+	}
+}
+
+impl Error for GltfError {}
+
+// Loads a .gltf with its .bin buffers and images, or a self contained .glb. Only the default scene is kept,
+// or the first one when the file doesn't name a default. Animations and skins are ignored.
+pub fn load_gltf(path : &Path) -> Result<SceneDescription, GltfError>
+{
+	let error = |message : String| GltfError { path : path.to_path_buf(), message : message };
+
+	let (document, buffers, images) = gltf::import(path).map_err(|gltf_error| error(gltf_error.to_string()))?;
+	convert_document(&document, &buffers, &images).map_err(error)
+}
+
+// Buffers and images are the document's, already loaded.
+fn convert_document(document : &gltf::Document, buffers : &[gltf::buffer::Data], images : &[gltf::image::Data]) -> Result<SceneDescription, String>
+{
+	let mut scene = SceneDescription::default();
+
+	for texture in document.textures()
+	{
+		let image = &images[texture.source().index()];
+		let sampler = texture.sampler();
+		scene.textures.push(Texture
+		{
+			width : image.width,
+			height : image.height,
+			pixels : convert_pixels(image),
+			wrap_u : wrap_mode(sampler.wrap_s()),
+			wrap_v : wrap_mode(sampler.wrap_t()),
+		});
+	}
+
+	scene.materials = document.materials().map(|material| convert_material(&material)).collect();
+
+	for mesh in document.meshes()
+	{
+		scene.meshes.push(convert_mesh(&mesh, buffers).map_err(|message| format!("mesh {}: {}", mesh.index(), message))?);
+	}
+
+	for camera in document.cameras()
+	{
+		let projection = match camera.projection()
+		{
+			// the window decides the aspect ratio, and with it an orthographic camera's width.
+			gltf::camera::Projection::Perspective(perspective) => Projection::Perspective
+			{
+				vertical_fov : Rad(perspective.yfov()),
+				near : perspective.znear(),
+				far : perspective.zfar(),
+			},
+			gltf::camera::Projection::Orthographic(orthographic) => Projection::Orthographic
+			{
+				half_height : orthographic.ymag(),
+				near : orthographic.znear(),
+				far : orthographic.zfar(),
+			},
+		};
+		scene.cameras.push(CameraDescription { projection : projection });
+	}
+
+	for light in document.lights().into_iter().flatten()
+	{
+		let kind = match light.kind()
+		{
+			gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional { angular_diameter : Rad(0.0) },
+			gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+			gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } => LightKind::Spot
+			{
+				inner_cone_angle : Rad(inner_cone_angle),
+				outer_cone_angle : Rad(outer_cone_angle),
+			},
+		};
+		scene.lights.push(LightDescription
+		{
+			kind : kind,
+			color : Vector3::from(light.color()),
+			intensity : light.intensity(),
+			range : light.range(),
+		});
+	}
+
+	for node in document.nodes()
+	{
+		let mut description = Node::new(node.name().unwrap_or(""));
+		description.children = node.children().map(|child| child.index()).collect();
+		description.local_transform = Matrix4::from(node.transform().matrix());
+		description.mesh = node.mesh().map(|mesh| mesh.index());
+		description.camera = node.camera().map(|camera| camera.index());
+		description.light = node.light().map(|light| light.index());
+		scene.nodes.push(description);
+	}
+	for index in 0..scene.nodes.len()
+	{
+		for child in scene.nodes[index].children.clone()
+		{
+			scene.nodes[child].parent = Some(index);
+		}
+	}
+
+	if let Some(default_scene) = document.default_scene().or_else(|| document.scenes().next())
+	{
+		scene.roots = default_scene.nodes().map(|node| node.index()).collect();
+	}
+	scene.update_world_transforms();
+
+	Ok(scene)
+}
+
+fn wrap_mode(mode : gltf::texture::WrappingMode) -> WrapMode
+{
+	match mode
+	{
+		gltf::texture::WrappingMode::Repeat => WrapMode::Repeat,
+		gltf::texture::WrappingMode::MirroredRepeat => WrapMode::MirroredRepeat,
+		gltf::texture::WrappingMode::ClampToEdge => WrapMode::ClampToEdge,
+	}
+}
+
+// Expands any of the decoded formats to RGBA in [0, 1]. Single channel images are grey, two channel ones red and green.
+fn convert_pixels(image : &gltf::image::Data) -> Vec<Vector4<f32>>
+{
+	use gltf::image::Format;
+
+	let (channel_count, channel_size) = match image.format
+	{
+		Format::R8 => (1, 1),
+		Format::R8G8 => (2, 1),
+		Format::R8G8B8 => (3, 1),
+		Format::R8G8B8A8 => (4, 1),
+		Format::R16 => (1, 2),
+		Format::R16G16 => (2, 2),
+		Format::R16G16B16 => (3, 2),
+		Format::R16G16B16A16 => (4, 2),
+		Format::R32G32B32FLOAT => (3, 4),
+		Format::R32G32B32A32FLOAT => (4, 4),
+	};
+
+	let channel = |bytes : &[u8]| -> f32
+	{
+		match channel_size
+		{
+			1 => bytes[0] as f32 / 255.0,
+			2 => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+			_ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+		}
+	};
+
+	image.pixels.chunks_exact(channel_count * channel_size)
+		.map(|pixel|
+		{
+			let values : Vec<f32> = pixel.chunks_exact(channel_size).map(channel).collect();
+			match channel_count
+			{
+				1 => Vector4::new(values[0], values[0], values[0], 1.0),
+				2 => Vector4::new(values[0], values[1], 0.0, 1.0),
+				3 => Vector4::new(values[0], values[1], values[2], 1.0),
+				_ => Vector4::new(values[0], values[1], values[2], values[3]),
+			}
+		})
+		.collect()
+}
+
+// Normal, occlusion and emissive maps and the alpha mode have nothing in the renderer to drive, so they are left out.
+fn convert_material(material : &gltf::Material) -> MaterialDescription
+{
+	let pbr = material.pbr_metallic_roughness();
+	let emissive_strength = material.emissive_strength().unwrap_or(1.0);
+
+	MaterialDescription
+	{
+		name : material.name().unwrap_or("").to_string(),
+		base_color : Vector4::from(pbr.base_color_factor()),
+		base_color_texture : pbr.base_color_texture().map(|info| info.texture().index()),
+		metallic : pbr.metallic_factor(),
+		roughness : pbr.roughness_factor(),
+		metallic_roughness_texture : pbr.metallic_roughness_texture().map(|info| info.texture().index()),
+		specular : material.specular().map_or(1.0, |specular| specular.specular_factor()),
+		emission : Vector3::from(material.emissive_factor()) * emissive_strength,
+		index_of_refraction : material.ior().unwrap_or(1.5),
+		transmission : material.transmission().map_or(0.0, |transmission| transmission.transmission_factor()),
+		double_sided : material.double_sided(),
+		principled : None,
+	}
+}
+
+// All of a glTF mesh's triangle primitives become one mesh with a material group per primitive.
+// Only the first texture coordinate and color sets are read. Point and line primitives are skipped.
+fn convert_mesh(mesh : &gltf::Mesh, buffers : &[gltf::buffer::Data]) -> Result<Mesh, String>
+{
+	let mut converted = Mesh { name : mesh.name().unwrap_or("").to_string(), ..Mesh::default() };
+
+	for primitive in mesh.primitives()
+	{
+		let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+		let positions : Vec<Vector3<f32>> = reader.read_positions()
+			.ok_or_else(|| format!("primitive {} has no positions", primitive.index()))?
+			.map(Vector3::from)
+			.collect();
+		let vertex_count = positions.len();
+
+		let indices : Vec<u32> = match reader.read_indices()
+		{
+			Some(indices) => indices.into_u32().collect(),
+			None => (0..vertex_count as u32).collect(),
+		};
+		if let Some(&index) = indices.iter().find(|&&index| index as usize >= vertex_count)
+		{
+			return Err(format!("primitive {} has index {} but only {} vertices", primitive.index(), index, vertex_count));
+		}
+
+		let triangles = match primitive.mode()
+		{
+			gltf::mesh::Mode::Triangles => indices.chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect(),
+			gltf::mesh::Mode::TriangleStrip => (2..indices.len())
+				.map(|corner|
+				{
+					// every other triangle of a strip winds the other way, swap two corners to undo that.
+					if corner % 2 == 0 { [indices[corner - 2], indices[corner - 1], indices[corner]] }
+					else { [indices[corner - 1], indices[corner - 2], indices[corner]] }
+				})
+				.collect(),
+			gltf::mesh::Mode::TriangleFan => (2..indices.len()).map(|corner| [indices[0], indices[corner - 1], indices[corner]]).collect(),
+			_ => Vec::<[u32; 3]>::new(),
+		};
+		if triangles.is_empty()
+		{
+			continue;
+		}
+
+		let normals : Option<Vec<Vector3<f32>>> = reader.read_normals().map(|normals| normals.map(Vector3::from).collect());
+		let uvs : Option<Vec<Vector2<f32>>> = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().map(Vector2::from).collect());
+		let colors : Option<Vec<Vector4<f32>>> = reader.read_colors(0).map(|colors| colors.into_rgba_f32().map(Vector4::from).collect());
+
+		// like the rasterizer would, vertex colors are multiplied by the material's base color.
+		let base_color = Vector4::from(primitive.material().pbr_metallic_roughness().base_color_factor());
+		let vertex = |index : u32, normal : Vector3<f32>| -> MeshVertex
+		{
+			let index = index as usize;
+			let color = colors.as_ref().map_or(Vector4::new(1.0, 1.0, 1.0, 1.0), |colors| colors[index]);
+			MeshVertex
+			{
+				position : positions[index],
+				normal : normal,
+				uv : uvs.as_ref().map_or(Vector2::new(0.0, 0.0), |uvs| uvs[index]),
+				color : Vector4::new(color.x * base_color.x, color.y * base_color.y, color.z * base_color.z, color.w * base_color.w),
+			}
+		};
+
+		let first_index = converted.indices.len();
+		match &normals
+		{
+			Some(normals) =>
+			{
+				let first_vertex = converted.vertices.len() as u32;
+				converted.vertices.extend((0..vertex_count as u32).map(|index| vertex(index, normals[index as usize])));
+				converted.indices.extend(triangles.iter().flatten().map(|index| first_vertex + index));
+			}
+			None =>
+			{
+				// the spec asks for flat normals, so no vertex is shared between triangles.
+				for triangle in &triangles
+				{
+					let [p0, p1, p2] = [positions[triangle[0] as usize], positions[triangle[1] as usize], positions[triangle[2] as usize]];
+					let face_normal = (p1 - p0).cross(p2 - p0);
+					let normal = if face_normal.magnitude2() > 0.0 { face_normal.normalize() } else { Vector3::new(0.0, 0.0, 1.0) };
+					for &index in triangle
+					{
+						converted.indices.push(converted.vertices.len() as u32);
+						converted.vertices.push(vertex(index, normal));
+					}
+				}
+			}
+		}
+
+		converted.groups.push(MaterialGroup
+		{
+			material : primitive.material().index(),
+			first_index : first_index,
+			index_count : converted.indices.len() - first_index,
+		});
+	}
+
+	Ok(converted)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use cgmath::Deg;
+
+	use super::*;
+	use crate::lights::Light;
+
+	// A quad in the xy plane drawn as a triangle strip by one mesh and as a fan by another. "parent" moves its
+	// children, "child" turns its strip and spot light to face -x, and "camera" looks down -z.
+	const SCENE_JSON : &str = r#"{
+		"asset" : { "version" : "2.0" },
+		"extensionsUsed" : ["KHR_lights_punctual"],
+		"extensions" : { "KHR_lights_punctual" : { "lights" : [
+			{ "type" : "spot", "color" : [1.0, 0.5, 0.25], "intensity" : 10.0, "range" : 20.0, "spot" : { "innerConeAngle" : 0.2, "outerConeAngle" : 0.6 } },
+			{ "type" : "point" }
+		] } },
+		"buffers" : [{ "byteLength" : 64 }],
+		"bufferViews" : [{ "buffer" : 0, "byteOffset" : 0, "byteLength" : 48 }, { "buffer" : 0, "byteOffset" : 48, "byteLength" : 16 }],
+		"accessors" : [
+			{ "bufferView" : 0, "componentType" : 5126, "count" : 4, "type" : "VEC3", "min" : [0.0, 0.0, 0.0], "max" : [1.0, 1.0, 0.0] },
+			{ "bufferView" : 1, "byteOffset" : 0, "componentType" : 5123, "count" : 4, "type" : "SCALAR" },
+			{ "bufferView" : 1, "byteOffset" : 8, "componentType" : 5123, "count" : 4, "type" : "SCALAR" }
+		],
+		"meshes" : [
+			{ "name" : "strip", "primitives" : [{ "attributes" : { "POSITION" : 0 }, "indices" : 1, "mode" : 5 }] },
+			{ "name" : "fan", "primitives" : [{ "attributes" : { "POSITION" : 0 }, "indices" : 2, "mode" : 6 }] }
+		],
+		"cameras" : [
+			{ "type" : "perspective", "perspective" : { "yfov" : 0.8, "znear" : 0.1, "zfar" : 50.0 } },
+			{ "type" : "orthographic", "orthographic" : { "xmag" : 3.0, "ymag" : 2.0, "znear" : 0.5, "zfar" : 20.0 } }
+		],
+		"nodes" : [
+			{ "name" : "parent", "translation" : [1.0, 2.0, 3.0], "children" : [1, 2] },
+			{ "name" : "child", "translation" : [0.0, 0.0, -1.0], "rotation" : [0.0, 0.70710677, 0.0, 0.70710677], "mesh" : 0,
+				"extensions" : { "KHR_lights_punctual" : { "light" : 0 } } },
+			{ "name" : "camera", "translation" : [0.0, 0.0, 5.0], "camera" : 0 },
+			{ "name" : "fan", "mesh" : 1, "extensions" : { "KHR_lights_punctual" : { "light" : 1 } } },
+			{ "name" : "unused", "camera" : 1 }
+		],
+		"scenes" : [{ "nodes" : [0, 3] }],
+		"scene" : 0
+	}"#;
+
+	// The quad's corners, then the strip's and the fan's indices.
+	fn scene_buffer() -> Vec<u8>
+	{
+		let positions = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+		let mut buffer : Vec<u8> = positions.iter().flatten().flat_map(|value| value.to_le_bytes()).collect();
+		buffer.extend([0u16, 1, 3, 2, 0, 1, 2, 3].iter().flat_map(|index| index.to_le_bytes()));
+		buffer
+	}
+
+	// A binary glTF holding json and the one buffer it describes.
+	fn glb(json : &str, buffer : &[u8]) -> Vec<u8>
+	{
+		let mut json = json.as_bytes().to_vec();
+		json.resize(json.len().div_ceil(4) * 4, b' ');
+		let mut buffer = buffer.to_vec();
+		buffer.resize(buffer.len().div_ceil(4) * 4, 0);
+
+		let length = 12 + 8 + json.len() + 8 + buffer.len();
+		let mut glb = Vec::with_capacity(length);
+		glb.extend_from_slice(b"glTF");
+		glb.extend_from_slice(&2u32.to_le_bytes());
+		glb.extend_from_slice(&(length as u32).to_le_bytes());
+		glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+		glb.extend_from_slice(b"JSON");
+		glb.extend_from_slice(&json);
+		glb.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+		glb.extend_from_slice(b"BIN\0");
+		glb.extend_from_slice(&buffer);
+		glb
+	}
+
+	fn load_scene() -> SceneDescription
+	{
+		let (document, buffers, images) = gltf::import_slice(glb(SCENE_JSON, &scene_buffer())).expect("the test scene is valid glTF");
+		convert_document(&document, &buffers, &images).expect("the test scene converts")
+	}
+
+	fn assert_near(actual : Vector3<f32>, expected : Vector3<f32>)
+	{
+		assert!((actual - expected).magnitude() < 1.0e-5, "{:?} is not {:?}", actual, expected);
+	}
+
+	// The positions of a mesh's triangle corners, in index order.
+	fn corner_positions(mesh : &Mesh) -> Vec<Vector3<f32>>
+	{
+		mesh.indices.iter().map(|&index| mesh.vertices[index as usize].position).collect()
+	}
+
+	#[test]
+	fn nodes_compose_their_parents_transforms()
+	{
+		let scene = load_scene();
+		assert_eq!(scene.roots, vec![0, 3]);
+		assert_eq!(scene.nodes[0].children, vec![1, 2]);
+		assert_eq!(scene.nodes[1].parent, Some(0));
+		assert_eq!(scene.nodes[3].parent, None);
+
+		let child = &scene.nodes[1];
+		assert_near(child.world_position(), Vector3::new(1.0, 2.0, 2.0));
+		assert_near(child.world_forward(), Vector3::new(-1.0, 0.0, 0.0));
+		assert_near(scene.nodes[2].world_position(), Vector3::new(1.0, 2.0, 8.0));
+
+		// the strip's corner at (1, 0, 0) turns to (0, 0, -1) and moves with the child.
+		let instances = scene.instanced_meshes();
+		assert_eq!(instances.iter().map(|mesh| mesh.name.as_str()).collect::<Vec<_>>(), vec!["child", "fan"]);
+		assert_near(corner_positions(&instances[0])[1], Vector3::new(1.0, 2.0, 1.0));
+		assert_eq!(corner_positions(&instances[1]), corner_positions(&scene.meshes[1]));
+	}
+
+	#[test]
+	fn cameras_keep_their_projection()
+	{
+		let scene = load_scene();
+		assert_eq!(scene.nodes[2].camera, Some(0));
+		assert_eq!(scene.cameras[0].projection, Projection::Perspective { vertical_fov : Rad(0.8), near : 0.1, far : Some(50.0) });
+		assert_eq!(scene.cameras[1].projection, Projection::Orthographic { half_height : 2.0, near : 0.5, far : 20.0 });
+
+		// the orthographic camera's node isn't in the scene, so the perspective one is the scene's camera.
+		let camera = scene.camera(2.0).expect("the scene has a camera");
+		assert_near(camera.position(), Vector3::new(1.0, 2.0, 8.0));
+		assert_near(camera.forward(), Vector3::new(0.0, 0.0, -1.0));
+		assert!((camera.vertical_fov() - Deg::from(Rad(0.8))).0.abs() < 1.0e-4);
+		assert_eq!((camera.near_plane(), camera.far_plane()), (0.1, 50.0));
+	}
+
+	#[test]
+	fn punctual_lights_are_imported()
+	{
+		let scene = load_scene();
+		assert_eq!(scene.lights, vec![
+			LightDescription
+			{
+				kind : LightKind::Spot { inner_cone_angle : Rad(0.2), outer_cone_angle : Rad(0.6) },
+				color : Vector3::new(1.0, 0.5, 0.25),
+				intensity : 10.0,
+				range : Some(20.0),
+			},
+			LightDescription { kind : LightKind::Point, color : Vector3::new(1.0, 1.0, 1.0), intensity : 1.0, range : None },
+		]);
+		assert_eq!((scene.nodes[1].light, scene.nodes[3].light), (Some(0), Some(1)));
+
+		match scene.instanced_lights()[0]
+		{
+			Light::Spot { position, direction, intensity, range, .. } =>
+			{
+				assert_near(position, Vector3::new(1.0, 2.0, 2.0));
+				assert_near(direction, Vector3::new(-1.0, 0.0, 0.0));
+				assert_eq!((intensity, range), (Vector3::new(10.0, 5.0, 2.5), 20.0));
+			}
+			light => panic!("expected a spot light, got {:?}", light),
+		}
+	}
+
+	#[test]
+	fn strips_and_fans_expand_to_counter_clockwise_triangles()
+	{
+		let scene = load_scene();
+		let corner = [Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 0.0), Vector3::new(0.0, 1.0, 0.0)];
+
+		// the strip 0 1 3 2 gives 0 1 3, then 3 1 2 with its winding restored.
+		let strip = &scene.meshes[0];
+		assert_eq!(corner_positions(strip), [0, 1, 3, 3, 1, 2].iter().map(|&index| corner[index]).collect::<Vec<_>>());
+		let fan = &scene.meshes[1];
+		assert_eq!(corner_positions(fan), [0, 1, 2, 0, 2, 3].iter().map(|&index| corner[index]).collect::<Vec<_>>());
+
+		for mesh in &[strip, fan]
+		{
+			assert_eq!(mesh.groups, vec![MaterialGroup { material : None, first_index : 0, index_count : 6 }]);
+			for vertex in &mesh.vertices
+			{
+				assert_eq!(vertex.normal, Vector3::new(0.0, 0.0, 1.0));
+			}
+		}
+	}
+}
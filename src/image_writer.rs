@@ -0,0 +1,536 @@
+use std::fs::File;
+use std::io::{ self, BufWriter, Write };
+use std::path::Path;
+
+use cgmath::Vector4;
+
+use crate::exr_image::{ self, ExrSettings };
+use crate::framebuffer::Framebuffer;
+use crate::tone_mapping::DisplayTransform;
+
+// File formats a framebuffer can be saved as. PNG and PPM store 8 bit display encoded color, HDR and EXR store the
+// linear values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat
+{
+	Png,
+	Ppm,
+	Hdr,
+	Exr,
+}
+
+impl ImageFormat
+{
+	pub fn from_path(path : &Path) -> Option<Self>
+	{
+		let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+		match extension.as_str()
+		{
+			"png" => Some(ImageFormat::Png),
+			"ppm" => Some(ImageFormat::Ppm),
+			"hdr" | "pic" => Some(ImageFormat::Hdr),
+			"exr" => Some(ImageFormat::Exr),
+			_ => None,
+		}
+	}
+}
+
+// Saves the framebuffer in the format picked by the file extension, through display for the 8 bit formats. EXR
+// files get only the beauty layer with the default settings, use exr_image::write_exr to add AOVs or pick the
+// encoding.
+pub fn write_image(framebuffer : &Framebuffer, path : &Path, display : &DisplayTransform) -> io::Result<()>
+{
+	match ImageFormat::from_path(path)
+	{
+		Some(ImageFormat::Png) => write_png(framebuffer, path, display),
+		Some(ImageFormat::Ppm) => write_ppm(framebuffer, path, display),
+		Some(ImageFormat::Hdr) => write_hdr(framebuffer, path),
+		Some(ImageFormat::Exr) =>
+		{
+			let layers = exr_image::render_layers(framebuffer, None);
+			exr_image::write_exr(path, &layers, &ExrSettings::default()).map_err(|error| io::Error::other(error.message))
+		}
+		None => Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown image extension, expected .png, .ppm, .hdr or .exr")),
+	}
+}
+
+// 8 bit RGBA with an sRGB chunk. Alpha is linear coverage and isn't encoded.
+pub fn write_png(framebuffer : &Framebuffer, path : &Path, display : &DisplayTransform) -> io::Result<()>
+{
+	let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), framebuffer.width(), framebuffer.height());
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+	let mut bytes = Vec::with_capacity(framebuffer.pixels().len() * 4);
+	for pixel in framebuffer.pixels()
+	{
+		bytes.extend_from_slice(&display.rgba8(*pixel));
+	}
+
+	let mut writer = encoder.write_header().map_err(png_error)?;
+	writer.write_image_data(&bytes).map_err(png_error)?;
+	writer.finish().map_err(png_error)
+}
+
+// Binary PPM (P6), 8 bit.
+pub fn write_ppm(framebuffer : &Framebuffer, path : &Path, display : &DisplayTransform) -> io::Result<()>
+{
+	let mut writer = BufWriter::new(File::create(path)?);
+	write!(writer, "P6\n{} {}\n255\n", framebuffer.width(), framebuffer.height())?;
+
+	let mut bytes = Vec::with_capacity(framebuffer.pixels().len() * 3);
+	for pixel in framebuffer.pixels()
+	{
+		bytes.extend_from_slice(&display.rgba8(*pixel)[..3]);
+	}
+	writer.write_all(&bytes)?;
+	writer.flush()
+}
+
+// Radiance RGBE with run length encoded scanlines. Keeps values above 1 and negative values are clamped to 0.
+pub fn write_hdr(framebuffer : &Framebuffer, path : &Path) -> io::Result<()>
+{
+	let (width, height) = (framebuffer.width() as usize, framebuffer.height() as usize);
+
+	let mut writer = BufWriter::new(File::create(path)?);
+	write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+
+	let mut scanline = Vec::with_capacity(width * 4);
+	for row in framebuffer.pixels().chunks(width)
+	{
+		let rgbe : Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+
+		scanline.clear();
+		// the run length scheme can only describe widths from 8 to 32767, anything else is stored flat.
+		if (8..0x8000).contains(&width)
+		{
+			scanline.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xFF) as u8]);
+			for component in 0..4
+			{
+				let values : Vec<u8> = rgbe.iter().map(|pixel| pixel[component]).collect();
+				encode_hdr_runs(&values, &mut scanline);
+			}
+		}
+		else
+		{
+			scanline.extend(rgbe.iter().flatten());
+		}
+		writer.write_all(&scanline)?;
+	}
+	writer.flush()
+}
+
+// Shared exponent encoding. The mantissas keep 8 bits relative to the brightest component.
+fn to_rgbe(pixel : &Vector4<f32>) -> [u8; 4]
+{
+	let clean = |value : f32| if value > 0.0 && value.is_finite() { value } else { 0.0 };
+	let (r, g, b) = (clean(pixel.x), clean(pixel.y), clean(pixel.z));
+
+	let brightest = r.max(g).max(b);
+	if brightest < 1e-32
+	{
+		return [0, 0, 0, 0];
+	}
+
+	// brightest = mantissa * 2^exponent with mantissa in [0.5, 1).
+	let mut exponent = brightest.log2().floor() as i32 + 1;
+	if brightest * 2.0_f32.powi(-exponent) >= 1.0
+	{
+		// log2 rounded up to an exact power of two.
+		exponent += 1;
+	}
+	// the largest floats need an exponent past what the byte holds, their mantissas saturate instead.
+	let exponent = exponent.min(127);
+	let scale = 2.0_f32.powi(8 - exponent);
+	[(r * scale) as u8, (g * scale) as u8, (b * scale) as u8, (exponent + 128) as u8]
+}
+
+// One component of a scanline as runs of up to 127 equal bytes (count + 128, value) and literal spans
+// of up to 128 bytes (count, values). Runs shorter than 3 aren't worth breaking a literal span for.
+fn encode_hdr_runs(values : &[u8], output : &mut Vec<u8>)
+{
+	const MIN_RUN : usize = 3;
+
+	let mut position = 0;
+	while position < values.len()
+	{
+		// find the next run long enough to encode as one.
+		let mut run_start = position;
+		let mut run_length = 0;
+		while run_start < values.len()
+		{
+			run_length = values[run_start..].iter().take(127).take_while(|&&value| value == values[run_start]).count();
+			if run_length >= MIN_RUN
+			{
+				break;
+			}
+			run_start += run_length;
+		}
+
+		while position < run_start
+		{
+			let literal_length = (run_start - position).min(128);
+			output.push(literal_length as u8);
+			output.extend_from_slice(&values[position..position + literal_length]);
+			position += literal_length;
+		}
+
+		if run_length >= MIN_RUN
+		{
+			output.push(128 + run_length as u8);
+			output.push(values[run_start]);
+			position = run_start + run_length;
+		}
+	}
+}
+
+// Reads a Radiance RGBE image like write_hdr's, or any other program's with the usual -Y +X orientation, with
+// flat, old style or new style run length encoded scanlines. XYZE images aren't converted so they're refused.
+pub fn read_hdr(path : &Path) -> io::Result<Framebuffer>
+{
+	let data = std::fs::read(path)?;
+	let mut position = 0;
+
+	let magic = read_line(&data, &mut position)?;
+	if !magic.starts_with("#?")
+	{
+		return Err(invalid_hdr("not a Radiance image"));
+	}
+	loop
+	{
+		let line = read_line(&data, &mut position)?;
+		if line.is_empty()
+		{
+			break;
+		}
+		if let Some(format) = line.strip_prefix("FORMAT=")
+		{
+			if format.trim() != "32-bit_rle_rgbe"
+			{
+				return Err(invalid_hdr(&format!("unsupported pixel format {}", format.trim())));
+			}
+		}
+	}
+
+	let resolution = read_line(&data, &mut position)?;
+	let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>().as_slice()
+	{
+		["-Y", height, "+X", width] => match (height.parse::<u32>(), width.parse::<u32>())
+		{
+			(Ok(height), Ok(width)) if width > 0 && height > 0 => (width, height),
+			_ => return Err(invalid_hdr(&format!("bad resolution '{}'", resolution))),
+		},
+		_ => return Err(invalid_hdr(&format!("unsupported orientation '{}', expected -Y height +X width", resolution))),
+	};
+
+	let mut framebuffer = Framebuffer::new(width, height);
+	let mut scanline = vec![[0u8; 4]; width as usize];
+	for row in framebuffer.pixels_mut().chunks_mut(width as usize)
+	{
+		read_hdr_scanline(&data, &mut position, &mut scanline)?;
+		for (pixel, rgbe) in row.iter_mut().zip(&scanline)
+		{
+			*pixel = from_rgbe(rgbe);
+		}
+	}
+	Ok(framebuffer)
+}
+
+// The header line starting at position, without its line feed.
+fn read_line(data : &[u8], position : &mut usize) -> io::Result<String>
+{
+	let length = data[*position..].iter().position(|&byte| byte == b'\n').ok_or_else(|| invalid_hdr("header ends early"))?;
+	let line = String::from_utf8_lossy(&data[*position..*position + length]).into_owned();
+	*position += length + 1;
+	Ok(line)
+}
+
+fn read_bytes<'a>(data : &'a [u8], position : &mut usize, count : usize) -> io::Result<&'a [u8]>
+{
+	let bytes = data.get(*position..*position + count).ok_or_else(|| invalid_hdr("pixels end early"))?;
+	*position += count;
+	Ok(bytes)
+}
+
+fn read_hdr_scanline(data : &[u8], position : &mut usize, scanline : &mut [[u8; 4]]) -> io::Result<()>
+{
+	let width = scanline.len();
+	let start = data.get(*position..*position + 4).unwrap_or(&[]);
+	if (8..0x8000).contains(&width) && start.len() == 4 && start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0
+	{
+		// new style, each component on its own as runs and literal spans.
+		if ((start[2] as usize) << 8 | start[3] as usize) != width
+		{
+			return Err(invalid_hdr("scanline width doesn't match the image"));
+		}
+		*position += 4;
+		for component in 0..4
+		{
+			let mut x = 0;
+			while x < width
+			{
+				let count = read_bytes(data, position, 1)?[0] as usize;
+				let (length, run) = if count > 128 { (count - 128, true) } else { (count, false) };
+				if length == 0 || x + length > width
+				{
+					return Err(invalid_hdr("bad run length"));
+				}
+				if run
+				{
+					let value = read_bytes(data, position, 1)?[0];
+					scanline[x..x + length].iter_mut().for_each(|pixel| pixel[component] = value);
+				}
+				else
+				{
+					let values = read_bytes(data, position, length)?;
+					scanline[x..x + length].iter_mut().zip(values).for_each(|(pixel, &value)| pixel[component] = value);
+				}
+				x += length;
+			}
+		}
+		return Ok(());
+	}
+
+	// flat pixels, where 1, 1, 1, count repeats the previous pixel, consecutive repeats counting in higher bytes.
+	let mut x = 0;
+	let mut shift = 0;
+	while x < width
+	{
+		let bytes = read_bytes(data, position, 4)?;
+		let pixel = [bytes[0], bytes[1], bytes[2], bytes[3]];
+		if pixel[..3] == [1, 1, 1] && x > 0
+		{
+			let count = (pixel[3] as usize).checked_shl(shift).unwrap_or(usize::MAX);
+			if count > width - x
+			{
+				return Err(invalid_hdr("bad run length"));
+			}
+			let previous = scanline[x - 1];
+			scanline[x..x + count].iter_mut().for_each(|repeat| *repeat = previous);
+			x += count;
+			shift += 8;
+		}
+		else
+		{
+			scanline[x] = pixel;
+			x += 1;
+			shift = 0;
+		}
+	}
+	Ok(())
+}
+
+// The middle of the range of values the mantissas stand for, as to_rgbe truncates.
+fn from_rgbe(rgbe : &[u8; 4]) -> Vector4<f32>
+{
+	if rgbe[3] == 0
+	{
+		return Vector4::new(0.0, 0.0, 0.0, 1.0);
+	}
+	let scale = 2.0_f32.powi(rgbe[3] as i32 - 136);
+	let value = |mantissa : u8| (mantissa as f32 + 0.5) * scale;
+	Vector4::new(value(rgbe[0]), value(rgbe[1]), value(rgbe[2]), 1.0)
+}
+
+fn invalid_hdr(message : &str) -> io::Error
+{
+	io::Error::new(io::ErrorKind::InvalidData, format!("Radiance HDR: {}", message))
+}
+
+fn png_error(error : png::EncodingError) -> io::Error
+{
+	match error
+	{
+		png::EncodingError::IoError(error) => error,
+		error => io::Error::other(error.to_string()),
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// A file in the temp directory that is deleted again when the test is done with it.
+	struct TempFile(std::path::PathBuf);
+
+	impl TempFile
+	{
+		fn new(name : &str) -> Self
+		{
+			TempFile(std::env::temp_dir().join(format!("rust_raytracer_{}_{}", std::process::id(), name)))
+		}
+	}
+
+	impl Drop for TempFile
+	{
+		fn drop(&mut self)
+		{
+			let _ = std::fs::remove_file(&self.0);
+		}
+	}
+
+	// Linear values and the sRGB encoded bytes the default display transform saves them as, clamped to [0, 1].
+	// Alpha is stored as it is.
+	const DISPLAY_PIXELS : [([f32; 4], [u8; 4]); 6] =
+	[
+		([0.0, 0.001, 0.05, 1.0], [0, 3, 63, 255]),
+		([0.2, 0.5, 0.8, 0.5], [124, 188, 231, 128]),
+		([1.0, 2.0, -1.0, 0.0], [255, 255, 0, 0]),
+		([0.5, 0.5, 0.5, 1.0], [188, 188, 188, 255]),
+		([0.0, 0.0, 0.0, 1.0], [0, 0, 0, 255]),
+		([1.0, 1.0, 1.0, 1.0], [255, 255, 255, 255]),
+	];
+
+	fn display_framebuffer() -> Framebuffer
+	{
+		let mut framebuffer = Framebuffer::new(3, 2);
+		for (pixel, (linear, _)) in framebuffer.pixels_mut().iter_mut().zip(&DISPLAY_PIXELS)
+		{
+			*pixel = Vector4::from(*linear);
+		}
+		framebuffer
+	}
+
+	fn display_bytes(channels : usize) -> Vec<u8>
+	{
+		DISPLAY_PIXELS.iter().flat_map(|(_, encoded)| encoded[..channels].to_vec()).collect()
+	}
+
+	#[test]
+	fn png_stores_srgb_encoded_rgba()
+	{
+		let file = TempFile::new("display.png");
+		write_image(&display_framebuffer(), &file.0, &DisplayTransform::default()).expect("the png is written");
+
+		let decoder = png::Decoder::new(io::BufReader::new(File::open(&file.0).expect("the png exists")));
+		let mut reader = decoder.read_info().expect("the png header reads back");
+		assert!(reader.info().srgb.is_some(), "the png has no sRGB chunk");
+		let mut bytes = vec![0; reader.output_buffer_size().expect("the png has a size")];
+		let frame = reader.next_frame(&mut bytes).expect("the png pixels read back");
+		assert_eq!((frame.width, frame.height, frame.color_type, frame.bit_depth), (3, 2, png::ColorType::Rgba, png::BitDepth::Eight));
+		assert_eq!(&bytes[..frame.buffer_size()], display_bytes(4).as_slice());
+	}
+
+	#[test]
+	fn ppm_stores_srgb_encoded_rgb()
+	{
+		let file = TempFile::new("display.ppm");
+		write_image(&display_framebuffer(), &file.0, &DisplayTransform::default()).expect("the ppm is written");
+
+		let mut expected = b"P6\n3 2\n255\n".to_vec();
+		expected.extend(display_bytes(3));
+		assert_eq!(std::fs::read(&file.0).expect("the ppm reads back"), expected);
+	}
+
+	// Writes an image with long runs and long literal spans, with values over a wide range and some negative ones,
+	// and reads it back. Returns the scanline data, for checking how it was stored.
+	fn check_hdr_round_trip(width : u32, height : u32) -> Vec<u8>
+	{
+		let mut image = Framebuffer::new(width, height);
+		for y in 0..height
+		{
+			for x in 0..width
+			{
+				let value = |scale : f32| if x < width / 2 { scale } else { scale * 2.0_f32.powi((x + y) as i32 % 40 - 20) };
+				let negative = if (x + 3 * y) % 11 == 0 { -1.0 } else { 1.0 };
+				image.set(x, y, Vector4::new(value(0.75), value(negative * 0.3), value(5.0), 1.0));
+			}
+		}
+
+		let file = TempFile::new(&format!("round_trip_{}x{}.hdr", width, height));
+		write_image(&image, &file.0, &DisplayTransform::default()).expect("the hdr is written");
+		let read = read_hdr(&file.0).expect("the hdr reads back");
+
+		assert_eq!((read.width(), read.height()), (width, height));
+		for (index, (written, read)) in image.pixels().iter().zip(read.pixels()).enumerate()
+		{
+			// every component is within half a mantissa step of the brightest one.
+			let written = Vector4::new(written.x.max(0.0), written.y.max(0.0), written.z.max(0.0), 1.0);
+			let tolerance = written.x.max(written.y).max(written.z) / 256.0;
+			for component in 0..4
+			{
+				assert!((read[component] - written[component]).abs() <= tolerance,
+					"{}x{} pixel {} reads back as {:?}, not {:?}", width, height, index, read, written);
+			}
+		}
+
+		let data = std::fs::read(&file.0).expect("the hdr reads back");
+		let header = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width);
+		assert!(data.starts_with(header.as_bytes()));
+		data[header.len()..].to_vec()
+	}
+
+	#[test]
+	fn hdr_round_trips_run_length_encoded_scanlines()
+	{
+		// runs longer than 127 and literal spans longer than 128 have to be split.
+		let scanlines = check_hdr_round_trip(300, 3);
+		assert_eq!(scanlines[..4], [2, 2, 1, 44]);
+		assert!(scanlines.len() < 300 * 3 * 4, "run length encoding saved nothing");
+	}
+
+	#[test]
+	fn hdr_round_trips_flat_scanlines_too_narrow_to_encode()
+	{
+		let scanlines = check_hdr_round_trip(7, 4);
+		assert_eq!(scanlines.len(), 7 * 4 * 4);
+	}
+
+	#[test]
+	fn hdr_round_trips_flat_scanlines_too_wide_to_encode()
+	{
+		let scanlines = check_hdr_round_trip(0x8000, 2);
+		assert_eq!(scanlines.len(), 0x8000 * 2 * 4);
+	}
+
+	fn rgbe(r : f32, g : f32, b : f32) -> [u8; 4]
+	{
+		to_rgbe(&Vector4::new(r, g, b, 1.0))
+	}
+
+	#[test]
+	fn rgbe_of_black_and_invalid_values_is_zero()
+	{
+		assert_eq!(rgbe(0.0, 0.0, 0.0), [0, 0, 0, 0]);
+		assert_eq!(rgbe(-1.0, f32::NAN, f32::INFINITY), [0, 0, 0, 0]);
+		assert_eq!(from_rgbe(&[0, 0, 0, 0]), Vector4::new(0.0, 0.0, 0.0, 1.0));
+	}
+
+	#[test]
+	fn rgbe_of_powers_of_two_fills_the_mantissa_from_the_middle()
+	{
+		assert_eq!(rgbe(1.0, 0.5, 0.0), [128, 64, 0, 129]);
+		assert_eq!(rgbe(0.25, 0.0, 0.0), [128, 0, 0, 127]);
+		assert_eq!(rgbe(1024.0, 1024.0, 1024.0), [128, 128, 128, 139]);
+	}
+
+	#[test]
+	fn rgbe_of_tiny_values()
+	{
+		// below 1e-32 rounds to black, just above it keeps a full mantissa.
+		assert_eq!(rgbe(1.0e-33, 1.0e-33, 1.0e-33), [0, 0, 0, 0]);
+		let encoded = rgbe(1.0e-30, 0.5e-30, 0.0);
+		assert!(encoded[0] >= 128);
+		let decoded = from_rgbe(&encoded);
+		assert!((decoded.x - 1.0e-30).abs() <= 1.0e-30 / 256.0, "{:?}", decoded);
+		assert!((decoded.y - 0.5e-30).abs() <= 1.0e-30 / 256.0, "{:?}", decoded);
+	}
+
+	#[test]
+	fn rgbe_of_the_largest_values_saturates()
+	{
+		// 1e38 still fits the exponent byte, the largest floats need one more and get the brightest color RGBE holds.
+		let encoded = rgbe(1.0e38, 0.0, 0.0);
+		assert_eq!(encoded[3], 255);
+		assert!((from_rgbe(&encoded).x - 1.0e38).abs() <= 1.0e38 / 256.0);
+
+		let brightest = from_rgbe(&[255, 255, 255, 255]).x;
+		let encoded = rgbe(f32::MAX, f32::MAX / 4.0, 1.0);
+		assert_eq!(encoded, [255, 127, 0, 255]);
+		let decoded = from_rgbe(&encoded);
+		assert_eq!(decoded.x, brightest);
+		assert!((decoded.y - f32::MAX / 4.0).abs() <= brightest / 256.0, "{:?}", decoded);
+	}
+}
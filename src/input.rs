@@ -0,0 +1,314 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key
+{
+	A, B, C, D, E, F, G, H, I, J, K, L, M,
+	N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+	Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+	Up,
+	Down,
+	Left,
+	Right,
+	Space,
+	Enter,
+	Tab,
+	Escape,
+	Shift,
+	Control,
+	Alt,
+	#[cfg_attr(not(windows), allow(dead_code))]
+	Other(u32), // platform key code without a mapping
+}
+
+impl Key
+{
+	const LETTERS : [Key; 26] =
+	[
+		Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+		Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+	];
+
+	const DIGITS : [Key; 10] =
+	[
+		Key::Digit0, Key::Digit1, Key::Digit2, Key::Digit3, Key::Digit4,
+		Key::Digit5, Key::Digit6, Key::Digit7, Key::Digit8, Key::Digit9,
+	];
+
+	// Maps an ASCII letter or digit, in either case, to its key.
+	pub fn from_char(character : char) -> Option<Key>
+	{
+		match character.to_ascii_uppercase()
+		{
+			letter @ 'A'..='Z' => Some(Self::LETTERS[(letter as u8 - b'A') as usize]),
+			digit @ '0'..='9' => Some(Self::DIGITS[(digit as u8 - b'0') as usize]),
+			_ => None,
+		}
+	}
+}
+
+impl FromStr for Key
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		let mut characters = value.chars();
+		if let (Some(character), None) = (characters.next(), characters.next())
+		{
+			if let Some(key) = Key::from_char(character)
+			{
+				return Ok(key);
+			}
+		}
+
+		match value.to_ascii_lowercase().as_str()
+		{
+			"up" => Ok(Key::Up),
+			"down" => Ok(Key::Down),
+			"left" => Ok(Key::Left),
+			"right" => Ok(Key::Right),
+			"space" => Ok(Key::Space),
+			"enter" => Ok(Key::Enter),
+			"tab" => Ok(Key::Tab),
+			"escape" => Ok(Key::Escape),
+			"shift" => Ok(Key::Shift),
+			"control" => Ok(Key::Control),
+			"alt" => Ok(Key::Alt),
+			_ => Err(format!("unknown key '{}'", value)),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton
+{
+	Left,
+	Right,
+	Middle,
+}
+
+impl FromStr for MouseButton
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		match value
+		{
+			"left" => Ok(MouseButton::Left),
+			"right" => Ok(MouseButton::Right),
+			"middle" => Ok(MouseButton::Middle),
+			_ => Err(format!("unknown mouse button '{}'", value)),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputEvent
+{
+	KeyDown(Key),
+	KeyUp(Key),
+	MouseMove { x : i32, y : i32 }, // client area pixels, origin at the top left
+	MouseButtonDown(MouseButton),
+	MouseButtonUp(MouseButton),
+	MouseWheel { delta : f32 },     // in notches, positive is away from the user
+	Resize { width : u32, height : u32 },
+	Focus(bool),                    // true when the window gains focus
+}
+
+// Input for platforms without a window. Events are queued up front and handed out on the frame they are scheduled for.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedInput
+{
+	events : VecDeque<(u64, InputEvent)>,
+}
+
+impl ScriptedInput
+{
+	pub fn new() -> Self
+	{
+		Self { events : VecDeque::new() }
+	}
+
+	// One event per line, '#' starts a comment:
+	//     <frame> key_down <key>          <frame> key_up <key>
+	//     <frame> mouse_move <x> <y>      <frame> wheel <delta>
+	//     <frame> mouse_down <button>     <frame> mouse_up <button>
+	//     <frame> resize <width> <height> <frame> focus <true|false>
+	// keys are letters, digits or up, down, left, right, space, enter, tab, escape, shift, control, alt.
+	// buttons are left, right or middle.
+	pub fn parse(script : &str) -> Result<Self, String>
+	{
+		let mut scripted_input = Self::new();
+
+		for (line_index, line) in script.lines().enumerate()
+		{
+			let line = line.split('#').next().unwrap_or("").trim();
+			if line.is_empty()
+			{
+				continue;
+			}
+
+			let (frame, event) = parse_script_line(line)
+				.map_err(|message| format!("line {}: {}", line_index + 1, message))?;
+			scripted_input.push(frame, event);
+		}
+
+		Ok(scripted_input)
+	}
+
+	// Schedules an event for the given frame. Events for the same frame are delivered in the order they were pushed.
+	pub fn push(&mut self, frame : u64, event : InputEvent)
+	{
+		let index = self.events.iter().position(|&(scheduled, _)| scheduled > frame).unwrap_or(self.events.len());
+		self.events.insert(index, (frame, event));
+	}
+
+	// Removes and returns every event scheduled on or before the given frame.
+	pub fn take_until(&mut self, frame : u64) -> Vec<InputEvent>
+	{
+		let mut events = Vec::new();
+		while let Some(&(scheduled, event)) = self.events.front()
+		{
+			if scheduled > frame
+			{
+				break;
+			}
+			events.push(event);
+			self.events.pop_front();
+		}
+		events
+	}
+}
+
+fn parse_script_line(line : &str) -> Result<(u64, InputEvent), String>
+{
+	let words : Vec<&str> = line.split_whitespace().collect();
+	let argument = |index : usize| words.get(index).copied().ok_or_else(|| format!("'{}' is missing arguments", line));
+
+	let frame = parse_value::<u64>(argument(0)?)?;
+	let event = match argument(1)?
+	{
+		"key_down" => InputEvent::KeyDown(argument(2)?.parse()?),
+		"key_up" => InputEvent::KeyUp(argument(2)?.parse()?),
+		"mouse_move" => InputEvent::MouseMove { x : parse_value(argument(2)?)?, y : parse_value(argument(3)?)? },
+		"mouse_down" => InputEvent::MouseButtonDown(argument(2)?.parse()?),
+		"mouse_up" => InputEvent::MouseButtonUp(argument(2)?.parse()?),
+		"wheel" => InputEvent::MouseWheel { delta : parse_value(argument(2)?)? },
+		"resize" => InputEvent::Resize { width : parse_value(argument(2)?)?, height : parse_value(argument(3)?)? },
+		"focus" => InputEvent::Focus(parse_value(argument(2)?)?),
+		unknown => return Err(format!("unknown event '{}'", unknown)),
+	};
+
+	Ok((frame, event))
+}
+
+fn parse_value<T : FromStr>(value : &str) -> Result<T, String>
+{
+	value.parse::<T>().map_err(|_| format!("'{}' is not a valid {}", value, std::any::type_name::<T>()))
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn keys_parse_from_letters_digits_and_names()
+	{
+		assert_eq!("w".parse::<Key>(), Ok(Key::W));
+		assert_eq!("W".parse::<Key>(), Ok(Key::W));
+		assert_eq!("7".parse::<Key>(), Ok(Key::Digit7));
+		assert_eq!("Escape".parse::<Key>(), Ok(Key::Escape));
+		assert_eq!("space".parse::<Key>(), Ok(Key::Space));
+		assert!("ww".parse::<Key>().is_err());
+		assert!("?".parse::<Key>().is_err());
+		assert!("".parse::<Key>().is_err());
+	}
+
+	#[test]
+	fn script_parses_every_event_kind()
+	{
+		let script = "
+			# warm up, then fly forward
+			0 key_down w
+			0 mouse_move 10 -20   # trailing comment
+			1 mouse_down right
+			2 wheel -1.5
+			3 resize 640 480
+			4 focus false
+			5 mouse_up middle
+			6 key_up W
+		";
+		let mut input = ScriptedInput::parse(script).expect("the script is valid");
+		assert_eq!(input.take_until(u64::MAX), vec![
+			InputEvent::KeyDown(Key::W),
+			InputEvent::MouseMove { x : 10, y : -20 },
+			InputEvent::MouseButtonDown(MouseButton::Right),
+			InputEvent::MouseWheel { delta : -1.5 },
+			InputEvent::Resize { width : 640, height : 480 },
+			InputEvent::Focus(false),
+			InputEvent::MouseButtonUp(MouseButton::Middle),
+			InputEvent::KeyUp(Key::W),
+		]);
+	}
+
+	#[test]
+	fn empty_and_comment_only_scripts_have_no_events()
+	{
+		let mut input = ScriptedInput::parse("\n   \n# nothing\n\t# at all\n").expect("comments are valid");
+		assert!(input.take_until(u64::MAX).is_empty());
+	}
+
+	#[test]
+	fn malformed_lines_report_their_line_number()
+	{
+		let cases =
+		[
+			("0 key_down", "line 1: '0 key_down' is missing arguments"),
+			("0 key_down w\nx key_up w", "line 2: 'x' is not a valid u64"),
+			("\n\n2 jump", "line 3: unknown event 'jump'"),
+			("0 key_down f13", "line 1: unknown key 'f13'"),
+			("0 mouse_down thumb", "line 1: unknown mouse button 'thumb'"),
+			("0 mouse_move 10", "line 1: '0 mouse_move 10' is missing arguments"),
+			("0 resize 640 tall", "line 1: 'tall' is not a valid u32"),
+			("0 focus maybe", "line 1: 'maybe' is not a valid bool"),
+			("-1 key_down w", "line 1: '-1' is not a valid u64"),
+		];
+		for (script, message) in cases.iter()
+		{
+			assert_eq!(ScriptedInput::parse(script).err().as_deref(), Some(*message), "script {:?}", script);
+		}
+	}
+
+	#[test]
+	fn events_arrive_on_their_frame_in_frame_order()
+	{
+		let mut input = ScriptedInput::parse("5 key_down b\n2 key_down a\n5 key_up b\n2 key_up a\n9 key_down c").expect("the script is valid");
+
+		assert!(input.take_until(0).is_empty());
+		assert!(input.take_until(1).is_empty());
+		assert_eq!(input.take_until(2), vec![InputEvent::KeyDown(Key::A), InputEvent::KeyUp(Key::A)]);
+		assert!(input.take_until(2).is_empty(), "events are only delivered once");
+
+		// a skipped frame catches up on everything scheduled before it.
+		assert_eq!(input.take_until(7), vec![InputEvent::KeyDown(Key::B), InputEvent::KeyUp(Key::B)]);
+		assert_eq!(input.take_until(100), vec![InputEvent::KeyDown(Key::C)]);
+		assert!(input.take_until(u64::MAX).is_empty());
+	}
+
+	#[test]
+	fn pushed_events_keep_their_order_within_a_frame()
+	{
+		let mut input = ScriptedInput::new();
+		input.push(1, InputEvent::Focus(true));
+		input.push(0, InputEvent::KeyDown(Key::Up));
+		input.push(1, InputEvent::Focus(false));
+		input.push(0, InputEvent::KeyUp(Key::Up));
+
+		assert_eq!(input.take_until(0), vec![InputEvent::KeyDown(Key::Up), InputEvent::KeyUp(Key::Up)]);
+		assert_eq!(input.take_until(1), vec![InputEvent::Focus(true), InputEvent::Focus(false)]);
+	}
+}
@@ -0,0 +1,692 @@
+use std::f32::consts::PI;
+
+use cgmath::{ InnerSpace, Matrix4, Vector3, Vector4 };
+
+use crate::area_lights::{ luminance, LightSample };
+use crate::geometry::{ orthonormal_basis, Intersect, Plane, Sphere };
+use crate::ray::Ray;
+use crate::scene_description::{ LightDescription, LightKind };
+
+// A light that isn't part of the geometry. Point, spot and sharp directional lights are infinitely small, so only
+// sampling them finds them. Rays can also hit rectangles and spheres, which end them, and leave the scene toward a
+// directional light with an angular diameter.
+// Intensities are in the units of material emission, read as nits: candela for point and spot lights, lux for
+// directional lights and nits for rectangles and spheres, which are equally bright all over.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Light
+{
+	Point
+	{
+		position : Vector3<f32>,
+		intensity : Vector3<f32>,
+		range : f32, // distance the light fades out by, infinite for the inverse square law alone
+	},
+	Spot
+	{
+		position : Vector3<f32>,
+		direction : Vector3<f32>, // unit length, the middle of the cone
+		intensity : Vector3<f32>,
+		range : f32,
+		cos_inner : f32,          // full intensity inside
+		cos_outer : f32,          // none outside
+	},
+	Directional
+	{
+		direction : Vector3<f32>,  // unit length, the way the light travels
+		irradiance : Vector3<f32>, // on a surface facing the light
+		half_angle : f32,          // radians, 0 for a sharp light
+	},
+	Rectangle
+	{
+		corner : Vector3<f32>,
+		edge_u : Vector3<f32>,
+		edge_v : Vector3<f32>,     // at right angles to edge_u
+		normal : Vector3<f32>,     // unit length, the side that emits
+		radiance : Vector3<f32>,
+	},
+	Sphere
+	{
+		center : Vector3<f32>,
+		radius : f32,
+		radiance : Vector3<f32>,
+	},
+}
+
+// Where a ray met a rectangle or sphere light, and the radiance it saw there.
+#[derive(Copy, Clone, Debug)]
+pub struct LightHit
+{
+	pub light : usize, // index into Lights
+	pub radiance : Vector3<f32>,
+}
+
+impl Light
+{
+	// The light a node places, which points down the node's -z axis. Rectangles lie in its xy plane around its origin.
+	pub fn new(description : &LightDescription, transform : &Matrix4<f32>) -> Self
+	{
+		let color = description.color * description.intensity;
+		let position = transform.w.truncate();
+		let forward = (-transform.z.truncate()).normalize();
+		let range = description.range.unwrap_or(f32::INFINITY);
+		match description.kind
+		{
+			LightKind::Point => Light::Point { position : position, intensity : color, range : range },
+			LightKind::Spot { inner_cone_angle, outer_cone_angle } => Light::Spot
+			{
+				position : position,
+				direction : forward,
+				intensity : color,
+				range : range,
+				cos_inner : inner_cone_angle.0.cos(),
+				cos_outer : outer_cone_angle.0.cos(),
+			},
+			LightKind::Directional { angular_diameter } => Light::Directional
+			{
+				direction : forward,
+				irradiance : color,
+				half_angle : (angular_diameter.0 * 0.5).clamp(0.0, 0.5 * PI),
+			},
+			LightKind::Rectangle { width, height } =>
+			{
+				let edge_u = (transform * Vector4::new(width, 0.0, 0.0, 0.0)).truncate();
+				let edge_v = (transform * Vector4::new(0.0, height, 0.0, 0.0)).truncate();
+				Light::Rectangle
+				{
+					corner : position - (edge_u + edge_v) * 0.5,
+					edge_u : edge_u,
+					edge_v : edge_v,
+					normal : edge_v.cross(edge_u).normalize(),
+					radiance : color,
+				}
+			}
+			LightKind::Sphere { radius } => Light::Sphere
+			{
+				center : position,
+				radius : radius * transform.x.truncate().magnitude(),
+				radiance : color,
+			},
+		}
+	}
+
+	// Luminance of the power the light emits, for picking between lights. Directional lights count what falls on a
+	// disk the size of the scene.
+	pub fn power(&self, scene_radius : f32) -> f32
+	{
+		match *self
+		{
+			Light::Point { intensity, .. } => 4.0 * PI * luminance(intensity),
+			Light::Spot { intensity, cos_inner, cos_outer, .. } => 2.0 * PI * luminance(intensity) * (1.0 - 0.5 * (cos_inner + cos_outer)),
+			Light::Directional { irradiance, .. } => PI * scene_radius * scene_radius * luminance(irradiance),
+			Light::Rectangle { edge_u, edge_v, radiance, .. } => PI * edge_u.cross(edge_v).magnitude() * luminance(radiance),
+			Light::Sphere { radius, radiance, .. } => 4.0 * PI * PI * radius * radius * luminance(radiance),
+		}
+	}
+
+	// Light arriving at point from a point on the light picked with two uniform numbers in [0, 1), None when none
+	// does. The density is per unit solid angle, or 1 for delta lights.
+	pub fn sample(&self, point : Vector3<f32>, u1 : f32, u2 : f32) -> Option<LightSample>
+	{
+		match *self
+		{
+			Light::Point { position, intensity, range } => delta_sample(position - point, intensity, range),
+			Light::Spot { position, direction, intensity, range, cos_inner, cos_outer } =>
+			{
+				let mut sample = delta_sample(position - point, intensity, range)?;
+				sample.radiance *= spot_falloff(-sample.direction.dot(direction), cos_inner, cos_outer);
+				Some(sample)
+			}
+			Light::Directional { direction, irradiance, half_angle } =>
+			{
+				if half_angle <= 0.0
+				{
+					return Some(LightSample { direction : -direction, distance : f32::INFINITY, radiance : irradiance, pdf : 1.0, delta : true });
+				}
+				let one_minus_cos = one_minus_cos(half_angle);
+				Some(LightSample
+				{
+					direction : sample_cone(-direction, one_minus_cos, u1, u2),
+					distance : f32::INFINITY,
+					radiance : irradiance / (PI * half_angle.sin().powi(2)),
+					pdf : 1.0 / (2.0 * PI * one_minus_cos),
+					delta : false,
+				})
+			}
+			Light::Rectangle { corner, edge_u, edge_v, normal, radiance } =>
+			{
+				let offset = corner + edge_u * u1 + edge_v * u2 - point;
+				let distance = offset.magnitude();
+				let direction = offset / distance;
+				let cosine = -direction.dot(normal);
+				if distance <= 0.0 || cosine <= 0.0
+				{
+					return None;
+				}
+				Some(LightSample
+				{
+					direction : direction,
+					distance : distance,
+					radiance : radiance,
+					pdf : distance * distance / (edge_u.cross(edge_v).magnitude() * cosine),
+					delta : false,
+				})
+			}
+			Light::Sphere { center, radius, radiance } =>
+			{
+				// uniform over the cone of directions the sphere covers, which sees only its near side.
+				let offset = center - point;
+				let distance_squared = offset.magnitude2();
+				if distance_squared <= radius * radius
+				{
+					return None;
+				}
+				let axis = offset / distance_squared.sqrt();
+				let one_minus_cos = sphere_one_minus_cos(radius, distance_squared);
+				let direction = sample_cone(axis, one_minus_cos, u1, u2);
+
+				// directions grazing the silhouette can miss it by rounding, they meet it where it comes closest.
+				let ray = Ray::new(point, direction);
+				let distance = Sphere::new(center, radius).intersect(&ray, 0.0, f32::INFINITY).map_or(offset.dot(direction), |hit| hit.t);
+				Some(LightSample { direction : direction, distance : distance, radiance : radiance, pdf : 1.0 / (2.0 * PI * one_minus_cos), delta : false })
+			}
+		}
+	}
+
+	// The density, per unit solid angle, with which sample picks the unit length direction from point. Zero for
+	// delta lights, which no direction picked any other way finds.
+	pub fn pdf(&self, point : Vector3<f32>, direction : Vector3<f32>) -> f32
+	{
+		match *self
+		{
+			Light::Point { .. } | Light::Spot { .. } => 0.0,
+			Light::Directional { direction : light_direction, half_angle, .. } =>
+			{
+				if half_angle > 0.0 && -direction.dot(light_direction) >= half_angle.cos() { 1.0 / (2.0 * PI * one_minus_cos(half_angle)) } else { 0.0 }
+			}
+			Light::Rectangle { edge_u, edge_v, normal, .. } => match self.hit(&Ray::new(point, direction), 0.0, f32::INFINITY)
+			{
+				Some((distance, _)) if -direction.dot(normal) > 0.0 =>
+				{
+					distance * distance / (edge_u.cross(edge_v).magnitude() * -direction.dot(normal))
+				}
+				_ => 0.0,
+			},
+			Light::Sphere { center, radius, .. } =>
+			{
+				let offset = center - point;
+				let distance_squared = offset.magnitude2();
+				if distance_squared <= radius * radius
+				{
+					return 0.0;
+				}
+				let one_minus_cos = sphere_one_minus_cos(radius, distance_squared);
+				if direction.dot(offset) / distance_squared.sqrt() >= 1.0 - one_minus_cos { 1.0 / (2.0 * PI * one_minus_cos) } else { 0.0 }
+			}
+		}
+	}
+
+	// Where a ray meets a rectangle or sphere light between t_min and t_max, and the radiance it sees there. The back
+	// of a rectangle blocks light but is black.
+	pub fn hit(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<(f32, Vector3<f32>)>
+	{
+		match *self
+		{
+			Light::Rectangle { corner, edge_u, edge_v, normal, radiance } =>
+			{
+				let hit = Plane::new(corner, normal).intersect(ray, t_min, t_max)?;
+				let offset = ray.at(hit.t) - corner;
+				let (u, v) = (offset.dot(edge_u) / edge_u.magnitude2(), offset.dot(edge_v) / edge_v.magnitude2());
+				if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v)
+				{
+					return None;
+				}
+				Some((hit.t, if hit.front_face { radiance } else { Vector3::new(0.0, 0.0, 0.0) }))
+			}
+			Light::Sphere { center, radius, radiance } =>
+			{
+				Sphere::new(center, radius).intersect(ray, t_min, t_max).map(|hit| (hit.t, radiance))
+			}
+			_ => None,
+		}
+	}
+
+	// Radiance arriving along direction from a directional light with an angular diameter, from beyond everything.
+	pub fn distant_radiance(&self, direction : Vector3<f32>) -> Vector3<f32>
+	{
+		match *self
+		{
+			Light::Directional { direction : light_direction, irradiance, half_angle } if half_angle > 0.0 && -direction.dot(light_direction) >= half_angle.cos() =>
+			{
+				irradiance / (PI * half_angle.sin().powi(2))
+			}
+			_ => Vector3::new(0.0, 0.0, 0.0),
+		}
+	}
+
+	// The light toward the middle of the light, for lighting that has to be deterministic.
+	pub fn sample_middle(&self, point : Vector3<f32>) -> Option<LightSample>
+	{
+		match self
+		{
+			Light::Rectangle { .. } => self.sample(point, 0.5, 0.5),
+			_ => self.sample(point, 0.0, 0.0), // the axis of the cone
+		}
+	}
+}
+
+// A point or spot light at offset from the shaded point, before the spot's falloff.
+fn delta_sample(offset : Vector3<f32>, intensity : Vector3<f32>, range : f32) -> Option<LightSample>
+{
+	let distance_squared = offset.magnitude2();
+	if distance_squared <= 0.0
+	{
+		return None;
+	}
+	let distance = distance_squared.sqrt();
+	Some(LightSample
+	{
+		direction : offset / distance,
+		distance : distance,
+		radiance : intensity * (range_window(distance, range) / distance_squared),
+		pdf : 1.0,
+		delta : true,
+	})
+}
+
+// KHR_lights_punctual's fade to nothing at range, on top of the inverse square law.
+fn range_window(distance : f32, range : f32) -> f32
+{
+	(1.0 - (distance / range).powi(4)).clamp(0.0, 1.0)
+}
+
+// KHR_lights_punctual's falloff from the inner cone to the outer one, the square of a ramp in cosines.
+pub fn spot_falloff(cosine : f32, cos_inner : f32, cos_outer : f32) -> f32
+{
+	let t = ((cosine - cos_outer) / (cos_inner - cos_outer).max(1.0e-3)).clamp(0.0, 1.0);
+	t * t
+}
+
+// 1 - cos(angle) without the cancellation of taking it from the cosine of a small angle.
+fn one_minus_cos(angle : f32) -> f32
+{
+	2.0 * (0.5 * angle).sin().powi(2)
+}
+
+// The same for the cone a sphere of radius covers from distance_squared away from its center.
+fn sphere_one_minus_cos(radius : f32, distance_squared : f32) -> f32
+{
+	let sin_squared = (radius * radius / distance_squared).min(1.0);
+	sin_squared / (1.0 + (1.0 - sin_squared).sqrt())
+}
+
+// A direction uniform in solid angle within the cone around the unit length axis whose 1 - cos(half angle) is
+// one_minus_cos. u1 = 0 is the axis itself.
+fn sample_cone(axis : Vector3<f32>, one_minus_cos : f32, u1 : f32, u2 : f32) -> Vector3<f32>
+{
+	// 1 - cos(theta) and sin(theta) from it, both without cancellation.
+	let one_minus_cos_theta = u1 * one_minus_cos;
+	let sin_theta = (one_minus_cos_theta * (2.0 - one_minus_cos_theta)).max(0.0).sqrt();
+	let (sin_phi, cos_phi) = (2.0 * PI * u2).sin_cos();
+	let (tangent, bitangent) = orthonormal_basis(axis);
+	((tangent * cos_phi + bitangent * sin_phi) * sin_theta + axis * (1.0 - one_minus_cos_theta)).normalize()
+}
+
+// Every light of the scene besides the emissive triangles, picked with probability proportional to its power.
+pub struct Lights
+{
+	lights : Vec<Light>,
+	cumulative_power : Vec<f32>,
+	total_power : f32,
+}
+
+impl Lights
+{
+	pub fn new() -> Self
+	{
+		Self { lights : Vec::new(), cumulative_power : Vec::new(), total_power : 0.0 }
+	}
+
+	// Lights that emit nothing are left out. scene_radius weighs directional lights against the others.
+	pub fn from_lights(lights : &[Light], scene_radius : f32) -> Self
+	{
+		let mut collection = Self::new();
+		for light in lights
+		{
+			let power = light.power(scene_radius);
+			if power > 0.0 && power.is_finite()
+			{
+				collection.total_power += power;
+				collection.cumulative_power.push(collection.total_power);
+				collection.lights.push(*light);
+			}
+		}
+		collection
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.lights.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Light> + '_
+	{
+		self.lights.iter()
+	}
+
+	// Picks a light with u_select and a point on it with u1 and u2, all uniform in [0, 1). The pdf includes the pick.
+	pub fn sample(&self, point : Vector3<f32>, u_select : f32, u1 : f32, u2 : f32) -> Option<LightSample>
+	{
+		if self.is_empty()
+		{
+			return None;
+		}
+		let target = u_select * self.total_power;
+		let index = self.cumulative_power.partition_point(|&power| power <= target).min(self.lights.len() - 1);
+		let mut sample = self.lights[index].sample(point, u1, u2)?;
+		sample.pdf *= self.probability(index);
+		Some(sample)
+	}
+
+	// The density with which sample picks the light at index and direction on it from point.
+	pub fn pdf(&self, index : usize, point : Vector3<f32>, direction : Vector3<f32>) -> f32
+	{
+		self.lights[index].pdf(point, direction) * self.probability(index)
+	}
+
+	// The nearest rectangle or sphere light a ray meets between t_min and t_max.
+	pub fn hit(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<LightHit>
+	{
+		let mut closest = None;
+		let mut closest_t = t_max;
+		for (index, light) in self.lights.iter().enumerate()
+		{
+			if let Some((t, radiance)) = light.hit(ray, t_min, closest_t)
+			{
+				closest_t = t;
+				closest = Some(LightHit { light : index, radiance : radiance });
+			}
+		}
+		closest
+	}
+
+	pub fn occluded(&self, ray : &Ray, t_min : f32, t_max : f32) -> bool
+	{
+		self.lights.iter().any(|light| light.hit(ray, t_min, t_max).is_some())
+	}
+
+	// The directional lights with an angular diameter that direction looks into, by index, and their radiance.
+	pub fn distant(&self, direction : Vector3<f32>) -> impl Iterator<Item = (usize, Vector3<f32>)> + '_
+	{
+		self.lights.iter().enumerate()
+			.map(move |(index, light)| (index, light.distant_radiance(direction)))
+			.filter(|(_, radiance)| *radiance != Vector3::new(0.0, 0.0, 0.0))
+	}
+
+	fn probability(&self, index : usize) -> f32
+	{
+		let previous = if index > 0 { self.cumulative_power[index - 1] } else { 0.0 };
+		(self.cumulative_power[index] - previous) / self.total_power
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::material::cosine_sample_hemisphere;
+	use crate::rng::Rng;
+	use std::f64::consts::PI as PI_F64;
+
+	const SAMPLES : u32 = 400_000;
+	const WHITE : Vector3<f32> = Vector3 { x : 1.0, y : 1.0, z : 1.0 };
+	const ORIGIN : Vector3<f32> = Vector3 { x : 0.0, y : 0.0, z : 0.0 };
+	const UP : Vector3<f32> = Vector3 { x : 0.0, y : 1.0, z : 0.0 };
+
+	fn relative_error(estimate : f64, expected : f64) -> f64
+	{
+		(estimate - expected).abs() / expected.abs().max(1.0e-12)
+	}
+
+	// True for lights only sampling can find, whose sample pdf is a probability rather than a density.
+	fn is_delta(light : &Light) -> bool
+	{
+		match *light
+		{
+			Light::Point { .. } | Light::Spot { .. } => true,
+			Light::Directional { half_angle, .. } => half_angle <= 0.0,
+			Light::Rectangle { .. } | Light::Sphere { .. } => false,
+		}
+	}
+
+	fn cone(degrees : f32) -> f32
+	{
+		(degrees * PI / 180.0).cos()
+	}
+
+	fn slant() -> Vector3<f32>
+	{
+		Vector3::new(1.0, -2.0, 0.5).normalize()
+	}
+
+	fn point_light() -> Light
+	{
+		Light::Point { position : Vector3::new(0.0, 2.0, 0.0), intensity : WHITE * 8.0, range : f32::INFINITY }
+	}
+
+	fn spot_light() -> Light
+	{
+		Light::Spot { position : Vector3::new(0.0, 2.0, 0.0), direction : -UP, intensity : WHITE * 8.0, range : f32::INFINITY,
+			cos_inner : cone(20.0), cos_outer : cone(30.0) }
+	}
+
+	// A unit square at height 1 straight above the origin, facing down.
+	fn rectangle_light() -> Light
+	{
+		Light::new(&LightDescription { kind : LightKind::Rectangle { width : 1.0, height : 1.0 }, color : WHITE, intensity : 2.0, range : None },
+			&(Matrix4::from_translation(Vector3::new(0.0, 1.0, 0.0)) * Matrix4::from_angle_x(cgmath::Deg(-90.0))))
+	}
+
+	// The rectangle light's irradiance at the origin: a square of side 2a at height h, the configuration factor to
+	// each quarter in closed form.
+	fn rectangle_irradiance() -> f64
+	{
+		let (a, h) = (0.5_f64, 1.0_f64);
+		let quarter = (a / h) / (1.0 + (a / h).powi(2)).sqrt() * ((a / h) / (1.0 + (a / h).powi(2)).sqrt()).atan() / PI_F64;
+		2.0 * PI_F64 * 4.0 * quarter
+	}
+
+	// Irradiance at the origin facing up from a delta light's one sample.
+	fn delta_irradiance(light : &Light) -> f32
+	{
+		light.sample(ORIGIN, 0.5, 0.5).map_or(0.0, |sample| luminance(sample.radiance) * sample.direction.y / sample.pdf)
+	}
+
+	// Irradiance on a surface facing up, by next event estimation with sample, which takes three uniform numbers.
+	fn sampled_irradiance(sample : &dyn Fn(f32, f32, f32) -> Option<LightSample>, rng : &mut Rng, count : u32) -> f64
+	{
+		let mut sum = 0.0_f64;
+		for _ in 0..count
+		{
+			if let Some(sample) = sample(rng.next_f32(), rng.next_f32(), rng.next_f32())
+			{
+				if sample.direction.y > 0.0 && sample.pdf > 0.0
+				{
+					sum += (luminance(sample.radiance) * sample.direction.y / sample.pdf) as f64;
+				}
+			}
+		}
+		sum / count as f64
+	}
+
+	// Irradiance on a surface at point facing up, by cosine weighted directions that look for the light themselves.
+	fn found_irradiance(light : &Light, point : Vector3<f32>, rng : &mut Rng, count : u32) -> f64
+	{
+		let mut sum = 0.0_f64;
+		for _ in 0..count
+		{
+			let local = cosine_sample_hemisphere(rng.next_f32(), rng.next_f32());
+			let direction = Vector3::new(local.x, local.z, local.y);
+			let radiance = match light.hit(&Ray::new(point, direction), 0.0, f32::INFINITY)
+			{
+				Some((_, radiance)) => radiance,
+				None => light.distant_radiance(direction),
+			};
+			sum += luminance(radiance) as f64;
+		}
+		PI as f64 * sum / count as f64
+	}
+
+	// Sampling a light that rays can find: the pdf agrees with the sample, the ray toward the sample meets the light
+	// where the sample says, and sampling lights a surface as much as looking for the light does.
+	fn check_area_light(light : &Light, point : Vector3<f32>, expected : f64)
+	{
+		let mut rng = Rng::new(11, 0);
+		let mut mismatches = 0;
+		for _ in 0..SAMPLES / 10
+		{
+			let sample = match light.sample(point, rng.next_f32(), rng.next_f32())
+			{
+				Some(sample) => sample,
+				None => continue,
+			};
+			let pdf = light.pdf(point, sample.direction);
+			let distance_agrees = sample.distance.is_infinite() || light.hit(&Ray::new(point, sample.direction), 0.0, f32::INFINITY)
+				.is_some_and(|(t, _)| (t - sample.distance).abs() <= 1.0e-3 * sample.distance);
+			if (pdf - sample.pdf).abs() > 1.0e-3 * sample.pdf || !distance_agrees || sample.delta != is_delta(light)
+			{
+				mismatches += 1;
+			}
+		}
+		assert!(mismatches <= SAMPLES / 10_000, "{} of {} samples disagree with the pdf or the hit", mismatches, SAMPLES / 10);
+
+		let sampled = sampled_irradiance(&|_, u1, u2| light.sample(point, u1, u2), &mut rng, SAMPLES);
+		assert!(relative_error(sampled, expected) <= 0.01, "sampled irradiance {}, expected {}", sampled, expected);
+		let found = found_irradiance(light, point, &mut rng, SAMPLES);
+		assert!(relative_error(found, expected) <= 0.03, "found irradiance {}, expected {}", found, expected);
+	}
+
+	// The rectangle straight above, a dark point light and a sphere off to the side, so the rectangle doesn't shade
+	// it. A sphere above the horizon lights like a point of intensity pi r^2 L at its center.
+	fn picked_lights() -> Lights
+	{
+		let sphere = Light::Sphere { center : Vector3::new(2.0, 2.0, 0.0), radius : 0.5, radiance : WHITE * 4.0 };
+		Lights::from_lights(&[point_light(), Light::Point { position : UP, intensity : Vector3::new(0.0, 0.0, 0.0), range : 1.0 }, rectangle_light(), sphere], 1.0)
+	}
+
+	#[test]
+	fn point_light_follows_the_inverse_square_law()
+	{
+		let irradiance = delta_irradiance(&point_light());
+		assert!(relative_error(irradiance as f64, 2.0) <= 1.0e-4, "{}, expected 2", irradiance);
+	}
+
+	#[test]
+	fn spot_light_is_full_inside_the_inner_cone()
+	{
+		let irradiance = delta_irradiance(&spot_light());
+		assert!(relative_error(irradiance as f64, 2.0) <= 1.0e-4, "{}, expected 2", irradiance);
+	}
+
+	#[test]
+	fn spot_light_is_dark_outside_the_outer_cone()
+	{
+		let outside = spot_light().sample(Vector3::new(2.0, 0.0, 0.0), 0.5, 0.5).map_or(0.0, |sample| luminance(sample.radiance));
+		assert_eq!(outside, 0.0);
+	}
+
+	#[test]
+	fn spot_falloff_is_smooth_between_the_cones()
+	{
+		let falloff = [cone(19.0), cone(22.0), cone(25.0), cone(28.0), cone(31.0)].iter()
+			.map(|&cosine| spot_falloff(cosine, cone(20.0), cone(30.0)))
+			.collect::<Vec<_>>();
+		assert!(falloff[0] == 1.0 && falloff[4] == 0.0, "{:?}", falloff);
+		assert!(falloff.windows(2).all(|pair| pair[1] < pair[0] || pair[1] == 0.0), "{:?}", falloff);
+	}
+
+	#[test]
+	fn sharp_directional_light_follows_the_cosine()
+	{
+		let sun = Light::Directional { direction : slant(), irradiance : WHITE * 3.0, half_angle : 0.0 };
+		let (irradiance, expected) = (delta_irradiance(&sun), 3.0 * -slant().y);
+		assert!(relative_error(irradiance as f64, expected as f64) <= 1.0e-4, "{}, expected {}", irradiance, expected);
+	}
+
+	#[test]
+	fn delta_lights_have_no_pdf()
+	{
+		let sun = Light::Directional { direction : slant(), irradiance : WHITE * 3.0, half_angle : 0.0 };
+		assert_eq!(point_light().pdf(ORIGIN, UP), 0.0);
+		assert_eq!(spot_light().pdf(ORIGIN, UP), 0.0);
+		assert_eq!(sun.pdf(ORIGIN, -slant()), 0.0);
+	}
+
+	#[test]
+	fn rectangle_light_matches_its_configuration_factor()
+	{
+		check_area_light(&rectangle_light(), ORIGIN, rectangle_irradiance());
+	}
+
+	// A sphere of radius r at height h straight above lights the point with pi L (r / h)^2.
+	#[test]
+	fn sphere_light_matches_its_solid_angle()
+	{
+		let sphere = Light::Sphere { center : Vector3::new(0.0, 2.0, 0.0), radius : 0.5, radiance : WHITE * 4.0 };
+		check_area_light(&sphere, ORIGIN, PI_F64 * 4.0 * 0.0625);
+	}
+
+	// A disk of the sky above the horizon covers pi sin^2 of its half angle of projected solid angle, times the
+	// cosine of its axis. Wide enough that the cosine weighted directions find it often.
+	#[test]
+	fn directional_light_with_a_diameter()
+	{
+		let soft_sun = Light::Directional { direction : slant(), irradiance : WHITE * 3.0, half_angle : 0.2 };
+		check_area_light(&soft_sun, ORIGIN, 3.0 * -slant().y as f64);
+	}
+
+	#[test]
+	fn lights_that_emit_nothing_are_dropped()
+	{
+		assert_eq!(picked_lights().iter().count(), 3);
+	}
+
+	#[test]
+	fn picked_samples_agree_with_the_light_they_hit()
+	{
+		let lights = picked_lights();
+		let mut rng = Rng::new(13, 0);
+		let mut mismatches = 0;
+		for _ in 0..10_000
+		{
+			if let Some(sample) = lights.sample(ORIGIN, rng.next_f32(), rng.next_f32(), rng.next_f32())
+			{
+				let ray = Ray::new(ORIGIN, sample.direction);
+				let hit = lights.hit(&ray, 0.0, f32::INFINITY);
+				let t = lights.iter().filter_map(|light| light.hit(&ray, 0.0, f32::INFINITY)).map(|(t, _)| t).fold(f32::INFINITY, f32::min);
+				let pdf = hit.map_or(0.0, |hit| lights.pdf(hit.light, ORIGIN, sample.direction));
+				let agrees = if sample.delta
+				{
+					(sample.pdf - lights.probability(0)).abs() <= 1.0e-5
+				}
+				else
+				{
+					hit.is_some() && (t - sample.distance).abs() <= 1.0e-3 * sample.distance && (pdf - sample.pdf).abs() <= 1.0e-3 * sample.pdf
+				};
+				if !agrees
+				{
+					mismatches += 1;
+				}
+			}
+		}
+		assert!(mismatches <= 10, "{} of 10000 differ", mismatches);
+	}
+
+	#[test]
+	fn sampled_irradiance_of_all_the_lights()
+	{
+		let lights = picked_lights();
+		let expected = 2.0 + rectangle_irradiance() + PI_F64 * 4.0 * 0.25 / 8.0 * 0.5_f64.sqrt();
+		let sampled = sampled_irradiance(&|u_select, u1, u2| lights.sample(ORIGIN, u_select, u1, u2), &mut Rng::new(17, 0), SAMPLES);
+		assert!(relative_error(sampled, expected) <= 0.01, "{}, expected {}", sampled, expected);
+	}
+}
@@ -1,40 +1,333 @@
 // Declare Modules
+#[cfg(all(windows, feature = "win32"))]
 mod win_window;
+#[cfg(all(windows, feature = "win32"))]
 mod win_utilities;
+#[cfg(all(windows, feature = "win32"))]
 mod win_platform;
+#[cfg(all(windows, feature = "dx12"))]
 mod dx_renderer;
 mod geometry;
+mod ray;
+mod scene;
+mod camera;
+mod framebuffer;
+mod film;
+mod image_writer;
+mod exr_image;
+mod cpu_raytracer;
+mod tile_scheduler;
+mod rng;
+mod tone_mapping;
+mod sampler;
+mod render_settings;
+mod command_line;
+mod window_handle;
+mod render_backend;
+#[cfg(feature = "software")]
+mod software_renderer;
+#[cfg(feature = "software")]
+mod accumulation;
+mod platform;
+mod null_platform;
+mod input;
+mod camera_controller;
+mod bvh;
+mod mesh;
+mod obj_loader;
+mod area_lights;
+mod lights;
+mod environment;
+mod material;
+mod microfacet;
+mod principled;
+mod path_tracer;
+mod whitted;
+mod reference_scenes;
+mod scene_description;
+mod gltf_loader;
 
 // Use Declarations
-use std::thread;
-use std::sync::mpsc;
+use std::process;
+use std::sync::Arc;
+
+use environment::EnvironmentMap;
+use input::{ InputEvent, Key, ScriptedInput };
+use mesh::Mesh;
+use render_backend::BackendKind;
+use scene_description::SceneDescription;
+use render_settings::{ G_WIDTH, G_HEIGHT };
+
+// How long the main loop waits between looks at the input while the renderer is idle.
+const IDLE_SLEEP_MILLISECONDS : u64 = 10;
+
+// The scene to render: the --scene file, picked by extension, or the sample triangle.
+fn load_scene(options : &command_line::Options) -> SceneDescription
+{
+	if let Some(reference_scene) = options.reference_scene
+	{
+		return reference_scene.build();
+	}
+	let path = match &options.scene
+	{
+		Some(path) => path,
+		None => return SceneDescription::from_meshes(vec![Mesh::sample_triangle()]),
+	};
+
+	let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_ascii_lowercase();
+	let result = match extension.as_str()
+	{
+		"gltf" | "glb" => gltf_loader::load_gltf(path).map_err(|error| error.to_string()),
+		_ => obj_loader::load_obj(path).map(SceneDescription::from_obj).map_err(|error| error.to_string()),
+	};
+
+	match result
+	{
+		Ok(description) =>
+		{
+			let triangle_count : usize = description.meshes.iter().map(Mesh::triangle_count).sum();
+			println!("Loaded {}: {} meshes, {} triangles, {} materials, {} textures, {} cameras, {} lights",
+				path.display(), description.meshes.len(), triangle_count, description.materials.len(),
+				description.textures.len(), description.cameras.len(), description.lights.len());
+			for mesh in &description.meshes
+			{
+				println!("  {}: {} triangles", mesh.name, mesh.triangle_count());
+			}
+			description
+		}
+		Err(error) =>
+		{
+			eprintln!("Failed to load scene: {}", error);
+			process::exit(1);
+		}
+	}
+}
+
+// The --environment map, turned and scaled as asked, or None for the plain background color.
+fn load_environment(options : &command_line::Options) -> Option<Arc<EnvironmentMap>>
+{
+	let path = options.environment.as_ref()?;
+	let rotation = cgmath::Deg(options.environment_rotation).into();
+	match EnvironmentMap::load(path, rotation, options.environment_intensity)
+	{
+		Ok(environment) =>
+		{
+			println!("Loaded {}: {}x{} environment", path.display(), environment.width(), environment.height());
+			Some(Arc::new(environment))
+		}
+		Err(error) =>
+		{
+			eprintln!("Failed to load environment {}: {}", path.display(), error);
+			process::exit(1);
+		}
+	}
+}
+
+// Every mesh instance in world space, ready for a backend.
+fn load_meshes(description : &SceneDescription) -> Vec<Arc<Mesh>>
+{
+	description.instanced_meshes().into_iter().map(Arc::new).collect()
+}
+
+// A camera from the scene file if it has one. Otherwise loaded scenes are framed to fit the view,
+// and the sample triangle keeps the camera it was laid out for. Then the projection from the command line,
+// focused on the center of the scene.
+fn initial_camera(options : &command_line::Options, description : &SceneDescription, meshes : &[Arc<Mesh>]) -> camera::Camera
+{
+	let aspect_ratio = G_WIDTH as f32 / G_HEIGHT as f32;
+	let bounds = meshes.iter().fold(geometry::Aabb::empty(), |bounds, mesh| bounds.union(&mesh.bounds()));
+	let mut camera = match description.camera(aspect_ratio)
+	{
+		Some(camera) => camera,
+		None if options.scene.is_none() => scene::sample_triangle_camera(aspect_ratio),
+		None => scene::framing_camera(&bounds, aspect_ratio),
+	};
+
+	if let Some(model) = options.projection_model(&camera, bounds.centroid())
+	{
+		camera.set_model(model);
+	}
+	camera
+}
+
+// Renders with the software backend without creating a window and writes the image to disk.
+#[cfg(feature = "software")]
+fn run_headless(options : &command_line::Options)
+{
+	use render_backend::RenderBackend;
+	use window_handle::RawWindowHandle;
+
+	if options.backend.unwrap_or(BackendKind::Software) != BackendKind::Software
+	{
+		eprintln!("Headless renders need the software backend.");
+		process::exit(2);
+	}
+
+	if image_writer::ImageFormat::from_path(&options.output).is_none()
+	{
+		eprintln!("Can't write {}, the output image has to be .png, .ppm, .hdr or .exr.", options.output.display());
+		process::exit(2);
+	}
+
+	let mut renderer = software_renderer::SoftwareRenderer::new(G_WIDTH, G_HEIGHT, options.samples, options.samples, options.integrator());
+	if let Some(threads) = options.threads
+	{
+		renderer.set_thread_count(threads as usize);
+	}
+	renderer.set_sampler(options.sampler);
+	renderer.set_filter(options.filter());
+	let description = load_scene(options);
+	let meshes = load_meshes(&description);
+	renderer.set_environment(load_environment(options));
+	renderer.load_pipeline(RawWindowHandle::Headless);
+	renderer.load_assets(&meshes, &description.materials, &description.textures, &description.instanced_lights());
+
+	println!("Rendering {}x{} with {} samples per pixel.", G_WIDTH, G_HEIGHT, options.samples);
+	renderer.update(&initial_camera(options, &description, &meshes));
+	renderer.render();
+
+	let framebuffer = renderer.framebuffer().expect("the software backend always has a framebuffer");
+	let result = if image_writer::ImageFormat::from_path(&options.output) == Some(image_writer::ImageFormat::Exr)
+	{
+		let layers = exr_image::render_layers(framebuffer, renderer.aovs());
+		exr_image::write_exr(&options.output, &layers, &options.exr).map_err(|error| error.message)
+	}
+	else
+	{
+		image_writer::write_image(framebuffer, &options.output, &options.display_transform()).map_err(|error| error.to_string())
+	};
+	match result
+	{
+		Ok(()) => println!("Wrote {}", options.output.display()),
+		Err(error) =>
+		{
+			eprintln!("Failed to write {}: {}", options.output.display(), error);
+			process::exit(1);
+		}
+	}
+}
+
+#[cfg(not(feature = "software"))]
+fn run_headless(_options : &command_line::Options)
+{
+	eprintln!("Headless renders need the software backend, build with the 'software' cargo feature.");
+	process::exit(2);
+}
+
+fn load_input_script(path : &std::path::Path) -> Result<ScriptedInput, String>
+{
+	let script = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+	ScriptedInput::parse(&script)
+}
 
 // Main Function
 fn main() 
 {
 	println!("Hello, Rust!");
 
-	let (window_sender, window_reciever) = mpsc::channel::<win_window::Window>();
-	let (exit_sender, _exit_receiver) = mpsc::channel::<win_platform::ExitResult>();
-	let (input_sender, _input_receiver) = mpsc::channel::<u32>();
-	
-	let windows_thread= thread::Builder::new()
-		.name("win_platform_thread".to_string())
-		.spawn(move || {win_platform::platform_thread_run(window_sender, exit_sender, input_sender)})
-		.expect("failed to spin up win_platform_thread");
+	let options = match command_line::parse(std::env::args().skip(1))
+	{
+		Ok(options) => options,
+		Err(message) =>
+		{
+			eprintln!("{}\n\n{}", message, command_line::USAGE);
+			process::exit(2);
+		}
+	};
+
+	if options.help
+	{
+		println!("{}", command_line::USAGE);
+		return;
+	}
+
+	if let Some(triangle_count) = options.bvh_benchmark
+	{
+		bvh::run_benchmark(triangle_count as usize);
+		return;
+	}
+
+	if let Some(path) = &options.exr_info
+	{
+		if let Err(error) = exr_image::print_info(path)
+		{
+			eprintln!("Failed to read {}", error);
+			process::exit(1);
+		}
+		return;
+	}
 
-	let window = window_reciever.recv().unwrap();
+	if options.headless
+	{
+		run_headless(&options);
+		return;
+	}
 
-	let mut renderer = dx_renderer::Renderer::new();
-	renderer.load_pipeline(window);
-	renderer.load_assets();
+	// scripts play back on the null platform, which has no window for the dx12 swap chain.
+	let default_backend = if options.input_script.is_some() { BackendKind::Software } else { BackendKind::default_backend() };
+	let backend = options.backend.unwrap_or(default_backend);
+	if backend == BackendKind::Dx12 && options.environment.is_some()
+	{
+		eprintln!("Environment maps need the software backend, the dx12 one only rasterizes vertex colors.");
+		process::exit(2);
+	}
+	if backend == BackendKind::Dx12 && options.input_script.is_some()
+	{
+		eprintln!("Input scripts need the software backend, they play back without a window for the dx12 one to draw in.");
+		process::exit(2);
+	}
+
+	let mut renderer = match render_backend::create_backend(backend, options.integrator(), options.sampler, options.filter(), options.target_samples)
+	{
+		Ok(renderer) => renderer,
+		Err(message) =>
+		{
+			eprintln!("{}", message);
+			process::exit(2);
+		}
+	};
+
+	let scripted_input = match &options.input_script
+	{
+		None => None,
+		Some(path) => match load_input_script(path)
+		{
+			Ok(scripted_input) => Some(scripted_input),
+			Err(message) =>
+			{
+				eprintln!("Failed to load input script {}: {}", path.display(), message);
+				process::exit(2);
+			}
+		}
+	};
+
+	let mut platform = platform::create_platform(scripted_input);
+	if let Err(platform_error) = platform.create_window("rust_raytracer", G_WIDTH, G_HEIGHT)
+	{
+		eprintln!("{}", platform_error);
+		process::exit(1);
+	}
+
+	let description = load_scene(&options);
+	let meshes = load_meshes(&description);
+	let display = options.display_transform();
+	renderer.set_display_transform(&display);
+	renderer.set_environment(load_environment(&options));
+	renderer.load_pipeline(platform.raw_handle());
+	renderer.load_assets(&meshes, &description.materials, &description.textures, &description.instanced_lights());
+
+	let mut camera = initial_camera(&options, &description, &meshes);
+	let mut camera_controller = camera_controller::FlyCameraController::new();
 
 	use std::time::{Instant};
 
 	// timing measures for Frames-Per-Second
 	let now = Instant::now();
+	let mut last_frame = now;
 	let mut second_fence = 0;
 	let mut count = 0;
+	let mut frames_rendered = 0;
+	let mut was_idle = false;
 
 	loop
 	{
@@ -50,7 +343,20 @@ fn main()
 			count += 1;
 		}
 		
-		renderer.update();
+		for event in platform.pump_events()
+		{
+			if event == InputEvent::KeyDown(Key::Escape)
+			{
+				platform.request_exit();
+			}
+			camera_controller.handle_event(&event);
+		}
+
+		let frame_start = Instant::now();
+		camera_controller.update(&mut camera, (frame_start - last_frame).as_secs_f32());
+		last_frame = frame_start;
+
+		renderer.update(&camera);
 		let result = renderer.render();
 
 		if result != 0
@@ -58,21 +364,41 @@ fn main()
 			break; 
 		}
 
-		let try_exit_result = _exit_receiver.try_recv();
-		match try_exit_result
+		if let Some(framebuffer) = renderer.framebuffer()
+		{
+			platform.present(framebuffer, &display);
+		}
+
+		// nothing changes until the camera moves, so stop spinning and just keep up with input.
+		let idle = renderer.idle();
+		if idle && !was_idle
+		{
+			println!("Reached {} samples per pixel.", options.target_samples);
+		}
+		was_idle = idle;
+		if idle
+		{
+			std::thread::sleep(std::time::Duration::from_millis(IDLE_SLEEP_MILLISECONDS));
+		}
+
+		frames_rendered += 1;
+		if Some(frames_rendered) == options.frames
 		{
-			Err(_) => (), // nothing received fromt the platform thread
-			Ok(exit_result) => 
+			platform.request_exit();
+		}
+
+		match platform.exit_result()
+		{
+			None => (), // the platform is still running
+			Some(exit_result) => 
 			{
 				match exit_result
 				{
-					Ok(exit_code) => println!("Platform Thread Exited Successfully. Exit Code {:?}", exit_code),
-					Err(platform_error) => println!("Platform Thread Exited with Error: {:?}", platform_error),
+					Ok(exit_code) => println!("Platform Exited Successfully. Exit Code {:?}", exit_code),
+					Err(platform_error) => println!("Platform Exited with Error: {:?}", platform_error),
 				}
 				break
 			}
 		}
 	}
-
-	windows_thread.join().expect("failed to join win_platform_thread");
 }
@@ -0,0 +1,1010 @@
+use cgmath::InnerSpace;
+use cgmath::{ Vector3, Vector4 };
+
+use crate::area_lights::luminance;
+use crate::geometry::orthonormal_basis;
+use crate::microfacet::Ggx;
+use crate::principled::Principled;
+use crate::scene_description::MaterialDescription;
+
+// Below this GGX alpha a conductor is treated as a perfect mirror.
+const SMOOTH_ALPHA : f32 = 1.0e-3;
+
+// Directions in BSDF space: z is the shading normal, on the side the ray arrived from.
+#[derive(Copy, Clone, Debug)]
+pub struct ShadingFrame
+{
+	tangent : Vector3<f32>,
+	bitangent : Vector3<f32>,
+	normal : Vector3<f32>,
+}
+
+impl ShadingFrame
+{
+	pub fn new(normal : Vector3<f32>) -> Self
+	{
+		let (tangent, bitangent) = orthonormal_basis(normal);
+		Self { tangent : tangent, bitangent : bitangent, normal : normal }
+	}
+
+	pub fn to_local(self, direction : Vector3<f32>) -> Vector3<f32>
+	{
+		Vector3::new(direction.dot(self.tangent), direction.dot(self.bitangent), direction.dot(self.normal))
+	}
+
+	pub fn to_world(self, direction : Vector3<f32>) -> Vector3<f32>
+	{
+		self.tangent * direction.x + self.bitangent * direction.y + self.normal * direction.z
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BsdfSample
+{
+	pub direction : Vector3<f32>, // wi, in BSDF space
+	pub weight : Vector3<f32>,    // f * |cos| / pdf, what the path throughput gets multiplied by
+	pub pdf : f32,                // per unit solid angle, or the probability of the lobe when specular
+	pub specular : bool,          // from a lobe evaluate and pdf can't see, like a perfect mirror
+}
+
+// Scattering at a surface point. wo points toward where the light goes (the viewer), wi toward where it comes from,
+// both unit length in BSDF space and pointing away from the surface.
+pub trait Bsdf
+{
+	// f(wo, wi), zero for the specular lobes.
+	fn evaluate(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>;
+
+	// Picks wi with three uniform numbers in [0, 1), the first choosing between lobes.
+	fn sample(&self, wo : Vector3<f32>, u_lobe : f32, u1 : f32, u2 : f32) -> Option<BsdfSample>;
+
+	// The density with which sample picks wi, zero for the specular lobes.
+	fn pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32;
+
+	// Radiance the surface itself emits toward wo.
+	fn emitted(&self, _wo : Vector3<f32>) -> Vector3<f32>
+	{
+		Vector3::new(0.0, 0.0, 0.0)
+	}
+}
+
+// A hit's material with its textures looked up, see Scene::surface.
+#[derive(Copy, Clone, Debug)]
+pub struct Surface<'a>
+{
+	pub material : Option<&'a MaterialDescription>,
+	pub color : Vector4<f32>, // the hit color, times the base color texture
+	pub metallic : f32,       // the material's, times the metallic-roughness texture
+	pub roughness : f32,
+}
+
+// The BSDF of a hit, following the glTF metallic-roughness model unless the material has principled parameters.
+// Transmission picks glass when it is the larger part, as the metallic-roughness BSDF has no transmission.
+// Hits without a material are diffuse in their vertex color.
+pub fn surface_bsdf(surface : &Surface, front_face : bool) -> Box<dyn Bsdf>
+{
+	let albedo = surface.color.truncate();
+	let material = match surface.material
+	{
+		Some(material) => material,
+		None => return Box::new(Lambertian { albedo : albedo }),
+	};
+
+	if luminance(material.emission) > 0.0
+	{
+		let emits = front_face || material.double_sided;
+		let radiance = if emits { material.emission } else { Vector3::new(0.0, 0.0, 0.0) };
+		return Box::new(DiffuseEmitter { radiance : radiance, surface : Lambertian { albedo : albedo } });
+	}
+
+	// hits from behind are leaving the material.
+	let eta = if front_face { material.index_of_refraction } else { 1.0 / material.index_of_refraction };
+	if let Some(parameters) = &material.principled
+	{
+		let specular = material.specular * dielectric_reflectance(material.index_of_refraction) / 0.08;
+		Box::new(Principled::new(albedo, surface.metallic, surface.roughness, specular, material.transmission, eta, parameters))
+	}
+	else if material.transmission >= 0.5
+	{
+		Box::new(Dielectric { eta : eta, tint : albedo })
+	}
+	else
+	{
+		let metallic = surface.metallic.clamp(0.0, 1.0);
+		let specular = material.specular.clamp(0.0, 1.0);
+		let roughness = surface.roughness.clamp(0.0, 1.0);
+		if metallic >= 1.0
+		{
+			Box::new(Conductor { reflectance : albedo, alpha : roughness * roughness })
+		}
+		else if metallic <= 0.0 && specular <= 0.0
+		{
+			Box::new(Lambertian { albedo : albedo })
+		}
+		else
+		{
+			Box::new(MetallicRoughness
+			{
+				base_color : albedo,
+				metallic : metallic,
+				specular : specular,
+				dielectric_reflectance : dielectric_reflectance(material.index_of_refraction),
+				distribution : Ggx::isotropic(roughness * roughness),
+			})
+		}
+	}
+}
+
+// Reflectance at normal incidence of a dielectric with this index of refraction in air, 0.04 for glTF's 1.5.
+pub fn dielectric_reflectance(index_of_refraction : f32) -> f32
+{
+	((index_of_refraction - 1.0) / (index_of_refraction + 1.0)).powi(2)
+}
+
+pub struct Lambertian
+{
+	pub albedo : Vector3<f32>,
+}
+
+impl Bsdf for Lambertian
+{
+	fn evaluate(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>
+	{
+		if wo.z > 0.0 && wi.z > 0.0 { self.albedo / std::f32::consts::PI } else { Vector3::new(0.0, 0.0, 0.0) }
+	}
+
+	// cosine weighted, for which f * cos / pdf is the albedo.
+	fn sample(&self, wo : Vector3<f32>, _u_lobe : f32, u1 : f32, u2 : f32) -> Option<BsdfSample>
+	{
+		if wo.z <= 0.0
+		{
+			return None;
+		}
+		let direction = cosine_sample_hemisphere(u1, u2);
+		Some(BsdfSample { direction : direction, weight : self.albedo, pdf : direction.z / std::f32::consts::PI, specular : false })
+	}
+
+	fn pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		if wo.z > 0.0 && wi.z > 0.0 { wi.z / std::f32::consts::PI } else { 0.0 }
+	}
+}
+
+// A metal. Perfectly smooth below SMOOTH_ALPHA, otherwise a GGX microfacet surface sampled by visible normals.
+pub struct Conductor
+{
+	pub reflectance : Vector3<f32>, // at normal incidence, Schlick's approximation does the rest
+	pub alpha : f32,
+}
+
+impl Bsdf for Conductor
+{
+	fn evaluate(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>
+	{
+		if self.alpha < SMOOTH_ALPHA || wo.z <= 0.0 || wi.z <= 0.0
+		{
+			return Vector3::new(0.0, 0.0, 0.0);
+		}
+		let half = (wo + wi).normalize();
+		schlick(self.reflectance, wi.dot(half)) * specular_reflection(&Ggx::isotropic(self.alpha), wo, wi, half)
+	}
+
+	fn sample(&self, wo : Vector3<f32>, _u_lobe : f32, u1 : f32, u2 : f32) -> Option<BsdfSample>
+	{
+		if wo.z <= 0.0
+		{
+			return None;
+		}
+		if self.alpha < SMOOTH_ALPHA
+		{
+			let direction = Vector3::new(-wo.x, -wo.y, wo.z);
+			return Some(BsdfSample { direction : direction, weight : schlick(self.reflectance, wo.z), pdf : 1.0, specular : true });
+		}
+
+		let half = Ggx::isotropic(self.alpha).sample_visible_normal(wo, u1, u2);
+		let direction = reflect(wo, half);
+		let pdf = self.pdf(wo, direction);
+		if direction.z <= 0.0 || pdf <= 0.0
+		{
+			return None;
+		}
+		Some(BsdfSample { direction : direction, weight : self.evaluate(wo, direction) * (direction.z / pdf), pdf : pdf, specular : false })
+	}
+
+	fn pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		if self.alpha < SMOOTH_ALPHA || wo.z <= 0.0 || wi.z <= 0.0
+		{
+			return 0.0;
+		}
+		Ggx::isotropic(self.alpha).reflection_pdf(wo, wi)
+	}
+}
+
+// glTF's metallic-roughness BRDF: a blend by metallic of a metal tinted by the base color, and a dielectric whose
+// GGX specular layer reflects the Fresnel share of the light and lets the rest through to a diffuse base.
+// specular scales the dielectric's Fresnel term, as in KHR_materials_specular. The base gets what the layer lets
+// through both ways, (1 - F(wo)) (1 - F(wi)), rather than glTF's 1 - F(wo . h), which gains energy at grazing angles.
+pub struct MetallicRoughness
+{
+	pub base_color : Vector3<f32>,
+	pub metallic : f32,
+	pub specular : f32,
+	pub dielectric_reflectance : f32, // at normal incidence
+	pub distribution : Ggx,
+}
+
+impl MetallicRoughness
+{
+	fn is_smooth(&self) -> bool
+	{
+		self.distribution.alpha_x.max(self.distribution.alpha_y) < SMOOTH_ALPHA
+	}
+
+	fn dielectric_fresnel(&self, cosine : f32) -> f32
+	{
+		let reflectance = self.dielectric_reflectance;
+		self.specular * (reflectance + (1.0 - reflectance) * schlick_weight(cosine))
+	}
+
+	// How often sample picks the specular lobe, roughly its share of the reflected light.
+	fn specular_probability(&self, wo : Vector3<f32>) -> f32
+	{
+		if self.metallic >= 1.0
+		{
+			return 1.0;
+		}
+		let share = self.metallic + (1.0 - self.metallic) * self.dielectric_fresnel(wo.z);
+		share.clamp(0.1, 0.9)
+	}
+
+	// The specular lobe of a smooth surface, which evaluate can't see, toward the mirror direction.
+	fn smooth_reflectance(&self, wo : Vector3<f32>) -> Vector3<f32>
+	{
+		let dielectric = self.dielectric_fresnel(wo.z);
+		schlick(self.base_color, wo.z) * self.metallic + Vector3::new(dielectric, dielectric, dielectric) * (1.0 - self.metallic)
+	}
+}
+
+impl Bsdf for MetallicRoughness
+{
+	fn evaluate(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>
+	{
+		if wo.z <= 0.0 || wi.z <= 0.0
+		{
+			return Vector3::new(0.0, 0.0, 0.0);
+		}
+		let transmitted = (1.0 - self.dielectric_fresnel(wo.z)) * (1.0 - self.dielectric_fresnel(wi.z));
+		let diffuse = self.base_color * (transmitted / std::f32::consts::PI);
+		if self.is_smooth()
+		{
+			return diffuse * (1.0 - self.metallic);
+		}
+
+		let half = (wo + wi).normalize();
+		let cosine = wo.dot(half);
+		let specular = specular_reflection(&self.distribution, wo, wi, half);
+		let metal = schlick(self.base_color, cosine) * specular;
+		let dielectric = diffuse + Vector3::new(1.0, 1.0, 1.0) * (self.dielectric_fresnel(cosine) * specular);
+		metal * self.metallic + dielectric * (1.0 - self.metallic)
+	}
+
+	// picks a lobe, then weighs by the density of both, so the weight stays bounded whichever lobe was picked.
+	fn sample(&self, wo : Vector3<f32>, u_lobe : f32, u1 : f32, u2 : f32) -> Option<BsdfSample>
+	{
+		if wo.z <= 0.0
+		{
+			return None;
+		}
+
+		let specular_probability = self.specular_probability(wo);
+		let direction = if u_lobe < specular_probability
+		{
+			if self.is_smooth()
+			{
+				let direction = Vector3::new(-wo.x, -wo.y, wo.z);
+				let weight = self.smooth_reflectance(wo) / specular_probability;
+				return Some(BsdfSample { direction : direction, weight : weight, pdf : specular_probability, specular : true });
+			}
+			reflect(wo, self.distribution.sample_visible_normal(wo, u1, u2))
+		}
+		else
+		{
+			cosine_sample_hemisphere(u1, u2)
+		};
+
+		let pdf = self.pdf(wo, direction);
+		if direction.z <= 0.0 || pdf <= 0.0
+		{
+			return None;
+		}
+		Some(BsdfSample { direction : direction, weight : self.evaluate(wo, direction) * (direction.z / pdf), pdf : pdf, specular : false })
+	}
+
+	fn pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		if wo.z <= 0.0 || wi.z <= 0.0
+		{
+			return 0.0;
+		}
+		let specular_probability = self.specular_probability(wo);
+		let diffuse = (1.0 - specular_probability) * wi.z / std::f32::consts::PI;
+		if self.is_smooth() { diffuse } else { diffuse + specular_probability * self.distribution.reflection_pdf(wo, wi) }
+	}
+}
+
+// Smooth glass, reflecting or refracting in proportion to the exact Fresnel reflectance. Transmitted light is
+// tinted, and not scaled by the change of index, which cancels out for closed objects seen from outside.
+pub struct Dielectric
+{
+	pub eta : f32, // index of refraction past the surface over the one wo is in
+	pub tint : Vector3<f32>,
+}
+
+impl Bsdf for Dielectric
+{
+	fn evaluate(&self, _wo : Vector3<f32>, _wi : Vector3<f32>) -> Vector3<f32>
+	{
+		Vector3::new(0.0, 0.0, 0.0)
+	}
+
+	fn sample(&self, wo : Vector3<f32>, u_lobe : f32, _u1 : f32, _u2 : f32) -> Option<BsdfSample>
+	{
+		if wo.z <= 0.0
+		{
+			return None;
+		}
+
+		let reflectance = fresnel_dielectric(wo.z, self.eta);
+		if u_lobe < reflectance
+		{
+			let direction = Vector3::new(-wo.x, -wo.y, wo.z);
+			return Some(BsdfSample { direction : direction, weight : Vector3::new(1.0, 1.0, 1.0), pdf : reflectance, specular : true });
+		}
+
+		// reflectance is 1 under total internal reflection, so there is a refracted direction here.
+		let direction = refract(wo, Vector3::new(0.0, 0.0, 1.0), self.eta)?;
+		Some(BsdfSample { direction : direction, weight : self.tint, pdf : 1.0 - reflectance, specular : true })
+	}
+
+	fn pdf(&self, _wo : Vector3<f32>, _wi : Vector3<f32>) -> f32
+	{
+		0.0
+	}
+}
+
+// An area light: emits radiance evenly in every direction of its front side and reflects diffusely.
+pub struct DiffuseEmitter
+{
+	pub radiance : Vector3<f32>,
+	pub surface : Lambertian,
+}
+
+impl Bsdf for DiffuseEmitter
+{
+	fn evaluate(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>
+	{
+		self.surface.evaluate(wo, wi)
+	}
+
+	fn sample(&self, wo : Vector3<f32>, u_lobe : f32, u1 : f32, u2 : f32) -> Option<BsdfSample>
+	{
+		self.surface.sample(wo, u_lobe, u1, u2)
+	}
+
+	fn pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		self.surface.pdf(wo, wi)
+	}
+
+	fn emitted(&self, wo : Vector3<f32>) -> Vector3<f32>
+	{
+		if wo.z > 0.0 { self.radiance } else { Vector3::new(0.0, 0.0, 0.0) }
+	}
+}
+
+// Malley's method, a uniform point on the disk projected up onto the hemisphere.
+pub fn cosine_sample_hemisphere(u1 : f32, u2 : f32) -> Vector3<f32>
+{
+	let radius = u1.sqrt();
+	let angle = 2.0 * std::f32::consts::PI * u2;
+	Vector3::new(radius * angle.cos(), radius * angle.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+// The mirror image of a direction leaving the surface, also leaving it.
+pub fn reflect(direction : Vector3<f32>, normal : Vector3<f32>) -> Vector3<f32>
+{
+	normal * (2.0 * direction.dot(normal)) - direction
+}
+
+// Snell's law for a unit direction leaving the surface on the normal's side, eta being the index of refraction past
+// the surface over the one on the normal's side. None under total internal reflection.
+pub fn refract(direction : Vector3<f32>, normal : Vector3<f32>, eta : f32) -> Option<Vector3<f32>>
+{
+	let cosine = direction.dot(normal);
+	let sine_squared = (1.0 - cosine * cosine) / (eta * eta);
+	if sine_squared >= 1.0
+	{
+		return None;
+	}
+	Some((normal * (cosine / eta - (1.0 - sine_squared).sqrt()) - direction / eta).normalize())
+}
+
+pub fn multiply(a : Vector3<f32>, b : Vector3<f32>) -> Vector3<f32>
+{
+	Vector3::new(a.x * b.x, a.y * b.y, a.z * b.z)
+}
+
+// D * G / (4 cos cos), the microfacet reflection without its Fresnel term.
+fn specular_reflection(distribution : &Ggx, wo : Vector3<f32>, wi : Vector3<f32>, half : Vector3<f32>) -> f32
+{
+	distribution.distribution(half) * distribution.masking_shadowing(wo, wi) / (4.0 * wo.z * wi.z)
+}
+
+// (1 - cos)^5, how far Schlick's Fresnel goes from its value at normal incidence toward 1.
+pub fn schlick_weight(cosine : f32) -> f32
+{
+	(1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+fn schlick(reflectance : Vector3<f32>, cosine : f32) -> Vector3<f32>
+{
+	reflectance + (Vector3::new(1.0, 1.0, 1.0) - reflectance) * schlick_weight(cosine)
+}
+
+// Unpolarized Fresnel reflectance for light arriving at cosine from the normal, eta being the ratio of the
+// index past the surface to the one the light is in.
+pub fn fresnel_dielectric(cosine : f32, eta : f32) -> f32
+{
+	let sine_squared = (1.0 - cosine * cosine) / (eta * eta);
+	if sine_squared >= 1.0
+	{
+		return 1.0;
+	}
+	let transmitted_cosine = (1.0 - sine_squared).sqrt();
+	let perpendicular = (cosine - eta * transmitted_cosine) / (cosine + eta * transmitted_cosine);
+	let parallel = (eta * cosine - transmitted_cosine) / (eta * cosine + transmitted_cosine);
+	0.5 * (perpendicular * perpendicular + parallel * parallel)
+}
+
+// Schlick's approximation of that. Going into the less dense medium it has to use the angle on the far side, which
+// is the larger one.
+pub fn schlick_dielectric(cosine : f32, eta : f32) -> f32
+{
+	let cosine = if eta < 1.0 { (1.0 - (1.0 - cosine * cosine) / (eta * eta)).max(0.0).sqrt() } else { cosine };
+	let r0 = dielectric_reflectance(eta);
+	r0 + (1.0 - r0) * schlick_weight(cosine)
+}
+
+#[cfg(test)]
+pub(crate) mod tests
+{
+	use super::*;
+	use crate::rng::Rng;
+	use crate::scene_description::PrincipledParameters;
+
+	const ANGLES : [f32; 3] = [0.0, 30.0, 60.0];
+
+	fn outgoing(angle : f32) -> Vector3<f32>
+	{
+		Vector3::new(angle.to_radians().sin(), 0.0, angle.to_radians().cos())
+	}
+
+	fn white() -> Vector3<f32>
+	{
+		Vector3::new(1.0, 1.0, 1.0)
+	}
+
+	fn metallic_roughness(metallic : f32, roughness : f32) -> MetallicRoughness
+	{
+		MetallicRoughness
+		{
+			base_color : white(),
+			metallic : metallic,
+			specular : 1.0,
+			dielectric_reflectance : dielectric_reflectance(1.5),
+			distribution : Ggx::isotropic(roughness * roughness),
+		}
+	}
+
+	fn principled(metallic : f32, roughness : f32, transmission : f32, eta : f32, parameters : PrincipledParameters) -> Principled
+	{
+		Principled::new(white(), metallic, roughness, 0.5, transmission, eta, &parameters)
+	}
+
+	// A uniformly distributed direction on the unit sphere.
+	pub(crate) fn uniform_sphere(u1 : f32, u2 : f32) -> Vector3<f32>
+	{
+		let z = 1.0 - 2.0 * u1;
+		let radius = (1.0 - z * z).max(0.0).sqrt();
+		let angle = 2.0 * std::f32::consts::PI * u2;
+		Vector3::new(radius * angle.cos(), radius * angle.sin(), z)
+	}
+
+	// Mean and standard error of the mean of values added one at a time.
+	struct Statistics
+	{
+		count : u32,
+		sum : f64,
+		sum_squared : f64,
+	}
+
+	impl Statistics
+	{
+		fn new() -> Self
+		{
+			Self { count : 0, sum : 0.0, sum_squared : 0.0 }
+		}
+
+		fn add(&mut self, value : f64)
+		{
+			self.count += 1;
+			self.sum += value;
+			self.sum_squared += value * value;
+		}
+
+		fn mean(&self) -> f64
+		{
+			self.sum / self.count as f64
+		}
+
+		fn standard_error(&self) -> f64
+		{
+			let mean = self.mean();
+			((self.sum_squared / self.count as f64 - mean * mean).max(0.0) / self.count as f64).sqrt()
+		}
+	}
+
+	// Directional albedo by sampling, and the share of it from lobes that aren't specular. The latter is also
+	// estimated with uniformly distributed directions and evaluate, along with the integral of pdf over the sphere;
+	// they can only agree when sample, evaluate and pdf describe the same distribution.
+	struct AlbedoEstimate
+	{
+		sampled : Statistics,
+		sampled_glossy : Statistics,
+		uniform : Statistics,
+		pdf_integral : Statistics,
+	}
+
+	fn estimate_albedo(bsdf : &dyn Bsdf, wo : Vector3<f32>, count : u32, rng : &mut Rng) -> AlbedoEstimate
+	{
+		let (mut sampled, mut sampled_glossy) = (Statistics::new(), Statistics::new());
+		for _ in 0..count
+		{
+			let sample = bsdf.sample(wo, rng.next_f32(), rng.next_f32(), rng.next_f32());
+			let value = sample.map_or(0.0, |sample| sample.weight.x as f64);
+			sampled.add(value);
+			sampled_glossy.add(if sample.is_some_and(|sample| sample.specular) { 0.0 } else { value });
+		}
+
+		let mut uniform = Statistics::new();
+		let mut pdf_integral = Statistics::new();
+		let sphere_area = 4.0 * std::f64::consts::PI;
+		for _ in 0..count
+		{
+			let wi = uniform_sphere(rng.next_f32(), rng.next_f32());
+			uniform.add(bsdf.evaluate(wo, wi).x as f64 * wi.z.abs() as f64 * sphere_area);
+			pdf_integral.add(bsdf.pdf(wo, wi) as f64 * sphere_area);
+		}
+
+		AlbedoEstimate { sampled : sampled, sampled_glossy : sampled_glossy, uniform : uniform, pdf_integral : pdf_integral }
+	}
+
+	// Energy conservation, agreement between sample, evaluate and pdf, and reciprocity (f(wo, wi) = f(wi, wo)).
+	// expected is the directional albedo when it is known. Burley's retro-reflection and sheen add to the specular
+	// rather than taking their share of it, so the diffuse lobes of the principled BSDF reflect more than they
+	// receive, as in Disney's and Blender's, and gains_energy lets them.
+	fn check_albedo_and_reciprocity(bsdf : &dyn Bsdf, expected : Option<f64>, gains_energy : bool)
+	{
+		let mut rng = Rng::new(0x0a1b, 0x5eed);
+		for &angle in &[0.0, 30.0, 60.0, 85.0]
+		{
+			let result = estimate_albedo(bsdf, outgoing(angle), 50_000, &mut rng);
+			let (sampled, glossy, uniform) = (&result.sampled, &result.sampled_glossy, &result.uniform);
+
+			let tolerance = 4.0 * sampled.standard_error() + 1.0e-3;
+			assert!(sampled.mean() <= 1.0 + tolerance || gains_energy, "{} degrees: albedo {} (+- {})", angle, sampled.mean(), sampled.standard_error());
+			if let Some(expected) = expected
+			{
+				assert!((sampled.mean() - expected).abs() <= tolerance, "{} degrees: albedo {} (+- {}), expected {}", angle, sampled.mean(), sampled.standard_error(), expected);
+			}
+			let difference = (glossy.mean() - uniform.mean()).abs();
+			assert!(difference <= 4.0 * (glossy.standard_error().powi(2) + uniform.standard_error().powi(2)).sqrt() + 1.0e-3,
+				"{} degrees: the glossy lobes sample {} but integrate to {}", angle, glossy.mean(), uniform.mean());
+			let pdf_integral = &result.pdf_integral;
+			assert!(pdf_integral.mean() <= 1.0 + 4.0 * pdf_integral.standard_error() + 0.02, "{} degrees: pdf integrates to {} (+- {})",
+				angle, pdf_integral.mean(), pdf_integral.standard_error());
+		}
+
+		// reflection only, as transmission that leaves radiance unscaled by the change of index isn't reciprocal.
+		let upper_hemisphere = |direction : Vector3<f32>| Vector3::new(direction.x, direction.y, direction.z.abs());
+		for _ in 0..10_000
+		{
+			let wo = upper_hemisphere(uniform_sphere(rng.next_f32(), rng.next_f32()));
+			let wi = upper_hemisphere(uniform_sphere(rng.next_f32(), rng.next_f32()));
+			let (forward, backward) = (bsdf.evaluate(wo, wi).x, bsdf.evaluate(wi, wo).x);
+			let difference = (forward - backward).abs() / forward.abs().max(backward.abs()).max(1.0e-3);
+			assert!(difference <= 1.0e-4, "f({:?}, {:?}) = {} but f reversed = {}", wo, wi, forward, backward);
+		}
+	}
+
+	#[test]
+	fn lambertian_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&Lambertian { albedo : white() }, Some(1.0), false);
+	}
+
+	#[test]
+	fn emitter_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&DiffuseEmitter { radiance : white(), surface : Lambertian { albedo : white() * 0.5 } }, Some(0.5), false);
+	}
+
+	#[test]
+	fn smooth_conductor_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&Conductor { reflectance : white(), alpha : 0.0 }, Some(1.0), false);
+	}
+
+	#[test]
+	fn conductor_alpha_0_1_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&Conductor { reflectance : white(), alpha : 0.1 }, None, false);
+	}
+
+	#[test]
+	fn conductor_alpha_0_5_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&Conductor { reflectance : white(), alpha : 0.5 }, None, false);
+	}
+
+	#[test]
+	fn conductor_alpha_1_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&Conductor { reflectance : white(), alpha : 1.0 }, None, false);
+	}
+
+	#[test]
+	fn glass_entering_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&Dielectric { eta : 1.5, tint : white() }, Some(1.0), false);
+	}
+
+	#[test]
+	fn glass_leaving_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&Dielectric { eta : 1.0 / 1.5, tint : white() }, Some(1.0), false);
+	}
+
+	#[test]
+	fn smooth_plastic_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&metallic_roughness(0.0, 0.0), None, false);
+	}
+
+	#[test]
+	fn plastic_roughness_0_5_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&metallic_roughness(0.0, 0.5), None, false);
+	}
+
+	#[test]
+	fn plastic_roughness_1_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&metallic_roughness(0.0, 1.0), None, false);
+	}
+
+	#[test]
+	fn half_metal_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&metallic_roughness(0.5, 0.5), None, false);
+	}
+
+	#[test]
+	fn disney_diffuse_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&principled(0.0, 0.5, 0.0, 1.5, PrincipledParameters::default()), None, true);
+	}
+
+	#[test]
+	fn disney_subsurface_albedo_and_reciprocity()
+	{
+		let parameters = PrincipledParameters { subsurface : 1.0, ..PrincipledParameters::default() };
+		check_albedo_and_reciprocity(&principled(0.0, 0.5, 0.0, 1.5, parameters), None, true);
+	}
+
+	#[test]
+	fn disney_sheen_albedo_and_reciprocity()
+	{
+		let parameters = PrincipledParameters { sheen : 1.0, ..PrincipledParameters::default() };
+		check_albedo_and_reciprocity(&principled(0.0, 0.8, 0.0, 1.5, parameters), None, true);
+	}
+
+	#[test]
+	fn disney_clearcoat_albedo_and_reciprocity()
+	{
+		let parameters = PrincipledParameters { clearcoat : 1.0, clearcoat_roughness : 0.3, ..PrincipledParameters::default() };
+		check_albedo_and_reciprocity(&principled(0.0, 0.5, 0.0, 1.5, parameters), None, true);
+	}
+
+	#[test]
+	fn disney_anisotropic_albedo_and_reciprocity()
+	{
+		let parameters = PrincipledParameters { anisotropic : 0.8, anisotropic_rotation : 0.2, ..PrincipledParameters::default() };
+		check_albedo_and_reciprocity(&principled(1.0, 0.5, 0.0, 1.5, parameters), None, false);
+	}
+
+	#[test]
+	fn disney_glass_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&principled(0.0, 0.5, 1.0, 1.5, PrincipledParameters::default()), None, false);
+	}
+
+	#[test]
+	fn disney_glass_leaving_albedo_and_reciprocity()
+	{
+		check_albedo_and_reciprocity(&principled(0.0, 0.5, 1.0, 1.0 / 1.5, PrincipledParameters::default()), None, false);
+	}
+
+	#[test]
+	fn refract_follows_snells_law()
+	{
+		// 30 degrees from the normal in air, into glass.
+		let direction = Vector3::new(0.5, 0.0, 0.75_f32.sqrt());
+		let refracted = refract(direction, Vector3::new(0.0, 0.0, 1.0), 1.5).expect("light enters glass at any angle");
+		assert!((refracted.magnitude() - 1.0).abs() < 1.0e-6);
+		assert!(refracted.z < 0.0 && refracted.x < 0.0);
+		assert!((-refracted.x * 1.5 - direction.x).abs() < 1.0e-6, "{:?}", refracted);
+
+		// past the critical angle of about 42 degrees on the way out, everything is reflected.
+		let grazing = Vector3::new(0.8, 0.0, 0.6);
+		assert!(refract(grazing, Vector3::new(0.0, 0.0, 1.0), 1.0 / 1.5).is_none());
+		assert_eq!(fresnel_dielectric(grazing.z, 1.0 / 1.5), 1.0);
+		assert_eq!(schlick_dielectric(grazing.z, 1.0 / 1.5), 1.0);
+	}
+
+	#[test]
+	fn schlick_dielectric_approximates_fresnel()
+	{
+		for &eta in &[1.5, 1.0 / 1.5]
+		{
+			assert!((schlick_dielectric(1.0, eta) - dielectric_reflectance(1.5)).abs() < 1.0e-6);
+			for &cosine in &[0.95_f32, 0.8, 0.6]
+			{
+				let (approximate, exact) = (schlick_dielectric(cosine, eta), fresnel_dielectric(cosine, eta));
+				assert!((approximate - exact).abs() < 0.02, "eta {} cosine {}: {} against {}", eta, cosine, approximate, exact);
+			}
+		}
+	}
+	// The integral over wi of D(h) G1(wo) / (4 cos), which is 1 for every wo when the masking function matches the
+	// distribution (Heitz 2014, the "weak white furnace").
+	fn weak_white_furnace(distribution : &Ggx, wo : Vector3<f32>, count : u32, rng : &mut Rng) -> Statistics
+	{
+		let mut statistics = Statistics::new();
+		let sphere_area = 4.0 * std::f64::consts::PI;
+		for _ in 0..count
+		{
+			let wi = uniform_sphere(rng.next_f32(), rng.next_f32());
+			let half = (wo + wi).normalize();
+			let value = distribution.distribution(half) * distribution.masking(wo) / (4.0 * wo.z);
+			statistics.add(value as f64 * sphere_area);
+		}
+		statistics
+	}
+
+	const CHI_SQUARE_COSINE_BINS : usize = 16;
+	const CHI_SQUARE_ANGLE_BINS : usize = 32;
+	const CHI_SQUARE_SAMPLES : u32 = 200_000;
+
+	// Pearson's chi-square test of the directions sample picks against the ones pdf predicts, on a grid of
+	// cos theta by phi cells, with the cells expecting fewer than 5 samples pooled. Returns the p-value, from the
+	// Wilson-Hilferty approximation of the chi-square distribution.
+	fn chi_square_test(bsdf : &dyn Bsdf, wo : Vector3<f32>, rng : &mut Rng) -> f64
+	{
+		let cell_count = CHI_SQUARE_COSINE_BINS * CHI_SQUARE_ANGLE_BINS;
+		let cosine_step = 2.0 / CHI_SQUARE_COSINE_BINS as f64;
+		let angle_step = 2.0 * std::f64::consts::PI / CHI_SQUARE_ANGLE_BINS as f64;
+		let direction = |cosine : f64, angle : f64|
+		{
+			let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+			Vector3::new((sine * angle.cos()) as f32, (sine * angle.sin()) as f32, cosine as f32)
+		};
+
+		let mut observed = vec![0.0f64; cell_count];
+		for _ in 0..CHI_SQUARE_SAMPLES
+		{
+			if let Some(sample) = bsdf.sample(wo, rng.next_f32(), rng.next_f32(), rng.next_f32())
+			{
+				let wi = sample.direction;
+				let angle = (wi.y as f64).atan2(wi.x as f64).rem_euclid(2.0 * std::f64::consts::PI);
+				let cosine_bin = (((wi.z as f64 + 1.0) / cosine_step) as usize).min(CHI_SQUARE_COSINE_BINS - 1);
+				let angle_bin = ((angle / angle_step) as usize).min(CHI_SQUARE_ANGLE_BINS - 1);
+				observed[cosine_bin * CHI_SQUARE_ANGLE_BINS + angle_bin] += 1.0;
+			}
+		}
+
+		// the density integrated over each cell with the midpoint rule in theta rather than cos theta, dw being
+		// sin theta d(theta) d(phi), and finely in theta to resolve the narrow lobes refraction makes around the poles.
+		let (theta_subdivisions, angle_subdivisions) = (64, 16);
+		let mut expected = vec![0.0f64; cell_count];
+		for (cell, expected) in expected.iter_mut().enumerate()
+		{
+			let (cosine_bin, angle_bin) = (cell / CHI_SQUARE_ANGLE_BINS, cell % CHI_SQUARE_ANGLE_BINS);
+			let theta_start = (-1.0 + (cosine_bin + 1) as f64 * cosine_step).min(1.0).acos();
+			let theta_step = ((-1.0 + cosine_bin as f64 * cosine_step).acos() - theta_start) / theta_subdivisions as f64;
+			let mut integral = 0.0;
+			for i in 0..theta_subdivisions
+			{
+				let theta = theta_start + (i as f64 + 0.5) * theta_step;
+				for j in 0..angle_subdivisions
+				{
+					let angle = (angle_bin as f64 + (j as f64 + 0.5) / angle_subdivisions as f64) * angle_step;
+					integral += bsdf.pdf(wo, direction(theta.cos(), angle)) as f64 * theta.sin();
+				}
+			}
+			*expected = integral * theta_step * angle_step / angle_subdivisions as f64 * CHI_SQUARE_SAMPLES as f64;
+		}
+
+		let (mut statistic, mut degrees_of_freedom) = (0.0, -1.0);
+		let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+		for (observed, expected) in observed.iter().zip(expected.iter())
+		{
+			if *expected < 5.0
+			{
+				pooled_observed += observed;
+				pooled_expected += expected;
+				continue;
+			}
+			statistic += (observed - expected) * (observed - expected) / expected;
+			degrees_of_freedom += 1.0;
+		}
+		if pooled_expected >= 5.0
+		{
+			statistic += (pooled_observed - pooled_expected) * (pooled_observed - pooled_expected) / pooled_expected;
+			degrees_of_freedom += 1.0;
+		}
+		else if pooled_observed > 20.0
+		{
+			// samples where pdf says there should be next to none.
+			return 0.0;
+		}
+		if degrees_of_freedom < 1.0
+		{
+			return 1.0;
+		}
+
+		let variance : f64 = 2.0 / (9.0 * degrees_of_freedom);
+		let z = ((statistic / degrees_of_freedom).cbrt() - (1.0 - variance)) / variance.sqrt();
+		0.5 * complementary_error_function(z / std::f64::consts::SQRT_2)
+	}
+
+	// Abramowitz and Stegun 7.1.26, accurate to about 1e-7.
+	fn complementary_error_function(x : f64) -> f64
+	{
+		let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+		let polynomial = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+		let value = polynomial * (-x * x).exp();
+		if x >= 0.0 { value } else { 2.0 - value }
+	}
+
+	fn check_weak_white_furnace(alpha_x : f32, alpha_y : f32)
+	{
+		let mut rng = Rng::new(0x0a1b, 0x5eed);
+		for &angle in &ANGLES
+		{
+			let result = weak_white_furnace(&Ggx { alpha_x : alpha_x, alpha_y : alpha_y }, outgoing(angle), 200_000, &mut rng);
+			assert!((result.mean() - 1.0).abs() <= 4.0 * result.standard_error() + 1.0e-3,
+				"{} degrees: {} (+- {})", angle, result.mean(), result.standard_error());
+		}
+	}
+
+	#[test]
+	fn ggx_weak_white_furnace_alpha_0_2()
+	{
+		check_weak_white_furnace(0.2, 0.2);
+	}
+
+	#[test]
+	fn ggx_weak_white_furnace_alpha_0_5()
+	{
+		check_weak_white_furnace(0.5, 0.5);
+	}
+
+	#[test]
+	fn ggx_weak_white_furnace_alpha_1()
+	{
+		check_weak_white_furnace(1.0, 1.0);
+	}
+
+	#[test]
+	fn ggx_weak_white_furnace_anisotropic()
+	{
+		check_weak_white_furnace(0.2, 0.6);
+	}
+
+	// Glossy BSDFs only, as the densities of specular lobes have no cells to fall in. The significance is split
+	// between every angle of every BSDF tested, so that all of them together fail by chance 1% of the time.
+	fn check_sampling_matches_pdf(bsdf : &dyn Bsdf)
+	{
+		let significance = 0.01 / (12 * ANGLES.len()) as f64;
+		let mut rng = Rng::new(0x0a1b, 0x5eed);
+		for &angle in &ANGLES
+		{
+			let p_value = chi_square_test(bsdf, outgoing(angle), &mut rng);
+			assert!(p_value >= significance, "{} degrees: p-value {}", angle, p_value);
+		}
+	}
+
+	#[test]
+	fn lambertian_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&Lambertian { albedo : white() });
+	}
+
+	#[test]
+	fn conductor_alpha_0_5_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&Conductor { reflectance : white(), alpha : 0.5 });
+	}
+
+	#[test]
+	fn conductor_alpha_1_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&Conductor { reflectance : white(), alpha : 1.0 });
+	}
+
+	#[test]
+	fn plastic_roughness_0_5_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&metallic_roughness(0.0, 0.5));
+	}
+
+	#[test]
+	fn plastic_roughness_1_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&metallic_roughness(0.0, 1.0));
+	}
+
+	#[test]
+	fn half_metal_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&metallic_roughness(0.5, 0.5));
+	}
+
+	#[test]
+	fn disney_diffuse_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&principled(0.0, 0.5, 0.0, 1.5, PrincipledParameters::default()));
+	}
+
+	#[test]
+	fn disney_sheen_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&principled(0.0, 0.8, 0.0, 1.5, PrincipledParameters { sheen : 1.0, ..PrincipledParameters::default() }));
+	}
+
+	#[test]
+	fn disney_clearcoat_sampling_matches_pdf()
+	{
+		let parameters = PrincipledParameters { clearcoat : 1.0, clearcoat_roughness : 0.3, ..PrincipledParameters::default() };
+		check_sampling_matches_pdf(&principled(0.0, 0.5, 0.0, 1.5, parameters));
+	}
+
+	#[test]
+	fn disney_anisotropic_sampling_matches_pdf()
+	{
+		let parameters = PrincipledParameters { anisotropic : 0.8, anisotropic_rotation : 0.2, ..PrincipledParameters::default() };
+		check_sampling_matches_pdf(&principled(1.0, 0.5, 0.0, 1.5, parameters));
+	}
+
+	#[test]
+	fn disney_glass_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&principled(0.0, 0.5, 1.0, 1.5, PrincipledParameters::default()));
+	}
+
+	#[test]
+	fn disney_glass_leaving_sampling_matches_pdf()
+	{
+		check_sampling_matches_pdf(&principled(0.0, 0.5, 1.0, 1.0 / 1.5, PrincipledParameters::default()));
+	}
+}
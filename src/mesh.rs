@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use cgmath::InnerSpace;
+use cgmath::{ Vector2, Vector3, Vector4 };
+
+use crate::geometry::{ Aabb, Bounded, ColoredVertex, Intersect, Triangle };
+use crate::ray::{ Ray, HitRecord, Hittable };
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshVertex
+{
+	pub position : Vector3<f32>,
+	pub normal : Vector3<f32>, // unit length
+	pub uv : Vector2<f32>,
+	pub color : Vector4<f32>,
+}
+
+// A run of triangles sharing a material. first_index and index_count count indices, three per triangle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialGroup
+{
+	pub material : Option<usize>, // index into the materials loaded alongside the mesh
+	pub first_index : usize,
+	pub index_count : usize,
+}
+
+// Indexed triangle list, counter clockwise triangles face outwards.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh
+{
+	pub name : String,
+	pub vertices : Vec<MeshVertex>,
+	pub indices : Vec<u32>,
+	pub groups : Vec<MaterialGroup>,
+}
+
+impl Mesh
+{
+	// The sample triangle as a mesh, so both backends draw it through the same path as loaded assets.
+	pub fn sample_triangle() -> Self
+	{
+		let colored_vertices = crate::geometry::sample_colored_triangle_vertices();
+		let edge1 = colored_vertices[1].position - colored_vertices[0].position;
+		let edge2 = colored_vertices[2].position - colored_vertices[0].position;
+		let normal = edge1.cross(edge2).normalize();
+
+		Self
+		{
+			name : String::from("sample triangle"),
+			vertices : colored_vertices.iter()
+				.map(|vertex| MeshVertex { position : vertex.position, normal : normal, uv : Vector2::new(0.0, 0.0), color : vertex.color })
+				.collect(),
+			indices : vec![0, 1, 2],
+			groups : vec![MaterialGroup { material : None, first_index : 0, index_count : 3 }],
+		}
+	}
+
+	pub fn triangle_count(&self) -> usize
+	{
+		self.indices.len() / 3
+	}
+
+	pub fn bounds(&self) -> Aabb
+	{
+		self.vertices.iter().fold(Aabb::empty(), |bounds, vertex| bounds.grow(vertex.position))
+	}
+
+	// The vertex layout the dx_renderer draws with.
+	#[cfg_attr(not(windows), allow(dead_code))]
+	pub fn colored_vertices(&self) -> Vec<ColoredVertex>
+	{
+		self.vertices.iter().map(|vertex| ColoredVertex { position : vertex.position, color : vertex.color }).collect()
+	}
+
+	// The material of the group a triangle's first index falls in.
+	pub fn material_at(&self, first_index : usize) -> Option<usize>
+	{
+		self.groups.iter()
+			.find(|group| first_index >= group.first_index && first_index < group.first_index + group.index_count)
+			.and_then(|group| group.material)
+	}
+
+	// One primitive per triangle, for building a BVH over the mesh.
+	pub fn triangles(mesh : &Arc<Mesh>) -> Vec<MeshTriangle>
+	{
+		(0..mesh.triangle_count())
+			.map(|triangle| MeshTriangle { mesh : Arc::clone(mesh), first_index : 3 * triangle, material : mesh.material_at(3 * triangle) })
+			.collect()
+	}
+}
+
+pub struct MeshTriangle
+{
+	mesh : Arc<Mesh>,
+	first_index : usize,
+	material : Option<usize>,
+}
+
+impl MeshTriangle
+{
+	fn vertices(&self) -> [&MeshVertex; 3]
+	{
+		let indices = &self.mesh.indices[self.first_index..self.first_index + 3];
+		[
+			&self.mesh.vertices[indices[0] as usize],
+			&self.mesh.vertices[indices[1] as usize],
+			&self.mesh.vertices[indices[2] as usize],
+		]
+	}
+}
+
+impl Bounded for MeshTriangle
+{
+	fn bounds(&self) -> Aabb
+	{
+		let [v0, v1, v2] = self.vertices();
+		Aabb::from_points(&[v0.position, v1.position, v2.position])
+	}
+}
+
+impl Hittable for MeshTriangle
+{
+	// Shades with the interpolated vertex normal and reports the interpolated texture coordinates as uv.
+	fn hit(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<HitRecord>
+	{
+		let [v0, v1, v2] = self.vertices();
+		let mut surface_hit = Triangle::new(v0.position, v1.position, v2.position).intersect(ray, t_min, t_max)?;
+
+		let (u, v) = (surface_hit.uv.x, surface_hit.uv.y);
+		let w = 1.0 - u - v;
+
+		let normal = v0.normal * w + v1.normal * u + v2.normal * v;
+		if normal.magnitude2() > 0.0
+		{
+			// keep the side the ray arrived from, which the geometric normal decided.
+			let normal = normal.normalize();
+			surface_hit.normal = if normal.dot(surface_hit.normal) < 0.0 { -normal } else { normal };
+		}
+		surface_hit.uv = v0.uv * w + v1.uv * u + v2.uv * v;
+
+		let color = v0.color * w + v1.color * u + v2.color * v;
+		let mut hit = HitRecord::new(ray, &surface_hit, color);
+		hit.material = self.material;
+		Some(hit)
+	}
+}
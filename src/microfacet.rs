@@ -0,0 +1,97 @@
+use cgmath::InnerSpace;
+use cgmath::Vector3;
+
+// The GGX (Trowbridge-Reitz) distribution of microfacet normals, in BSDF space, with alpha along x and y.
+// alpha is the square of glTF's perceptual roughness.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ggx
+{
+	pub alpha_x : f32,
+	pub alpha_y : f32,
+}
+
+impl Ggx
+{
+	pub fn isotropic(alpha : f32) -> Self
+	{
+		Self { alpha_x : alpha, alpha_y : alpha }
+	}
+
+	// D(h), the density of microfacet normals per unit solid angle, projected onto the macro surface.
+	pub fn distribution(&self, half : Vector3<f32>) -> f32
+	{
+		if half.z <= 0.0
+		{
+			return 0.0;
+		}
+		let (x, y) = (half.x / self.alpha_x, half.y / self.alpha_y);
+		let denominator = x * x + y * y + half.z * half.z;
+		1.0 / (std::f32::consts::PI * self.alpha_x * self.alpha_y * denominator * denominator)
+	}
+
+	// Smith's auxiliary function, from which both masking forms follow.
+	pub fn lambda(&self, direction : Vector3<f32>) -> f32
+	{
+		let cosine_squared = direction.z * direction.z;
+		if cosine_squared <= 0.0
+		{
+			return f32::INFINITY;
+		}
+		let (x, y) = (self.alpha_x * direction.x, self.alpha_y * direction.y);
+		0.5 * (-1.0 + (1.0 + (x * x + y * y) / cosine_squared).sqrt())
+	}
+
+	// The fraction of microfacets facing direction that it sees.
+	pub fn masking(&self, direction : Vector3<f32>) -> f32
+	{
+		1.0 / (1.0 + self.lambda(direction))
+	}
+
+	// Height correlated masking-shadowing, the fraction of microfacets both directions see.
+	pub fn masking_shadowing(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+	}
+
+	// D_wo(h), the density of the normals wo sees.
+	pub fn visible_normal_pdf(&self, wo : Vector3<f32>, half : Vector3<f32>) -> f32
+	{
+		if wo.z <= 0.0
+		{
+			return 0.0;
+		}
+		self.masking(wo) * wo.dot(half).max(0.0) * self.distribution(half) / wo.z
+	}
+
+	// A normal distributed as D_wo, by projecting a point on a disk onto the hemisphere stretched to alpha
+	// (Heitz 2018, "Sampling the GGX Distribution of Visible Normals").
+	pub fn sample_visible_normal(&self, wo : Vector3<f32>, u1 : f32, u2 : f32) -> Vector3<f32>
+	{
+		let stretched = Vector3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+		let length_squared = stretched.x * stretched.x + stretched.y * stretched.y;
+		let t1 = if length_squared > 0.0 { Vector3::new(-stretched.y, stretched.x, 0.0) / length_squared.sqrt() } else { Vector3::new(1.0, 0.0, 0.0) };
+		let t2 = stretched.cross(t1);
+
+		let radius = u1.sqrt();
+		let angle = 2.0 * std::f32::consts::PI * u2;
+		let p1 = radius * angle.cos();
+		let s = 0.5 * (1.0 + stretched.z);
+		let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * radius * angle.sin();
+
+		let normal = t1 * p1 + t2 * p2 + stretched * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+		Vector3::new(self.alpha_x * normal.x, self.alpha_y * normal.y, normal.z.max(1.0e-6)).normalize()
+	}
+
+	// The density of reflecting wo into wi off a visible normal sampled with sample_visible_normal.
+	pub fn reflection_pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		let half = wo + wi;
+		if half.magnitude2() <= 0.0
+		{
+			return 0.0;
+		}
+		let half = half.normalize();
+		let cosine = wo.dot(half);
+		if cosine <= 0.0 { 0.0 } else { self.visible_normal_pdf(wo, half) / (4.0 * cosine) }
+	}
+}
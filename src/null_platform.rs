@@ -0,0 +1,136 @@
+use crate::framebuffer::Framebuffer;
+use crate::input::{ InputEvent, ScriptedInput };
+use crate::platform::{ Platform, PlatformError, ExitCode, ExitResult };
+use crate::tone_mapping::DisplayTransform;
+use crate::window_handle::RawWindowHandle;
+
+// A platform without a window, for Linux build boxes and CI. Frames are rendered but never shown.
+// Input comes from a script instead of a keyboard and mouse, one pump_events call per frame.
+pub struct NullPlatform
+{
+	scripted_input : ScriptedInput,
+	frame : u64,
+	exit_requested : bool,
+	exit_reported : bool,
+}
+
+impl NullPlatform
+{
+	pub fn with_script(scripted_input : ScriptedInput) -> Self
+	{
+		Self
+		{
+			scripted_input : scripted_input,
+			frame : 0,
+			exit_requested : false,
+			exit_reported : false,
+		}
+	}
+}
+
+impl Platform for NullPlatform
+{
+	fn create_window(&mut self, _title : &str, _width : u32, _height : u32) -> Result<(), PlatformError>
+	{
+		Ok(())
+	}
+
+	fn raw_handle(&self) -> RawWindowHandle
+	{
+		RawWindowHandle::Headless
+	}
+
+	fn pump_events(&mut self) -> Vec<InputEvent>
+	{
+		let events = self.scripted_input.take_until(self.frame);
+		self.frame += 1;
+		events
+	}
+
+	fn exit_result(&mut self) -> Option<ExitResult>
+	{
+		if self.exit_requested && !self.exit_reported
+		{
+			self.exit_reported = true;
+			return Some(Ok(ExitCode::Quit));
+		}
+		None
+	}
+
+	fn request_exit(&mut self)
+	{
+		self.exit_requested = true;
+	}
+
+	fn present(&mut self, _framebuffer : &Framebuffer, _display : &DisplayTransform)
+	{
+		// Nowhere to show it. Headless mode writes images to disk instead.
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn runs_until_asked_to_exit()
+	{
+		let mut platform = NullPlatform::with_script(ScriptedInput::new());
+		assert!(platform.create_window("test", 64, 32).is_ok());
+		assert!(matches!(platform.raw_handle(), RawWindowHandle::Headless));
+		for _ in 0..3
+		{
+			assert!(platform.pump_events().is_empty());
+			assert!(platform.exit_result().is_none());
+		}
+
+		platform.request_exit();
+		assert!(matches!(platform.exit_result(), Some(Ok(ExitCode::Quit))));
+		// reported once, like the window's quit message.
+		assert!(platform.exit_result().is_none());
+	}
+
+	// The main loop's part: pump every frame, ask to exit on escape and stop once the platform says it is done.
+	#[test]
+	fn script_drives_the_platform_to_exit()
+	{
+		use crate::input::Key;
+
+		let script = ScriptedInput::parse("0 key_down w\n2 mouse_move 3 4\n2 key_up w\n4 key_down escape\n9 key_down q").expect("the script is valid");
+		let mut platform = NullPlatform::with_script(script);
+		platform.create_window("test", 64, 32).expect("the null platform always has a window");
+
+		let mut frames = Vec::new();
+		let exit = loop
+		{
+			let events = platform.pump_events();
+			if events.contains(&InputEvent::KeyDown(Key::Escape))
+			{
+				platform.request_exit();
+			}
+			frames.push(events);
+			if let Some(exit) = platform.exit_result()
+			{
+				break exit;
+			}
+			assert!(frames.len() < 100, "the script never exits");
+		};
+
+		assert!(matches!(exit, Ok(ExitCode::Quit)));
+		assert_eq!(frames, vec![
+			vec![InputEvent::KeyDown(Key::W)],
+			vec![],
+			vec![InputEvent::MouseMove { x : 3, y : 4 }, InputEvent::KeyUp(Key::W)],
+			vec![],
+			vec![InputEvent::KeyDown(Key::Escape)],
+		]);
+	}
+
+	#[test]
+	fn platform_errors_display_their_details()
+	{
+		let error : Box<dyn std::error::Error> = Box::new(PlatformError::new("no display"));
+		assert_eq!(error.to_string(), "no display");
+	}
+}
@@ -0,0 +1,621 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{ Path, PathBuf };
+use std::str::FromStr;
+
+use cgmath::InnerSpace;
+use cgmath::{ Vector2, Vector3, Vector4 };
+
+use crate::mesh::{ MaterialGroup, Mesh, MeshVertex };
+
+// The parts of an MTL material the renderer understands.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMaterial
+{
+	pub name : String,
+	pub diffuse : Vector3<f32>,             // Kd
+	pub specular : Vector3<f32>,            // Ks
+	pub emission : Vector3<f32>,            // Ke
+	pub shininess : f32,                    // Ns, the Phong exponent
+	pub index_of_refraction : f32,          // Ni
+	pub dissolve : f32,                     // d, 1 is opaque. Tr is read as 1 - d
+	pub pbr : Option<MtlPbr>,               // from the first PBR extension statement on
+}
+
+// The PBR extension to MTL, as Blender writes it for its Principled BSDF. Ks then holds Blender's specular.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlPbr
+{
+	pub roughness : f32,           // Pr
+	pub metallic : f32,            // Pm
+	pub sheen : f32,               // Ps
+	pub clearcoat : f32,           // Pc
+	pub clearcoat_roughness : f32, // Pcr
+	pub anisotropy : f32,          // aniso
+	pub anisotropy_rotation : f32, // anisor
+}
+
+impl Default for MtlPbr
+{
+	// What Blender's Principled BSDF starts with.
+	fn default() -> Self
+	{
+		Self
+		{
+			roughness : 0.5,
+			metallic : 0.0,
+			sheen : 0.0,
+			clearcoat : 0.0,
+			clearcoat_roughness : 0.03,
+			anisotropy : 0.0,
+			anisotropy_rotation : 0.0,
+		}
+	}
+}
+
+impl ObjMaterial
+{
+	fn new(name : &str) -> Self
+	{
+		Self
+		{
+			name : name.to_string(),
+			diffuse : Vector3::new(0.8, 0.8, 0.8),
+			specular : Vector3::new(0.0, 0.0, 0.0),
+			emission : Vector3::new(0.0, 0.0, 0.0),
+			shininess : 0.0,
+			index_of_refraction : 1.0,
+			dissolve : 1.0,
+			pbr : None,
+		}
+	}
+}
+
+// One mesh per 'o' statement. Faces before the first 'o' go into a mesh named after the file.
+#[derive(Clone, Debug, Default)]
+pub struct ObjScene
+{
+	pub meshes : Vec<Mesh>,
+	pub materials : Vec<ObjMaterial>,
+}
+
+#[derive(Debug)]
+pub struct ObjError
+{
+	pub path : PathBuf,
+	pub line : Option<usize>, // 1 based, None for errors that are not about a line, e.g. a missing file
+	pub message : String,
+}
+
+impl fmt::Display for ObjError
+{
+	fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+	{
+		match self.line
+		{
+			Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+			None => write!(f, "{}: {}", self.path.display(), self.message),
+		}
+	}
+}
+
+impl Error for ObjError {}
+
+// Reads an OBJ file and the MTL libraries it references.
+pub fn load_obj(path : &Path) -> Result<ObjScene, ObjError>
+{
+	let source = read_file(path)?;
+	parse_obj(&source, path)
+}
+
+// Reads an MTL material library.
+pub fn load_mtl(path : &Path) -> Result<Vec<ObjMaterial>, ObjError>
+{
+	let source = read_file(path)?;
+	parse_mtl(&source, path)
+}
+
+fn read_file(path : &Path) -> Result<String, ObjError>
+{
+	std::fs::read_to_string(path).map_err(|error| ObjError { path : path.to_path_buf(), line : None, message : error.to_string() })
+}
+
+// path names the file in errors and warnings and is where mtllib paths are resolved from.
+pub fn parse_obj(source : &str, path : &Path) -> Result<ObjScene, ObjError>
+{
+	let mut parser = ObjParser::new(path);
+
+	for (line_index, line) in source.lines().enumerate()
+	{
+		parser.line_number = line_index + 1;
+		parser.parse_line(line).map_err(|message| ObjError { path : path.to_path_buf(), line : Some(line_index + 1), message : message })?;
+	}
+
+	Ok(parser.finish())
+}
+
+// Where a mesh vertex came from: a position and, when the face gave them, a texture coordinate and normal.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct VertexKey
+{
+	position : usize,
+	uv : Option<usize>,
+	normal : Option<usize>,
+	material : Option<usize>, // vertices without a color take the material's, so they can't be shared across materials
+}
+
+struct MeshBuilder
+{
+	mesh : Mesh,
+	vertex_keys : Vec<VertexKey>,
+	vertex_lookup : HashMap<VertexKey, u32>,
+}
+
+impl MeshBuilder
+{
+	fn new(name : &str) -> Self
+	{
+		Self { mesh : Mesh { name : name.to_string(), ..Mesh::default() }, vertex_keys : Vec::new(), vertex_lookup : HashMap::new() }
+	}
+}
+
+struct ObjParser<'a>
+{
+	path : &'a Path,
+	line_number : usize,
+	positions : Vec<Vector3<f32>>,
+	position_colors : Vec<Option<Vector3<f32>>>, // the 'v x y z r g b' extension
+	uvs : Vec<Vector2<f32>>,
+	normals : Vec<Vector3<f32>>,
+	generated_normals : Vec<Vector3<f32>>,       // area weighted sum of the face normals around each position
+	materials : Vec<ObjMaterial>,
+	current_material : Option<usize>,
+	meshes : Vec<MeshBuilder>,
+}
+
+impl<'a> ObjParser<'a>
+{
+	fn new(path : &'a Path) -> Self
+	{
+		let name = path.file_stem().map_or(String::from("obj"), |stem| stem.to_string_lossy().into_owned());
+
+		Self
+		{
+			path : path,
+			line_number : 0,
+			positions : Vec::new(),
+			position_colors : Vec::new(),
+			uvs : Vec::new(),
+			normals : Vec::new(),
+			generated_normals : Vec::new(),
+			materials : Vec::new(),
+			current_material : None,
+			meshes : vec![MeshBuilder::new(&name)],
+		}
+	}
+
+	fn parse_line(&mut self, line : &str) -> Result<(), String>
+	{
+		let line = line.split('#').next().unwrap_or("");
+		let mut words = line.split_whitespace();
+		let keyword = match words.next()
+		{
+			Some(keyword) => keyword,
+			None => return Ok(()),
+		};
+		let arguments : Vec<&str> = words.collect();
+
+		match keyword
+		{
+			"v" =>
+			{
+				let values = parse_numbers(&arguments, keyword)?;
+				match values.len()
+				{
+					3 | 4 => self.position_colors.push(None), // the optional w is only meaningful for rational curves
+					6 => self.position_colors.push(Some(Vector3::new(values[3], values[4], values[5]))),
+					_ => return Err(format!("'v' expects x y z [w] or x y z r g b, got {} values", values.len())),
+				}
+				self.positions.push(Vector3::new(values[0], values[1], values[2]));
+				self.generated_normals.push(Vector3::new(0.0, 0.0, 0.0));
+			}
+			"vt" =>
+			{
+				let values = parse_numbers(&arguments, keyword)?;
+				if values.is_empty() || values.len() > 3
+				{
+					return Err(format!("'vt' expects u [v [w]], got {} values", values.len()));
+				}
+				self.uvs.push(Vector2::new(values[0], values.get(1).copied().unwrap_or(0.0)));
+			}
+			"vn" =>
+			{
+				let values = parse_numbers(&arguments, keyword)?;
+				if values.len() != 3
+				{
+					return Err(format!("'vn' expects x y z, got {} values", values.len()));
+				}
+				let normal = Vector3::new(values[0], values[1], values[2]);
+				self.normals.push(if normal.magnitude2() > 0.0 { normal.normalize() } else { normal });
+			}
+			"f" => self.parse_face(&arguments)?,
+			"o" => self.meshes.push(MeshBuilder::new(&arguments.join(" "))),
+			"usemtl" =>
+			{
+				// exporters leave out libraries they failed to write, which shouldn't stop the geometry from loading.
+				let name = arguments.join(" ");
+				self.current_material = self.materials.iter().position(|material| material.name == name);
+				if self.current_material.is_none()
+				{
+					eprintln!("{}:{}: material '{}' is not defined by any mtllib before it, using the default material",
+						self.path.display(), self.line_number, name);
+				}
+			}
+			"mtllib" =>
+			{
+				// libraries are whitespace separated, so names with spaces are not supported.
+				for library in arguments
+				{
+					let library_path = self.path.parent().unwrap_or_else(|| Path::new("")).join(library);
+					let materials = load_mtl(&library_path).map_err(|error| format!("in mtllib {}", error))?;
+					self.materials.extend(materials);
+				}
+			}
+			// groups, smoothing groups, lines, points and the free form geometry statements don't affect triangle meshes.
+			_ => (),
+		}
+
+		Ok(())
+	}
+
+	fn parse_face(&mut self, arguments : &[&str]) -> Result<(), String>
+	{
+		if arguments.len() < 3
+		{
+			return Err(format!("a face needs at least 3 vertices, got {}", arguments.len()));
+		}
+
+		let mut keys = Vec::with_capacity(arguments.len());
+		for corner in arguments
+		{
+			let mut parts = corner.split('/');
+			let position = resolve_index(parts.next().unwrap_or(""), self.positions.len(), "position")?
+				.ok_or_else(|| format!("face vertex '{}' has no position", corner))?;
+			let uv = resolve_index(parts.next().unwrap_or(""), self.uvs.len(), "texture coordinate")?;
+			let normal = resolve_index(parts.next().unwrap_or(""), self.normals.len(), "normal")?;
+			if parts.next().is_some()
+			{
+				return Err(format!("face vertex '{}' has more than 3 indices", corner));
+			}
+			keys.push(VertexKey { position : position, uv : uv, normal : normal, material : self.current_material });
+		}
+
+		// polygons are split into a fan around their first vertex, which is right for the convex ones exporters write.
+		let (normals, uvs, materials) = (&self.normals, &self.uvs, &self.materials);
+		let builder = self.meshes.last_mut().expect("there is always a mesh to add to");
+		for corner in 1..keys.len() - 1
+		{
+			let triangle = [keys[0], keys[corner], keys[corner + 1]];
+
+			let p0 = self.positions[triangle[0].position];
+			let face_normal = (self.positions[triangle[1].position] - p0).cross(self.positions[triangle[2].position] - p0);
+			for key in &triangle
+			{
+				self.generated_normals[key.position] += face_normal;
+			}
+
+			for key in &triangle
+			{
+				let index = match builder.vertex_lookup.get(key)
+				{
+					Some(&index) => index,
+					None =>
+					{
+						let index = builder.mesh.vertices.len() as u32;
+						builder.mesh.vertices.push(MeshVertex
+						{
+							position : self.positions[key.position],
+							normal : key.normal.map_or(Vector3::new(0.0, 0.0, 0.0), |normal| normals[normal]),
+							uv : key.uv.map_or(Vector2::new(0.0, 0.0), |uv| uvs[uv]),
+							color : vertex_color(self.position_colors[key.position], key.material.map(|material| &materials[material])),
+						});
+						builder.vertex_keys.push(*key);
+						builder.vertex_lookup.insert(*key, index);
+						index
+					}
+				};
+				builder.mesh.indices.push(index);
+			}
+
+			let groups = &mut builder.mesh.groups;
+			match groups.last_mut()
+			{
+				Some(group) if group.material == self.current_material => group.index_count += 3,
+				_ =>
+				{
+					let first_index = builder.mesh.indices.len() - 3;
+					groups.push(MaterialGroup { material : self.current_material, first_index : first_index, index_count : 3 });
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn finish(self) -> ObjScene
+	{
+		let generated_normals = self.generated_normals;
+
+		let meshes = self.meshes.into_iter()
+			.filter(|builder| !builder.mesh.indices.is_empty())
+			.map(|mut builder|
+			{
+				// vertices the file gave no normal get the smooth normal of the faces around their position.
+				for (vertex, key) in builder.mesh.vertices.iter_mut().zip(&builder.vertex_keys)
+				{
+					if key.normal.is_none()
+					{
+						let normal = generated_normals[key.position];
+						vertex.normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { Vector3::new(0.0, 0.0, 1.0) };
+					}
+				}
+				builder.mesh
+			})
+			.collect();
+
+		ObjScene { meshes : meshes, materials : self.materials }
+	}
+}
+
+fn vertex_color(position_color : Option<Vector3<f32>>, material : Option<&ObjMaterial>) -> Vector4<f32>
+{
+	match (position_color, material)
+	{
+		(Some(color), _) => color.extend(1.0),
+		(None, Some(material)) => material.diffuse.extend(material.dissolve),
+		(None, None) => Vector4::new(1.0, 1.0, 1.0, 1.0),
+	}
+}
+
+// OBJ indices start at 1, negative ones count back from the latest element. Returns None for an empty index, as in 'f 1//3'.
+fn resolve_index(value : &str, count : usize, kind : &str) -> Result<Option<usize>, String>
+{
+	if value.is_empty()
+	{
+		return Ok(None);
+	}
+
+	let index = value.parse::<i64>().map_err(|_| format!("'{}' is not a valid {} index", value, kind))?;
+	let resolved = if index > 0 { index - 1 } else { count as i64 + index };
+	if index == 0 || resolved < 0 || resolved >= count as i64
+	{
+		return Err(format!("{} index {} is out of range, {} defined so far", kind, index, count));
+	}
+
+	Ok(Some(resolved as usize))
+}
+
+fn parse_numbers<T : FromStr>(arguments : &[&str], keyword : &str) -> Result<Vec<T>, String>
+{
+	arguments.iter()
+		.map(|argument| argument.parse::<T>().map_err(|_| format!("'{}' expects numbers, got '{}'", keyword, argument)))
+		.collect()
+}
+
+// path names the file in errors.
+pub fn parse_mtl(source : &str, path : &Path) -> Result<Vec<ObjMaterial>, ObjError>
+{
+	let mut materials : Vec<ObjMaterial> = Vec::new();
+
+	for (line_index, line) in source.lines().enumerate()
+	{
+		parse_mtl_line(line, &mut materials)
+			.map_err(|message| ObjError { path : path.to_path_buf(), line : Some(line_index + 1), message : message })?;
+	}
+
+	Ok(materials)
+}
+
+fn parse_mtl_line(line : &str, materials : &mut Vec<ObjMaterial>) -> Result<(), String>
+{
+	let line = line.split('#').next().unwrap_or("");
+	let mut words = line.split_whitespace();
+	let keyword = match words.next()
+	{
+		Some(keyword) => keyword,
+		None => return Ok(()),
+	};
+	let arguments : Vec<&str> = words.collect();
+
+	if keyword == "newmtl"
+	{
+		if arguments.is_empty()
+		{
+			return Err(String::from("'newmtl' expects a name"));
+		}
+		materials.push(ObjMaterial::new(&arguments.join(" ")));
+		return Ok(());
+	}
+
+	let material = materials.last_mut().ok_or_else(|| format!("'{}' before the first newmtl", keyword))?;
+	match keyword
+	{
+		"Kd" => material.diffuse = parse_color(&arguments, keyword)?,
+		"Ks" => material.specular = parse_color(&arguments, keyword)?,
+		"Ke" => material.emission = parse_color(&arguments, keyword)?,
+		"Ns" => material.shininess = parse_scalar(&arguments, keyword)?,
+		"Ni" => material.index_of_refraction = parse_scalar(&arguments, keyword)?,
+		"d" => material.dissolve = parse_scalar(&arguments, keyword)?,
+		"Tr" => material.dissolve = 1.0 - parse_scalar(&arguments, keyword)?,
+		"Pr" => material.pbr.get_or_insert_with(MtlPbr::default).roughness = parse_scalar(&arguments, keyword)?,
+		"Pm" => material.pbr.get_or_insert_with(MtlPbr::default).metallic = parse_scalar(&arguments, keyword)?,
+		"Ps" => material.pbr.get_or_insert_with(MtlPbr::default).sheen = parse_scalar(&arguments, keyword)?,
+		"Pc" => material.pbr.get_or_insert_with(MtlPbr::default).clearcoat = parse_scalar(&arguments, keyword)?,
+		"Pcr" => material.pbr.get_or_insert_with(MtlPbr::default).clearcoat_roughness = parse_scalar(&arguments, keyword)?,
+		"aniso" => material.pbr.get_or_insert_with(MtlPbr::default).anisotropy = parse_scalar(&arguments, keyword)?,
+		"anisor" => material.pbr.get_or_insert_with(MtlPbr::default).anisotropy_rotation = parse_scalar(&arguments, keyword)?,
+		// illumination models and texture maps are not used by the renderer.
+		_ => (),
+	}
+
+	Ok(())
+}
+
+// 'r', or 'r g b'. The spectral and xyz forms are not supported.
+fn parse_color(arguments : &[&str], keyword : &str) -> Result<Vector3<f32>, String>
+{
+	let values = parse_numbers::<f32>(arguments, keyword)?;
+	match values.len()
+	{
+		1 => Ok(Vector3::new(values[0], values[0], values[0])),
+		3 => Ok(Vector3::new(values[0], values[1], values[2])),
+		_ => Err(format!("'{}' expects r [g b], got {} values", keyword, values.len())),
+	}
+}
+
+fn parse_scalar(arguments : &[&str], keyword : &str) -> Result<f32, String>
+{
+	let values = parse_numbers::<f32>(arguments, keyword)?;
+	match values.len()
+	{
+		1 => Ok(values[0]),
+		_ => Err(format!("'{}' expects one value, got {}", keyword, values.len())),
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn parse(source : &str) -> ObjScene
+	{
+		parse_obj(source, Path::new("test.obj")).expect("the test source parses")
+	}
+
+	fn parse_error(source : &str) -> ObjError
+	{
+		parse_obj(source, Path::new("test.obj")).expect_err("the test source is malformed")
+	}
+
+	fn assert_near(actual : Vector3<f32>, expected : Vector3<f32>)
+	{
+		assert!((actual - expected).magnitude() < 1.0e-5, "{:?} is not {:?}", actual, expected);
+	}
+
+	#[test]
+	fn positive_indices_count_from_one()
+	{
+		assert_eq!(resolve_index("1", 3, "position"), Ok(Some(0)));
+		assert_eq!(resolve_index("3", 3, "position"), Ok(Some(2)));
+	}
+
+	#[test]
+	fn negative_indices_count_back_from_the_latest()
+	{
+		assert_eq!(resolve_index("-1", 3, "position"), Ok(Some(2)));
+		assert_eq!(resolve_index("-3", 3, "position"), Ok(Some(0)));
+	}
+
+	#[test]
+	fn empty_indices_are_absent()
+	{
+		assert_eq!(resolve_index("", 0, "normal"), Ok(None));
+	}
+
+	#[test]
+	fn invalid_indices_are_errors()
+	{
+		assert!(resolve_index("0", 3, "position").is_err());
+		assert!(resolve_index("4", 3, "position").is_err());
+		assert!(resolve_index("-4", 3, "position").is_err());
+		assert!(resolve_index("x", 3, "position").is_err());
+	}
+
+	#[test]
+	fn relative_and_absolute_faces_match()
+	{
+		let corners = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+		let absolute = parse(&format!("{}f 1 2 3\n", corners));
+		let relative = parse(&format!("{}f -3 -2 -1\n", corners));
+		assert_eq!(absolute.meshes[0].vertices, relative.meshes[0].vertices);
+		assert_eq!(absolute.meshes[0].indices, relative.meshes[0].indices);
+	}
+
+	#[test]
+	fn quads_split_into_a_fan()
+	{
+		let scene = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n");
+		let mesh = &scene.meshes[0];
+		assert_eq!(mesh.vertices.len(), 4);
+		assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+		assert_eq!(mesh.groups, vec![MaterialGroup { material : None, first_index : 0, index_count : 6 }]);
+	}
+
+	#[test]
+	fn polygons_split_into_a_fan_around_their_first_vertex()
+	{
+		let scene = parse("v 0 0 0\nv 2 0 0\nv 3 1 0\nv 1 2 0\nv -1 1 0\nf 5 1 2 3 4\n");
+		let mesh = &scene.meshes[0];
+		let file_positions = [Vector3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0), Vector3::new(3.0, 1.0, 0.0), Vector3::new(1.0, 2.0, 0.0), Vector3::new(-1.0, 1.0, 0.0)];
+		let positions : Vec<Vector3<f32>> = mesh.indices.iter().map(|&index| mesh.vertices[index as usize].position).collect();
+		let expected : Vec<Vector3<f32>> = [5, 1, 2, 5, 2, 3, 5, 3, 4].iter().map(|&position| file_positions[position - 1]).collect();
+		assert_eq!(positions, expected);
+		assert_eq!(mesh.groups[0].index_count, 9);
+	}
+
+	#[test]
+	fn missing_normals_are_smoothed_over_the_faces_around_a_position()
+	{
+		// a roof of two right triangles meeting at the ridge along x, one facing +y +z and the other -y +z.
+		let scene = parse("v 0 0 1\nv 1 0 1\nv 0 1 0\nv 0 -1 0\nf 1 2 3\nf 1 4 2\n");
+		let mesh = &scene.meshes[0];
+		let normal_at = |position : Vector3<f32>| mesh.vertices.iter().find(|vertex| vertex.position == position).unwrap().normal;
+		let half = 0.5f32.sqrt();
+		assert_near(normal_at(Vector3::new(0.0, 0.0, 1.0)), Vector3::new(0.0, 0.0, 1.0));
+		assert_near(normal_at(Vector3::new(1.0, 0.0, 1.0)), Vector3::new(0.0, 0.0, 1.0));
+		assert_near(normal_at(Vector3::new(0.0, 1.0, 0.0)), Vector3::new(0.0, half, half));
+		assert_near(normal_at(Vector3::new(0.0, -1.0, 0.0)), Vector3::new(0.0, -half, half));
+	}
+
+	#[test]
+	fn given_normals_are_kept()
+	{
+		let scene = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 2\nvn 1 0 0\nf 1//1 2//2 3\n");
+		let mesh = &scene.meshes[0];
+		assert_near(mesh.vertices[0].normal, Vector3::new(0.0, 0.0, 1.0));
+		assert_near(mesh.vertices[1].normal, Vector3::new(1.0, 0.0, 0.0));
+		assert_near(mesh.vertices[2].normal, Vector3::new(0.0, 0.0, 1.0));
+	}
+
+	#[test]
+	fn malformed_faces_report_their_line()
+	{
+		let error = parse_error("v 0 0 0\nv 1 0 0\n\nf 1 2\n");
+		assert_eq!(error.line, Some(4));
+		assert_eq!(error.path, Path::new("test.obj"));
+		assert!(error.to_string().starts_with("test.obj:4: "), "{}", error);
+	}
+
+	#[test]
+	fn out_of_range_faces_report_their_line()
+	{
+		let error = parse_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n# a comment\nf 1 2 4\n");
+		assert_eq!(error.line, Some(6));
+		assert!(error.message.contains("out of range"), "{}", error.message);
+
+		let error = parse_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/1 2/1 3/1\n");
+		assert_eq!(error.line, Some(4));
+		assert!(error.message.contains("texture coordinate"), "{}", error.message);
+	}
+
+	#[test]
+	fn undefined_materials_fall_back_to_the_default()
+	{
+		let scene = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl missing\nf 1 2 3\n");
+		let mesh = &scene.meshes[0];
+		assert_eq!(mesh.groups, vec![MaterialGroup { material : None, first_index : 0, index_count : 3 }]);
+		assert_eq!(mesh.vertices[0].color, Vector4::new(1.0, 1.0, 1.0, 1.0));
+	}
+}
@@ -0,0 +1,469 @@
+use cgmath::InnerSpace;
+use cgmath::Vector3;
+
+use crate::cpu_raytracer::RAY_EPSILON;
+use crate::material::{ self, multiply, ShadingFrame };
+use crate::ray::{ Ray, Hittable };
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+
+// How direct lighting is estimated at every bounce.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LightSampling
+{
+	Bsdf,     // only by bouncing into lights, works for any scene but is noisy with small lights
+	Light,    // next event estimation only, lights found by bouncing are ignored after the camera ray
+	Multiple, // both, weighted with the power heuristic
+}
+
+impl std::str::FromStr for LightSampling
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		match value
+		{
+			"bsdf" => Ok(LightSampling::Bsdf),
+			"light" => Ok(LightSampling::Light),
+			"mis" => Ok(LightSampling::Multiple),
+			_ => Err(format!("unknown light sampling '{}', expected bsdf, light or mis", value)),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PathTracerSettings
+{
+	pub max_depth : u32,               // surface interactions per path, 1 is direct lighting only
+	pub russian_roulette_depth : u32,  // interactions before paths may be terminated early
+	pub light_sampling : LightSampling,
+}
+
+impl Default for PathTracerSettings
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			max_depth : 8,
+			russian_roulette_depth : 3,
+			light_sampling : LightSampling::Multiple,
+		}
+	}
+}
+
+// Radiance arriving along the ray, scattered by the BSDF of every surface's material.
+// Every bounce takes the same dimensions from the sampler, whichever way it goes, so they line up between paths.
+pub fn radiance(ray : &Ray, scene : &Scene, settings : &PathTracerSettings, sampler : &mut dyn Sampler) -> Vector3<f32>
+{
+	let sample_lights = settings.light_sampling != LightSampling::Bsdf && scene.has_lights();
+
+	let mut radiance = Vector3::new(0.0, 0.0, 0.0);
+	let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+	let mut ray = *ray;
+	let mut bsdf_pdf = 0.0; // solid angle density of the bounce that produced ray
+	let mut specular_bounce = false;
+
+	for depth in 0..=settings.max_depth
+	{
+		// light found by the bounce. Next event estimation already counted it, wholly or in part, unless the
+		// bounce was specular, which it can't sample.
+		let counted_by_bounce = depth == 0 || !sample_lights || specular_bounce;
+		let hit = scene.hit(&ray, RAY_EPSILON, f32::INFINITY);
+
+		// rectangles and spheres aren't geometry, so look for them in front of whatever the ray hit.
+		let t_max = hit.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+		if let Some(light_hit) = scene.lights.hit(&ray, RAY_EPSILON, t_max)
+		{
+			let weight = if counted_by_bounce
+			{
+				1.0
+			}
+			else
+			{
+				found_light_weight(settings.light_sampling, bsdf_pdf, scene.light_pdf(light_hit.light, ray.origin, ray.direction))
+			};
+			radiance += multiply(throughput, light_hit.radiance) * weight;
+			break;
+		}
+
+		let hit = match hit
+		{
+			Some(hit) => hit,
+			None =>
+			{
+				let weight = if counted_by_bounce || !scene.samples_environment()
+				{
+					1.0
+				}
+				else
+				{
+					found_light_weight(settings.light_sampling, bsdf_pdf, scene.environment_pdf(ray.direction))
+				};
+				radiance += multiply(throughput, scene.background_radiance(ray.direction)) * weight;
+
+				for (index, light_radiance) in scene.lights.distant(ray.direction)
+				{
+					let weight = if counted_by_bounce
+					{
+						1.0
+					}
+					else
+					{
+						found_light_weight(settings.light_sampling, bsdf_pdf, scene.light_pdf(index, ray.origin, ray.direction))
+					};
+					radiance += multiply(throughput, light_radiance) * weight;
+				}
+				break;
+			}
+		};
+
+		let bsdf = material::surface_bsdf(&scene.surface(&hit), hit.front_face);
+		let frame = ShadingFrame::new(hit.normal);
+		let wo = frame.to_local(-ray.direction);
+
+		let emission = bsdf.emitted(wo);
+		if emission != Vector3::new(0.0, 0.0, 0.0)
+		{
+			let weight = if counted_by_bounce
+			{
+				1.0
+			}
+			else
+			{
+				found_light_weight(settings.light_sampling, bsdf_pdf, scene.area_light_pdf(emission, hit.t, hit.normal.dot(ray.direction)))
+			};
+			radiance += multiply(throughput, emission) * weight;
+		}
+
+		if depth == settings.max_depth
+		{
+			break;
+		}
+
+		// next event estimation.
+		if sample_lights
+		{
+			let u_light = sampler.next_1d();
+			let (u1, u2) = sampler.next_2d();
+			if let Some(light) = scene.sample_light(hit.point, u_light, u1, u2)
+			{
+				let wi = frame.to_local(light.direction);
+				let value = bsdf.evaluate(wo, wi);
+				let shadow_ray = Ray::new(hit.point, light.direction);
+				if value != Vector3::new(0.0, 0.0, 0.0) && !scene.occluded(&shadow_ray, RAY_EPSILON, light.distance * (1.0 - 1.0e-4))
+				{
+					// a bounce never finds a point or spot light, or a sun without a disk, so their samples count wholly.
+					let weight = if settings.light_sampling == LightSampling::Multiple && !light.delta { power_heuristic(light.pdf, bsdf.pdf(wo, wi)) } else { 1.0 };
+					radiance += multiply(throughput, multiply(value, light.radiance)) * (wi.z.abs() * weight / light.pdf);
+				}
+			}
+		}
+
+		let u_lobe = sampler.next_1d();
+		let (u1, u2) = sampler.next_2d();
+		let sample = match bsdf.sample(wo, u_lobe, u1, u2)
+		{
+			Some(sample) => sample,
+			None => break,
+		};
+		bsdf_pdf = sample.pdf;
+		specular_bounce = sample.specular;
+		throughput = multiply(throughput, sample.weight);
+
+		let u_survival = sampler.next_1d();
+		if depth + 1 >= settings.russian_roulette_depth
+		{
+			let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+			if survival <= 0.0 || u_survival >= survival
+			{
+				break;
+			}
+			throughput /= survival;
+		}
+
+		ray = Ray::new(hit.point, frame.to_world(sample.direction));
+	}
+
+	radiance
+}
+
+// How much of the light a bounce found counts, when next event estimation could have picked it with light_pdf.
+fn found_light_weight(light_sampling : LightSampling, bsdf_pdf : f32, light_pdf : f32) -> f32
+{
+	match light_sampling
+	{
+		LightSampling::Bsdf => 1.0,
+		LightSampling::Light => 0.0,
+		LightSampling::Multiple => power_heuristic(bsdf_pdf, light_pdf),
+	}
+}
+
+// Veach's power heuristic with an exponent of 2, the weight of the strategy whose density is pdf.
+fn power_heuristic(pdf : f32, other_pdf : f32) -> f32
+{
+	let (squared, other_squared) = (pdf * pdf, other_pdf * other_pdf);
+	if squared + other_squared > 0.0 { squared / (squared + other_squared) } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::sync::Arc;
+
+	use cgmath::{ Rad, Vector4 };
+
+	use super::*;
+	use crate::environment::EnvironmentMap;
+	use crate::framebuffer::Framebuffer;
+	use crate::mesh::Mesh;
+	use crate::reference_scenes::*;
+	use crate::sampler::IndependentSampler;
+	use crate::scene_description::SceneDescription;
+
+	// Mean radiance of many paths and the standard error of that mean, per channel.
+	struct Estimate
+	{
+		mean : Vector3<f64>,
+		standard_error : Vector3<f64>,
+	}
+
+	fn estimate(scene : &Scene, settings : &PathTracerSettings, rays : &[Ray], samples_per_ray : u32) -> Estimate
+	{
+		let mut sum = Vector3::new(0.0, 0.0, 0.0);
+		let mut sum_squared = Vector3::new(0.0, 0.0, 0.0);
+		for (ray_index, ray) in rays.iter().enumerate()
+		{
+			let mut sampler = IndependentSampler::new();
+			for sample_index in 0..samples_per_ray
+			{
+				sampler.start_pixel_sample(ray_index as u32, 0, sample_index);
+				let value = radiance(ray, scene, settings, &mut sampler).cast::<f64>().unwrap_or_else(|| Vector3::new(0.0, 0.0, 0.0));
+				sum += value;
+				sum_squared += Vector3::new(value.x * value.x, value.y * value.y, value.z * value.z);
+			}
+		}
+
+		let count = (rays.len() as u32 * samples_per_ray) as f64;
+		let mean = sum / count;
+		let variance = sum_squared / count - Vector3::new(mean.x * mean.x, mean.y * mean.y, mean.z * mean.z);
+		let standard_error = Vector3::new(variance.x.max(0.0).sqrt(), variance.y.max(0.0).sqrt(), variance.z.max(0.0).sqrt()) / count.sqrt();
+		Estimate { mean : mean, standard_error : standard_error }
+	}
+
+	// Passes when every channel is within 4 standard errors of the expected value, plus a little for float rounding.
+	fn assert_converged(estimate : &Estimate, expected : Vector3<f64>, expected_error : Vector3<f64>)
+	{
+		let within = |mean : f64, error : f64, expected : f64, expected_error : f64|
+		{
+			(mean - expected).abs() <= 4.0 * (error * error + expected_error * expected_error).sqrt() + 1.0e-3 * expected.abs()
+		};
+		assert!(within(estimate.mean.x, estimate.standard_error.x, expected.x, expected_error.x)
+			&& within(estimate.mean.y, estimate.standard_error.y, expected.y, expected_error.y)
+			&& within(estimate.mean.z, estimate.standard_error.z, expected.z, expected_error.z),
+			"{:?} (+- {:?}), expected {:?} (+- {:?})", estimate.mean, estimate.standard_error, expected, expected_error);
+	}
+
+	fn build_scene(description : &SceneDescription) -> Scene
+	{
+		let meshes : Vec<Arc<Mesh>> = description.instanced_meshes().into_iter().map(Arc::new).collect();
+		let mut scene = Scene::from_meshes(&meshes, &description.materials, &description.textures, &description.instanced_lights());
+		scene.background = Vector4::new(0.0, 0.0, 0.0, 1.0);
+		scene
+	}
+
+	fn settings(max_depth : u32, light_sampling : LightSampling) -> PathTracerSettings
+	{
+		PathTracerSettings { max_depth : max_depth, russian_roulette_depth : 3, light_sampling : light_sampling }
+	}
+
+	// A 4 by 4 grid of rays through the image of a scene's camera.
+	fn camera_rays(description : &SceneDescription) -> Vec<Ray>
+	{
+		let camera = description.camera(1.0).expect("the scene has a camera");
+		(0..16).map(|index| camera.get_ray((index % 4) as f32 / 4.0 + 0.125, (index / 4) as f32 / 4.0 + 0.125)).collect()
+	}
+
+	fn exact(value : Vector3<f64>) -> (Vector3<f64>, Vector3<f64>)
+	{
+		(value, Vector3::new(0.0, 0.0, 0.0))
+	}
+
+	// Every path in a closed box of albedo a and emission e gathers e + a e + a^2 e + ... = e / (1 - a).
+	fn check_furnace(light_sampling : LightSampling)
+	{
+		let scene = build_scene(&furnace(FURNACE_ALBEDO, FURNACE_EMISSION));
+		let expected = (FURNACE_EMISSION / (1.0 - FURNACE_ALBEDO)) as f64;
+		let directions = [(1.0, 0.0, 0.0), (-1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, -1.0, 0.0), (0.0, 0.0, 1.0), (0.0, 0.0, -1.0), (0.6, 0.5, 0.3), (-0.2, -0.7, 0.4)];
+		let rays : Vec<Ray> = directions.iter().map(|&(x, y, z)| Ray::new(Vector3::new(0.1, 0.2, -0.1), Vector3::new(x, y, z).normalize())).collect();
+		let (expected, expected_error) = exact(Vector3::new(expected, expected, expected));
+		assert_converged(&estimate(&scene, &settings(64, light_sampling), &rays, 2000), expected, expected_error);
+	}
+
+	#[test]
+	fn furnace_with_bsdf_sampling()
+	{
+		check_furnace(LightSampling::Bsdf);
+	}
+
+	#[test]
+	fn furnace_with_light_sampling()
+	{
+		check_furnace(LightSampling::Light);
+	}
+
+	#[test]
+	fn furnace_with_multiple_importance_sampling()
+	{
+		check_furnace(LightSampling::Multiple);
+	}
+
+	// Irradiance factor of a point under the corner of an a by b rectangle at height h, parallel to the point's surface.
+	// Radiance from a Lambertian surface there is albedo * emission * factor.
+	fn rectangle_corner_form_factor(a : f64, b : f64, h : f64) -> f64
+	{
+		let (x, y) = (a / h, b / h);
+		let (root_x, root_y) = ((1.0 + x * x).sqrt(), (1.0 + y * y).sqrt());
+		(x / root_x * (y / root_x).atan() + y / root_y * (x / root_y).atan()) / (2.0 * std::f64::consts::PI)
+	}
+
+	// Direct light at the middle of the empty Cornell box's floor, against the analytic value.
+	fn check_cornell_direct_light(light_sampling : LightSampling, samples : u32)
+	{
+		let scene = build_scene(&cornell_box(false));
+		let factor = 4.0 * rectangle_corner_form_factor(CORNELL_LIGHT_HALF_SIZE as f64, CORNELL_LIGHT_HALF_SIZE as f64, CORNELL_LIGHT_HEIGHT as f64);
+		let expected = CORNELL_LIGHT_EMISSION.cast::<f64>().unwrap_or_else(|| Vector3::new(0.0, 0.0, 0.0)) * (CORNELL_WHITE as f64 * factor);
+		let rays = [Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0))];
+		let (expected, expected_error) = exact(expected);
+		assert_converged(&estimate(&scene, &settings(1, light_sampling), &rays, samples), expected, expected_error);
+	}
+
+	#[test]
+	fn cornell_direct_light_with_bsdf_sampling()
+	{
+		check_cornell_direct_light(LightSampling::Bsdf, 100_000);
+	}
+
+	#[test]
+	fn cornell_direct_light_with_light_sampling()
+	{
+		check_cornell_direct_light(LightSampling::Light, 10_000);
+	}
+
+	#[test]
+	fn cornell_direct_light_with_multiple_importance_sampling()
+	{
+		check_cornell_direct_light(LightSampling::Multiple, 10_000);
+	}
+
+	// Radiance from a Lambertian surface facing +y under an environment image nothing shadows, which is albedo / pi times
+	// the sum over the texels above the horizon of their radiance times the integral of cos(theta) over their solid angle.
+	fn environment_floor_radiance(image : &Framebuffer, intensity : f32, albedo : f32) -> Vector3<f64>
+	{
+		use std::f64::consts::{ FRAC_PI_2, PI };
+
+		let (width, height) = (image.width() as usize, image.height() as usize);
+		let mut irradiance = Vector3::new(0.0, 0.0, 0.0);
+		for (row, texels) in image.pixels().chunks(width).enumerate()
+		{
+			let top = row as f64 / height as f64 * PI;
+			let bottom = ((row + 1) as f64 / height as f64 * PI).min(FRAC_PI_2);
+			if top >= FRAC_PI_2
+			{
+				break;
+			}
+			let weight = (bottom.sin().powi(2) - top.sin().powi(2)) / 2.0 * (2.0 * PI / width as f64);
+			for texel in texels
+			{
+				irradiance += texel.truncate().cast::<f64>().unwrap_or_else(|| Vector3::new(0.0, 0.0, 0.0)) * weight;
+			}
+		}
+		irradiance * (albedo as f64 * intensity as f64 / PI)
+	}
+
+	// A floor lit by the reference sky, which has a small bright sun.
+	fn check_environment_floor(light_sampling : LightSampling, samples : u32)
+	{
+		let sky = sky_environment();
+		let mut scene = build_scene(&floor(FLOOR_ALBEDO));
+		scene.environment = Some(Arc::new(EnvironmentMap::new(&sky, Rad(1.0), 0.5)));
+		let rays = [Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0))];
+		let (expected, expected_error) = exact(environment_floor_radiance(&sky, 0.5, FLOOR_ALBEDO));
+		assert_converged(&estimate(&scene, &settings(1, light_sampling), &rays, samples), expected, expected_error);
+	}
+
+	#[test]
+	fn environment_floor_with_bsdf_sampling()
+	{
+		check_environment_floor(LightSampling::Bsdf, 200_000);
+	}
+
+	#[test]
+	fn environment_floor_with_light_sampling()
+	{
+		check_environment_floor(LightSampling::Light, 50_000);
+	}
+
+	#[test]
+	fn environment_floor_with_multiple_importance_sampling()
+	{
+		check_environment_floor(LightSampling::Multiple, 50_000);
+	}
+
+	// Mean radiance along camera_rays of the Cornell box with its blocks, with 16 bounces, and its standard error.
+	// Measured with 400000 multiple importance sampled paths per ray.
+	const CORNELL_REFERENCE : Vector3<f64> = Vector3 { x : 0.157_89, y : 0.094_377, z : 0.024_271 };
+	const CORNELL_REFERENCE_ERROR : Vector3<f64> = Vector3 { x : 0.000_08, y : 0.000_05, z : 0.000_015 };
+
+	fn check_cornell_global_illumination(light_sampling : LightSampling, samples : u32)
+	{
+		let description = cornell_box(true);
+		let scene = build_scene(&description);
+		let result = estimate(&scene, &settings(16, light_sampling), &camera_rays(&description), samples);
+		assert_converged(&result, CORNELL_REFERENCE, CORNELL_REFERENCE_ERROR);
+	}
+
+	#[test]
+	fn cornell_global_illumination_with_bsdf_sampling()
+	{
+		check_cornell_global_illumination(LightSampling::Bsdf, 4000);
+	}
+
+	#[test]
+	fn cornell_global_illumination_with_light_sampling()
+	{
+		check_cornell_global_illumination(LightSampling::Light, 2000);
+	}
+
+	#[test]
+	fn cornell_global_illumination_with_multiple_importance_sampling()
+	{
+		check_cornell_global_illumination(LightSampling::Multiple, 2000);
+	}
+
+	// The same for the scene with every kind of light, with 4 bounces, measured the same way. BSDF sampling alone never finds the point and
+	// spot lights, so it is not expected to agree.
+	const LIGHTS_REFERENCE : Vector3<f64> = Vector3 { x : 0.285_30, y : 0.300_35, z : 0.270_94 };
+	const LIGHTS_REFERENCE_ERROR : Vector3<f64> = Vector3 { x : 0.000_43, y : 0.000_58, z : 0.000_66 };
+
+	fn check_every_kind_of_light(light_sampling : LightSampling, samples : u32)
+	{
+		let description = lights();
+		let scene = build_scene(&description);
+		let result = estimate(&scene, &settings(4, light_sampling), &camera_rays(&description), samples);
+		assert_converged(&result, LIGHTS_REFERENCE, LIGHTS_REFERENCE_ERROR);
+	}
+
+	#[test]
+	fn every_kind_of_light_with_light_sampling()
+	{
+		check_every_kind_of_light(LightSampling::Light, 4000);
+	}
+
+	#[test]
+	fn every_kind_of_light_with_multiple_importance_sampling()
+	{
+		check_every_kind_of_light(LightSampling::Multiple, 4000);
+	}
+}
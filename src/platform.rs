@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::fmt;
+use std::result::Result;
+use std::string::String;
+
+use crate::framebuffer::Framebuffer;
+use crate::input::{ InputEvent, ScriptedInput };
+use crate::tone_mapping::DisplayTransform;
+use crate::window_handle::RawWindowHandle;
+
+#[cfg(all(windows, feature = "win32"))]
+use crate::win_platform;
+use crate::null_platform;
+
+#[derive(Debug)]
+pub enum ExitCode
+{
+	Quit,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlatformError
+{
+	details : String,
+}
+
+impl PlatformError
+{
+	#[cfg_attr(not(windows), allow(dead_code))]
+	pub fn new(details : &str) -> Self
+	{
+		Self { details : details.to_string() }
+	}
+}
+
+impl fmt::Display for PlatformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for PlatformError {}
+
+pub type ExitResult = Result<ExitCode, PlatformError>;
+
+// The operating system side of the program: the window, its events and getting pixels on screen.
+pub trait Platform
+{
+	fn create_window(&mut self, title : &str, width : u32, height : u32) -> Result<(), PlatformError>;
+
+	// Handle to the window made by create_window, for backends that create their own swap chain.
+	fn raw_handle(&self) -> RawWindowHandle;
+
+	// Processes whatever the operating system has queued up since the last call and returns the input it produced.
+	fn pump_events(&mut self) -> Vec<InputEvent>;
+
+	// Some once the platform has shut down, e.g. the window was closed.
+	fn exit_result(&mut self) -> Option<ExitResult>;
+
+	// Asks the platform to shut down. exit_result reports when it is done.
+	fn request_exit(&mut self);
+
+	// Shows a scene linear CPU rendered image in the window, brought to the display by display.
+	fn present(&mut self, framebuffer : &Framebuffer, display : &DisplayTransform);
+}
+
+// The native platform for this build: Win32 on Windows, otherwise the null platform.
+// Scripted input always runs on the null platform, so a script plays back the same everywhere.
+pub fn create_platform(scripted_input : Option<ScriptedInput>) -> Box<dyn Platform>
+{
+	match scripted_input
+	{
+		Some(scripted_input) => Box::new(null_platform::NullPlatform::with_script(scripted_input)),
+		None => create_native_platform(),
+	}
+}
+
+#[cfg(all(windows, feature = "win32"))]
+fn create_native_platform() -> Box<dyn Platform>
+{
+	Box::new(win_platform::Win32Platform::new())
+}
+
+#[cfg(not(all(windows, feature = "win32")))]
+fn create_native_platform() -> Box<dyn Platform>
+{
+	Box::new(null_platform::NullPlatform::with_script(ScriptedInput::new()))
+}
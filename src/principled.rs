@@ -0,0 +1,307 @@
+use cgmath::InnerSpace;
+use cgmath::Vector3;
+
+use crate::area_lights::luminance;
+use crate::material::{ cosine_sample_hemisphere, fresnel_dielectric, reflect, schlick_weight, Bsdf, BsdfSample };
+use crate::microfacet::Ggx;
+use crate::scene_description::PrincipledParameters;
+
+// Disney clamps alpha here, so the principled BSDF never has a specular lobe.
+const MINIMUM_ALPHA : f32 = 1.0e-3;
+
+// Alpha of the GGX masking the clearcoat uses, fixed by Disney.
+const CLEARCOAT_MASKING_ALPHA : f32 = 0.25;
+
+// Burley's principled BSDF ("Physically Based Shading at Disney", 2012, with the specular transmission of the
+// 2015 notes), with its inputs named like Blender's Principled node. Its lobes are a retro-reflective diffuse
+// blended toward an approximation of subsurface scattering, sheen, an anisotropic GGX reflection tinted toward
+// the base color, a GTR1 clearcoat and rough glass, which reflects and refracts by the exact Fresnel term.
+// Like Disney's, the diffuse and sheen reflect more than they receive at grazing angles.
+// Anisotropy follows the tangent of the shading frame, as meshes carry no tangents.
+pub struct Principled
+{
+	base_color : Vector3<f32>,
+	metallic : f32,
+	subsurface : f32,
+	roughness : f32,
+	transmission : f32,
+	eta : f32, // index of refraction past the surface over the one wo is in
+	sheen_color : Vector3<f32>,
+	specular_color : Vector3<f32>, // reflectance at normal incidence
+	transmission_color : Vector3<f32>,
+	clearcoat : f32,
+	clearcoat_alpha : f32,
+	rotation : (f32, f32), // cosine and sine of the anisotropic rotation
+	distribution : Ggx,
+}
+
+impl Principled
+{
+	// specular as in Blender, where 0.5 is a reflectance of 0.04 at normal incidence.
+	pub fn new(base_color : Vector3<f32>, metallic : f32, roughness : f32, specular : f32, transmission : f32, eta : f32, parameters : &PrincipledParameters) -> Self
+	{
+		let clamp = |value : f32| value.clamp(0.0, 1.0);
+		let (metallic, roughness, transmission) = (clamp(metallic), clamp(roughness), clamp(transmission));
+		let lerp = |a : Vector3<f32>, b : Vector3<f32>, t : f32| a + (b - a) * t;
+		let white = Vector3::new(1.0, 1.0, 1.0);
+
+		// the base color's hue and saturation, without its luminance.
+		let base_luminance = luminance(base_color);
+		let tint = if base_luminance > 0.0 { base_color / base_luminance } else { white };
+
+		let specular_color = lerp(lerp(white, tint, clamp(parameters.specular_tint)) * (0.08 * specular.max(0.0)), base_color, metallic);
+		let aspect = (1.0 - 0.9 * clamp(parameters.anisotropic)).sqrt();
+		let alpha = roughness * roughness;
+		let angle = 2.0 * std::f32::consts::PI * parameters.anisotropic_rotation;
+		let clearcoat_roughness = clamp(parameters.clearcoat_roughness);
+
+		Self
+		{
+			base_color : base_color,
+			metallic : metallic,
+			subsurface : clamp(parameters.subsurface),
+			roughness : roughness,
+			transmission : transmission,
+			eta : eta,
+			sheen_color : lerp(white, tint, clamp(parameters.sheen_tint)) * parameters.sheen.max(0.0),
+			specular_color : specular_color,
+			// the square root, so light passing in and back out is tinted by the base color once.
+			transmission_color : Vector3::new(base_color.x.max(0.0).sqrt(), base_color.y.max(0.0).sqrt(), base_color.z.max(0.0).sqrt()),
+			clearcoat : 0.25 * parameters.clearcoat.max(0.0),
+			clearcoat_alpha : (clearcoat_roughness * clearcoat_roughness).clamp(MINIMUM_ALPHA, 0.999),
+			rotation : (angle.cos(), angle.sin()),
+			distribution : Ggx { alpha_x : (alpha / aspect).max(MINIMUM_ALPHA), alpha_y : (alpha * aspect).max(MINIMUM_ALPHA) },
+		}
+	}
+
+	// BSDF space turned so x is the direction of anisotropy, and back.
+	fn to_anisotropic(&self, direction : Vector3<f32>) -> Vector3<f32>
+	{
+		let (cosine, sine) = self.rotation;
+		Vector3::new(direction.x * cosine + direction.y * sine, direction.y * cosine - direction.x * sine, direction.z)
+	}
+
+	fn to_shading(&self, direction : Vector3<f32>) -> Vector3<f32>
+	{
+		let (cosine, sine) = self.rotation;
+		Vector3::new(direction.x * cosine - direction.y * sine, direction.x * sine + direction.y * cosine, direction.z)
+	}
+
+	// The weight of the glass, which takes the place of the diffuse and of the dielectric part of the specular.
+	fn glass(&self) -> f32
+	{
+		(1.0 - self.metallic) * self.transmission
+	}
+
+	// How often sample picks the diffuse, specular, clearcoat and glass lobes, roughly their share of the
+	// scattered light.
+	fn lobe_probabilities(&self, wo : Vector3<f32>) -> [f32; 4]
+	{
+		let fresnel = schlick_weight(wo.z);
+		let dielectric = 1.0 - self.metallic;
+		let specular = self.specular_color + (Vector3::new(1.0, 1.0, 1.0) - self.specular_color) * fresnel;
+		let weights =
+		[
+			dielectric * (1.0 - self.transmission) * (luminance(self.base_color) + luminance(self.sheen_color)),
+			(1.0 - self.glass()) * luminance(specular),
+			self.clearcoat * (0.04 + 0.96 * fresnel),
+			self.glass(),
+		];
+		let total : f32 = weights.iter().sum();
+		if total > 0.0 { weights.map(|weight| weight / total) } else { [0.0; 4] }
+	}
+
+	fn evaluate_reflection(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>
+	{
+		let half = (wo + wi).normalize();
+		let cosine = wi.dot(half);
+		let (light, view, half_weight) = (schlick_weight(wi.z), schlick_weight(wo.z), schlick_weight(cosine));
+
+		let diffuse_90 = 0.5 + 2.0 * self.roughness * cosine * cosine;
+		let diffuse = (1.0 + (diffuse_90 - 1.0) * light) * (1.0 + (diffuse_90 - 1.0) * view);
+
+		// Hanrahan-Krueger like flattening of the diffuse, which stands in for subsurface scattering.
+		let subsurface_90 = self.roughness * cosine * cosine;
+		let flattening = (1.0 + (subsurface_90 - 1.0) * light) * (1.0 + (subsurface_90 - 1.0) * view);
+		let subsurface = 1.25 * (flattening * (1.0 / (wi.z + wo.z) - 0.5) + 0.5);
+
+		let diffuse_part = self.base_color * ((diffuse + (subsurface - diffuse) * self.subsurface) / std::f32::consts::PI) + self.sheen_color * half_weight;
+		let diffuse_part = diffuse_part * ((1.0 - self.metallic) * (1.0 - self.transmission));
+
+		let specular_fresnel = self.specular_color + (Vector3::new(1.0, 1.0, 1.0) - self.specular_color) * half_weight;
+		let specular = self.distribution.distribution(half) * self.distribution.masking_shadowing(wo, wi) / (4.0 * wo.z * wi.z);
+		let glass_reflection = self.glass() * fresnel_dielectric(wo.dot(half), self.eta) * specular;
+
+		let clearcoat_masking = Ggx::isotropic(CLEARCOAT_MASKING_ALPHA);
+		let clearcoat = self.clearcoat * (0.04 + 0.96 * half_weight) * gtr1(half.z, self.clearcoat_alpha)
+			* clearcoat_masking.masking(wo) * clearcoat_masking.masking(wi) / (4.0 * wo.z * wi.z);
+
+		let uncolored = clearcoat + glass_reflection;
+		diffuse_part + specular_fresnel * ((1.0 - self.glass()) * specular) + Vector3::new(uncolored, uncolored, uncolored)
+	}
+
+	// The microfacet normal refracting wo into wi, facing up, with wo . h and wi . h. None unless they are on
+	// either side of it.
+	fn refraction_normal(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Option<(Vector3<f32>, f32, f32)>
+	{
+		let half = wo + wi * self.eta;
+		if half.magnitude2() <= 0.0
+		{
+			return None;
+		}
+		let half = if half.z < 0.0 { -half.normalize() } else { half.normalize() };
+		let (outgoing, incoming) = (wo.dot(half), wi.dot(half));
+		if outgoing <= 0.0 || incoming >= 0.0 { None } else { Some((half, outgoing, incoming)) }
+	}
+
+	// Walter et al. 2007. Like the smooth dielectric, it leaves radiance unscaled by the change of index.
+	fn evaluate_transmission(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>
+	{
+		let (half, outgoing, incoming) = match self.refraction_normal(wo, wi)
+		{
+			Some(normal) => normal,
+			None => return Vector3::new(0.0, 0.0, 0.0),
+		};
+		let denominator = outgoing + self.eta * incoming;
+		let fresnel = fresnel_dielectric(outgoing, self.eta);
+		let value = self.glass() * (1.0 - fresnel) * self.distribution.distribution(half)
+			* self.distribution.masking_shadowing(wo, wi) * (incoming * outgoing).abs() * self.eta * self.eta / (wi.z.abs() * wo.z * denominator * denominator);
+		self.transmission_color * value
+	}
+
+	fn transmission_pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		match self.refraction_normal(wo, wi)
+		{
+			Some((half, outgoing, incoming)) =>
+			{
+				let denominator = outgoing + self.eta * incoming;
+				(1.0 - fresnel_dielectric(outgoing, self.eta)) * self.distribution.visible_normal_pdf(wo, half) * self.eta * self.eta * incoming.abs()
+					/ (denominator * denominator)
+			}
+			None => 0.0,
+		}
+	}
+
+	fn clearcoat_pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		let half = (wo + wi).normalize();
+		let cosine = wo.dot(half);
+		if cosine <= 0.0 { 0.0 } else { gtr1(half.z, self.clearcoat_alpha) * half.z / (4.0 * cosine) }
+	}
+
+	// Both arguments in the anisotropic frame.
+	fn pdf_anisotropic(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		if wo.z <= 0.0
+		{
+			return 0.0;
+		}
+		let [diffuse, specular, clearcoat, glass] = self.lobe_probabilities(wo);
+		if wi.z > 0.0
+		{
+			let half = (wo + wi).normalize();
+			let glass_reflection = if glass > 0.0 { glass * fresnel_dielectric(wo.dot(half), self.eta) } else { 0.0 };
+			diffuse * wi.z / std::f32::consts::PI + (specular + glass_reflection) * self.distribution.reflection_pdf(wo, wi) + clearcoat * self.clearcoat_pdf(wo, wi)
+		}
+		else
+		{
+			glass * self.transmission_pdf(wo, wi)
+		}
+	}
+
+	fn evaluate_anisotropic(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>
+	{
+		if wo.z <= 0.0 || wi.z == 0.0
+		{
+			Vector3::new(0.0, 0.0, 0.0)
+		}
+		else if wi.z > 0.0
+		{
+			self.evaluate_reflection(wo, wi)
+		}
+		else
+		{
+			self.evaluate_transmission(wo, wi)
+		}
+	}
+}
+
+impl Bsdf for Principled
+{
+	fn evaluate(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> Vector3<f32>
+	{
+		self.evaluate_anisotropic(self.to_anisotropic(wo), self.to_anisotropic(wi))
+	}
+
+	fn sample(&self, wo : Vector3<f32>, u_lobe : f32, u1 : f32, u2 : f32) -> Option<BsdfSample>
+	{
+		let wo = self.to_anisotropic(wo);
+		if wo.z <= 0.0
+		{
+			return None;
+		}
+
+		let [diffuse, specular, clearcoat, glass] = self.lobe_probabilities(wo);
+		let (direction, transmitted) = if u_lobe < diffuse
+		{
+			(cosine_sample_hemisphere(u1, u2), false)
+		}
+		else if u_lobe < diffuse + specular
+		{
+			(reflect(wo, self.distribution.sample_visible_normal(wo, u1, u2)), false)
+		}
+		else if u_lobe < diffuse + specular + clearcoat
+		{
+			(reflect(wo, gtr1_sample_normal(self.clearcoat_alpha, u1, u2)), false)
+		}
+		else
+		{
+			// what is left of u_lobe picks reflection or refraction, total internal reflection always reflecting.
+			let u_glass = (u_lobe - diffuse - specular - clearcoat) / glass;
+			let half = self.distribution.sample_visible_normal(wo, u1, u2);
+			let cosine = wo.dot(half);
+			if u_glass < fresnel_dielectric(cosine, self.eta)
+			{
+				(reflect(wo, half), false)
+			}
+			else
+			{
+				let sine_squared = (1.0 - cosine * cosine) / (self.eta * self.eta);
+				((half * (cosine / self.eta - (1.0 - sine_squared).sqrt()) - wo / self.eta).normalize(), true)
+			}
+		};
+
+		// microfacets can send light to the wrong side of the surface, which pdf and evaluate take for the other event.
+		let pdf = self.pdf_anisotropic(wo, direction);
+		if direction.z == 0.0 || (direction.z < 0.0) != transmitted || pdf <= 0.0
+		{
+			return None;
+		}
+		let weight = self.evaluate_anisotropic(wo, direction) * (direction.z.abs() / pdf);
+		Some(BsdfSample { direction : self.to_shading(direction), weight : weight, pdf : pdf, specular : false })
+	}
+
+	fn pdf(&self, wo : Vector3<f32>, wi : Vector3<f32>) -> f32
+	{
+		self.pdf_anisotropic(self.to_anisotropic(wo), self.to_anisotropic(wi))
+	}
+}
+
+// Burley's generalized Trowbridge-Reitz distribution with an exponent of 1, the long tailed one of the clearcoat.
+fn gtr1(cosine : f32, alpha : f32) -> f32
+{
+	let alpha_squared = alpha * alpha;
+	let t = 1.0 + (alpha_squared - 1.0) * cosine * cosine;
+	(alpha_squared - 1.0) / (std::f32::consts::PI * alpha_squared.ln() * t)
+}
+
+// A normal distributed as gtr1 * cos.
+fn gtr1_sample_normal(alpha : f32, u1 : f32, u2 : f32) -> Vector3<f32>
+{
+	let alpha_squared = alpha * alpha;
+	let cosine = ((1.0 - alpha_squared.powf(1.0 - u1)) / (1.0 - alpha_squared)).max(0.0).sqrt();
+	let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+	let angle = 2.0 * std::f32::consts::PI * u2;
+	Vector3::new(sine * angle.cos(), sine * angle.sin(), cosine)
+}
@@ -0,0 +1,138 @@
+use cgmath::Vector2;
+use cgmath::Vector3;
+use cgmath::Vector4;
+
+use crate::geometry::SurfaceHit;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Ray
+{
+	pub origin : Vector3<f32>,
+	pub direction : Vector3<f32>,
+}
+
+impl Ray
+{
+	pub fn new(origin : Vector3<f32>, direction : Vector3<f32>) -> Self
+	{
+		Self { origin : origin, direction : direction }
+	}
+
+	pub fn at(&self, t : f32) -> Vector3<f32>
+	{
+		self.origin + self.direction * t
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct HitRecord
+{
+	pub t : f32,
+
+	pub point : Vector3<f32>,
+
+	pub normal : Vector3<f32>, // always points against the incoming ray
+
+	pub front_face : bool,     // true when the ray hit the outward facing side
+
+	pub uv : Vector2<f32>,
+
+	pub color : Vector4<f32>,
+
+	pub object_id : u32,       // 1 + the index of the scene object that was hit, 0 until the scene fills it in
+
+	pub material : Option<usize>, // index into the scene's materials
+}
+
+impl HitRecord
+{
+	pub fn new(ray : &Ray, surface_hit : &SurfaceHit, color : Vector4<f32>) -> Self
+	{
+		let normal = if surface_hit.front_face { surface_hit.normal } else { -surface_hit.normal };
+
+		Self
+		{
+			t : surface_hit.t,
+			point : ray.at(surface_hit.t),
+			normal : normal,
+			front_face : surface_hit.front_face,
+			uv : surface_hit.uv,
+			color : color,
+			object_id : 0,
+			material : None,
+		}
+	}
+}
+
+pub trait Hittable : Send + Sync
+{
+	// Returns the closest hit with t in [t_min, t_max], if any.
+	fn hit(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<HitRecord>;
+
+	// True when anything is hit with t in [t_min, t_max]. Shadow rays only need this, so implementations
+	// are free to stop at the first hit instead of searching for the closest one.
+	fn occluded(&self, ray : &Ray, t_min : f32, t_max : f32) -> bool
+	{
+		self.hit(ray, t_min, t_max).is_some()
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use cgmath::{ InnerSpace, Vector2, Vector3, Vector4 };
+
+	use super::*;
+
+	// A wall at t = 1 along any ray, for the default occluded.
+	struct Wall;
+
+	impl Hittable for Wall
+	{
+		fn hit(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<HitRecord>
+		{
+			if !(t_min..=t_max).contains(&1.0)
+			{
+				return None;
+			}
+			let surface_hit = SurfaceHit { t : 1.0, normal : -ray.direction, front_face : true, uv : Vector2::new(0.0, 0.0) };
+			Some(HitRecord::new(ray, &surface_hit, Vector4::new(1.0, 1.0, 1.0, 1.0)))
+		}
+	}
+
+	#[test]
+	fn at_walks_along_the_direction()
+	{
+		let ray = Ray::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.0, 0.0, -2.0));
+		assert_eq!(ray.at(0.0), ray.origin);
+		assert_eq!(ray.at(1.5), Vector3::new(1.0, 2.0, 0.0));
+		assert_eq!(ray.at(-1.0), Vector3::new(1.0, 2.0, 5.0));
+	}
+
+	#[test]
+	fn hit_record_normal_faces_the_ray()
+	{
+		let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+		let outward = Vector3::new(0.0, 0.0, -1.0); // seen from behind
+		let surface_hit = SurfaceHit { t : 2.0, normal : outward, front_face : false, uv : Vector2::new(0.25, 0.75) };
+		let record = HitRecord::new(&ray, &surface_hit, Vector4::new(0.5, 0.5, 0.5, 1.0));
+
+		assert_eq!(record.point, Vector3::new(0.0, 0.0, -2.0));
+		assert_eq!(record.normal, -outward);
+		assert!(record.normal.dot(ray.direction) < 0.0);
+		assert!(!record.front_face);
+		assert_eq!(record.uv, Vector2::new(0.25, 0.75));
+		assert_eq!(record.object_id, 0);
+		assert_eq!(record.material, None);
+	}
+
+	#[test]
+	fn occluded_defaults_to_any_hit_in_range()
+	{
+		let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+		assert!(Wall.occluded(&ray, 0.0, 2.0));
+		assert!(Wall.occluded(&ray, 1.0, 1.0));
+		assert!(!Wall.occluded(&ray, 0.0, 0.5));
+		assert!(!Wall.occluded(&ray, 1.5, f32::INFINITY));
+	}
+}
@@ -0,0 +1,322 @@
+use cgmath::InnerSpace;
+use cgmath::{ Deg, Matrix4, Point3, Rad, SquareMatrix, Vector2, Vector3, Vector4 };
+
+#[cfg(test)]
+use crate::framebuffer::Framebuffer;
+use crate::mesh::{ MaterialGroup, Mesh, MeshVertex };
+use crate::scene_description::{ CameraDescription, LightDescription, LightKind, MaterialDescription, Node, Projection, SceneDescription };
+
+// Scenes with known answers, built in code so they render the same everywhere.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReferenceScene
+{
+	CornellBox,
+	EmptyCornellBox,
+	SpecularCornellBox,
+	Furnace,
+	Floor,
+	Lights,
+}
+
+impl std::str::FromStr for ReferenceScene
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		match value
+		{
+			"cornell" => Ok(ReferenceScene::CornellBox),
+			"cornell-empty" => Ok(ReferenceScene::EmptyCornellBox),
+			"cornell-specular" => Ok(ReferenceScene::SpecularCornellBox),
+			"furnace" => Ok(ReferenceScene::Furnace),
+			"floor" => Ok(ReferenceScene::Floor),
+			"lights" => Ok(ReferenceScene::Lights),
+			_ => Err(format!("unknown reference scene '{}', expected cornell, cornell-empty, cornell-specular, furnace, floor or lights", value)),
+		}
+	}
+}
+
+impl ReferenceScene
+{
+	pub fn build(self) -> SceneDescription
+	{
+		match self
+		{
+			ReferenceScene::CornellBox => cornell_box(true),
+			ReferenceScene::EmptyCornellBox => cornell_box(false),
+			ReferenceScene::SpecularCornellBox => specular_cornell_box(),
+			ReferenceScene::Furnace => furnace(FURNACE_ALBEDO, FURNACE_EMISSION),
+			ReferenceScene::Floor => floor(FLOOR_ALBEDO),
+			ReferenceScene::Lights => lights(),
+		}
+	}
+}
+
+pub const FURNACE_ALBEDO : f32 = 0.5;
+pub const FURNACE_EMISSION : f32 = 1.0;
+
+pub const FLOOR_ALBEDO : f32 = 0.5;
+pub const FLOOR_HALF_SIZE : f32 = 10.0;
+
+// The sky the environment tests light with, 64x32 texels: a blue gradient down to the horizon, dark ground below
+// it and a bright sun 4 texels in size.
+#[cfg(test)]
+pub fn sky_environment() -> Framebuffer
+{
+	let (width, height) = (64, 32);
+	let mut sky = Framebuffer::new(width, height);
+	for y in 0..height
+	{
+		for x in 0..width
+		{
+			let color = if (40..42).contains(&x) && (6..8).contains(&y)
+			{
+				Vector3::new(500.0, 450.0, 400.0)
+			}
+			else if y < height / 2
+			{
+				let t = y as f32 / (height / 2) as f32;
+				Vector3::new(0.3 + 0.5 * t, 0.5 + 0.3 * t, 1.0)
+			}
+			else
+			{
+				Vector3::new(0.1, 0.08, 0.05)
+			};
+			sky.set(x, y, color.extend(1.0));
+		}
+	}
+	sky
+}
+
+// Dimensions of the Cornell box, which is 2 units on a side with the floor at y = 0 and the open side facing +z.
+pub const CORNELL_HEIGHT : f32 = 2.0;
+pub const CORNELL_LIGHT_HALF_SIZE : f32 = 0.25;
+pub const CORNELL_LIGHT_HEIGHT : f32 = 1.98;
+pub const CORNELL_WHITE : f32 = 0.73;
+pub const CORNELL_LIGHT_EMISSION : Vector3<f32> = Vector3 { x : 17.0, y : 12.0, z : 4.0 };
+
+// Collects quads into a mesh, one material group per material.
+struct MeshBuilder
+{
+	mesh : Mesh,
+}
+
+impl MeshBuilder
+{
+	fn new(name : &str) -> Self
+	{
+		Self { mesh : Mesh { name : name.to_string(), ..Mesh::default() } }
+	}
+
+	// Corners in counter clockwise order seen from the side the quad faces.
+	fn quad(&mut self, corners : [Vector3<f32>; 4], color : Vector3<f32>, material : usize)
+	{
+		let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalize();
+		let first_vertex = self.mesh.vertices.len() as u32;
+		let uvs = [Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0), Vector2::new(1.0, 1.0), Vector2::new(0.0, 1.0)];
+		for (corner, uv) in corners.iter().zip(uvs.iter())
+		{
+			self.mesh.vertices.push(MeshVertex { position : *corner, normal : normal, uv : *uv, color : color.extend(1.0) });
+		}
+
+		let first_index = self.mesh.indices.len();
+		self.mesh.indices.extend_from_slice(&[0, 1, 2, 0, 2, 3].map(|index| first_vertex + index));
+		match self.mesh.groups.last_mut()
+		{
+			Some(group) if group.material == Some(material) => group.index_count += 6,
+			_ => self.mesh.groups.push(MaterialGroup { material : Some(material), first_index : first_index, index_count : 6 }),
+		}
+	}
+
+	// An upright box standing on y = 0, turned around the y axis, with its faces pointing out.
+	fn block(&mut self, center : Vector3<f32>, half_size : Vector3<f32>, angle : Deg<f32>, color : Vector3<f32>, material : usize)
+	{
+		let rotation = Matrix4::from_angle_y(angle);
+		let corner = |x : f32, y : f32, z : f32|
+		{
+			let offset = (rotation * Vector4::new(x * half_size.x, 0.0, z * half_size.z, 0.0)).truncate();
+			center + offset + Vector3::new(0.0, (y + 1.0) * half_size.y, 0.0)
+		};
+
+		self.quad([corner(-1.0, 1.0, 1.0), corner(1.0, 1.0, 1.0), corner(1.0, 1.0, -1.0), corner(-1.0, 1.0, -1.0)], color, material);
+		self.quad([corner(-1.0, -1.0, 1.0), corner(1.0, -1.0, 1.0), corner(1.0, 1.0, 1.0), corner(-1.0, 1.0, 1.0)], color, material);
+		self.quad([corner(1.0, -1.0, -1.0), corner(-1.0, -1.0, -1.0), corner(-1.0, 1.0, -1.0), corner(1.0, 1.0, -1.0)], color, material);
+		self.quad([corner(1.0, -1.0, 1.0), corner(1.0, -1.0, -1.0), corner(1.0, 1.0, -1.0), corner(1.0, 1.0, 1.0)], color, material);
+		self.quad([corner(-1.0, -1.0, -1.0), corner(-1.0, -1.0, 1.0), corner(-1.0, 1.0, 1.0), corner(-1.0, 1.0, -1.0)], color, material);
+	}
+}
+
+fn diffuse_material(name : &str, emission : Vector3<f32>) -> MaterialDescription
+{
+	MaterialDescription
+	{
+		name : name.to_string(),
+		metallic : 0.0,
+		specular : 0.0,
+		emission : emission,
+		..MaterialDescription::default()
+	}
+}
+
+fn add_camera(scene : &mut SceneDescription, position : Vector3<f32>, target : Vector3<f32>, vertical_fov : Deg<f32>)
+{
+	let mut node = Node::new("camera");
+	let view = Matrix4::look_at(Point3::new(position.x, position.y, position.z), Point3::new(target.x, target.y, target.z), Vector3::unit_y());
+	node.local_transform = view.invert().unwrap_or_else(Matrix4::identity);
+	node.camera = Some(scene.cameras.len());
+
+	scene.cameras.push(CameraDescription
+	{
+		projection : Projection::Perspective { vertical_fov : Rad::from(vertical_fov), near : 0.01, far : None },
+	});
+	scene.roots.push(scene.nodes.len());
+	scene.nodes.push(node);
+	scene.update_world_transforms();
+}
+
+// A light at position pointing at target. Which way is up only matters for rectangles, whose width runs across it.
+fn add_light(scene : &mut SceneDescription, kind : LightKind, color : Vector3<f32>, intensity : f32, position : Vector3<f32>, target : Vector3<f32>)
+{
+	let direction = target - position;
+	let up = if direction.x.abs() + direction.z.abs() < 1.0e-3 * direction.y.abs() { Vector3::unit_z() } else { Vector3::unit_y() };
+	let view = Matrix4::look_at(Point3::new(position.x, position.y, position.z), Point3::new(target.x, target.y, target.z), up);
+
+	let mut node = Node::new(&format!("light {}", scene.lights.len()));
+	node.local_transform = view.invert().unwrap_or_else(Matrix4::identity);
+	node.light = Some(scene.lights.len());
+
+	scene.lights.push(LightDescription { kind : kind, color : color, intensity : intensity, range : None });
+	scene.roots.push(scene.nodes.len());
+	scene.nodes.push(node);
+	scene.update_world_transforms();
+}
+
+// The classic Cornell box: white floor, ceiling and back wall, red left wall, green right wall and a square
+// light just under the ceiling, optionally with the two white blocks.
+pub fn cornell_box(with_blocks : bool) -> SceneDescription
+{
+	let (white, red, green) = (Vector3::new(CORNELL_WHITE, CORNELL_WHITE, CORNELL_WHITE), Vector3::new(0.65, 0.05, 0.05), Vector3::new(0.12, 0.45, 0.15));
+	let corner = |x : f32, y : f32, z : f32| Vector3::new(x, y, z);
+	let (top, light) = (CORNELL_HEIGHT, CORNELL_LIGHT_HALF_SIZE);
+
+	let mut builder = MeshBuilder::new("cornell box");
+	builder.quad([corner(-1.0, 0.0, 1.0), corner(1.0, 0.0, 1.0), corner(1.0, 0.0, -1.0), corner(-1.0, 0.0, -1.0)], white, 0);
+	builder.quad([corner(-1.0, top, -1.0), corner(1.0, top, -1.0), corner(1.0, top, 1.0), corner(-1.0, top, 1.0)], white, 0);
+	builder.quad([corner(-1.0, 0.0, -1.0), corner(1.0, 0.0, -1.0), corner(1.0, top, -1.0), corner(-1.0, top, -1.0)], white, 0);
+	if with_blocks
+	{
+		builder.block(Vector3::new(0.33, 0.0, 0.3), Vector3::new(0.3, 0.3, 0.3), Deg(-17.0), white, 0);
+		builder.block(Vector3::new(-0.35, 0.0, -0.3), Vector3::new(0.3, 0.6, 0.3), Deg(17.0), white, 0);
+	}
+	builder.quad([corner(-1.0, 0.0, 1.0), corner(-1.0, 0.0, -1.0), corner(-1.0, top, -1.0), corner(-1.0, top, 1.0)], red, 0);
+	builder.quad([corner(1.0, 0.0, -1.0), corner(1.0, 0.0, 1.0), corner(1.0, top, 1.0), corner(1.0, top, -1.0)], green, 0);
+	let light_y = CORNELL_LIGHT_HEIGHT;
+	builder.quad([corner(-light, light_y, -light), corner(light, light_y, -light), corner(light, light_y, light), corner(-light, light_y, light)], Vector3::new(0.78, 0.78, 0.78), 1);
+
+	let mut scene = SceneDescription::from_meshes(vec![builder.mesh]);
+	scene.materials = vec![diffuse_material("diffuse", Vector3::new(0.0, 0.0, 0.0)), diffuse_material("light", CORNELL_LIGHT_EMISSION)];
+	add_camera(&mut scene, Vector3::new(0.0, 1.0, 3.9), Vector3::new(0.0, 1.0, 0.0), Deg(40.0));
+	scene
+}
+
+// The empty Cornell box with a mirror block and a glass block, each a mesh of its own.
+pub fn specular_cornell_box() -> SceneDescription
+{
+	let mut scene = cornell_box(false);
+	let (mirror, glass) = (scene.materials.len(), scene.materials.len() + 1);
+	scene.materials.push(MaterialDescription { name : String::from("mirror"), metallic : 1.0, roughness : 0.0, ..MaterialDescription::default() });
+	scene.materials.push(MaterialDescription
+	{
+		name : String::from("glass"),
+		metallic : 0.0,
+		roughness : 0.0,
+		transmission : 1.0,
+		index_of_refraction : 1.5,
+		..MaterialDescription::default()
+	});
+
+	let mut tall_block = MeshBuilder::new("mirror block");
+	tall_block.block(Vector3::new(-0.35, 0.0, -0.3), Vector3::new(0.3, 0.6, 0.3), Deg(17.0), Vector3::new(0.9, 0.9, 0.9), mirror);
+	let mut short_block = MeshBuilder::new("glass block");
+	short_block.block(Vector3::new(0.33, 0.0, 0.3), Vector3::new(0.3, 0.3, 0.3), Deg(-17.0), Vector3::new(1.0, 1.0, 1.0), glass);
+
+	for mesh in [tall_block.mesh, short_block.mesh]
+	{
+		let mut node = Node::new(&mesh.name);
+		node.mesh = Some(scene.meshes.len());
+		scene.roots.push(scene.nodes.len());
+		scene.nodes.push(node);
+		scene.meshes.push(mesh);
+	}
+	scene.update_world_transforms();
+	scene
+}
+
+// A closed box, inside out so every wall faces the middle, that emits emission and reflects albedo everywhere.
+// Radiance inside converges to emission / (1 - albedo) whichever way you look.
+pub fn furnace(albedo : f32, emission : f32) -> SceneDescription
+{
+	let color = Vector3::new(albedo, albedo, albedo);
+	let corner = |x : f32, y : f32, z : f32| Vector3::new(x, y, z);
+
+	let mut builder = MeshBuilder::new("furnace");
+	builder.quad([corner(-1.0, -1.0, 1.0), corner(1.0, -1.0, 1.0), corner(1.0, -1.0, -1.0), corner(-1.0, -1.0, -1.0)], color, 0);
+	builder.quad([corner(-1.0, 1.0, -1.0), corner(1.0, 1.0, -1.0), corner(1.0, 1.0, 1.0), corner(-1.0, 1.0, 1.0)], color, 0);
+	builder.quad([corner(-1.0, -1.0, -1.0), corner(1.0, -1.0, -1.0), corner(1.0, 1.0, -1.0), corner(-1.0, 1.0, -1.0)], color, 0);
+	builder.quad([corner(1.0, -1.0, 1.0), corner(-1.0, -1.0, 1.0), corner(-1.0, 1.0, 1.0), corner(1.0, 1.0, 1.0)], color, 0);
+	builder.quad([corner(-1.0, -1.0, 1.0), corner(-1.0, -1.0, -1.0), corner(-1.0, 1.0, -1.0), corner(-1.0, 1.0, 1.0)], color, 0);
+	builder.quad([corner(1.0, -1.0, -1.0), corner(1.0, -1.0, 1.0), corner(1.0, 1.0, 1.0), corner(1.0, 1.0, -1.0)], color, 0);
+
+	let mut scene = SceneDescription::from_meshes(vec![builder.mesh]);
+	scene.materials = vec![diffuse_material("furnace", Vector3::new(emission, emission, emission))];
+	add_camera(&mut scene, Vector3::new(0.0, 0.0, 0.5), Vector3::new(0.0, 0.0, 0.0), Deg(60.0));
+	scene
+}
+
+// A square of diffuse floor at y = 0 facing up and nothing else, to be lit by an environment map.
+pub fn floor(albedo : f32) -> SceneDescription
+{
+	let color = Vector3::new(albedo, albedo, albedo);
+	let size = FLOOR_HALF_SIZE;
+
+	let mut builder = MeshBuilder::new("floor");
+	builder.quad([Vector3::new(-size, 0.0, size), Vector3::new(size, 0.0, size), Vector3::new(size, 0.0, -size), Vector3::new(-size, 0.0, -size)], color, 0);
+
+	let mut scene = SceneDescription::from_meshes(vec![builder.mesh]);
+	scene.materials = vec![diffuse_material("floor", Vector3::new(0.0, 0.0, 0.0))];
+	add_camera(&mut scene, Vector3::new(0.0, 2.0, 6.0), Vector3::new(0.0, 0.5, 0.0), Deg(45.0));
+	scene
+}
+
+// The floor with two blocks on it, lit by one light of every kind: a point light, a spot light, a soft sun, a
+// rectangle, a sphere and an emissive panel standing behind the blocks.
+pub fn lights() -> SceneDescription
+{
+	let mut scene = floor(FLOOR_ALBEDO);
+	let white = Vector3::new(0.73, 0.73, 0.73);
+	scene.materials.push(diffuse_material("panel", Vector3::new(4.0, 1.5, 0.5)));
+
+	let mut builder = MeshBuilder::new("blocks");
+	builder.block(Vector3::new(-0.8, 0.0, 0.0), Vector3::new(0.4, 0.8, 0.4), Deg(20.0), white, 0);
+	builder.block(Vector3::new(0.9, 0.0, 0.4), Vector3::new(0.4, 0.4, 0.4), Deg(-15.0), white, 0);
+	builder.quad([Vector3::new(-0.5, 0.0, -2.0), Vector3::new(0.5, 0.0, -2.0), Vector3::new(0.5, 1.5, -2.0), Vector3::new(-0.5, 1.5, -2.0)], white, 1);
+
+	let mut node = Node::new(&builder.mesh.name);
+	node.mesh = Some(scene.meshes.len());
+	scene.roots.push(scene.nodes.len());
+	scene.nodes.push(node);
+	scene.meshes.push(builder.mesh);
+
+	let origin = Vector3::new(0.0, 0.0, 0.0);
+	add_light(&mut scene, LightKind::Point, Vector3::new(1.0, 0.8, 0.6), 4.0, Vector3::new(-2.5, 2.0, 1.5), origin);
+	add_light(&mut scene, LightKind::Spot { inner_cone_angle : Rad::from(Deg(15.0)), outer_cone_angle : Rad::from(Deg(25.0)) },
+		Vector3::new(0.6, 0.8, 1.0), 30.0, Vector3::new(2.0, 3.5, 1.5), Vector3::new(0.9, 0.0, 0.4));
+	add_light(&mut scene, LightKind::Directional { angular_diameter : Rad::from(Deg(2.0)) },
+		Vector3::new(1.0, 0.95, 0.9), 2.0, Vector3::new(-3.0, 10.0, 4.0), origin);
+	add_light(&mut scene, LightKind::Rectangle { width : 1.5, height : 0.5 }, Vector3::new(1.0, 1.0, 1.0), 3.0,
+		Vector3::new(0.0, 2.8, -1.0), Vector3::new(0.0, 0.0, -1.0));
+	add_light(&mut scene, LightKind::Sphere { radius : 0.15 }, Vector3::new(0.4, 1.0, 0.5), 20.0,
+		Vector3::new(0.0, 0.3, 1.6), origin);
+	scene
+}
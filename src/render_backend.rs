@@ -0,0 +1,133 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::camera::Camera;
+use crate::cpu_raytracer::Integrator;
+use crate::environment::EnvironmentMap;
+use crate::film::Filter;
+use crate::framebuffer::{ AovBuffers, Framebuffer };
+use crate::lights::Light;
+use crate::mesh::Mesh;
+use crate::sampler::SamplerKind;
+use crate::scene_description::{ MaterialDescription, Texture };
+use crate::tone_mapping::DisplayTransform;
+use crate::window_handle::RawWindowHandle;
+
+#[cfg(all(windows, feature = "dx12"))]
+use crate::dx_renderer;
+#[cfg(feature = "software")]
+use crate::render_settings::{ G_WIDTH, G_HEIGHT };
+#[cfg(feature = "software")]
+use crate::software_renderer::SoftwareRenderer;
+
+// Lifecycle every renderer goes through: load_pipeline and load_assets once, then update and render every frame.
+pub trait RenderBackend
+{
+	fn load_pipeline(&mut self, window : RawWindowHandle);
+	fn load_assets(&mut self, meshes : &[Arc<Mesh>], materials : &[MaterialDescription], textures : &[Texture], lights : &[Light]);
+	fn update(&mut self, camera : &Camera);
+
+	// Returns 0 when the frame rendered, anything else stops the main loop.
+	fn render(&mut self) -> i32;
+
+	// How backends that draw straight to the window bring their colors to the display. Framebuffers from the CPU
+	// backends are scene linear and go through the transform when they are presented or saved instead.
+	fn set_display_transform(&mut self, _display : &DisplayTransform)
+	{
+	}
+
+	// What lights the scene from beyond its geometry and shows behind it, None for the plain background color.
+	// Backends that can't light with it, like the rasterizer, ignore it.
+	fn set_environment(&mut self, _environment : Option<Arc<EnvironmentMap>>)
+	{
+	}
+
+	// True while rendering more frames would add nothing, like a progressive renderer that reached its target,
+	// so the caller can wait for input instead.
+	fn idle(&self) -> bool
+	{
+		false
+	}
+
+	// Backends that render on the CPU expose their image, so it can be presented or saved.
+	fn framebuffer(&self) -> Option<&Framebuffer>
+	{
+		None
+	}
+
+	// Albedo, normal, depth and object id of what the last frame's pixels saw, from backends that record them.
+	fn aovs(&self) -> Option<&AovBuffers>
+	{
+		None
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BackendKind
+{
+	Dx12,
+	Software,
+}
+
+impl BackendKind
+{
+	// The first backend compiled in, preferring the GPU.
+	pub fn default_backend() -> Self
+	{
+		if cfg!(all(windows, feature = "dx12")) { BackendKind::Dx12 } else { BackendKind::Software }
+	}
+
+	pub fn feature_name(self) -> &'static str
+	{
+		match self
+		{
+			BackendKind::Dx12 => "dx12",
+			BackendKind::Software => "software",
+		}
+	}
+}
+
+impl fmt::Display for BackendKind
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.feature_name())
+	}
+}
+
+impl FromStr for BackendKind
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		match value
+		{
+			"dx12" => Ok(BackendKind::Dx12),
+			"software" => Ok(BackendKind::Software),
+			_ => Err(format!("unknown backend '{}', expected dx12 or software", value)),
+		}
+	}
+}
+
+// The integrator, sampler, filter and target samples per pixel only matter to the software backend, the dx12 one
+// rasterizes.
+pub fn create_backend(kind : BackendKind, integrator : Integrator, sampler : SamplerKind, filter : Filter, target_samples : u32) -> Result<Box<dyn RenderBackend>, String>
+{
+	match kind
+	{
+		#[cfg(all(windows, feature = "dx12"))]
+		BackendKind::Dx12 => Ok(Box::new(dx_renderer::Renderer::new())),
+		#[cfg(feature = "software")]
+		BackendKind::Software =>
+		{
+			let mut renderer = SoftwareRenderer::new(G_WIDTH, G_HEIGHT, 1, target_samples, integrator);
+			renderer.set_sampler(sampler);
+			renderer.set_filter(filter);
+			Ok(Box::new(renderer))
+		}
+		#[allow(unreachable_patterns)]
+		_ => Err(format!("the {} backend is not compiled in, build with the '{}' cargo feature on a supported target", kind, kind.feature_name())),
+	}
+}
@@ -0,0 +1,8 @@
+// Output resolution shared by the window, the swap chain and the CPU framebuffer.
+pub const G_WIDTH : u32 = 1280;
+pub const G_HEIGHT : u32 = 720;
+
+// Scene linear color of everything the camera sees that isn't geometry, which the rasterizer clears to. Through
+// the default display transform it shows as about [0.0, 0.48, 0.66], like the software renderer's images, where
+// the rasterizer used to write it unencoded.
+pub const G_BACKGROUND : [f32; 4] = [0.0, 0.2, 0.4, 1.0];
@@ -0,0 +1,37 @@
+// PCG32 (O'Neill, pcg-random.org). Small, fast and reproducible, which is all the renderer needs.
+#[derive(Copy, Clone, Debug)]
+pub struct Rng
+{
+	state : u64,
+	increment : u64,
+}
+
+const PCG_MULTIPLIER : u64 = 6364136223846793005;
+
+impl Rng
+{
+	pub fn new(seed : u64, stream : u64) -> Self
+	{
+		let mut rng = Self { state : 0, increment : (stream << 1) | 1 };
+		rng.next_u32();
+		rng.state = rng.state.wrapping_add(seed);
+		rng.next_u32();
+		rng
+	}
+
+	pub fn next_u32(&mut self) -> u32
+	{
+		let old_state = self.state;
+		self.state = old_state.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.increment);
+
+		let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+		let rotation = (old_state >> 59) as u32;
+		xor_shifted.rotate_right(rotation)
+	}
+
+	// uniform in [0, 1)
+	pub fn next_f32(&mut self) -> f32
+	{
+		(self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+	}
+}
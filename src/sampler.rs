@@ -0,0 +1,767 @@
+use lazy_static::lazy_static;
+
+use crate::rng::Rng;
+
+// Values in [0, 1) for sample sample_index of pixel x, y, one dimension at a time. The values only depend on the
+// pixel, the sample index and the dimension, never on what was sampled before, so renders come out the same on
+// every run, for any thread count and however the samples are split between frames.
+pub trait Sampler
+{
+	// Starts a sample, going back to dimension 0.
+	fn start_pixel_sample(&mut self, x : u32, y : u32, sample_index : u32);
+	fn next_1d(&mut self) -> f32;
+	fn next_2d(&mut self) -> (f32, f32);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SamplerKind
+{
+	Independent,
+	Stratified,
+	Halton,
+	Sobol,
+	BlueNoise,
+}
+
+impl SamplerKind
+{
+	pub const ALL : [SamplerKind; 5] = [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise];
+
+	// samples_per_pixel is how many samples the pattern is laid out for, only the stratified sampler needs it.
+	pub fn create(self, samples_per_pixel : u32) -> Box<dyn Sampler>
+	{
+		match self
+		{
+			SamplerKind::Independent => Box::new(IndependentSampler::new()),
+			SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel)),
+			SamplerKind::Halton => Box::new(HaltonSampler::new()),
+			SamplerKind::Sobol => Box::new(SobolSampler::new()),
+			SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new()),
+		}
+	}
+
+	pub fn name(self) -> &'static str
+	{
+		match self
+		{
+			SamplerKind::Independent => "random",
+			SamplerKind::Stratified => "stratified",
+			SamplerKind::Halton => "halton",
+			SamplerKind::Sobol => "sobol",
+			SamplerKind::BlueNoise => "blue-noise",
+		}
+	}
+}
+
+impl std::str::FromStr for SamplerKind
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		SamplerKind::ALL.iter()
+			.copied()
+			.find(|kind| kind.name() == value)
+			.ok_or_else(|| format!("unknown sampler '{}', expected random, stratified, halton, sobol or blue-noise", value))
+	}
+}
+
+// Uncorrelated random numbers, PCG seeded by a hash of the pixel and sample index. Seeding the same stream
+// differently keeps samples apart, where different streams from the same seed would be correlated.
+pub struct IndependentSampler
+{
+	rng : Rng,
+}
+
+impl IndependentSampler
+{
+	pub fn new() -> Self
+	{
+		Self { rng : Rng::new(0, 0) }
+	}
+}
+
+impl Sampler for IndependentSampler
+{
+	fn start_pixel_sample(&mut self, x : u32, y : u32, sample_index : u32)
+	{
+		self.rng = Rng::new(hash(pixel_seed(x, y), sample_index as u64), 0);
+	}
+
+	fn next_1d(&mut self) -> f32
+	{
+		self.rng.next_f32()
+	}
+
+	fn next_2d(&mut self) -> (f32, f32)
+	{
+		(self.rng.next_f32(), self.rng.next_f32())
+	}
+}
+
+// Jittered strata, samples_per_pixel of them in 1D and a grid of at least as many in 2D, visited in an order
+// shuffled for every pixel and dimension so the dimensions don't line up. Sample indices past the count start
+// another round with a new order.
+pub struct StratifiedSampler
+{
+	samples_per_pixel : u32,
+	pixel_seed : u64,
+	sample_index : u32,
+	dimension : u32,
+}
+
+impl StratifiedSampler
+{
+	pub fn new(samples_per_pixel : u32) -> Self
+	{
+		Self { samples_per_pixel : samples_per_pixel.max(1), pixel_seed : 0, sample_index : 0, dimension : 0 }
+	}
+
+	// The stratum this sample falls in out of count, and a seed for jittering within it.
+	fn stratum(&mut self, count : u32) -> (u32, u64)
+	{
+		let round = self.sample_index / count;
+		let seed = hash(hash(self.pixel_seed, self.dimension as u64), round as u64);
+		self.dimension += 1;
+		(permutation_element(self.sample_index % count, count, seed as u32), hash(seed, self.sample_index as u64))
+	}
+}
+
+impl Sampler for StratifiedSampler
+{
+	fn start_pixel_sample(&mut self, x : u32, y : u32, sample_index : u32)
+	{
+		self.pixel_seed = pixel_seed(x, y);
+		self.sample_index = sample_index;
+		self.dimension = 0;
+	}
+
+	fn next_1d(&mut self) -> f32
+	{
+		let count = self.samples_per_pixel;
+		let (stratum, seed) = self.stratum(count);
+		((stratum as f32 + unit_float(seed)) / count as f32).min(ONE_MINUS_EPSILON)
+	}
+
+	fn next_2d(&mut self) -> (f32, f32)
+	{
+		let columns = (self.samples_per_pixel as f32).sqrt().ceil() as u32;
+		let rows = self.samples_per_pixel.div_ceil(columns);
+		let (stratum, seed) = self.stratum(columns * rows);
+		let (column, row) = (stratum % columns, stratum / columns);
+		let x = (column as f32 + unit_float(seed)) / columns as f32;
+		let y = (row as f32 + unit_float(hash(seed, 1))) / rows as f32;
+		(x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+	}
+}
+
+// Primes for the dimensions of the Halton sequence, the ones past them are random.
+const HALTON_PRIMES : [u32; 64] =
+[
+	2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+	137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+// The Halton sequence, each dimension the radical inverse of the sample index in its own prime base, Owen
+// scrambled per pixel and dimension: every digit goes through a random permutation picked by the digits before it.
+// Without that the high bases, whose first few samples fall on a line, would be useless.
+pub struct HaltonSampler
+{
+	pixel_seed : u64,
+	sample_index : u32,
+	dimension : u32,
+}
+
+impl HaltonSampler
+{
+	pub fn new() -> Self
+	{
+		Self { pixel_seed : 0, sample_index : 0, dimension : 0 }
+	}
+}
+
+impl Sampler for HaltonSampler
+{
+	fn start_pixel_sample(&mut self, x : u32, y : u32, sample_index : u32)
+	{
+		self.pixel_seed = pixel_seed(x, y);
+		self.sample_index = sample_index;
+		self.dimension = 0;
+	}
+
+	fn next_1d(&mut self) -> f32
+	{
+		let dimension = self.dimension;
+		self.dimension += 1;
+		let seed = hash(self.pixel_seed, dimension as u64);
+		match HALTON_PRIMES.get(dimension as usize)
+		{
+			Some(&base) => scrambled_radical_inverse(self.sample_index, base, seed),
+			None => unit_float(hash(seed, self.sample_index as u64)),
+		}
+	}
+
+	fn next_2d(&mut self) -> (f32, f32)
+	{
+		(self.next_1d(), self.next_1d())
+	}
+}
+
+// The first two dimensions of Sobol's sequence, padded to any number of dimensions by giving every 1D and 2D
+// request its own Owen scrambling and its own shuffle of the sample order, both hashed from the pixel and the
+// dimension (Burley 2020, "Practical Hash-based Owen Scrambling").
+pub struct SobolSampler
+{
+	pixel_seed : u64,
+	sample_index : u32,
+	dimension : u32,
+}
+
+impl SobolSampler
+{
+	pub fn new() -> Self
+	{
+		Self { pixel_seed : 0, sample_index : 0, dimension : 0 }
+	}
+
+	fn next_seed(&mut self) -> u64
+	{
+		let seed = hash(self.pixel_seed, self.dimension as u64);
+		self.dimension += 1;
+		seed
+	}
+}
+
+impl Sampler for SobolSampler
+{
+	fn start_pixel_sample(&mut self, x : u32, y : u32, sample_index : u32)
+	{
+		self.pixel_seed = pixel_seed(x, y);
+		self.sample_index = sample_index;
+		self.dimension = 0;
+	}
+
+	fn next_1d(&mut self) -> f32
+	{
+		let seed = self.next_seed();
+		let index = nested_uniform_scramble(self.sample_index, seed as u32);
+		bits_to_float(nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32))
+	}
+
+	fn next_2d(&mut self) -> (f32, f32)
+	{
+		let seed = self.next_seed();
+		let index = nested_uniform_scramble(self.sample_index, seed as u32);
+		let second_seed = hash(seed, 1);
+		(
+			bits_to_float(nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32)),
+			bits_to_float(nested_uniform_scramble(sobol_second_dimension(index), second_seed as u32)),
+		)
+	}
+}
+
+// Size of the blue noise mask, tiled over the image.
+const BLUE_NOISE_SIZE : usize = 64;
+
+lazy_static!
+{
+	static ref BLUE_NOISE_MASK : Vec<f32> = void_and_cluster(BLUE_NOISE_SIZE, 1.5);
+}
+
+// R2's generators (Roberts 2018), odd and in 32 bit fixed point. Multiplied by the bit reversed sample index they
+// give an extensible rank-1 lattice, every power of two prefix of which is a lattice with as many points.
+const LATTICE_GENERATORS : (u32, u32) = (3242174889, 2447445413);
+
+// A rank-1 lattice shared by every pixel, shifted toroidally by a blue noise mask so neighbouring pixels sample
+// far apart, which leaves the error of a frame as high frequency noise. Each dimension looks the mask up at its
+// own offset, and reorders the lattice, so dimensions don't line up.
+pub struct BlueNoiseSampler
+{
+	x : u32,
+	y : u32,
+	sample_index : u32,
+	dimension : u32,
+}
+
+impl BlueNoiseSampler
+{
+	pub fn new() -> Self
+	{
+		Self { x : 0, y : 0, sample_index : 0, dimension : 0 }
+	}
+
+	// The mask at this pixel, offset for the dimension, and an index into the lattice reordered for it.
+	fn next_shift(&mut self, seed : u64) -> (f32, u32)
+	{
+		let size = BLUE_NOISE_SIZE as u32;
+		let offset = hash(self.dimension as u64, seed);
+		let x = (self.x + offset as u32 % size) % size;
+		let y = (self.y + (offset >> 32) as u32 % size) % size;
+		self.dimension += 1;
+		(BLUE_NOISE_MASK[(y * size + x) as usize], (self.sample_index ^ (offset >> 16) as u32).reverse_bits())
+	}
+}
+
+impl Sampler for BlueNoiseSampler
+{
+	fn start_pixel_sample(&mut self, x : u32, y : u32, sample_index : u32)
+	{
+		self.x = x;
+		self.y = y;
+		self.sample_index = sample_index;
+		self.dimension = 0;
+	}
+
+	fn next_1d(&mut self) -> f32
+	{
+		let (shift, index) = self.next_shift(0);
+		fraction(bits_to_float(index) + shift)
+	}
+
+	fn next_2d(&mut self) -> (f32, f32)
+	{
+		let (shift_x, index) = self.next_shift(0);
+		let (shift_y, _) = self.next_shift(1);
+		let (generator_x, generator_y) = LATTICE_GENERATORS;
+		(fraction(bits_to_float(index.wrapping_mul(generator_x)) + shift_x), fraction(bits_to_float(index.wrapping_mul(generator_y)) + shift_y))
+	}
+}
+
+// Ulichney's void and cluster method, the rank in (0, 1) of every cell of a size by size toroidal blue noise
+// mask. Energy is a Gaussian of the given standard deviation in cells around every set cell, and each step sets
+// the emptiest cell or clears the most crowded one.
+fn void_and_cluster(size : usize, sigma : f32) -> Vec<f32>
+{
+	let count = size * size;
+	let kernel : Vec<f32> = (0..count)
+		.map(|cell|
+		{
+			let wrap = |d : usize| d.min(size - d) as f32;
+			let (dx, dy) = (wrap(cell % size), wrap(cell / size));
+			(-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+		})
+		.collect();
+
+	let mut energy = vec![0.0f32; count];
+	let mut set = vec![false; count];
+	let update = |energy : &mut Vec<f32>, cell : usize, sign : f32|
+	{
+		let (cx, cy) = (cell % size, cell / size);
+		for (other, value) in energy.iter_mut().enumerate()
+		{
+			let dx = (other % size + size - cx) % size;
+			let dy = (other / size + size - cy) % size;
+			*value += sign * kernel[dy * size + dx];
+		}
+	};
+	let tightest_cluster = |energy : &[f32], set : &[bool]| (0..count).filter(|&cell| set[cell]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap();
+	let largest_void = |energy : &[f32], set : &[bool]| (0..count).filter(|&cell| !set[cell]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap();
+
+	// a random tenth of the cells, spread out by moving the most crowded one to the emptiest spot until it stays.
+	let mut rng = Rng::new(0xb1e, 0x0153);
+	let initial = count / 10;
+	let mut placed = 0;
+	while placed < initial
+	{
+		let cell = (rng.next_u32() as usize) % count;
+		if !set[cell]
+		{
+			set[cell] = true;
+			update(&mut energy, cell, 1.0);
+			placed += 1;
+		}
+	}
+	loop
+	{
+		let cluster = tightest_cluster(&energy, &set);
+		set[cluster] = false;
+		update(&mut energy, cluster, -1.0);
+		let void = largest_void(&energy, &set);
+		set[void] = true;
+		update(&mut energy, void, 1.0);
+		if void == cluster
+		{
+			break;
+		}
+	}
+
+	// rank the initial cells by taking them out most crowded first, then fill the rest emptiest first.
+	let mut rank = vec![0usize; count];
+	let (mut removed_energy, mut removed_set) = (energy.clone(), set.clone());
+	for position in (0..initial).rev()
+	{
+		let cluster = tightest_cluster(&removed_energy, &removed_set);
+		removed_set[cluster] = false;
+		update(&mut removed_energy, cluster, -1.0);
+		rank[cluster] = position;
+	}
+	for position in initial..count
+	{
+		let void = largest_void(&energy, &set);
+		set[void] = true;
+		update(&mut energy, void, 1.0);
+		rank[void] = position;
+	}
+	rank.into_iter().map(|rank| (rank as f32 + 0.5) / count as f32).collect()
+}
+
+// The largest f32 below 1.
+pub const ONE_MINUS_EPSILON : f32 = 1.0 - f32::EPSILON / 2.0;
+
+fn fraction(value : f32) -> f32
+{
+	let fraction = value - value.floor();
+	fraction.min(ONE_MINUS_EPSILON)
+}
+
+// The top 24 bits as a float in [0, 1).
+fn bits_to_float(bits : u32) -> f32
+{
+	(bits >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+fn unit_float(hash : u64) -> f32
+{
+	bits_to_float((hash >> 32) as u32)
+}
+
+fn pixel_seed(x : u32, y : u32) -> u64
+{
+	hash(x as u64, y as u64)
+}
+
+// SplitMix64's finalizer, a good 64 bit mix.
+fn mix_bits(mut value : u64) -> u64
+{
+	value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+	value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+	value ^ (value >> 31)
+}
+
+fn hash(a : u64, b : u64) -> u64
+{
+	mix_bits(a ^ mix_bits(b.wrapping_add(0x9e3779b97f4a7c15)))
+}
+
+// Element index of a random permutation of 0..count picked by seed, without storing it (Kensler 2013,
+// "Correlated Multi-Jittered Sampling").
+fn permutation_element(mut index : u32, count : u32, seed : u32) -> u32
+{
+	let mut mask = count - 1;
+	mask |= mask >> 1;
+	mask |= mask >> 2;
+	mask |= mask >> 4;
+	mask |= mask >> 8;
+	mask |= mask >> 16;
+	loop
+	{
+		index ^= seed;
+		index = index.wrapping_mul(0xe170893d);
+		index ^= seed >> 16;
+		index ^= (index & mask) >> 4;
+		index ^= seed >> 8;
+		index = index.wrapping_mul(0x0929eb3f);
+		index ^= seed >> 23;
+		index ^= (index & mask) >> 1;
+		index = index.wrapping_mul(1 | seed >> 27);
+		index = index.wrapping_mul(0x6935fa69);
+		index ^= (index & mask) >> 11;
+		index = index.wrapping_mul(0x74dcb303);
+		index ^= (index & mask) >> 2;
+		index = index.wrapping_mul(0x9e501cc3);
+		index ^= (index & mask) >> 2;
+		index = index.wrapping_mul(0xc860a3df);
+		index &= mask;
+		index ^= index >> 5;
+		if index < count
+		{
+			return index.wrapping_add(seed) % count;
+		}
+	}
+}
+
+// Radical inverse of index in base, each digit permuted by a hash of the seed, its position and the digits of
+// index below it. Digits continue past the last nonzero one of index until they fall below f32 precision.
+fn scrambled_radical_inverse(mut index : u32, base : u32, seed : u64) -> f32
+{
+	let inverse_base = 1.0 / base as f64;
+	let (mut result, mut weight) = (0.0f64, inverse_base);
+	let mut prefix = 0u64;
+	let mut position = 0u64;
+	while weight > 1.0e-8
+	{
+		let digit = index % base;
+		index /= base;
+		let permutation = hash(hash(seed, position), prefix) as u32;
+		result += permutation_element(digit, base, permutation) as f64 * weight;
+		prefix = prefix.wrapping_mul(base as u64).wrapping_add(digit as u64 + 1);
+		weight *= inverse_base;
+		position += 1;
+	}
+	(result as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Sobol's second dimension as 32 bits of fraction, its direction numbers following from the polynomial x + 1.
+fn sobol_second_dimension(mut index : u32) -> u32
+{
+	let mut direction = 1u32 << 31;
+	let mut result = 0;
+	while index != 0
+	{
+		if index & 1 != 0
+		{
+			result ^= direction;
+		}
+		index >>= 1;
+		direction ^= direction >> 1;
+	}
+	result
+}
+
+// Owen scrambling of the 32 bit fraction value by Laine and Karras' hash, as improved by Burley.
+fn nested_uniform_scramble(value : u32, seed : u32) -> u32
+{
+	let mut x = value.reverse_bits().wrapping_add(seed);
+	x ^= x.wrapping_mul(0x6c50b47c);
+	x ^= x.wrapping_mul(0xb82f1e52);
+	x ^= x.wrapping_mul(0xc7afe638);
+	x ^= x.wrapping_mul(0x8d22f6e6);
+	x.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// 32 rounds of a 2D, a 1D and the second half of the 2D draw, so the dimensions interleave.
+	fn draws(sampler : &mut dyn Sampler, x : u32, y : u32, sample_index : u32) -> Vec<f32>
+	{
+		sampler.start_pixel_sample(x, y, sample_index);
+		(0..32).flat_map(|_| { let (u, v) = sampler.next_2d(); vec![u, sampler.next_1d(), v] }).collect()
+	}
+
+	// The values of scattered pixel samples don't depend on what the sampler drew before, and stay in [0, 1).
+	fn check_deterministic_and_in_range(kind : SamplerKind)
+	{
+		let (mut first, mut second) = (kind.create(16), kind.create(16));
+		for index in 0..256u32
+		{
+			let (x, y, sample_index) = (index * 37 % 101, index * 11 % 67, index % 40);
+			draws(second.as_mut(), y, x, sample_index + 1);
+			let (a, b) = (draws(first.as_mut(), x, y, sample_index), draws(second.as_mut(), x, y, sample_index));
+			assert_eq!(a, b, "pixel {} {} sample {}", x, y, sample_index);
+			assert!(a.iter().all(|&value| (0.0..1.0).contains(&value)), "pixel {} {} sample {}: {:?}", x, y, sample_index, a);
+		}
+	}
+
+	// Renders take the samples of a pixel in frames of any size, each with its own sampler, and visit the pixels of
+	// a frame in any order. Every pixel, sample index and dimension has to see the same values as one frame with all
+	// of them would.
+	fn check_split_invariance(kind : SamplerKind)
+	{
+		const SAMPLES : u32 = 16;
+		let pixels : Vec<(u32, u32)> = (0..12).map(|index| (index * 5 % 7, index / 3)).collect();
+
+		let mut whole = kind.create(SAMPLES);
+		let expected : Vec<Vec<Vec<f32>>> = pixels.iter()
+			.map(|&(x, y)| (0..SAMPLES).map(|sample_index| draws(whole.as_mut(), x, y, sample_index)).collect())
+			.collect();
+
+		for &frame_size in &[1, 3, 5, 7]
+		{
+			let mut start = 0;
+			while start < SAMPLES
+			{
+				let end = (start + frame_size).min(SAMPLES);
+				let mut sampler = kind.create(SAMPLES);
+				for (pixel, &(x, y)) in pixels.iter().enumerate().rev()
+				{
+					for sample_index in start..end
+					{
+						let values = draws(sampler.as_mut(), x, y, sample_index);
+						assert_eq!(values, expected[pixel][sample_index as usize], "pixel {} {} sample {} in frames of {}", x, y, sample_index, frame_size);
+					}
+				}
+				start = end;
+			}
+		}
+	}
+
+	// Mean of a sampler's estimates of an integral over many pixels, and how far a pixel's estimate is off on average.
+	struct IntegrationError
+	{
+		mean : f64,
+		standard_error : f64,
+		rms_error : f64,
+	}
+
+	type Integrand = fn(f32, f32) -> f64;
+
+	// Estimates the integral of function over the unit square with samples_per_pixel samples in each of pixels
+	// pixels, from the 2D dimension that skip_dimensions 1D dimensions come before.
+	fn integrate(kind : SamplerKind, samples_per_pixel : u32, pixels : u32, skip_dimensions : u32, function : Integrand, expected : f64) -> IntegrationError
+	{
+		let mut sampler = kind.create(samples_per_pixel);
+		let (mut sum, mut sum_squared, mut sum_error) = (0.0, 0.0, 0.0);
+		for pixel in 0..pixels
+		{
+			let mut estimate = 0.0;
+			for sample_index in 0..samples_per_pixel
+			{
+				sampler.start_pixel_sample(pixel % 64, pixel / 64, sample_index);
+				for _ in 0..skip_dimensions
+				{
+					sampler.next_1d();
+				}
+				let (u, v) = sampler.next_2d();
+				estimate += function(u, v);
+			}
+			estimate /= samples_per_pixel as f64;
+			sum += estimate;
+			sum_squared += estimate * estimate;
+			sum_error += (estimate - expected) * (estimate - expected);
+		}
+		let count = pixels as f64;
+		let mean = sum / count;
+		let variance = (sum_squared / count - mean * mean).max(0.0);
+		IntegrationError { mean : mean, standard_error : (variance / count).sqrt(), rms_error : (sum_error / count).sqrt() }
+	}
+
+	// Integrates a smooth function and a discontinuous one without bias, and the low discrepancy samplers do so
+	// with less error than independent random numbers, also for 2D dimensions after many 1D ones.
+	fn check_integration(kind : SamplerKind)
+	{
+		// exp(-x^2 - y^2) is the square of a Gaussian integral, the disk is a quarter of the unit disk.
+		let gaussian_integral = 0.746_824_132_812_427_f64;
+		let functions : [(Integrand, f64); 2] =
+		[
+			(|u, v| (-(u * u + v * v) as f64).exp(), gaussian_integral * gaussian_integral),
+			(|u, v| if u * u + v * v < 1.0 { 1.0 } else { 0.0 }, std::f64::consts::FRAC_PI_4),
+		];
+		let (samples_per_pixel, pixels) = (64, 1024);
+		for (function_index, &(function, expected)) in functions.iter().enumerate()
+		{
+			for &skip in &[0, 21]
+			{
+				let result = integrate(kind, samples_per_pixel, pixels, skip, function, expected);
+				assert!((result.mean - expected).abs() <= 4.0 * result.standard_error + 1.0e-4,
+					"function {} skip {}: mean {} (+- {}), expected {}", function_index, skip, result.mean, result.standard_error, expected);
+				if kind != SamplerKind::Independent
+				{
+					let reference = integrate(SamplerKind::Independent, samples_per_pixel, pixels, skip, function, expected);
+					assert!(result.rms_error < reference.rms_error,
+						"function {} skip {}: rms error {}, random gets {}", function_index, skip, result.rms_error, reference.rms_error);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn independent_sampler_is_deterministic_and_in_range()
+	{
+		check_deterministic_and_in_range(SamplerKind::Independent);
+	}
+
+	#[test]
+	fn stratified_sampler_is_deterministic_and_in_range()
+	{
+		check_deterministic_and_in_range(SamplerKind::Stratified);
+	}
+
+	#[test]
+	fn halton_sampler_is_deterministic_and_in_range()
+	{
+		check_deterministic_and_in_range(SamplerKind::Halton);
+	}
+
+	#[test]
+	fn sobol_sampler_is_deterministic_and_in_range()
+	{
+		check_deterministic_and_in_range(SamplerKind::Sobol);
+	}
+
+	#[test]
+	fn blue_noise_sampler_is_deterministic_and_in_range()
+	{
+		check_deterministic_and_in_range(SamplerKind::BlueNoise);
+	}
+
+	#[test]
+	fn independent_sampler_is_split_invariant()
+	{
+		check_split_invariance(SamplerKind::Independent);
+	}
+
+	#[test]
+	fn stratified_sampler_is_split_invariant()
+	{
+		check_split_invariance(SamplerKind::Stratified);
+	}
+
+	#[test]
+	fn halton_sampler_is_split_invariant()
+	{
+		check_split_invariance(SamplerKind::Halton);
+	}
+
+	#[test]
+	fn sobol_sampler_is_split_invariant()
+	{
+		check_split_invariance(SamplerKind::Sobol);
+	}
+
+	#[test]
+	fn blue_noise_sampler_is_split_invariant()
+	{
+		check_split_invariance(SamplerKind::BlueNoise);
+	}
+
+	#[test]
+	fn independent_sampler_integrates_without_bias()
+	{
+		check_integration(SamplerKind::Independent);
+	}
+
+	#[test]
+	fn stratified_sampler_integrates_better_than_random()
+	{
+		check_integration(SamplerKind::Stratified);
+	}
+
+	#[test]
+	fn halton_sampler_integrates_better_than_random()
+	{
+		check_integration(SamplerKind::Halton);
+	}
+
+	#[test]
+	fn sobol_sampler_integrates_better_than_random()
+	{
+		check_integration(SamplerKind::Sobol);
+	}
+
+	#[test]
+	fn blue_noise_sampler_integrates_better_than_random()
+	{
+		check_integration(SamplerKind::BlueNoise);
+	}
+
+	#[test]
+	fn blue_noise_mask_neighbours_differ_more_than_white_noise()
+	{
+		let size = BLUE_NOISE_SIZE;
+		let mut difference = 0.0;
+		for y in 0..size
+		{
+			for x in 0..size
+			{
+				let value = BLUE_NOISE_MASK[y * size + x];
+				difference += (value - BLUE_NOISE_MASK[y * size + (x + 1) % size]).abs() + (value - BLUE_NOISE_MASK[(y + 1) % size * size + x]).abs();
+			}
+		}
+		// neighbours of uniform white noise differ by a third on average.
+		let difference = difference / (2 * size * size) as f32;
+		assert!(difference > 0.4, "mean difference between neighbours {}", difference);
+	}
+}
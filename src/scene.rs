@@ -0,0 +1,340 @@
+use std::sync::Arc;
+
+use cgmath::{ Deg, Rad, Vector3, Vector4 };
+use cgmath::InnerSpace;
+
+use crate::area_lights::{ AreaLights, LightSample };
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::environment::EnvironmentMap;
+use crate::geometry::Aabb;
+use crate::lights::{ Light, Lights };
+use crate::material::Surface;
+use crate::mesh::Mesh;
+use crate::render_settings::G_BACKGROUND;
+use crate::ray::{ Ray, HitRecord, Hittable };
+use crate::sampler::ONE_MINUS_EPSILON;
+use crate::scene_description::{ MaterialDescription, Texture };
+use crate::tone_mapping::srgb_to_linear;
+
+pub struct Scene
+{
+	pub objects : Vec<Box<dyn Hittable>>,
+	pub background : Vector4<f32>, // also the radiance arriving from every direction that misses, for the path tracer
+	pub environment : Option<Arc<EnvironmentMap>>, // replaces the background when there is one
+	pub materials : Vec<MaterialDescription>,
+	pub textures : Vec<Texture>,
+	pub area_lights : AreaLights,
+	pub lights : Lights, // not geometry, integrators that show rectangles and spheres look for them themselves
+}
+
+impl Scene
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			objects : Vec::new(),
+			background : Vector4::from(G_BACKGROUND),
+			environment : None,
+			materials : Vec::new(),
+			textures : Vec::new(),
+			area_lights : AreaLights::new(),
+			lights : Lights::new(),
+		}
+	}
+
+	pub fn add(&mut self, object : Box<dyn Hittable>)
+	{
+		self.objects.push(object);
+	}
+
+	// One BVH per mesh, the same meshes the dx_renderer rasterizes. Emissive triangles also become area lights.
+	pub fn from_meshes(meshes : &[Arc<Mesh>], materials : &[MaterialDescription], textures : &[Texture], lights : &[Light]) -> Self
+	{
+		let mut scene = Self::new();
+		for mesh in meshes
+		{
+			scene.add(Box::new(Bvh::build(Mesh::triangles(mesh))));
+		}
+		scene.materials = materials.to_vec();
+		scene.textures = textures.to_vec();
+		scene.area_lights = AreaLights::from_meshes(meshes, materials);
+
+		let bounds = meshes.iter().fold(Aabb::empty(), |bounds, mesh| bounds.union(&mesh.bounds()));
+		let radius = if bounds.is_empty() { 1.0 } else { (bounds.extent().magnitude() * 0.5).max(1.0e-3) };
+		scene.lights = Lights::from_lights(lights, radius);
+		scene
+	}
+
+	// Radiance arriving along direction from beyond the geometry.
+	pub fn background_radiance(&self, direction : Vector3<f32>) -> Vector3<f32>
+	{
+		match &self.environment
+		{
+			Some(environment) => environment.radiance(direction),
+			None => self.background.truncate(),
+		}
+	}
+
+	// The mean of that over every direction.
+	pub fn ambient_radiance(&self) -> Vector3<f32>
+	{
+		match &self.environment
+		{
+			Some(environment) => environment.average(),
+			None => self.background.truncate(),
+		}
+	}
+
+	// Whether sample_light ever picks the environment. A plain background color is never sampled.
+	pub fn samples_environment(&self) -> bool
+	{
+		self.environment.as_ref().is_some_and(|environment| !environment.is_black())
+	}
+
+	pub fn has_lights(&self) -> bool
+	{
+		self.light_group_count() > 0
+	}
+
+	// Picks the environment, the emissive triangles or the other lights, evenly between those the scene has, then a
+	// light among them and a point on it, with three uniform numbers in [0, 1). The pdf includes every pick.
+	pub fn sample_light(&self, point : Vector3<f32>, u_select : f32, u1 : f32, u2 : f32) -> Option<LightSample>
+	{
+		let count = self.light_group_count();
+		if count == 0
+		{
+			return None;
+		}
+		let scaled = u_select * count as f32;
+		let mut group = (scaled as usize).min(count - 1);
+		let u_select = (scaled - group as f32).clamp(0.0, ONE_MINUS_EPSILON);
+
+		let mut light = None;
+		if self.samples_environment()
+		{
+			if group == 0
+			{
+				light = self.environment.as_ref().and_then(|environment| environment.sample(u1, u2));
+			}
+			group = group.wrapping_sub(1);
+		}
+		if !self.area_lights.is_empty()
+		{
+			if group == 0
+			{
+				light = self.area_lights.sample(point, u_select, u1, u2);
+			}
+			group = group.wrapping_sub(1);
+		}
+		if group == 0
+		{
+			light = self.lights.sample(point, u_select, u1, u2);
+		}
+
+		let mut light = light?;
+		light.pdf /= count as f32;
+		Some(light)
+	}
+
+	// The densities, per unit solid angle, with which sample_light would have picked an emissive triangle a ray hit,
+	// see AreaLights::pdf, the environment in direction, or a direction from point toward lights[index].
+	pub fn area_light_pdf(&self, emission : Vector3<f32>, distance : f32, cosine : f32) -> f32
+	{
+		self.area_lights.pdf(emission, distance, cosine) / self.light_group_count().max(1) as f32
+	}
+
+	pub fn environment_pdf(&self, direction : Vector3<f32>) -> f32
+	{
+		match &self.environment
+		{
+			Some(environment) if self.samples_environment() => environment.pdf(direction) / self.light_group_count() as f32,
+			_ => 0.0,
+		}
+	}
+
+	pub fn light_pdf(&self, index : usize, point : Vector3<f32>, direction : Vector3<f32>) -> f32
+	{
+		self.lights.pdf(index, point, direction) / self.light_group_count().max(1) as f32
+	}
+
+	fn light_group_count(&self) -> usize
+	{
+		self.samples_environment() as usize + !self.area_lights.is_empty() as usize + !self.lights.is_empty() as usize
+	}
+
+	pub fn material(&self, hit : &HitRecord) -> Option<&MaterialDescription>
+	{
+		hit.material.and_then(|material| self.materials.get(material))
+	}
+
+	// The material at the hit point. The base color texture is sRGB encoded and scales the hit color, the
+	// metallic-roughness texture scales roughness by its green and metallic by its blue channel.
+	pub fn surface(&self, hit : &HitRecord) -> Surface<'_>
+	{
+		let material = self.material(hit);
+		let texture = |index : Option<usize>| index.and_then(|index| self.textures.get(index)).map(|texture| texture.sample(hit.uv));
+		let mut surface = Surface
+		{
+			material : material,
+			color : hit.color,
+			metallic : material.map_or(0.0, |material| material.metallic),
+			roughness : material.map_or(1.0, |material| material.roughness),
+		};
+
+		if let Some(material) = material
+		{
+			if let Some(texel) = texture(material.base_color_texture)
+			{
+				let color = &mut surface.color;
+				*color = Vector4::new(color.x * srgb_to_linear(texel.x), color.y * srgb_to_linear(texel.y), color.z * srgb_to_linear(texel.z), color.w * texel.w);
+			}
+			if let Some(texel) = texture(material.metallic_roughness_texture)
+			{
+				surface.roughness *= texel.y;
+				surface.metallic *= texel.z;
+			}
+		}
+		surface
+	}
+
+	// Radiance leaving the hit point back along the ray. Lights only emit from their front side unless double sided.
+	pub fn emission(&self, hit : &HitRecord) -> Vector3<f32>
+	{
+		match self.material(hit)
+		{
+			Some(material) if hit.front_face || material.double_sided => material.emission,
+			_ => Vector3::new(0.0, 0.0, 0.0),
+		}
+	}
+}
+
+impl Hittable for Scene
+{
+	fn hit(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<HitRecord>
+	{
+		let mut closest = None;
+		let mut closest_t = t_max;
+
+		for (index, object) in self.objects.iter().enumerate()
+		{
+			if let Some(mut hit) = object.hit(ray, t_min, closest_t)
+			{
+				closest_t = hit.t;
+				hit.object_id = index as u32 + 1;
+				closest = Some(hit);
+			}
+		}
+
+		closest
+	}
+
+	fn occluded(&self, ray : &Ray, t_min : f32, t_max : f32) -> bool
+	{
+		self.objects.iter().any(|object| object.occluded(ray, t_min, t_max)) || self.lights.occluded(ray, t_min, t_max)
+	}
+}
+
+// Looks down -z at the middle of bounds, far enough back for all of it to fit in the vertical field of view.
+pub fn framing_camera(bounds : &Aabb, aspect_ratio : f32) -> Camera
+{
+	let vertical_fov = Deg(45.0);
+	let center = bounds.centroid();
+	let radius = (bounds.extent().magnitude() * 0.5).max(1.0e-3);
+
+	// the bounding sphere fits when it fits the narrower of the vertical and horizontal field of view.
+	let half_vertical : Rad<f32> = (vertical_fov * 0.5).into();
+	let half_horizontal = (aspect_ratio * half_vertical.0.tan()).atan();
+	let distance = radius / half_vertical.0.min(half_horizontal).sin();
+
+	Camera::look_at(center + Vector3::new(0.0, 0.0, distance), center, vertical_fov, aspect_ratio)
+}
+
+// Frames the sample triangle scene.
+pub fn sample_triangle_camera(aspect_ratio : f32) -> Camera
+{
+	Camera::look_at(
+		Vector3::new(0.0, 0.0, 2.0),
+		Vector3::new(0.0, 0.0, 0.0),
+		Deg(30.0),
+		aspect_ratio)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use cgmath::Vector2;
+
+	use super::*;
+	use crate::geometry::SurfaceHit;
+	use crate::scene_description::WrapMode;
+
+	fn textured_scene() -> Scene
+	{
+		// the base color texture is dark on the left and white on the right, the metallic-roughness one is uniform.
+		let base_color = Texture
+		{
+			width : 2,
+			height : 1,
+			pixels : vec![Vector4::new(0.5, 0.5, 0.5, 1.0), Vector4::new(1.0, 1.0, 1.0, 0.5)],
+			wrap_u : WrapMode::ClampToEdge,
+			wrap_v : WrapMode::ClampToEdge,
+		};
+		let metallic_roughness = Texture
+		{
+			width : 1,
+			height : 1,
+			pixels : vec![Vector4::new(0.0, 0.5, 0.25, 1.0)],
+			wrap_u : WrapMode::Repeat,
+			wrap_v : WrapMode::Repeat,
+		};
+
+		let mut scene = Scene::new();
+		scene.textures = vec![base_color, metallic_roughness];
+		scene.materials.push(MaterialDescription
+		{
+			base_color_texture : Some(0),
+			metallic_roughness_texture : Some(1),
+			metallic : 1.0,
+			roughness : 0.8,
+			..MaterialDescription::default()
+		});
+		scene
+	}
+
+	fn hit_at(uv : Vector2<f32>, material : Option<usize>) -> HitRecord
+	{
+		let ray = Ray::new(Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+		let surface_hit = SurfaceHit { t : 1.0, normal : Vector3::new(0.0, 0.0, 1.0), front_face : true, uv : uv };
+		let mut hit = HitRecord::new(&ray, &surface_hit, Vector4::new(1.0, 0.5, 0.25, 1.0));
+		hit.material = material;
+		hit
+	}
+
+	#[test]
+	fn surface_applies_the_material_textures_at_the_hit_uv()
+	{
+		let scene = textured_scene();
+
+		let left = scene.surface(&hit_at(Vector2::new(0.25, 0.5), Some(0)));
+		let dark = srgb_to_linear(0.5);
+		assert!((left.color - Vector4::new(dark, 0.5 * dark, 0.25 * dark, 1.0)).magnitude() < 1.0e-6, "{:?}", left.color);
+		assert!((left.metallic - 0.25).abs() < 1.0e-6);
+		assert!((left.roughness - 0.4).abs() < 1.0e-6);
+
+		// alpha is not sRGB encoded.
+		let right = scene.surface(&hit_at(Vector2::new(0.75, 0.5), Some(0)));
+		assert_eq!(right.color, Vector4::new(1.0, 0.5, 0.25, 0.5));
+	}
+
+	#[test]
+	fn surface_without_a_material_is_the_hit_color()
+	{
+		let scene = textured_scene();
+		let surface = scene.surface(&hit_at(Vector2::new(0.25, 0.5), None));
+		assert!(surface.material.is_none());
+		assert_eq!(surface.color, Vector4::new(1.0, 0.5, 0.25, 1.0));
+		assert_eq!(surface.metallic, 0.0);
+	}
+}
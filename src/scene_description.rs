@@ -0,0 +1,464 @@
+use cgmath::InnerSpace;
+use cgmath::{ Matrix, Matrix4, SquareMatrix, Rad, Deg };
+use cgmath::{ Vector2, Vector3, Vector4 };
+
+use crate::camera::{ Camera, ProjectionModel };
+use crate::lights::Light;
+use crate::material::dielectric_reflectance;
+use crate::mesh::Mesh;
+use crate::obj_loader::{ MtlPbr, ObjScene };
+
+// What a loaded file describes, before it is turned into anything a backend renders.
+// Meshes are in their own object space, nodes place them in the world.
+#[derive(Clone, Debug, Default)]
+pub struct SceneDescription
+{
+	pub nodes : Vec<Node>,
+	pub roots : Vec<usize>, // nodes without a parent that belong to the scene
+	pub meshes : Vec<Mesh>,
+	pub materials : Vec<MaterialDescription>,
+	pub textures : Vec<Texture>,
+	pub cameras : Vec<CameraDescription>,
+	pub lights : Vec<LightDescription>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Node
+{
+	pub name : String,
+	pub parent : Option<usize>,
+	pub children : Vec<usize>,
+	pub local_transform : Matrix4<f32>,
+	pub world_transform : Matrix4<f32>,
+	pub mesh : Option<usize>,
+	pub camera : Option<usize>,
+	pub light : Option<usize>,
+}
+
+impl Node
+{
+	pub fn new(name : &str) -> Self
+	{
+		Self
+		{
+			name : name.to_string(),
+			parent : None,
+			children : Vec::new(),
+			local_transform : Matrix4::identity(),
+			world_transform : Matrix4::identity(),
+			mesh : None,
+			camera : None,
+			light : None,
+		}
+	}
+
+	pub fn world_position(&self) -> Vector3<f32>
+	{
+		self.world_transform.w.truncate()
+	}
+
+	// Cameras, spot lights and directional lights point down their node's -z axis.
+	pub fn world_forward(&self) -> Vector3<f32>
+	{
+		(-self.world_transform.z.truncate()).normalize()
+	}
+}
+
+// glTF's metallic-roughness model. OBJ materials are converted to it when loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialDescription
+{
+	pub name : String,
+	pub base_color : Vector4<f32>,                  // linear, alpha is opacity
+	pub base_color_texture : Option<usize>,         // sRGB encoded
+	pub metallic : f32,
+	pub roughness : f32,                            // perceptual, squared to get GGX alpha
+	pub metallic_roughness_texture : Option<usize>, // roughness in green, metallic in blue
+	pub specular : f32,                             // scales the reflection of the non-metallic part, 0 leaves it diffuse only
+	pub emission : Vector3<f32>,                    // linear radiance, already scaled by any emissive strength
+	pub index_of_refraction : f32,
+	pub transmission : f32,                         // 1 is fully transmissive, like glass
+	pub double_sided : bool,
+	pub principled : Option<PrincipledParameters>,  // renders with the Disney principled BSDF when present
+}
+
+impl Default for MaterialDescription
+{
+	// The glTF default material.
+	fn default() -> Self
+	{
+		Self
+		{
+			name : String::new(),
+			base_color : Vector4::new(1.0, 1.0, 1.0, 1.0),
+			base_color_texture : None,
+			metallic : 1.0,
+			roughness : 1.0,
+			metallic_roughness_texture : None,
+			specular : 1.0,
+			emission : Vector3::new(0.0, 0.0, 0.0),
+			index_of_refraction : 1.5,
+			transmission : 0.0,
+			double_sided : false,
+			principled : None,
+		}
+	}
+}
+
+// The inputs of Blender's Principled BSDF that metallic-roughness has no equivalent for. Base color, metallic,
+// roughness, specular, IOR, transmission and emission are the material's own.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PrincipledParameters
+{
+	pub subsurface : f32,
+	pub specular_tint : f32,
+	pub anisotropic : f32,
+	pub anisotropic_rotation : f32, // in turns, 1 is all the way around
+	pub sheen : f32,
+	pub sheen_tint : f32,
+	pub clearcoat : f32,
+	pub clearcoat_roughness : f32,
+}
+
+impl Default for PrincipledParameters
+{
+	// Blender's defaults.
+	fn default() -> Self
+	{
+		Self
+		{
+			subsurface : 0.0,
+			specular_tint : 0.0,
+			anisotropic : 0.0,
+			anisotropic_rotation : 0.0,
+			sheen : 0.0,
+			sheen_tint : 0.5,
+			clearcoat : 0.0,
+			clearcoat_roughness : 0.03,
+		}
+	}
+}
+
+// How texture coordinates outside of [0, 1] are treated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WrapMode
+{
+	Repeat,
+	MirroredRepeat,
+	ClampToEdge,
+}
+
+// Decoded image and how to sample it. Pixels are RGBA in [0, 1] as stored in the file, row 0 at the top.
+// Whether they are sRGB encoded depends on which material slot uses the texture.
+#[derive(Clone, Debug)]
+pub struct Texture
+{
+	pub width : u32,
+	pub height : u32,
+	pub pixels : Vec<Vector4<f32>>,
+	pub wrap_u : WrapMode,
+	pub wrap_v : WrapMode,
+}
+
+impl Texture
+{
+	// Bilinear filtering between the four nearest pixels, with uv (0, 0) at the top left corner of the image.
+	pub fn sample(&self, uv : Vector2<f32>) -> Vector4<f32>
+	{
+		if self.pixels.is_empty()
+		{
+			return Vector4::new(1.0, 1.0, 1.0, 1.0);
+		}
+
+		let x = uv.x * self.width as f32 - 0.5;
+		let y = uv.y * self.height as f32 - 0.5;
+		let (left, top) = (x.floor(), y.floor());
+		let (fx, fy) = (x - left, y - top);
+		let pixel = |column : f32, row : f32| -> Vector4<f32>
+		{
+			let column = wrap(self.wrap_u, column as i64, self.width);
+			let row = wrap(self.wrap_v, row as i64, self.height);
+			self.pixels[(row * self.width + column) as usize]
+		};
+
+		let upper = pixel(left, top) * (1.0 - fx) + pixel(left + 1.0, top) * fx;
+		let lower = pixel(left, top + 1.0) * (1.0 - fx) + pixel(left + 1.0, top + 1.0) * fx;
+		upper * (1.0 - fy) + lower * fy
+	}
+}
+
+// Maps a pixel index that may lie outside the image onto one inside it.
+fn wrap(mode : WrapMode, index : i64, size : u32) -> u32
+{
+	let size = size as i64;
+	let wrapped = match mode
+	{
+		WrapMode::Repeat => index.rem_euclid(size),
+		WrapMode::MirroredRepeat =>
+		{
+			let index = index.rem_euclid(2 * size);
+			if index < size { index } else { 2 * size - 1 - index }
+		}
+		WrapMode::ClampToEdge => index.clamp(0, size - 1),
+	};
+	wrapped as u32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraDescription
+{
+	pub projection : Projection,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection
+{
+	Perspective { vertical_fov : Rad<f32>, near : f32, far : Option<f32> }, // no far plane is an infinite projection
+	Orthographic { half_height : f32, near : f32, far : f32 },
+}
+
+// KHR_lights_punctual lights, and the area lights it doesn't have. Intensity is in candela for point and spot
+// lights, lux for directional lights and nits for rectangles and spheres.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightDescription
+{
+	pub kind : LightKind,
+	pub color : Vector3<f32>,
+	pub intensity : f32,
+	pub range : Option<f32>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind
+{
+	Directional { angular_diameter : Rad<f32> }, // zero for a sharp light
+	Point,
+	Spot { inner_cone_angle : Rad<f32>, outer_cone_angle : Rad<f32> },
+	Rectangle { width : f32, height : f32 },      // in the node's xy plane, emitting toward -z
+	Sphere { radius : f32 },
+}
+
+impl SceneDescription
+{
+	// A scene of one untransformed mesh per node.
+	pub fn from_meshes(meshes : Vec<Mesh>) -> Self
+	{
+		let mut scene = Self::default();
+		for (index, mesh) in meshes.into_iter().enumerate()
+		{
+			let mut node = Node::new(&mesh.name);
+			node.mesh = Some(index);
+			scene.nodes.push(node);
+			scene.roots.push(index);
+			scene.meshes.push(mesh);
+		}
+		scene
+	}
+
+	// OBJ has no hierarchy, cameras or lights. Its Phong style materials map onto dielectric metallic-roughness
+	// ones, with the specular exponent converted to the roughness that gives a similar highlight, and no
+	// specular reflection when Ks is black. Materials using the PBR extension become principled ones.
+	// Texture maps are not decoded, and dissolve is kept as the base color's alpha.
+	pub fn from_obj(obj_scene : ObjScene) -> Self
+	{
+		let mut scene = Self::from_meshes(obj_scene.meshes);
+		scene.materials = obj_scene.materials.iter()
+			.map(|material|
+			{
+				let description = MaterialDescription
+				{
+					name : material.name.clone(),
+					base_color : material.diffuse.extend(material.dissolve),
+					metallic : 0.0,
+					roughness : (2.0 / (material.shininess + 2.0)).sqrt().sqrt(),
+					specular : material.specular.x.max(material.specular.y).max(material.specular.z).min(1.0),
+					emission : material.emission,
+					index_of_refraction : material.index_of_refraction,
+					..MaterialDescription::default()
+				};
+				match &material.pbr
+				{
+					Some(pbr) => principled_from_mtl(description, pbr, material.specular.x),
+					None => description,
+				}
+			})
+			.collect();
+		scene
+	}
+
+	// Computes world_transform from local_transform for every node under the roots.
+	pub fn update_world_transforms(&mut self)
+	{
+		let mut stack : Vec<(usize, Matrix4<f32>)> = self.roots.iter().map(|&root| (root, Matrix4::identity())).collect();
+		while let Some((index, parent_transform)) = stack.pop()
+		{
+			let node = &mut self.nodes[index];
+			node.world_transform = parent_transform * node.local_transform;
+			let world_transform = node.world_transform;
+			stack.extend(node.children.iter().map(|&child| (child, world_transform)));
+		}
+	}
+
+	// Every node under the roots, parents before their children.
+	fn scene_nodes(&self) -> Vec<usize>
+	{
+		let mut nodes = Vec::new();
+		let mut stack : Vec<usize> = self.roots.iter().rev().copied().collect();
+		while let Some(index) = stack.pop()
+		{
+			nodes.push(index);
+			stack.extend(self.nodes[index].children.iter().rev());
+		}
+		nodes
+	}
+
+	// Copies of the meshes moved into world space, one per node that references a mesh.
+	pub fn instanced_meshes(&self) -> Vec<Mesh>
+	{
+		self.scene_nodes().into_iter()
+			.filter_map(|index|
+			{
+				let node = &self.nodes[index];
+				node.mesh.map(|mesh| transform_mesh(&self.meshes[mesh], &node.world_transform, &node.name))
+			})
+			.collect()
+	}
+
+	// Every light in world space, one per node that references a light.
+	pub fn instanced_lights(&self) -> Vec<Light>
+	{
+		self.scene_nodes().into_iter()
+			.filter_map(|index|
+			{
+				let node = &self.nodes[index];
+				node.light.map(|light| Light::new(&self.lights[light], &node.world_transform))
+			})
+			.collect()
+	}
+
+	// The first camera in the scene, with the given aspect ratio since the window decides that, and an orthographic
+	// camera's height and clip planes. Camera has no roll, so a camera rolled around its view direction comes out level.
+	// An infinite perspective projection keeps the camera's default far plane.
+	pub fn camera(&self, aspect_ratio : f32) -> Option<Camera>
+	{
+		self.scene_nodes().into_iter()
+			.map(|index| &self.nodes[index])
+			.find_map(|node| node.camera.map(|camera| (node, &self.cameras[camera])))
+			.map(|(node, camera)|
+			{
+				let (vertical_fov, model, near, far) = match camera.projection
+				{
+					Projection::Perspective { vertical_fov, near, far } => (vertical_fov.into(), ProjectionModel::Pinhole, near, far),
+					Projection::Orthographic { half_height, near, far } =>
+						(Deg(45.0), ProjectionModel::Orthographic { half_height : half_height }, near, Some(far)),
+				};
+				let position = node.world_position();
+				let mut camera = Camera::look_at(position, position + node.world_forward(), vertical_fov, aspect_ratio);
+				camera.set_model(model);
+				camera.set_clip_planes(near, far.unwrap_or_else(|| camera.far_plane()));
+				camera
+			})
+	}
+}
+
+// Blender writes its specular input to Ks, where 0.5 means a reflectance of 0.04 at normal incidence.
+fn principled_from_mtl(material : MaterialDescription, pbr : &MtlPbr, blender_specular : f32) -> MaterialDescription
+{
+	// Blender's default index of refraction, for files without Ni.
+	let index_of_refraction = if material.index_of_refraction > 1.0 { material.index_of_refraction } else { 1.45 };
+	MaterialDescription
+	{
+		metallic : pbr.metallic,
+		roughness : pbr.roughness,
+		specular : blender_specular * 0.08 / dielectric_reflectance(index_of_refraction),
+		index_of_refraction : index_of_refraction,
+		principled : Some(PrincipledParameters
+		{
+			anisotropic : pbr.anisotropy,
+			anisotropic_rotation : pbr.anisotropy_rotation,
+			sheen : pbr.sheen,
+			clearcoat : pbr.clearcoat,
+			clearcoat_roughness : pbr.clearcoat_roughness,
+			..PrincipledParameters::default()
+		}),
+		..material
+	}
+}
+
+fn transform_mesh(mesh : &Mesh, transform : &Matrix4<f32>, name : &str) -> Mesh
+{
+	// normals go through the inverse transpose, so they stay perpendicular under non uniform scale.
+	let normal_transform = transform.invert().unwrap_or_else(Matrix4::identity).transpose();
+
+	let mut instance = mesh.clone();
+	instance.name = if name.is_empty() { mesh.name.clone() } else { name.to_string() };
+	for vertex in &mut instance.vertices
+	{
+		vertex.position = (transform * vertex.position.extend(1.0)).truncate();
+		let normal = (normal_transform * vertex.normal.extend(0.0)).truncate();
+		vertex.normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { vertex.normal };
+	}
+
+	// a mirroring transform turns counter clockwise triangles clockwise, swap two corners to keep them facing out.
+	if transform.determinant() < 0.0
+	{
+		for triangle in instance.indices.chunks_mut(3)
+		{
+			triangle.swap(1, 2);
+		}
+	}
+
+	instance
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn grey(value : f32) -> Vector4<f32>
+	{
+		Vector4::new(value, value, value, 1.0)
+	}
+
+	// 2 by 2 pixels, 0 and 1 on the top row, 2 and 3 below.
+	fn ramp(wrap_mode : WrapMode) -> Texture
+	{
+		Texture { width : 2, height : 2, pixels : vec![grey(0.0), grey(1.0), grey(2.0), grey(3.0)], wrap_u : wrap_mode, wrap_v : wrap_mode }
+	}
+
+	#[test]
+	fn pixel_centers_sample_the_pixel()
+	{
+		let texture = ramp(WrapMode::ClampToEdge);
+		assert_eq!(texture.sample(Vector2::new(0.25, 0.25)), grey(0.0));
+		assert_eq!(texture.sample(Vector2::new(0.75, 0.25)), grey(1.0));
+		assert_eq!(texture.sample(Vector2::new(0.25, 0.75)), grey(2.0));
+		assert_eq!(texture.sample(Vector2::new(0.75, 0.75)), grey(3.0));
+	}
+
+	#[test]
+	fn between_pixel_centers_is_bilinear()
+	{
+		let texture = ramp(WrapMode::ClampToEdge);
+		assert_eq!(texture.sample(Vector2::new(0.5, 0.25)), grey(0.5));
+		assert_eq!(texture.sample(Vector2::new(0.25, 0.5)), grey(1.0));
+		assert_eq!(texture.sample(Vector2::new(0.5, 0.5)), grey(1.5));
+	}
+
+	#[test]
+	fn wrap_modes_extend_the_image()
+	{
+		// half a pixel left of the first column's center lies between the last column and the first.
+		let uv = Vector2::new(0.0, 0.25);
+		assert_eq!(ramp(WrapMode::ClampToEdge).sample(uv), grey(0.0));
+		assert_eq!(ramp(WrapMode::Repeat).sample(uv), grey(0.5));
+		assert_eq!(ramp(WrapMode::MirroredRepeat).sample(uv), grey(0.0));
+
+		assert_eq!(ramp(WrapMode::Repeat).sample(Vector2::new(-0.75, 1.25)), grey(0.0));
+		assert_eq!(ramp(WrapMode::MirroredRepeat).sample(Vector2::new(-0.25, 0.25)), grey(0.0));
+		assert_eq!(ramp(WrapMode::MirroredRepeat).sample(Vector2::new(-0.75, 0.25)), grey(1.0));
+		assert_eq!(ramp(WrapMode::ClampToEdge).sample(Vector2::new(5.0, -5.0)), grey(1.0));
+	}
+}
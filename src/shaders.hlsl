@@ -1,3 +1,46 @@
+cbuffer CameraConstants : register(b0)
+{
+    float4x4 view_projection;
+};
+
+// The display transform of tone_mapping.rs, as the render target is UNORM and stores display encoded color.
+cbuffer DisplayConstants : register(b1)
+{
+    float4 color_rows[3]; // white balance with the exposure folded in, w unused
+    uint tone_curve;      // 0 none, 1 reinhard, 2 hable, 3 aces, 4 agx
+    uint encoding;        // 0 sRGB, 1 a pure power of 1 / gamma
+    float gamma;
+    float padding;
+};
+
+// Row major, the same matrices as tone_mapping.rs.
+static const float3x3 ACES_INPUT =
+{
+    0.59719, 0.35458, 0.04823,
+    0.07600, 0.90834, 0.01566,
+    0.02840, 0.13383, 0.83777,
+};
+static const float3x3 ACES_OUTPUT =
+{
+    1.60475, -0.53108, -0.07367,
+    -0.10208, 1.10813, -0.00605,
+    -0.00327, -0.07276, 1.07602,
+};
+static const float3x3 AGX_INSET =
+{
+    0.84247905, 0.0784336, 0.079223745,
+    0.042328242, 0.87846863, 0.07916613,
+    0.042375654, 0.0784336, 0.879143,
+};
+static const float3x3 AGX_OUTSET =
+{
+    1.196879, -0.09802088, -0.09902974,
+    -0.052896854, 1.1519032, -0.098961174,
+    -0.052971635, -0.09804345, 1.1510737,
+};
+static const float AGX_MIN_EV = -12.47393;
+static const float AGX_MAX_EV = 4.026069;
+
 struct PSInput
 {
     float4 position : SV_POSITION;
@@ -8,13 +51,64 @@ PSInput VSMain(float4 position : POSITION, float4 color : COLOR)
 {
     PSInput result;
 
-    result.position = position;
+    result.position = mul(view_projection, float4(position.xyz, 1.0));
     result.color = color;
 
     return result;
 }
 
+float3 HablePartial(float3 x)
+{
+    const float a = 0.15, b = 0.50, c = 0.10, d = 0.20, e = 0.02, f = 0.30;
+    return (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f;
+}
+
+float3 AcesFitted(float3 x)
+{
+    return (x * (x + 0.0245786) - 0.000090537) / (x * (0.983729 * x + 0.432951) + 0.238081);
+}
+
+float3 AgxContrast(float3 x)
+{
+    float3 x2 = x * x;
+    float3 x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+float3 ApplyCurve(float3 color)
+{
+    switch (tone_curve)
+    {
+    case 1:
+        color = max(color, 0.0);
+        return color / (1.0 + color);
+    case 2:
+        return min(HablePartial(2.0 * max(color, 0.0)) / HablePartial(float3(11.2, 11.2, 11.2)), 1.0);
+    case 3:
+        return saturate(mul(ACES_OUTPUT, AcesFitted(mul(ACES_INPUT, color))));
+    case 4:
+    {
+        float3 encoded = (clamp(log2(max(mul(AGX_INSET, color), 1.0e-10)), AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+        // the sigmoid leaves values encoded for a 2.2 gamma display.
+        return min(pow(max(mul(AGX_OUTSET, AgxContrast(encoded)), 0.0), 2.2), 1.0);
+    }
+    default:
+        return saturate(color);
+    }
+}
+
+float3 Encode(float3 value)
+{
+    value = saturate(value);
+    if (encoding == 1)
+    {
+        return pow(value, 1.0 / gamma);
+    }
+    return value <= 0.0031308 ? value * 12.92 : 1.055 * pow(value, 1.0 / 2.4) - 0.055;
+}
+
 float4 PSMain(PSInput input) : SV_TARGET
 {
-    return input.color;
+    float3 balanced = float3(dot(color_rows[0].xyz, input.color.rgb), dot(color_rows[1].xyz, input.color.rgb), dot(color_rows[2].xyz, input.color.rgb));
+    return float4(Encode(ApplyCurve(balanced)), saturate(input.color.a));
 }
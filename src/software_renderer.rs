@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use crate::accumulation::Accumulator;
+use crate::camera::Camera;
+use crate::cpu_raytracer;
+use crate::cpu_raytracer::{ Integrator, TracerSettings };
+use crate::environment::EnvironmentMap;
+use crate::film::Filter;
+use crate::framebuffer::{ AovBuffers, Framebuffer };
+use crate::lights::Light;
+use crate::mesh::Mesh;
+use crate::render_backend::RenderBackend;
+use crate::sampler::SamplerKind;
+use crate::scene;
+use crate::scene::Scene;
+use crate::scene_description::{ MaterialDescription, Texture };
+use crate::tile_scheduler;
+use crate::window_handle::RawWindowHandle;
+
+// Ray traces the scene progressively, each frame adding samples_per_frame samples per pixel to the average on
+// display until there are target_samples of them. Moving the camera or loading assets starts over.
+pub struct SoftwareRenderer
+{
+	accumulator : Accumulator,
+	aovs : AovBuffers,
+	samples_per_frame : u32,
+	settings : TracerSettings,
+	scene : Scene,
+	environment : Option<Arc<EnvironmentMap>>, // kept for the scene the next assets make
+	camera : Camera,
+}
+
+impl SoftwareRenderer
+{
+	pub fn new(width : u32, height : u32, samples_per_frame : u32, target_samples : u32, integrator : Integrator) -> Self
+	{
+		let accumulator = Accumulator::new(width, height, Filter::default(), target_samples);
+		let camera = scene::sample_triangle_camera(accumulator.aspect_ratio());
+
+		Self
+		{
+			accumulator : accumulator,
+			aovs : AovBuffers::new(width, height),
+			samples_per_frame : samples_per_frame,
+			settings : TracerSettings
+			{
+				integrator : integrator,
+				sampler : SamplerKind::Sobol,
+				samples_per_pixel : target_samples.max(1),
+				thread_count : tile_scheduler::default_thread_count(),
+			},
+			scene : Scene::new(),
+			environment : None,
+			camera : camera,
+		}
+	}
+
+	pub fn set_thread_count(&mut self, thread_count : usize)
+	{
+		self.settings.thread_count = thread_count;
+	}
+
+	pub fn set_sampler(&mut self, sampler : SamplerKind)
+	{
+		self.settings.sampler = sampler;
+		self.accumulator.reset();
+	}
+
+	pub fn set_filter(&mut self, filter : Filter)
+	{
+		self.accumulator.set_filter(filter);
+	}
+}
+
+impl RenderBackend for SoftwareRenderer
+{
+	fn load_pipeline(&mut self, _window : RawWindowHandle)
+	{
+		// Nothing to create. The image is presented from framebuffer() by whoever owns the window.
+	}
+
+	fn load_assets(&mut self, meshes : &[Arc<Mesh>], materials : &[MaterialDescription], textures : &[Texture], lights : &[Light])
+	{
+		self.scene = Scene::from_meshes(meshes, materials, textures, lights);
+		self.scene.environment = self.environment.clone();
+		self.accumulator.reset();
+	}
+
+	fn set_environment(&mut self, environment : Option<Arc<EnvironmentMap>>)
+	{
+		self.scene.environment = environment.clone();
+		self.environment = environment;
+		self.accumulator.reset();
+	}
+
+	fn update(&mut self, camera : &Camera)
+	{
+		// the framebuffer size is fixed, so keep its aspect ratio whatever the window is doing.
+		let mut camera = *camera;
+		camera.set_aspect_ratio(self.accumulator.aspect_ratio());
+		if camera != self.camera
+		{
+			self.camera = camera;
+			self.accumulator.reset();
+		}
+	}
+
+	fn render(&mut self) -> i32
+	{
+		if self.accumulator.is_complete()
+		{
+			return 0;
+		}
+
+		let samples = self.accumulator.next_samples(self.samples_per_frame);
+		let film = self.accumulator.film(&samples);
+		cpu_raytracer::render(&self.scene, &self.camera, film, Some(&mut self.aovs), &self.settings, samples.clone());
+		self.accumulator.add(samples);
+		0
+	}
+
+	fn idle(&self) -> bool
+	{
+		self.accumulator.is_complete()
+	}
+
+	fn framebuffer(&self) -> Option<&Framebuffer>
+	{
+		Some(self.accumulator.average())
+	}
+
+	fn aovs(&self) -> Option<&AovBuffers>
+	{
+		Some(&self.aovs)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use cgmath::Vector3;
+
+	use super::*;
+	use crate::film::Film;
+	use crate::path_tracer::PathTracerSettings;
+	use crate::reference_scenes;
+
+	const WIDTH : u32 = 12;
+	const HEIGHT : u32 = 9;
+	const SAMPLES_PER_FRAME : u32 = 3;
+	const TARGET_SAMPLES : u32 = 8;
+
+	// A path traced Cornell box, so every sample adds noise a miscounted average would show.
+	fn cornell_renderer() -> (SoftwareRenderer, Camera)
+	{
+		let mut renderer = SoftwareRenderer::new(WIDTH, HEIGHT, SAMPLES_PER_FRAME, TARGET_SAMPLES, Integrator::PathTracer(PathTracerSettings::default()));
+		renderer.set_thread_count(1);
+		let camera = load_cornell_box(&mut renderer);
+		renderer.update(&camera);
+		(renderer, camera)
+	}
+
+	// Returns the box's camera.
+	fn load_cornell_box(renderer : &mut SoftwareRenderer) -> Camera
+	{
+		let description = reference_scenes::cornell_box(true);
+		let meshes : Vec<Arc<Mesh>> = description.instanced_meshes().into_iter().map(Arc::new).collect();
+		renderer.load_assets(&meshes, &description.materials, &description.textures, &description.instanced_lights());
+		description.camera(WIDTH as f32 / HEIGHT as f32).expect("the Cornell box has a camera")
+	}
+
+	// How many samples per pixel the average on display holds.
+	fn sample_count(renderer : &SoftwareRenderer) -> u32
+	{
+		renderer.accumulator.next_samples(1).start
+	}
+
+	fn render_to_completion(renderer : &mut SoftwareRenderer)
+	{
+		while !renderer.idle()
+		{
+			renderer.render();
+		}
+	}
+
+	#[test]
+	fn accumulated_frames_average_like_one_render_of_all_samples()
+	{
+		let (mut renderer, camera) = cornell_renderer();
+		render_to_completion(&mut renderer);
+
+		let mut film = Film::new(WIDTH, HEIGHT, Filter::default());
+		cpu_raytracer::render(&renderer.scene, &camera, &mut film, None, &renderer.settings, 0..TARGET_SAMPLES);
+		let mut single = Framebuffer::new(WIDTH, HEIGHT);
+		film.resolve(&mut single, 1.0 / TARGET_SAMPLES as f32);
+
+		// the same samples summed in another order, which only rounding can tell apart.
+		let accumulated = renderer.framebuffer().expect("the software renderer has a framebuffer");
+		for (index, (accumulated, single)) in accumulated.pixels().iter().zip(single.pixels()).enumerate()
+		{
+			for component in 0..4
+			{
+				let tolerance = 1.0e-5 * single[component].abs().max(1.0);
+				assert!((accumulated[component] - single[component]).abs() <= tolerance, "pixel {}: {:?} is not {:?}", index, accumulated, single);
+			}
+		}
+		assert!(single.pixels().iter().any(|pixel| pixel.x > 0.0), "the render is black");
+	}
+
+	#[test]
+	fn idle_exactly_at_the_target_sample_count()
+	{
+		let (mut renderer, _) = cornell_renderer();
+		for &expected in &[3, 6, 8]
+		{
+			assert!(!renderer.idle());
+			renderer.render();
+			assert_eq!(sample_count(&renderer), expected);
+		}
+		assert!(renderer.idle());
+
+		// more frames don't add samples or change the image.
+		let finished = renderer.framebuffer().unwrap().pixels().to_vec();
+		renderer.render();
+		assert_eq!(sample_count(&renderer), TARGET_SAMPLES);
+		assert_eq!(renderer.framebuffer().unwrap().pixels(), finished.as_slice());
+	}
+
+	#[test]
+	fn moving_the_camera_starts_over()
+	{
+		let (mut renderer, camera) = cornell_renderer();
+		renderer.render();
+		renderer.render();
+
+		// the renderer keeps its own aspect ratio, so the window's doesn't count as a change.
+		let mut stretched = camera;
+		stretched.set_aspect_ratio(3.0);
+		renderer.update(&stretched);
+		assert_eq!(sample_count(&renderer), 2 * SAMPLES_PER_FRAME);
+
+		let mut moved = camera;
+		moved.set_position(camera.position() + Vector3::new(0.0, 0.0, 0.1));
+		renderer.update(&moved);
+		assert_eq!(sample_count(&renderer), 0);
+		renderer.render();
+		assert_eq!(sample_count(&renderer), SAMPLES_PER_FRAME);
+	}
+
+	#[test]
+	fn loading_assets_starts_over()
+	{
+		let (mut renderer, _) = cornell_renderer();
+		render_to_completion(&mut renderer);
+		load_cornell_box(&mut renderer);
+		assert_eq!(sample_count(&renderer), 0);
+		assert!(!renderer.idle());
+	}
+
+	#[test]
+	fn changing_the_environment_starts_over()
+	{
+		let (mut renderer, _) = cornell_renderer();
+		render_to_completion(&mut renderer);
+		renderer.set_environment(None);
+		assert_eq!(sample_count(&renderer), 0);
+		assert!(!renderer.idle());
+	}
+}
@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+// Square tiles, small enough to balance the load and big enough that scheduling them costs nothing.
+pub const TILE_SIZE : u32 = 32;
+
+// A rectangle of pixels, x and y being its top left corner. Tiles on the right and bottom edges may be smaller.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile
+{
+	pub x : u32,
+	pub y : u32,
+	pub width : u32,
+	pub height : u32,
+}
+
+// Covers a width by height image with tiles, in rows from the top left.
+pub fn tiles(width : u32, height : u32, tile_size : u32) -> Vec<Tile>
+{
+	let mut tiles = Vec::new();
+	for y in (0..height).step_by(tile_size as usize)
+	{
+		for x in (0..width).step_by(tile_size as usize)
+		{
+			tiles.push(Tile { x : x, y : y, width : tile_size.min(width - x), height : tile_size.min(height - y) });
+		}
+	}
+	tiles
+}
+
+// One thread per core the system reports, or one if it can't tell.
+pub fn default_thread_count() -> usize
+{
+	thread::available_parallelism().map_or(1, |count| count.get())
+}
+
+// Renders every tile on a pool of thread_count workers and hands each result to consume on the calling thread as
+// soon as it is done, in whatever order they finish.
+// Tiles are dealt to the workers' queues in turn, so each starts with tiles spread over the whole image. A worker
+// takes tiles from the front of its own queue and, once that is empty, steals from the back of the others'.
+pub fn run<T, R, C>(tiles : Vec<Tile>, thread_count : usize, render : R, mut consume : C)
+	where T : Send, R : Fn(Tile) -> T + Sync, C : FnMut(Tile, T)
+{
+	let thread_count = thread_count.max(1).min(tiles.len().max(1));
+	let mut queues : Vec<VecDeque<Tile>> = vec![VecDeque::new(); thread_count];
+	for (index, tile) in tiles.into_iter().enumerate()
+	{
+		queues[index % thread_count].push_back(tile);
+	}
+	let queues : Vec<Mutex<VecDeque<Tile>>> = queues.into_iter().map(Mutex::new).collect();
+
+	// no tile is ever added, so a worker that finds every queue empty is done.
+	let next_tile = |worker : usize| -> Option<Tile>
+	{
+		if let Some(tile) = queues[worker].lock().unwrap().pop_front()
+		{
+			return Some(tile);
+		}
+		(1..thread_count)
+			.map(|offset| (worker + offset) % thread_count)
+			.find_map(|victim| queues[victim].lock().unwrap().pop_back())
+	};
+
+	let (sender, receiver) = mpsc::channel::<(Tile, T)>();
+	thread::scope(|scope|
+	{
+		for worker in 0..thread_count
+		{
+			let sender = sender.clone();
+			let (next_tile, render) = (&next_tile, &render);
+			thread::Builder::new()
+				.name(format!("render_worker_{}", worker))
+				.spawn_scoped(scope, move ||
+				{
+					while let Some(tile) = next_tile(worker)
+					{
+						if sender.send((tile, render(tile))).is_err()
+						{
+							return;
+						}
+					}
+				})
+				.expect("failed to spin up a render worker");
+		}
+
+		// the workers hold the only senders left, so the loop ends when the last of them finishes.
+		drop(sender);
+		for (tile, result) in receiver
+		{
+			consume(tile, result);
+		}
+	});
+}
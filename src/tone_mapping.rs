@@ -0,0 +1,442 @@
+use cgmath::{ Vector3, Vector4 };
+
+use crate::framebuffer;
+
+// How scene linear values above 1 are brought into the display's range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToneCurve
+{
+	Clamp,    // none, everything above 1 clips
+	Reinhard, // x / (1 + x) per channel
+	Hable,    // John Hable's Uncharted 2 filmic curve
+	Aces,     // Stephen Hill's fit of the ACES reference and sRGB output transforms
+	AgX,      // Troy Sobotka's AgX, desaturating highlights towards white instead of skewing their hue
+}
+
+impl ToneCurve
+{
+	pub const ALL : [ToneCurve; 5] = [ToneCurve::Clamp, ToneCurve::Reinhard, ToneCurve::Hable, ToneCurve::Aces, ToneCurve::AgX];
+
+	pub fn name(self) -> &'static str
+	{
+		match self
+		{
+			ToneCurve::Clamp => "none",
+			ToneCurve::Reinhard => "reinhard",
+			ToneCurve::Hable => "hable",
+			ToneCurve::Aces => "aces",
+			ToneCurve::AgX => "agx",
+		}
+	}
+}
+
+impl std::str::FromStr for ToneCurve
+{
+	type Err = String;
+
+	fn from_str(value : &str) -> Result<Self, Self::Err>
+	{
+		ToneCurve::ALL.iter()
+			.copied()
+			.find(|curve| curve.name() == value)
+			.ok_or_else(|| format!("unknown tone curve '{}', expected none, reinhard, hable, aces or agx", value))
+	}
+}
+
+// Display transfer function, from display linear values in [0, 1] to what is sent to the display.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Encoding
+{
+	Srgb,       // the piecewise sRGB OETF
+	Gamma(f32), // a pure power of 1 / gamma, 1 for targets that encode on their own, like sRGB render target views
+}
+
+// Row major 3x3 matrices for colors.
+type ColorMatrix = [[f32; 3]; 3];
+
+const IDENTITY : ColorMatrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+// Linear sRGB, or Rec. 709, to CIE XYZ and back, both with a D65 white.
+const SRGB_TO_XYZ : ColorMatrix =
+[
+	[0.412_456_4, 0.357_576_1, 0.180_437_5],
+	[0.212_672_9, 0.715_152_2, 0.072_175_0],
+	[0.019_333_9, 0.119_192, 0.950_304_1],
+];
+const XYZ_TO_SRGB : ColorMatrix =
+[
+	[3.240_454_2, -1.537_138_5, -0.498_531_4],
+	[-0.969_266, 1.876_010_8, 0.041_556_0],
+	[0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+
+// Bradford's cone response space for chromatic adaptation, and back.
+const XYZ_TO_BRADFORD : ColorMatrix =
+[
+	[0.895_1, 0.266_4, -0.161_4],
+	[-0.750_2, 1.713_5, 0.036_7],
+	[0.038_9, -0.068_5, 1.029_6],
+];
+const BRADFORD_TO_XYZ : ColorMatrix =
+[
+	[0.986_992_9, -0.147_054_3, 0.159_962_7],
+	[0.432_305_3, 0.518_360_3, 0.049_291_2],
+	[-0.008_528_7, 0.040_042_8, 0.968_486_7],
+];
+
+// Hill's ACES fit, from linear sRGB to the RRT's space with the sRGB to ACES adaptation folded in, and back.
+const ACES_INPUT : ColorMatrix =
+[
+	[0.597_19, 0.354_58, 0.048_23],
+	[0.076_00, 0.908_34, 0.015_66],
+	[0.028_40, 0.133_83, 0.837_77],
+];
+const ACES_OUTPUT : ColorMatrix =
+[
+	[1.604_75, -0.531_08, -0.073_67],
+	[-0.102_08, 1.108_13, -0.006_05],
+	[-0.003_27, -0.072_76, 1.076_02],
+];
+
+// AgX's inset and outset of linear sRGB primaries, and the range of exposures its log encoding covers.
+const AGX_INSET : ColorMatrix =
+[
+	[0.842_479_05, 0.078_433_6, 0.079_223_745],
+	[0.042_328_242, 0.878_468_63, 0.079_166_13],
+	[0.042_375_654, 0.078_433_6, 0.879_143],
+];
+const AGX_OUTSET : ColorMatrix =
+[
+	[1.196_879, -0.098_020_88, -0.099_029_74],
+	[-0.052_896_854, 1.151_903_2, -0.098_961_174],
+	[-0.052_971_635, -0.098_043_45, 1.151_073_7],
+];
+const AGX_MIN_EV : f32 = -12.473_93;
+const AGX_MAX_EV : f32 = 4.026_069;
+
+// Scene linear to display encoded color: white balance, exposure, a tone curve and the display's transfer
+// function, in that order. Alpha is coverage and passes through.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DisplayTransform
+{
+	exposure : f32, // in stops
+	curve : ToneCurve,
+	encoding : Encoding,
+	white_balance : ColorMatrix,
+}
+
+impl Default for DisplayTransform
+{
+	// Clips and encodes as sRGB, what images were always saved as.
+	fn default() -> Self
+	{
+		Self::new(0.0, ToneCurve::Clamp, None, Encoding::Srgb)
+	}
+}
+
+impl DisplayTransform
+{
+	// white_balance is the color temperature in kelvin of the light that should come out white, None leaves
+	// colors alone.
+	pub fn new(exposure : f32, curve : ToneCurve, white_balance : Option<f32>, encoding : Encoding) -> Self
+	{
+		Self
+		{
+			exposure : exposure,
+			curve : curve,
+			encoding : encoding,
+			white_balance : white_balance.map_or(IDENTITY, white_balance_matrix),
+		}
+	}
+
+	// Display encoded color in [0, 1].
+	pub fn apply(&self, color : Vector4<f32>) -> Vector4<f32>
+	{
+		let scale = self.exposure.exp2();
+		let balanced = multiply(&self.white_balance, color.truncate()) * scale;
+		let display = apply_curve(self.curve, balanced);
+		let encode = |value : f32| encode(self.encoding, value);
+		Vector4::new(encode(display.x), encode(display.y), encode(display.z), color.w.clamp(0.0, 1.0))
+	}
+
+	// White balance with the exposure folded in, the curve and the encoding, for backends that apply the transform
+	// on the GPU.
+	#[cfg(all(windows, feature = "dx12"))]
+	pub fn shader_parameters(&self) -> ([[f32; 3]; 3], ToneCurve, Encoding)
+	{
+		let scale = self.exposure.exp2();
+		let mut color_matrix = self.white_balance;
+		color_matrix.iter_mut().flatten().for_each(|value| *value *= scale);
+		(color_matrix, self.curve, self.encoding)
+	}
+
+	// 8 bit RGBA for saving or presenting.
+	pub fn rgba8(&self, color : Vector4<f32>) -> [u8; 4]
+	{
+		let display = self.apply(color);
+		[framebuffer::to_unorm8(display.x), framebuffer::to_unorm8(display.y), framebuffer::to_unorm8(display.z), framebuffer::to_unorm8(display.w)]
+	}
+}
+
+// The sRGB transfer function, linear in [0, 1] to encoded in [0, 1].
+pub fn linear_to_srgb(value : f32) -> f32
+{
+	let value = value.clamp(0.0, 1.0);
+	if value <= 0.003_130_8
+	{
+		value * 12.92
+	}
+	else
+	{
+		1.055 * value.powf(1.0 / 2.4) - 0.055
+	}
+}
+
+// Its inverse, for decoding sRGB textures.
+pub fn srgb_to_linear(value : f32) -> f32
+{
+	let value = value.clamp(0.0, 1.0);
+	if value <= 0.040_45
+	{
+		value / 12.92
+	}
+	else
+	{
+		((value + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+fn encode(encoding : Encoding, value : f32) -> f32
+{
+	match encoding
+	{
+		Encoding::Srgb => linear_to_srgb(value),
+		Encoding::Gamma(gamma) => value.clamp(0.0, 1.0).powf(1.0 / gamma),
+	}
+}
+
+// Display linear color in [0, 1] for scene linear color.
+fn apply_curve(curve : ToneCurve, color : Vector3<f32>) -> Vector3<f32>
+{
+	let per_channel = |function : fn(f32) -> f32| Vector3::new(function(color.x), function(color.y), function(color.z));
+	match curve
+	{
+		ToneCurve::Clamp => per_channel(|value| value.clamp(0.0, 1.0)),
+		ToneCurve::Reinhard => per_channel(|value| { let value = value.max(0.0); value / (1.0 + value) }),
+		ToneCurve::Hable => per_channel(hable),
+		ToneCurve::Aces =>
+		{
+			let fitted = |value : f32| (value * (value + 0.024_578_6) - 0.000_090_537) / (value * (0.983_729 * value + 0.432_951) + 0.238_081);
+			let color = multiply(&ACES_INPUT, color);
+			let color = multiply(&ACES_OUTPUT, Vector3::new(fitted(color.x), fitted(color.y), fitted(color.z)));
+			Vector3::new(color.x.clamp(0.0, 1.0), color.y.clamp(0.0, 1.0), color.z.clamp(0.0, 1.0))
+		}
+		ToneCurve::AgX =>
+		{
+			let log_encode = |value : f32| (value.max(1.0e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+			let color = multiply(&AGX_INSET, color);
+			let color = Vector3::new(agx_contrast(log_encode(color.x)), agx_contrast(log_encode(color.y)), agx_contrast(log_encode(color.z)));
+			// the sigmoid leaves values encoded for a 2.2 gamma display.
+			let color = multiply(&AGX_OUTSET, color);
+			Vector3::new(color.x.max(0.0).powf(2.2).min(1.0), color.y.max(0.0).powf(2.2).min(1.0), color.z.max(0.0).powf(2.2).min(1.0))
+		}
+	}
+}
+
+// Hable's curve with his constants, an exposure bias of 2 and a white point of 11.2.
+fn hable(value : f32) -> f32
+{
+	let partial = |x : f32|
+	{
+		let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+		(x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+	};
+	(partial(2.0 * value.max(0.0)) / partial(11.2)).min(1.0)
+}
+
+// Polynomial fit of AgX's default sigmoid, from the log encoded value to the display encoded one.
+fn agx_contrast(x : f32) -> f32
+{
+	let (x2, x4) = (x * x, x * x * x * x);
+	15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.002_32
+}
+
+fn multiply(matrix : &ColorMatrix, color : Vector3<f32>) -> Vector3<f32>
+{
+	let row = |row : &[f32; 3]| row[0] * color.x + row[1] * color.y + row[2] * color.z;
+	Vector3::new(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]))
+}
+
+fn multiply_matrices(a : &ColorMatrix, b : &ColorMatrix) -> ColorMatrix
+{
+	let mut result = [[0.0; 3]; 3];
+	for (row, result_row) in result.iter_mut().enumerate()
+	{
+		for (column, value) in result_row.iter_mut().enumerate()
+		{
+			*value = (0..3).map(|index| a[row][index] * b[index][column]).sum();
+		}
+	}
+	result
+}
+
+// CIE xy chromaticity of a light of the given color temperature: the daylight locus from 4000 K up, which D65
+// lies on at 6504 K, and below that the Planckian locus of incandescent light (Kim et al. 2002).
+fn chromaticity(temperature : f32) -> (f32, f32)
+{
+	let t = temperature.clamp(1667.0, 25000.0) as f64;
+	let (t2, t3) = (t * t, t * t * t);
+	let (x, y) = if t >= 4000.0
+	{
+		let x = if t <= 7000.0
+		{
+			-4.607_0e9 / t3 + 2.967_8e6 / t2 + 0.099_11e3 / t + 0.244_063
+		}
+		else
+		{
+			-2.006_4e9 / t3 + 1.901_8e6 / t2 + 0.247_48e3 / t + 0.237_040
+		};
+		(x, -3.0 * x * x + 2.87 * x - 0.275)
+	}
+	else
+	{
+		let x = -0.266_123_9e9 / t3 - 0.234_358_9e6 / t2 + 0.877_695_6e3 / t + 0.179_910;
+		let y = if t <= 2222.0
+		{
+			-1.106_381_4 * x * x * x - 1.348_110_20 * x * x + 2.185_558_32 * x - 0.202_196_83
+		}
+		else
+		{
+			-0.954_947_6 * x * x * x - 1.374_185_93 * x * x + 2.091_370_15 * x - 0.167_488_67
+		};
+		(x, y)
+	};
+	(x as f32, y as f32)
+}
+
+// Adapts colors lit by a light of the given temperature to the sRGB white with Bradford's von Kries transform,
+// so the light itself comes out neutral.
+fn white_balance_matrix(temperature : f32) -> ColorMatrix
+{
+	let (x, y) = chromaticity(temperature);
+	let source = multiply(&XYZ_TO_BRADFORD, Vector3::new(x / y, 1.0, (1.0 - x - y) / y));
+	let target = multiply(&XYZ_TO_BRADFORD, multiply(&SRGB_TO_XYZ, Vector3::new(1.0, 1.0, 1.0)));
+	let scale = [[target.x / source.x, 0.0, 0.0], [0.0, target.y / source.y, 0.0], [0.0, 0.0, target.z / source.z]];
+	let adaptation = multiply_matrices(&BRADFORD_TO_XYZ, &multiply_matrices(&scale, &XYZ_TO_BRADFORD));
+	multiply_matrices(&XYZ_TO_SRGB, &multiply_matrices(&adaptation, &SRGB_TO_XYZ))
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// Golden values worked out independently in double precision.
+	fn check(transform : DisplayTransform, color : Vector4<f32>, expected : [f32; 3])
+	{
+		let result = transform.apply(color);
+		let error = (result.x - expected[0]).abs().max((result.y - expected[1]).abs()).max((result.z - expected[2]).abs());
+		assert!(error <= 2.0e-4, "{:?} gives {:?}, expected {:?}", color, result, expected);
+		assert_eq!(result.w, color.w);
+	}
+
+	// The curve rises monotonically from black.
+	fn check_monotonic(tone_curve : ToneCurve)
+	{
+		let transform = curve(tone_curve);
+		let values : Vec<f32> = (0..=1000).map(|index| transform.apply(grey(64.0 * (index as f32 / 1000.0).powi(3))).y).collect();
+		assert!(values[0] <= 1.0e-3, "black comes out as {}", values[0]);
+		assert!(values.windows(2).all(|pair| pair[1] >= pair[0]), "not monotonic");
+	}
+
+	fn curve(curve : ToneCurve) -> DisplayTransform
+	{
+		DisplayTransform::new(0.0, curve, None, Encoding::Srgb)
+	}
+
+	fn grey(value : f32) -> Vector4<f32>
+	{
+		Vector4::new(value, value, value, 1.0)
+	}
+
+	const SATURATED : Vector4<f32> = Vector4::new(4.0, 1.0, 0.25, 1.0);
+
+	#[test]
+	fn no_curve_clips()
+	{
+		check(DisplayTransform::default(), grey(0.18), [0.461_356, 0.461_356, 0.461_356]);
+		check(DisplayTransform::default(), grey(1.0), [1.0, 1.0, 1.0]);
+		check(DisplayTransform::default(), grey(4.0), [1.0, 1.0, 1.0]);
+		check(DisplayTransform::default(), SATURATED, [1.0, 1.0, 0.537_099]);
+		check_monotonic(ToneCurve::Clamp);
+	}
+
+	#[test]
+	fn reinhard()
+	{
+		check(curve(ToneCurve::Reinhard), grey(0.18), [0.426_946, 0.426_946, 0.426_946]);
+		check(curve(ToneCurve::Reinhard), grey(1.0), [0.735_357, 0.735_357, 0.735_357]);
+		check(curve(ToneCurve::Reinhard), grey(4.0), [0.906_332, 0.906_332, 0.906_332]);
+		check(curve(ToneCurve::Reinhard), SATURATED, [0.906_332, 0.735_357, 0.484_529]);
+		check_monotonic(ToneCurve::Reinhard);
+	}
+
+	#[test]
+	fn hable()
+	{
+		check(curve(ToneCurve::Hable), grey(0.18), [0.393_471, 0.393_471, 0.393_471]);
+		check(curve(ToneCurve::Hable), grey(1.0), [0.730_674, 0.730_674, 0.730_674]);
+		check(curve(ToneCurve::Hable), grey(4.0), [0.963_067, 0.963_067, 0.963_067]);
+		check(curve(ToneCurve::Hable), SATURATED, [0.963_067, 0.730_674, 0.451_630]);
+		check_monotonic(ToneCurve::Hable);
+	}
+
+	#[test]
+	fn aces()
+	{
+		check(curve(ToneCurve::Aces), grey(0.18), [0.358_457, 0.358_457, 0.358_456]);
+		check(curve(ToneCurve::Aces), grey(1.0), [0.808_954, 0.808_954, 0.808_950]);
+		check(curve(ToneCurve::Aces), grey(4.0), [0.958_888, 0.958_888, 0.958_884]);
+		check(curve(ToneCurve::Aces), SATURATED, [0.997_751, 0.834_174, 0.598_402]);
+		check_monotonic(ToneCurve::Aces);
+	}
+
+	#[test]
+	fn agx()
+	{
+		check(curve(ToneCurve::AgX), grey(0.18), [0.500_460, 0.500_531, 0.500_535]);
+		check(curve(ToneCurve::AgX), grey(1.0), [0.791_773, 0.791_911, 0.791_919]);
+		check(curve(ToneCurve::AgX), grey(4.0), [0.936_040, 0.936_222, 0.936_234]);
+		check(curve(ToneCurve::AgX), SATURATED, [0.963_019, 0.807_021, 0.646_023]);
+		check_monotonic(ToneCurve::AgX);
+	}
+
+	#[test]
+	fn exposure_is_in_stops()
+	{
+		check(DisplayTransform::new(2.0, ToneCurve::Clamp, None, Encoding::Srgb), grey(0.18), [0.865_042, 0.865_042, 0.865_042]);
+		check(DisplayTransform::new(-1.0, ToneCurve::Clamp, None, Encoding::Gamma(1.0)), grey(0.5), [0.25, 0.25, 0.25]);
+	}
+
+	#[test]
+	fn white_balance()
+	{
+		// warm light turns white blue, D65 is sRGB's own white and leaves colors alone, and cool light warms them.
+		check(DisplayTransform::new(0.0, ToneCurve::Clamp, Some(3200.0), Encoding::Gamma(1.0)), grey(1.0), [0.357_779, 1.0, 1.0]);
+		check(DisplayTransform::new(0.0, ToneCurve::Clamp, Some(6504.0), Encoding::Gamma(1.0)), grey(0.5), [0.500_368, 0.499_859, 0.500_311]);
+		check(DisplayTransform::new(0.0, ToneCurve::Clamp, Some(9000.0), Encoding::Srgb), Vector4::new(0.2, 0.5, 0.8, 1.0), [0.546_536, 0.732_851, 0.805_363]);
+	}
+
+	#[test]
+	fn encodings()
+	{
+		check(DisplayTransform::new(-1.0, ToneCurve::Reinhard, None, Encoding::Gamma(2.2)), Vector4::new(0.5, 0.25, 0.1, 1.0), [0.481_157, 0.368_344, 0.250_606]);
+		check(DisplayTransform::new(0.0, ToneCurve::Aces, None, Encoding::Gamma(1.0)), Vector4::new(0.5, 0.25, 0.1, 1.0), [0.366_549, 0.172_765, 0.056_129]);
+		check(DisplayTransform::new(0.0, ToneCurve::Clamp, None, Encoding::Gamma(1.0)), Vector4::new(0.5, 0.25, 0.1, 0.5), [0.5, 0.25, 0.1]);
+		for index in 0..=1000
+		{
+			let value = index as f32 / 1000.0;
+			assert!((srgb_to_linear(linear_to_srgb(value)) - value).abs() <= 1.0e-5, "sRGB round trip of {}", value);
+		}
+	}
+}
@@ -0,0 +1,148 @@
+use cgmath::InnerSpace;
+use cgmath::Vector3;
+
+use crate::cpu_raytracer::RAY_EPSILON;
+use crate::material::{ multiply, reflect, refract, schlick_dielectric };
+use crate::ray::{ Ray, HitRecord, Hittable };
+use crate::scene::Scene;
+
+// How much of the background reaches diffuse surfaces. All of it would be right for a surface under an open sky,
+// far too much for anything indoors.
+const AMBIENT_FRACTION : f32 = 0.1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WhittedSettings
+{
+	pub max_depth : u32, // reflection and refraction bounces
+}
+
+impl Default for WhittedSettings
+{
+	fn default() -> Self
+	{
+		Self { max_depth : 5 }
+	}
+}
+
+// Whitted style ray tracing: hard shadowed direct light, perfect mirrors and perfect refraction, no noise.
+// Every emissive triangle lights the scene as a point at its centroid, every other light from its middle, and a
+// fraction of the background stands in for the indirect light.
+// Scenes without lights or emissive materials get the headlight shading instead, so they still preview.
+//
+// Materials are read as a blend of three lobes: a transmission part refracts, a metallic part mirrors and
+// the rest is diffuse. Roughness is ignored.
+pub fn radiance(ray : &Ray, scene : &Scene, settings : &WhittedSettings) -> Vector3<f32>
+{
+	trace(ray, scene, settings, 0)
+}
+
+fn trace(ray : &Ray, scene : &Scene, settings : &WhittedSettings, depth : u32) -> Vector3<f32>
+{
+	let hit = scene.hit(ray, RAY_EPSILON, f32::INFINITY);
+	let t_max = hit.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+	if let Some(light_hit) = scene.lights.hit(ray, RAY_EPSILON, t_max)
+	{
+		return light_hit.radiance;
+	}
+
+	let hit = match hit
+	{
+		Some(hit) => hit,
+		None => return scene.lights.distant(ray.direction).fold(scene.background_radiance(ray.direction), |radiance, (_, light)| radiance + light),
+	};
+
+	let surface = scene.surface(&hit);
+	let material = surface.material;
+	let base_color = surface.color.truncate();
+	let metallic = surface.metallic.clamp(0.0, 1.0);
+	let transmission = material.map_or(0.0, |material| material.transmission.clamp(0.0, 1.0));
+	let index_of_refraction = material.map_or(1.5, |material| material.index_of_refraction);
+
+	let mut radiance = scene.emission(&hit);
+
+	let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+	if diffuse_weight > 0.0
+	{
+		radiance += multiply(base_color, diffuse_light(ray, scene, &hit)) * diffuse_weight;
+	}
+
+	if depth < settings.max_depth
+	{
+		let reflected = reflect(-ray.direction, hit.normal);
+
+		let mirror_weight = metallic * (1.0 - transmission);
+		if mirror_weight > 0.0
+		{
+			radiance += multiply(base_color, trace(&Ray::new(hit.point, reflected), scene, settings, depth + 1)) * mirror_weight;
+		}
+
+		if transmission > 0.0
+		{
+			// hit.normal faces the incoming ray, so leaving the surface swaps the indices of refraction.
+			let eta = if hit.front_face { index_of_refraction } else { 1.0 / index_of_refraction };
+			let cosine = -ray.direction.dot(hit.normal);
+			let reflectance = match refract(-ray.direction, hit.normal, eta)
+			{
+				Some(refracted) =>
+				{
+					let reflectance = schlick_dielectric(cosine, eta);
+					let transmitted = trace(&Ray::new(hit.point, refracted), scene, settings, depth + 1);
+					radiance += multiply(base_color, transmitted) * (transmission * (1.0 - reflectance));
+					reflectance
+				}
+				None => 1.0, // total internal reflection
+			};
+			radiance += trace(&Ray::new(hit.point, reflected), scene, settings, depth + 1) * (transmission * reflectance);
+		}
+	}
+
+	radiance
+}
+
+// Irradiance over pi from every light that isn't shadowed, plus ambient light.
+fn diffuse_light(ray : &Ray, scene : &Scene, hit : &HitRecord) -> Vector3<f32>
+{
+	if scene.area_lights.is_empty() && scene.lights.is_empty()
+	{
+		let facing = hit.normal.dot(-ray.direction).max(0.0);
+		let shade = 0.2 + 0.8 * facing;
+		return Vector3::new(shade, shade, shade);
+	}
+
+	let mut light = scene.ambient_radiance() * AMBIENT_FRACTION;
+	for point in scene.area_lights.point_approximations()
+	{
+		let offset = point.position - hit.point;
+		let distance_squared = offset.magnitude2();
+		let distance = distance_squared.sqrt();
+		let direction = offset / distance;
+
+		let surface_cosine = direction.dot(hit.normal);
+		let light_cosine = -direction.dot(point.normal);
+		let light_cosine = if point.double_sided { light_cosine.abs() } else { light_cosine };
+		if surface_cosine <= 0.0 || light_cosine <= 0.0
+		{
+			continue;
+		}
+
+		if !scene.occluded(&Ray::new(hit.point, direction), RAY_EPSILON, distance * (1.0 - 1.0e-4))
+		{
+			light += point.power * (surface_cosine * light_cosine / (distance_squared * std::f32::consts::PI));
+		}
+	}
+
+	for sample in scene.lights.iter().filter_map(|light| light.sample_middle(hit.point))
+	{
+		let surface_cosine = sample.direction.dot(hit.normal);
+		if surface_cosine <= 0.0 || sample.pdf <= 0.0
+		{
+			continue;
+		}
+
+		if !scene.occluded(&Ray::new(hit.point, sample.direction), RAY_EPSILON, sample.distance * (1.0 - 1.0e-4))
+		{
+			light += sample.radiance * (surface_cosine / (sample.pdf * std::f32::consts::PI));
+		}
+	}
+	light
+}
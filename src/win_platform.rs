@@ -1,9 +1,9 @@
-use std::error::Error;
-use std::fmt;
+use std::cell::RefCell;
 use std::mem;
 use std::result::Result;
 use std::string::String;
 use std::sync::mpsc;
+use std::thread;
 
 use winapi::um::winuser::{
 	MSG,
@@ -14,6 +14,36 @@ use winapi::um::winuser::{
     TranslateMessage,
     DispatchMessageW,
 	PeekMessageW,
+	PostMessageW,
+	SetCapture,
+	ReleaseCapture,
+	WM_KEYDOWN,
+	WM_KEYUP,
+	WM_SYSKEYDOWN,
+	WM_SYSKEYUP,
+	WM_MOUSEMOVE,
+	WM_LBUTTONDOWN,
+	WM_LBUTTONUP,
+	WM_RBUTTONDOWN,
+	WM_RBUTTONUP,
+	WM_MBUTTONDOWN,
+	WM_MBUTTONUP,
+	WM_MOUSEWHEEL,
+	WM_SIZE,
+	WM_SETFOCUS,
+	WM_KILLFOCUS,
+	WHEEL_DELTA,
+	VK_UP,
+	VK_DOWN,
+	VK_LEFT,
+	VK_RIGHT,
+	VK_SPACE,
+	VK_RETURN,
+	VK_TAB,
+	VK_ESCAPE,
+	VK_SHIFT,
+	VK_CONTROL,
+	VK_MENU,
 };
 
 use winapi::shared::windef::
@@ -29,33 +59,76 @@ use winapi::shared::minwindef::
 	LRESULT
 };
 
+use crate::framebuffer::Framebuffer;
+use crate::input::{ InputEvent, Key, MouseButton };
+use crate::platform::{ Platform, PlatformError, ExitCode, ExitResult };
+use crate::tone_mapping::DisplayTransform;
 use crate::win_window;
+use crate::window_handle::RawWindowHandle;
 
-#[derive(Debug)]
-pub enum ExitCode
+thread_local!
 {
-	Quit,
+	// window_proc has no state of its own, so it reaches the input channel through the thread that owns the window.
+	static INPUT_SENDER : RefCell<Option<mpsc::Sender<InputEvent>>> = RefCell::new(None);
 }
 
-#[derive(Debug, Clone)]
-pub struct PlatformError
+fn send_input(event : InputEvent)
 {
-	details : String,
+	INPUT_SENDER.with(|input_sender| 
+	{
+		if let Some(input_sender) = input_sender.borrow().as_ref()
+		{
+			let _ = input_sender.send(event); // nobody listening once the main loop has finished
+		}
+	});
 }
 
-impl fmt::Display for PlatformError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.details)
-    }
+fn key_from_virtual_key(w_param : WPARAM) -> Key
+{
+	let virtual_key = w_param as i32;
+	match virtual_key
+	{
+		0x30..=0x39 | 0x41..=0x5A => Key::from_char(virtual_key as u8 as char).unwrap(), // same codes as ASCII
+		VK_UP => Key::Up,
+		VK_DOWN => Key::Down,
+		VK_LEFT => Key::Left,
+		VK_RIGHT => Key::Right,
+		VK_SPACE => Key::Space,
+		VK_RETURN => Key::Enter,
+		VK_TAB => Key::Tab,
+		VK_ESCAPE => Key::Escape,
+		VK_SHIFT => Key::Shift,
+		VK_CONTROL => Key::Control,
+		VK_MENU => Key::Alt,
+		_ => Key::Other(virtual_key as u32),
+	}
 }
 
-impl Error for PlatformError {
-    fn description(&self) -> &str {
-        &self.details
-    }
+fn low_word(l_param : LPARAM) -> i32
+{
+	(l_param & 0xFFFF) as i16 as i32 // sign extended, positions left of or above the client area are negative
 }
 
-pub type ExitResult = Result<ExitCode, PlatformError>;
+fn high_word(l_param : LPARAM) -> i32
+{
+	((l_param >> 16) & 0xFFFF) as i16 as i32
+}
+
+unsafe fn mouse_button(hwnd : HWND, button : MouseButton, is_down : bool) -> LRESULT
+{
+	// capture the mouse while a button is held, so drags keep reporting outside of the window.
+	if is_down
+	{
+		SetCapture(hwnd);
+		send_input(InputEvent::MouseButtonDown(button));
+	}
+	else
+	{
+		ReleaseCapture();
+		send_input(InputEvent::MouseButtonUp(button));
+	}
+	0
+}
 
 pub unsafe extern "system" fn window_proc(
 	hwnd : HWND, 
@@ -67,16 +140,69 @@ pub unsafe extern "system" fn window_proc(
     { 
         WM_CLOSE => {winapi::um::winuser::DestroyWindow(hwnd); 0 }
 		WM_DESTROY => {winapi::um::winuser::PostQuitMessage(0); 0 }
+		WM_KEYDOWN | WM_SYSKEYDOWN =>
+		{
+			let is_repeat = (l_param >> 30) & 1 != 0; // bit 30 is set when the key was already down
+			if !is_repeat
+			{
+				send_input(InputEvent::KeyDown(key_from_virtual_key(w_param)));
+			}
+			winapi::um::winuser::DefWindowProcW(hwnd, u_msg, w_param, l_param) // keeps Alt+F4 working
+		}
+		WM_KEYUP | WM_SYSKEYUP =>
+		{
+			send_input(InputEvent::KeyUp(key_from_virtual_key(w_param)));
+			winapi::um::winuser::DefWindowProcW(hwnd, u_msg, w_param, l_param)
+		}
+		WM_MOUSEMOVE => { send_input(InputEvent::MouseMove { x : low_word(l_param), y : high_word(l_param) }); 0 }
+		WM_LBUTTONDOWN => mouse_button(hwnd, MouseButton::Left, true),
+		WM_LBUTTONUP => mouse_button(hwnd, MouseButton::Left, false),
+		WM_RBUTTONDOWN => mouse_button(hwnd, MouseButton::Right, true),
+		WM_RBUTTONUP => mouse_button(hwnd, MouseButton::Right, false),
+		WM_MBUTTONDOWN => mouse_button(hwnd, MouseButton::Middle, true),
+		WM_MBUTTONUP => mouse_button(hwnd, MouseButton::Middle, false),
+		WM_MOUSEWHEEL =>
+		{
+			let wheel_delta = high_word(w_param as LPARAM);
+			send_input(InputEvent::MouseWheel { delta : wheel_delta as f32 / WHEEL_DELTA as f32 });
+			0
+		}
+		WM_SIZE =>
+		{
+			let (width, height) = (l_param & 0xFFFF, (l_param >> 16) & 0xFFFF);
+			if width > 0 && height > 0 // minimized
+			{
+				send_input(InputEvent::Resize { width : width as u32, height : height as u32 });
+			}
+			0
+		}
+		WM_SETFOCUS => { send_input(InputEvent::Focus(true)); 0 }
+		WM_KILLFOCUS => { send_input(InputEvent::Focus(false)); 0 }
         _ => { winapi::um::winuser::DefWindowProcW(hwnd, u_msg, w_param, l_param) },
     }
 }
 
 pub fn platform_thread_run(
+	title : String,
+	width : u32,
+	height : u32,
 	window_sender : mpsc::Sender::<win_window::Window>,
-	_exit_sender : mpsc::Sender::<ExitResult>,
-	_input_sender : mpsc::Sender::<u32>)
+	exit_sender : mpsc::Sender::<ExitResult>,
+	input_sender : mpsc::Sender::<InputEvent>)
 {
-	let window = win_window::create_window().unwrap();
+	// installed before the window exists, creation already sends WM_SIZE and WM_SETFOCUS.
+	INPUT_SENDER.with(|thread_input_sender| *thread_input_sender.borrow_mut() = Some(input_sender));
+
+	let window = match win_window::create_window(&title, width, height)
+	{
+		Ok(window) => window,
+		Err(error) =>
+		{
+			let details = format!("Failed to create window: {}", error);
+			exit_sender.send(Err(PlatformError::new(&details))).expect("Failed to emit window creation error.");
+			return;
+		}
+	};
 	win_window::show_window(window);
 	window_sender.send(window).expect("Failed to send window out of this thread.");
 
@@ -93,7 +219,7 @@ pub fn platform_thread_run(
 			// break out and do not process WM_QUIT
 			if msg_value == WM_QUIT
 			{
-				_exit_sender.send(Ok(ExitCode::Quit)).expect("Failed to emit quit message.");
+				exit_sender.send(Ok(ExitCode::Quit)).expect("Failed to emit quit message.");
 				break;
 			}
 
@@ -111,3 +237,139 @@ pub fn platform_thread_run(
         }
     }
 }
+
+// Runs the Win32 message pump on its own thread, so a window being dragged or resized never stalls rendering.
+pub struct Win32Platform
+{
+	window : Option<win_window::Window>,
+	platform_thread : Option<thread::JoinHandle<()>>,
+	exit_receiver : Option<mpsc::Receiver<ExitResult>>,
+	input_receiver : Option<mpsc::Receiver<InputEvent>>,
+	exited : bool,
+}
+
+impl Win32Platform
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			window : None,
+			platform_thread : None,
+			exit_receiver : None,
+			input_receiver : None,
+			exited : false,
+		}
+	}
+}
+
+impl Platform for Win32Platform
+{
+	fn create_window(&mut self, title : &str, width : u32, height : u32) -> Result<(), PlatformError>
+	{
+		assert!(self.platform_thread.is_none(), "Win32Platform only supports one window.");
+
+		let (window_sender, window_reciever) = mpsc::channel::<win_window::Window>();
+		let (exit_sender, exit_receiver) = mpsc::channel::<ExitResult>();
+		let (input_sender, input_receiver) = mpsc::channel::<InputEvent>();
+		let title = title.to_string();
+
+		let windows_thread = thread::Builder::new()
+			.name("win_platform_thread".to_string())
+			.spawn(move || {platform_thread_run(title, width, height, window_sender, exit_sender, input_sender)})
+			.map_err(|error| PlatformError::new(&format!("failed to spin up win_platform_thread: {}", error)))?;
+
+		match window_reciever.recv()
+		{
+			Ok(window) =>
+			{
+				self.window = Some(window);
+				self.platform_thread = Some(windows_thread);
+				self.exit_receiver = Some(exit_receiver);
+				self.input_receiver = Some(input_receiver);
+				Ok(())
+			}
+			Err(_) =>
+			{
+				// the thread hung up without a window, it reports why on the exit channel.
+				windows_thread.join().expect("failed to join win_platform_thread");
+				self.exited = true;
+				match exit_receiver.try_recv()
+				{
+					Ok(Err(platform_error)) => Err(platform_error),
+					_ => Err(PlatformError::new("win_platform_thread exited before creating a window")),
+				}
+			}
+		}
+	}
+
+	fn raw_handle(&self) -> RawWindowHandle
+	{
+		match self.window
+		{
+			Some(window) => window.raw_handle(),
+			None => RawWindowHandle::Headless,
+		}
+	}
+
+	fn pump_events(&mut self) -> Vec<InputEvent>
+	{
+		// Messages are pumped on win_platform_thread, window_proc has already turned them into input.
+		match &self.input_receiver
+		{
+			Some(input_receiver) => input_receiver.try_iter().collect(),
+			None => Vec::new(),
+		}
+	}
+
+	fn exit_result(&mut self) -> Option<ExitResult>
+	{
+		if self.exited
+		{
+			return None;
+		}
+
+		let exit_result = self.exit_receiver.as_ref()?.try_recv().ok();
+		self.exited = exit_result.is_some();
+		exit_result
+	}
+
+	fn request_exit(&mut self)
+	{
+		if let Some(window) = self.window
+		{
+			// closing the window walks the same WM_CLOSE -> WM_DESTROY -> WM_QUIT path as the user closing it.
+			unsafe { PostMessageW(window.handle, WM_CLOSE, 0, 0); }
+		}
+	}
+
+	fn present(&mut self, framebuffer : &Framebuffer, display : &DisplayTransform)
+	{
+		if let Some(window) = self.window
+		{
+			let pixels : Vec<u32> = framebuffer.pixels().iter()
+				.map(|pixel| 
+				{
+					let [red, green, blue, _] = display.rgba8(*pixel);
+					(red as u32) << 16 | (green as u32) << 8 | blue as u32
+				})
+				.collect();
+			win_window::present_pixels(window, &pixels, framebuffer.width(), framebuffer.height());
+		}
+	}
+}
+
+impl Drop for Win32Platform
+{
+	fn drop(&mut self)
+	{
+		if let Some(windows_thread) = self.platform_thread.take()
+		{
+			if !self.exited
+			{
+				self.request_exit();
+			}
+			windows_thread.join().expect("failed to join win_platform_thread");
+		}
+	}
+}
@@ -1,7 +1,16 @@
 use std::io::Error; // Err / Ok
+use std::mem;
 use std::ptr::null_mut; // nullptr
 
 use winapi::shared::windef::HWND;
+use winapi::um::wingdi::{
+	BITMAPINFO,
+	BITMAPINFOHEADER,
+	BI_RGB,
+	DIB_RGB_COLORS,
+	SRCCOPY,
+	StretchDIBits,
+};
 use winapi::um::libloaderapi::GetModuleHandleW;
 use winapi::um::winuser::{
     WNDCLASSW,
@@ -15,11 +24,14 @@ use winapi::um::winuser::{
     RegisterClassW,
 	CreateWindowExW,
 	ShowWindow,
-	UpdateWindow
+	UpdateWindow,
+	GetDC,
+	ReleaseDC,
 };
 
 use crate::win_utilities::win32_string;
 use crate::win_platform::{ window_proc };
+use crate::window_handle::RawWindowHandle;
 
 #[derive(Copy, Clone)]
 pub struct Window
@@ -28,10 +40,18 @@ pub struct Window
 }
 unsafe impl std::marker::Send for Window {}
 
-pub fn create_window() -> Result<Window, Error>
+impl Window
+{
+	pub fn raw_handle(&self) -> RawWindowHandle
+	{
+		RawWindowHandle::Win32 { hwnd : self.handle as *mut _ }
+	}
+}
+
+pub fn create_window(title : &str, width : u32, height : u32) -> Result<Window, Error>
 {
 	let name = win32_string("sample");
-	let title = win32_string("title");
+	let title = win32_string(title);
 
 	let style = WS_OVERLAPPEDWINDOW | WS_VISIBLE;
 
@@ -62,8 +82,8 @@ pub fn create_window() -> Result<Window, Error>
 			style,			// dwStyle: DWORD,
 			CW_USEDEFAULT,	// x: c_int,
 			CW_USEDEFAULT,	// y: c_int,
-			1280,			// nWidth: c_int,
-			720,			// nHeight: c_int,
+			width as i32,	// nWidth: c_int,
+			height as i32,	// nHeight: c_int,
 			null_mut(),		// hWndParent: HWND,
 			null_mut(),		// hMenu: HMENU,
 			hinstance,		// hInstance: HINSTANCE,
@@ -90,4 +110,32 @@ pub fn show_window( window : Window )
 		ShowWindow(window.handle, SW_SHOW);
 		UpdateWindow(window.handle);
 	}
+}
+
+// Copies 0x00RRGGBB pixels, top row first, into the window's client area.
+pub fn present_pixels( window : Window, pixels : &[u32], width : u32, height : u32 )
+{
+	assert!(pixels.len() == (width * height) as usize);
+
+	unsafe
+	{
+		let mut bitmap_info : BITMAPINFO = mem::zeroed();
+		bitmap_info.bmiHeader.biSize = mem::size_of::<BITMAPINFOHEADER>() as u32;
+		bitmap_info.bmiHeader.biWidth = width as i32;
+		bitmap_info.bmiHeader.biHeight = -(height as i32); // negative height is a top-down bitmap
+		bitmap_info.bmiHeader.biPlanes = 1;
+		bitmap_info.bmiHeader.biBitCount = 32;
+		bitmap_info.bmiHeader.biCompression = BI_RGB;
+
+		let device_context = GetDC(window.handle);
+		StretchDIBits(
+			device_context,
+			0, 0, width as i32, height as i32,	// destination rectangle
+			0, 0, width as i32, height as i32,	// source rectangle
+			pixels.as_ptr() as *const _,
+			&bitmap_info,
+			DIB_RGB_COLORS,
+			SRCCOPY);
+		ReleaseDC(window.handle, device_context);
+	}
 }
\ No newline at end of file
@@ -0,0 +1,11 @@
+use std::ffi::c_void;
+
+// Platform-neutral handle to the surface a backend presents to.
+#[derive(Copy, Clone, Debug)]
+pub enum RawWindowHandle
+{
+	#[cfg_attr(not(windows), allow(dead_code))]
+	Win32 { hwnd : *mut c_void },
+	Headless,
+}
+unsafe impl std::marker::Send for RawWindowHandle {}